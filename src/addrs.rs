@@ -0,0 +1,1516 @@
+use std::cmp::Ordering;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV6};
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::explain::Verdict;
+use crate::netlink::{self, Netlink};
+use crate::procfs;
+use crate::ranges::{ipv4_within, ipv6_within};
+use crate::{
+    if_index, if_name, Destination, Error, IpQuery, IpVersion, Ipv4Scope, Ipv6Scope, OptimisticDad,
+    Result, Scope,
+};
+
+const IFA_F_SECONDARY: u32 = 0x01;
+const IFA_F_TEMPORARY: u32 = IFA_F_SECONDARY;
+const IFA_F_OPTIMISTIC: u32 = 0x04;
+const IFA_F_DADFAILED: u32 = 0x08;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_TENTATIVE: u32 = 0x40;
+const IFA_F_PERMANENT: u32 = 0x80;
+
+const INFINITY_LIFE_TIME: u32 = u32::MAX;
+
+/// An address assigned to an interface, as reported by the kernel.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InterfaceAddr {
+    /// The index of the interface the address is assigned to.
+    pub index: u32,
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+    /// The `IFA_F_*` flags of the address.
+    pub flags: u32,
+    /// The label of an IPv4 address, e.g. `eth0:mgmt`.
+    /// Defaults to the interface name. Always `None` for IPv6 addresses.
+    pub label: Option<String>,
+    /// The remaining preferred lifetime of the address,
+    /// `None` if it is infinite or unknown, e.g. if the addresses
+    /// were read from procfs.
+    pub preferred_lifetime: Option<Duration>,
+}
+
+impl InterfaceAddr {
+    pub(crate) fn from_netlink(addr: &netlink::Addr) -> Option<Self> {
+        Some(Self {
+            index: addr.index,
+            addr: addr.local.or(addr.address)?,
+            prefix_len: addr.prefix_len,
+            flags: addr.flags,
+            label: addr.label.clone(),
+            preferred_lifetime: addr
+                .lifetimes
+                .map(|(preferred, _)| preferred)
+                .filter(|&preferred| preferred != INFINITY_LIFE_TIME)
+                .map(|preferred| Duration::from_secs(preferred.into())),
+        })
+    }
+
+    /// Report whether this is a temporary IPv6 address (RFC 8981).
+    pub fn is_temporary(&self) -> bool {
+        self.addr.is_ipv6() && self.flags & IFA_F_TEMPORARY != 0
+    }
+
+    /// Report whether this is a secondary IPv4 address.
+    pub fn is_secondary(&self) -> bool {
+        self.addr.is_ipv4() && self.flags & IFA_F_SECONDARY != 0
+    }
+
+    /// Report whether duplicate address detection is still in progress.
+    pub fn is_tentative(&self) -> bool {
+        self.flags & IFA_F_TENTATIVE != 0
+    }
+
+    /// Report whether the address is in use while duplicate address
+    /// detection is still in progress (RFC 4429).
+    pub fn is_optimistic(&self) -> bool {
+        self.flags & IFA_F_OPTIMISTIC != 0
+    }
+
+    /// Report whether duplicate address detection failed.
+    pub fn is_dad_failed(&self) -> bool {
+        self.flags & IFA_F_DADFAILED != 0
+    }
+
+    /// Report whether the preferred lifetime of the address has expired.
+    pub fn is_deprecated(&self) -> bool {
+        self.flags & IFA_F_DEPRECATED != 0
+    }
+
+    /// Report whether the address was configured statically.
+    pub fn is_permanent(&self) -> bool {
+        self.flags & IFA_F_PERMANENT != 0
+    }
+
+    /// Report whether the address can be used as a source address
+    /// under the given policy.
+    ///
+    /// Failed duplicate address detection takes precedence:
+    /// Such addresses are never usable. Optimistic addresses
+    /// (which the kernel also flags as tentative) are usable
+    /// unless the policy is [`OptimisticDad::Reject`].
+    /// Other tentative addresses are never usable.
+    pub fn is_usable(&self, optimistic_dad: OptimisticDad) -> bool {
+        Verdict::of(self, optimistic_dad, |_| true).is_accepted()
+    }
+}
+
+/// The maximum number of addresses that are collected by the functions
+/// returning all addresses of an interface, e.g. [`interface_addresses`].
+/// They fail with [`Error::TooManyAddresses`] if there are more.
+///
+/// Functions that select a single address, like the getters,
+/// aren't limited. They consider the addresses one at a time
+/// without collecting them.
+pub const MAX_ADDRESSES: usize = 16384;
+
+/// Get the addresses assigned to the given interface,
+/// at most [`MAX_ADDRESSES`].
+pub fn interface_addresses(interface: &str) -> Result<Vec<InterfaceAddr>> {
+    addresses(Some(interface))
+}
+
+/// Pass the addresses assigned to the given interface,
+/// or to any interface if `None`, to `f` until it breaks.
+/// Falls back to procfs if netlink isn't permitted.
+pub(crate) fn try_for_each_address<B>(
+    interface: Option<&str>,
+    mut f: impl FnMut(InterfaceAddr) -> ControlFlow<B>,
+) -> Result<Option<B>> {
+    let index = interface.map(if_index).transpose()?;
+
+    let result = Netlink::open().and_then(|mut netlink| {
+        netlink.addrs_each(None, |addr| {
+            match InterfaceAddr::from_netlink(&addr)
+                .filter(|_| index.is_none_or(|index| addr.index == index))
+            {
+                Some(addr) => f(addr),
+                None => ControlFlow::Continue(()),
+            }
+        })
+    });
+
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            // procfs only has whole files to offer anyway.
+            Ok(
+                match procfs::addresses(interface)?.into_iter().try_for_each(f) {
+                    ControlFlow::Break(b) => Some(b),
+                    ControlFlow::Continue(()) => None,
+                },
+            )
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Get the addresses assigned to the given interface,
+/// or to any interface if `None`, at most [`MAX_ADDRESSES`].
+pub(crate) fn addresses(interface: Option<&str>) -> Result<Vec<InterfaceAddr>> {
+    let mut addrs = Vec::new();
+    let exceeded = try_for_each_address(interface, |addr| {
+        if addrs.len() == MAX_ADDRESSES {
+            return ControlFlow::Break(());
+        }
+
+        addrs.push(addr);
+        ControlFlow::Continue(())
+    })?;
+
+    match exceeded {
+        Some(()) => Err(Error::TooManyAddresses {
+            interface: interface.map(Into::into),
+            limit: MAX_ADDRESSES,
+        }),
+        None => Ok(addrs),
+    }
+}
+
+/// Get the first address assigned to the given interface,
+/// or to any interface if `None`, that satisfies the predicate.
+pub(crate) fn find_address(
+    interface: Option<&str>,
+    predicate: impl Fn(&InterfaceAddr) -> bool,
+) -> Result<Option<InterfaceAddr>> {
+    try_for_each_address(interface, |addr| match predicate(&addr) {
+        true => ControlFlow::Break(addr),
+        false => ControlFlow::Continue(()),
+    })
+}
+
+/// Get the first address in the order of [`deterministic_order`]
+/// that satisfies the predicate.
+pub(crate) fn min_address(
+    interface: Option<&str>,
+    predicate: impl Fn(&InterfaceAddr) -> bool,
+) -> Result<Option<InterfaceAddr>> {
+    let mut min: Option<InterfaceAddr> = None;
+    try_for_each_address(interface, |addr| {
+        // The predicate sees every address, so that explanations are complete.
+        let is_less = predicate(&addr)
+            && min
+                .as_ref()
+                .is_none_or(|min| deterministic_order(&addr, min) == Ordering::Less);
+        if is_less {
+            min = Some(addr);
+        }
+
+        ControlFlow::<()>::Continue(())
+    })?;
+
+    Ok(min)
+}
+
+/// Look up an address on any interface.
+pub(crate) fn find(ip: IpAddr) -> Result<Option<InterfaceAddr>> {
+    find_address(None, |addr| addr.addr == ip)
+}
+
+/// Get the names of all interfaces the address is assigned to,
+/// ordered by index, e.g. to detect addresses that are shared
+/// between interfaces in anycast or VRRP setups.
+///
+/// IPv4-mapped addresses are looked up as IPv4 addresses.
+/// Loopback addresses are reported like any other address,
+/// usually on `lo`. No interface has the unspecified address.
+pub fn address_owners(addr: IpAddr) -> Result<Vec<String>> {
+    Ok(owner_indexes(addr)?
+        .into_iter()
+        .filter_map(if_name)
+        .collect())
+}
+
+/// Get the indexes of the interfaces the address is assigned to, in order.
+fn owner_indexes(addr: IpAddr) -> Result<Vec<u32>> {
+    let addr = addr.to_canonical();
+    if addr.is_unspecified() {
+        return Ok(Vec::new());
+    }
+
+    let mut indexes = Vec::new();
+    try_for_each_address(None, |found| {
+        if found.addr == addr && !indexes.contains(&found.index) {
+            indexes.push(found.index);
+        }
+
+        ControlFlow::<()>::Continue(())
+    })?;
+
+    indexes.sort_unstable();
+    Ok(indexes)
+}
+
+/// The order of [`IpQuery::deterministic`](crate::IpQuery::deterministic):
+/// Addresses that aren't deprecated come first, then stable IPv6
+/// addresses before temporary ones, then the numerically lowest address.
+///
+/// Whether an IPv4 address is primary or secondary only depends on the
+/// order the addresses were added in, so it is ignored.
+pub(crate) fn deterministic_order(a: &InterfaceAddr, b: &InterfaceAddr) -> Ordering {
+    a.is_deprecated()
+        .cmp(&b.is_deprecated())
+        .then(a.is_temporary().cmp(&b.is_temporary()))
+        .then(a.addr.cmp(&b.addr))
+}
+
+/// An IPv6 address together with the index of the interface
+/// it is assigned to, as needed for connecting to or binding to
+/// link-local addresses.
+///
+/// Scoped addresses are ordered by address first, then by scope id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScopedIpv6Addr {
+    pub addr: Ipv6Addr,
+    pub scope_id: u32,
+}
+
+impl ScopedIpv6Addr {
+    /// Get a socket address with the given port and this scope id.
+    pub fn socket_addr(&self, port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(self.addr, port, 0, self.scope_id)
+    }
+}
+
+impl From<ScopedIpv6Addr> for Destination {
+    fn from(addr: ScopedIpv6Addr) -> Self {
+        addr.socket_addr(0).into()
+    }
+}
+
+impl IpQuery<'_> {
+    /// Get all usable IPv6 link-local addresses of the interface,
+    /// or of all interfaces if the query isn't bound to one.
+    ///
+    /// If the query is bound to an interface, the address the kernel
+    /// prefers comes first. The others are sorted like with
+    /// [`IpQuery::deterministic`], and by interface index if the same
+    /// address is assigned to several interfaces.
+    /// Fails with [`Error::NoAddress`] if there are none, not with
+    /// [`Error::NoLinkLocal`] like [`IpQuery::ipv6_unicast_link_local`].
+    /// That error carries the address the kernel chose instead
+    /// of a link-local one, and there is none to report here.
+    pub fn ipv6_unicast_link_local_all(&self) -> Result<Vec<ScopedIpv6Addr>> {
+        let preferred = match self.interface {
+            Some(_) => match self.ipv6_unicast_link_local() {
+                Ok(ipv6) => Some(ipv6),
+                Err(e) if e.is_scope_miss() => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let addrs =
+            link_local_candidates(addresses(self.interface)?, preferred, self.optimistic_dad);
+        if addrs.is_empty() {
+            Err(Error::NoAddress {
+                interface: self.interface_name(),
+                family: IpVersion::V6,
+            })
+        } else {
+            Ok(addrs)
+        }
+    }
+}
+
+/// Filter the usable IPv6 link-local addresses and sort them
+/// for [`IpQuery::ipv6_unicast_link_local_all`].
+fn link_local_candidates(
+    addrs: Vec<InterfaceAddr>,
+    preferred: Option<Ipv6Addr>,
+    optimistic_dad: OptimisticDad,
+) -> Vec<ScopedIpv6Addr> {
+    let mut addrs: Vec<_> = addrs
+        .into_iter()
+        .filter(|addr| addr.is_usable(optimistic_dad))
+        .filter(|addr| match addr.addr {
+            IpAddr::V6(ipv6) => ipv6.is_unicast_link_local(),
+            IpAddr::V4(_) => false,
+        })
+        .collect();
+
+    addrs.sort_by(|a, b| {
+        let a_preferred = Some(a.addr) == preferred.map(IpAddr::V6);
+        let b_preferred = Some(b.addr) == preferred.map(IpAddr::V6);
+
+        b_preferred
+            .cmp(&a_preferred)
+            .then(deterministic_order(a, b))
+            .then(a.index.cmp(&b.index))
+    });
+
+    addrs
+        .into_iter()
+        .filter_map(|addr| match addr.addr {
+            IpAddr::V6(ipv6) => Some(ScopedIpv6Addr {
+                addr: ipv6,
+                scope_id: addr.index,
+            }),
+            IpAddr::V4(_) => None,
+        })
+        .collect()
+}
+
+impl IpQuery<'_> {
+    /// Get the IPv4 address with the given label, e.g. `eth0:mgmt`,
+    /// on the interface or on any interface if the query isn't bound
+    /// to one. The label has to match exactly.
+    pub fn ipv4_by_label(&self, label: &str) -> Result<Ipv4Addr> {
+        let mut available: Vec<String> = Vec::new();
+
+        let found = try_for_each_address(self.interface, |addr| {
+            match_label(addr, label, &mut available)
+        })?;
+
+        found.ok_or_else(|| Error::NoLabel {
+            interface: self.interface_name(),
+            label: label.into(),
+            available,
+        })
+    }
+}
+
+/// Stop at the IPv4 address with the given label,
+/// collecting the other labels in the order they are first seen.
+fn match_label(
+    addr: InterfaceAddr,
+    label: &str,
+    available: &mut Vec<String>,
+) -> ControlFlow<Ipv4Addr> {
+    match (addr.addr, addr.label) {
+        (IpAddr::V4(ipv4), Some(found)) if found == label => ControlFlow::Break(ipv4),
+        (_, Some(other)) => {
+            if !available.contains(&other) {
+                available.push(other);
+            }
+            ControlFlow::Continue(())
+        }
+        (_, None) => ControlFlow::Continue(()),
+    }
+}
+
+impl IpQuery<'_> {
+    /// Report whether the interface, or any interface if the query
+    /// isn't bound to one, has a usable address of the given scope.
+    ///
+    /// The addresses are enumerated instead of probed, so no socket
+    /// is connected and routes don't matter. Only if they can't be
+    /// enumerated, the scope is probed instead. Errors other than
+    /// a missing address of the scope, e.g. a missing interface,
+    /// are returned rather than reported as `false`.
+    pub fn has(&self, scope: Scope) -> Result<bool> {
+        let found = find_address(self.interface, |addr| {
+            addr.is_usable(self.optimistic_dad) && scope.contains(&addr.addr)
+        });
+
+        found_or_probe(found.map(|found| found.is_some()), || self.get(scope))
+    }
+}
+
+/// Get the result of the enumeration, or of the probe if the addresses
+/// couldn't be enumerated. Only a missing address of the scope is `false`.
+fn found_or_probe(found: Result<bool>, probe: impl FnOnce() -> Result<IpAddr>) -> Result<bool> {
+    match found {
+        Err(Error::IoError(e))
+            if matches!(
+                e.kind(),
+                io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound
+            ) =>
+        {
+            match probe() {
+                Ok(_) => Ok(true),
+                Err(e) if e.is_scope_miss() => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+        found => found,
+    }
+}
+
+/// Report whether the given interface has a usable IPv6 link-local address.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv6_unicast_link_local(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv6Scope::UnicastLinkLocal.into())
+}
+
+/// Report whether the given interface has a usable IPv6 ULA.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv6_unique_local(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv6Scope::UniqueLocal.into())
+}
+
+/// Report whether the given interface has a usable IPv6 GUA.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv6_unicast_global(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv6Scope::UnicastGlobal.into())
+}
+
+/// Report whether the given interface has a usable IPv4 link-local address.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv4_link_local(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv4Scope::LinkLocal.into())
+}
+
+/// Report whether the given interface has a usable IPv4 private address.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv4_private(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv4Scope::Private.into())
+}
+
+/// Report whether the given interface has a usable IPv4 global address.
+/// See [`IpQuery::has`] for details.
+pub fn has_ipv4_global(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).has(Ipv4Scope::Global.into())
+}
+
+/// Whether the global IPv6 address of an interface
+/// is within an expected prefix, see [`IpQuery::verify_ipv6_within`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrefixVerdict {
+    /// The preferred address is within the prefix.
+    Inside(Ipv6Addr),
+    /// The preferred address is outside of the prefix.
+    Outside {
+        addr: Ipv6Addr,
+        /// A usable global address of the interface
+        /// that is within the prefix, if there is one.
+        suggestion: Option<Ipv6Addr>,
+    },
+    /// There is no global address to verify.
+    NoGlobal,
+}
+
+/// An address together with a prefix length,
+/// e.g. a network to select addresses within.
+/// Lengths above the size of the address are treated as its size.
+///
+/// Addresses convert into a prefix of their size, and tuples of
+/// an address and a length into that prefix. With the `ipnet` feature,
+/// the prefix types of the `ipnet` crate convert into and from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpPrefix {
+    pub addr: IpAddr,
+    pub len: u8,
+}
+
+impl IpPrefix {
+    /// Report whether the address is within the prefix.
+    /// Addresses of the other family never are.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (addr, self.addr) {
+            (IpAddr::V6(ipv6), IpAddr::V6(prefix)) => ipv6_within(ipv6, prefix, self.len),
+            (IpAddr::V4(ipv4), IpAddr::V4(prefix)) => ipv4_within(ipv4, prefix, self.len),
+            _ => false,
+        }
+    }
+
+    fn ipv6(self) -> Result<(Ipv6Addr, u8)> {
+        match self.addr {
+            IpAddr::V6(ipv6) => Ok((ipv6, self.len)),
+            IpAddr::V4(_) => Err(Error::WrongIpVer(IpVersion::V6, self.addr)),
+        }
+    }
+
+    fn ipv4(self) -> Result<(Ipv4Addr, u8)> {
+        match self.addr {
+            IpAddr::V4(ipv4) => Ok((ipv4, self.len)),
+            IpAddr::V6(_) => Err(Error::WrongIpVer(IpVersion::V4, self.addr)),
+        }
+    }
+}
+
+impl From<(IpAddr, u8)> for IpPrefix {
+    fn from((addr, len): (IpAddr, u8)) -> Self {
+        Self { addr, len }
+    }
+}
+
+impl From<(Ipv6Addr, u8)> for IpPrefix {
+    fn from((addr, len): (Ipv6Addr, u8)) -> Self {
+        Self {
+            addr: addr.into(),
+            len,
+        }
+    }
+}
+
+impl From<(Ipv4Addr, u8)> for IpPrefix {
+    fn from((addr, len): (Ipv4Addr, u8)) -> Self {
+        Self {
+            addr: addr.into(),
+            len,
+        }
+    }
+}
+
+impl From<IpAddr> for IpPrefix {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V6(ipv6) => ipv6.into(),
+            IpAddr::V4(ipv4) => ipv4.into(),
+        }
+    }
+}
+
+impl From<Ipv6Addr> for IpPrefix {
+    fn from(addr: Ipv6Addr) -> Self {
+        (addr, 128).into()
+    }
+}
+
+impl From<Ipv4Addr> for IpPrefix {
+    fn from(addr: Ipv4Addr) -> Self {
+        (addr, 32).into()
+    }
+}
+
+impl From<&InterfaceAddr> for IpPrefix {
+    fn from(addr: &InterfaceAddr) -> Self {
+        (addr.addr, addr.prefix_len).into()
+    }
+}
+
+impl IpQuery<'_> {
+    /// Check whether the preferred outgoing IPv6 GUA is within the given
+    /// prefix, e.g. to detect leftovers of an old prefix after renumbering.
+    /// Fails with [`Error::WrongIpVer`] if the prefix is an IPv4 one.
+    ///
+    /// If it isn't, the usable GUAs of the interface (or of all interfaces
+    /// if the query isn't bound to one) are searched for one within
+    /// the prefix, sorted like with [`IpQuery::deterministic`].
+    pub fn verify_ipv6_within(&self, prefix: impl Into<IpPrefix>) -> Result<PrefixVerdict> {
+        let (prefix, len) = prefix.into().ipv6()?;
+
+        let addr = match self.ipv6_unicast_global() {
+            Ok(addr) => addr,
+            Err(e) if e.is_scope_miss() => return Ok(PrefixVerdict::NoGlobal),
+            Err(e) => return Err(e),
+        };
+
+        if ipv6_within(addr, prefix, len) {
+            return Ok(PrefixVerdict::Inside(addr));
+        }
+
+        let suggestion = min_address(self.interface, |candidate| {
+            is_suggestion(candidate, prefix, len, self.optimistic_dad)
+        })?;
+
+        let suggestion = suggestion.and_then(|candidate| match candidate.addr {
+            IpAddr::V6(ipv6) => Some(ipv6),
+            IpAddr::V4(_) => None,
+        });
+
+        Ok(PrefixVerdict::Outside { addr, suggestion })
+    }
+}
+
+/// Report whether the address can be suggested by
+/// [`IpQuery::verify_ipv6_within`], i.e. whether it is a usable GUA
+/// within the prefix.
+fn is_suggestion(
+    candidate: &InterfaceAddr,
+    prefix: Ipv6Addr,
+    len: u8,
+    optimistic_dad: OptimisticDad,
+) -> bool {
+    candidate.is_usable(optimistic_dad)
+        && match candidate.addr {
+            IpAddr::V6(ipv6) => {
+                Scope::from(Ipv6Scope::UnicastGlobal).contains(&candidate.addr)
+                    && ipv6_within(ipv6, prefix, len)
+            }
+            IpAddr::V4(_) => false,
+        }
+}
+
+impl IpQuery<'_> {
+    /// Get the first usable IPv6 address within the given prefix
+    /// on the interface, or on any interface if the query isn't bound
+    /// to one, in the order the kernel lists them.
+    /// Fails with [`Error::WrongIpVer`] if the prefix is an IPv4 one.
+    ///
+    /// Unlike the getters, this doesn't ask the kernel for its choice.
+    /// The enumeration stops at the first match.
+    pub fn ipv6_in_prefix(&self, prefix: impl Into<IpPrefix>) -> Result<Ipv6Addr> {
+        let (prefix, len) = prefix.into().ipv6()?;
+
+        let found = find_address(self.interface, |addr| {
+            addr.is_usable(self.optimistic_dad)
+                && match addr.addr {
+                    IpAddr::V6(ipv6) => ipv6_within(ipv6, prefix, len),
+                    IpAddr::V4(_) => false,
+                }
+        })?;
+
+        match found.map(|addr| addr.addr) {
+            Some(IpAddr::V6(ipv6)) => Ok(ipv6),
+            _ => Err(Error::NoAddress {
+                interface: self.interface_name(),
+                family: IpVersion::V6,
+            }),
+        }
+    }
+
+    /// Get the first usable IPv4 address within the given subnet.
+    /// Fails with [`Error::WrongIpVer`] if the subnet is an IPv6 one.
+    /// See [`IpQuery::ipv6_in_prefix`] for details.
+    pub fn ipv4_in_subnet(&self, subnet: impl Into<IpPrefix>) -> Result<Ipv4Addr> {
+        let (subnet, len) = subnet.into().ipv4()?;
+
+        let found = find_address(self.interface, |addr| {
+            addr.is_usable(self.optimistic_dad)
+                && match addr.addr {
+                    IpAddr::V4(ipv4) => ipv4_within(ipv4, subnet, len),
+                    IpAddr::V6(_) => false,
+                }
+        })?;
+
+        match found.map(|addr| addr.addr) {
+            Some(IpAddr::V4(ipv4)) => Ok(ipv4),
+            _ => Err(Error::NoAddress {
+                interface: self.interface_name(),
+                family: IpVersion::V4,
+            }),
+        }
+    }
+}
+
+/// Get the first usable IPv6 address within the given prefix
+/// on the given interface.
+/// See [`IpQuery::ipv6_in_prefix`] for details.
+pub fn ipv6_in_prefix(interface: &str, prefix: impl Into<IpPrefix>) -> Result<Ipv6Addr> {
+    IpQuery::new(interface).ipv6_in_prefix(prefix)
+}
+
+/// Get the first usable IPv4 address within the given subnet
+/// on the given interface.
+/// See [`IpQuery::ipv4_in_subnet`] for details.
+pub fn ipv4_in_subnet(interface: &str, subnet: impl Into<IpPrefix>) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_in_subnet(subnet)
+}
+
+/// Check whether the preferred outgoing IPv6 GUA of the given interface
+/// is within the given prefix.
+/// See [`IpQuery::verify_ipv6_within`] for details.
+pub fn verify_ipv6_within(interface: &str, prefix: impl Into<IpPrefix>) -> Result<PrefixVerdict> {
+    IpQuery::new(interface).verify_ipv6_within(prefix)
+}
+
+/// Get the IPv4 address with the given label on the given interface.
+/// See [`IpQuery::ipv4_by_label`] for details.
+pub fn ipv4_by_label(interface: &str, label: &str) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_by_label(label)
+}
+
+/// Get all usable IPv6 link-local addresses of the given interface,
+/// preferred first. See [`IpQuery::ipv6_unicast_link_local_all`]
+/// for details.
+pub fn ipv6_unicast_link_local_all(interface: &str) -> Result<Vec<ScopedIpv6Addr>> {
+    IpQuery::new(interface).ipv6_unicast_link_local_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::mem;
+
+    use super::*;
+    use crate::explain::Rank;
+
+    fn interface_addr(addr: &str, flags: u32) -> InterfaceAddr {
+        indexed_addr(2, addr, flags)
+    }
+
+    fn indexed_addr(index: u32, addr: &str, flags: u32) -> InterfaceAddr {
+        InterfaceAddr {
+            index,
+            addr: addr.parse().unwrap(),
+            prefix_len: 64,
+            flags,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    fn sorted(mut addrs: Vec<InterfaceAddr>) -> Vec<IpAddr> {
+        addrs.sort_by(deterministic_order);
+        addrs.into_iter().map(|addr| addr.addr).collect()
+    }
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn deterministic_order_prefers_lowest() {
+        let addrs = vec![
+            interface_addr("2a01:4f8::20", IFA_F_PERMANENT),
+            interface_addr("2a01:4f8::3", 0),
+            interface_addr("2a01:4f8::1:0", IFA_F_PERMANENT),
+        ];
+        assert_eq!(
+            sorted(addrs),
+            ips(&["2a01:4f8::3", "2a01:4f8::20", "2a01:4f8::1:0"])
+        );
+    }
+
+    #[test]
+    fn deterministic_order_prefers_stable_to_temporary() {
+        let addrs = vec![
+            interface_addr("2a01:4f8::1", IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8::ff", 0),
+            interface_addr("2a01:4f8::2", IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8::fe", IFA_F_PERMANENT),
+        ];
+        assert_eq!(
+            sorted(addrs),
+            ips(&["2a01:4f8::fe", "2a01:4f8::ff", "2a01:4f8::1", "2a01:4f8::2"])
+        );
+    }
+
+    #[test]
+    fn deterministic_order_puts_deprecated_last() {
+        let addrs = vec![
+            interface_addr("2a01:4f8::1", IFA_F_DEPRECATED),
+            interface_addr("2a01:4f8::3", IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8::2", IFA_F_DEPRECATED | IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8::4", 0),
+        ];
+        assert_eq!(
+            sorted(addrs),
+            ips(&["2a01:4f8::4", "2a01:4f8::3", "2a01:4f8::1", "2a01:4f8::2"])
+        );
+    }
+
+    #[test]
+    fn deterministic_order_ignores_ipv4_secondaries() {
+        // The secondary flag has the same value as the temporary flag,
+        // but doesn't make IPv4 addresses rank lower.
+        let addrs = vec![
+            interface_addr("192.168.1.20", 0),
+            interface_addr("192.168.1.3", IFA_F_SECONDARY),
+            interface_addr("192.168.1.10", IFA_F_SECONDARY),
+        ];
+        assert!(!addrs[1].is_temporary());
+        assert_eq!(
+            sorted(addrs),
+            ips(&["192.168.1.3", "192.168.1.10", "192.168.1.20"])
+        );
+
+        let addrs = vec![
+            interface_addr("192.168.1.2", IFA_F_DEPRECATED),
+            interface_addr("192.168.1.3", IFA_F_SECONDARY),
+        ];
+        assert_eq!(sorted(addrs), ips(&["192.168.1.3", "192.168.1.2"]));
+    }
+
+    #[test]
+    fn deterministic_order_is_independent_of_input_order() {
+        let addrs = [
+            interface_addr("2a01:4f8::1", IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8::2", 0),
+            interface_addr("2a01:4f8::3", IFA_F_DEPRECATED),
+            interface_addr("2a01:4f8::4", IFA_F_PERMANENT),
+            interface_addr("192.168.1.1", IFA_F_SECONDARY),
+        ];
+        let expected = sorted(addrs.to_vec());
+        assert_eq!(
+            expected,
+            ips(&[
+                "192.168.1.1",
+                "2a01:4f8::2",
+                "2a01:4f8::4",
+                "2a01:4f8::1",
+                "2a01:4f8::3"
+            ])
+        );
+
+        // Every rotation and its reverse sort the same.
+        for i in 0..addrs.len() {
+            let mut rotated = addrs.to_vec();
+            rotated.rotate_left(i);
+            assert_eq!(sorted(rotated.clone()), expected);
+            rotated.reverse();
+            assert_eq!(sorted(rotated), expected);
+        }
+    }
+
+    #[test]
+    fn dad_flag_matrix() {
+        use OptimisticDad::*;
+
+        const OPTIMISTIC: u32 = IFA_F_OPTIMISTIC | IFA_F_TENTATIVE;
+
+        #[rustfmt::skip]
+        let table = [
+            // flags, verdict with Reject, verdict with Accept(AndFlag)
+            (0, Verdict::Accepted(Rank { deprecated: false, temporary: false }), None),
+            (IFA_F_TENTATIVE, Verdict::Tentative, None),
+            // The kernel always sets both flags, but the optimistic flag
+            // alone is treated the same.
+            (OPTIMISTIC, Verdict::Optimistic, Some(true)),
+            (IFA_F_OPTIMISTIC, Verdict::Optimistic, Some(true)),
+            (IFA_F_DADFAILED, Verdict::DadFailed, None),
+            (IFA_F_DADFAILED | IFA_F_TENTATIVE, Verdict::DadFailed, None),
+            (IFA_F_DADFAILED | OPTIMISTIC, Verdict::DadFailed, None),
+            (OPTIMISTIC | IFA_F_DEPRECATED, Verdict::Optimistic, Some(true)),
+        ];
+
+        for (flags, rejected, accepted_optimistic) in table {
+            let addr = interface_addr("2a01:4f8::1", flags);
+
+            assert_eq!(
+                Verdict::of(&addr, Reject, |_| true),
+                rejected,
+                "{:#x}",
+                flags
+            );
+            assert_eq!(
+                addr.is_usable(Reject),
+                rejected.is_accepted(),
+                "{:#x}",
+                flags
+            );
+
+            for policy in [Accept, AcceptAndFlag] {
+                let verdict = Verdict::of(&addr, policy, |_| true);
+                match accepted_optimistic {
+                    Some(optimistic) => {
+                        assert!(verdict.is_accepted(), "{:#x} {:?}", flags, policy);
+                        assert_eq!(addr.is_optimistic(), optimistic);
+                    }
+                    None => assert_eq!(verdict, rejected, "{:#x} {:?}", flags, policy),
+                }
+                assert_eq!(addr.is_usable(policy), verdict.is_accepted());
+            }
+        }
+    }
+
+    #[test]
+    fn dad_flags_take_precedence_over_scope() {
+        let addr = interface_addr("2a01:4f8::1", IFA_F_DADFAILED);
+        let verdict = Verdict::of(&addr, OptimisticDad::Accept, |_| false);
+        assert_eq!(verdict, Verdict::DadFailed);
+
+        let addr = interface_addr("2a01:4f8::1", IFA_F_OPTIMISTIC | IFA_F_TENTATIVE);
+        let verdict = Verdict::of(&addr, OptimisticDad::Accept, |_| false);
+        assert_eq!(verdict, Verdict::OutOfScope);
+    }
+
+    fn scoped(addr: &str, scope_id: u32) -> ScopedIpv6Addr {
+        ScopedIpv6Addr {
+            addr: addr.parse().unwrap(),
+            scope_id,
+        }
+    }
+
+    #[test]
+    fn link_local_candidates_prefer_kernel_choice() {
+        let addrs = vec![
+            indexed_addr(2, "fe80::1", IFA_F_PERMANENT),
+            indexed_addr(2, "fe80::5054:ff:fe12:3456", IFA_F_PERMANENT),
+            indexed_addr(2, "fe80::3", IFA_F_DEPRECATED),
+            indexed_addr(2, "fe80::2", 0),
+        ];
+        let preferred = "fe80::5054:ff:fe12:3456".parse().ok();
+
+        let candidates = link_local_candidates(addrs.clone(), preferred, OptimisticDad::default());
+        assert_eq!(
+            candidates,
+            [
+                scoped("fe80::5054:ff:fe12:3456", 2),
+                scoped("fe80::1", 2),
+                scoped("fe80::2", 2),
+                scoped("fe80::3", 2),
+            ]
+        );
+
+        // Even a deprecated address comes first if the kernel prefers it.
+        let preferred = "fe80::3".parse().ok();
+        let candidates = link_local_candidates(addrs, preferred, OptimisticDad::default());
+        assert_eq!(candidates[0], scoped("fe80::3", 2));
+    }
+
+    #[test]
+    fn link_local_candidates_of_all_interfaces() {
+        let addrs = vec![
+            indexed_addr(3, "fe80::1", 0),
+            indexed_addr(1, "::1", IFA_F_PERMANENT),
+            indexed_addr(2, "fe80::2", 0),
+            indexed_addr(2, "fe80::1", 0),
+            indexed_addr(2, "192.168.1.1", 0),
+            indexed_addr(4, "2a01:4f8::1", 0),
+        ];
+
+        let candidates = link_local_candidates(addrs, None, OptimisticDad::default());
+        assert_eq!(
+            candidates,
+            [
+                scoped("fe80::1", 2),
+                scoped("fe80::1", 3),
+                scoped("fe80::2", 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn link_local_candidates_are_usable() {
+        let addrs = vec![
+            indexed_addr(2, "fe80::1", IFA_F_TENTATIVE),
+            indexed_addr(2, "fe80::2", IFA_F_DADFAILED),
+            indexed_addr(2, "fe80::3", IFA_F_OPTIMISTIC | IFA_F_TENTATIVE),
+            indexed_addr(2, "fe80::4", 0),
+        ];
+
+        let candidates = link_local_candidates(addrs.clone(), None, OptimisticDad::Accept);
+        assert_eq!(candidates, [scoped("fe80::3", 2), scoped("fe80::4", 2)]);
+
+        let candidates = link_local_candidates(addrs, None, OptimisticDad::Reject);
+        assert_eq!(candidates, [scoped("fe80::4", 2)]);
+
+        let addrs = vec![indexed_addr(2, "fe80::1", IFA_F_DADFAILED)];
+        assert!(link_local_candidates(addrs, None, OptimisticDad::Accept).is_empty());
+    }
+
+    fn labeled(addr: &str, label: Option<&str>) -> InterfaceAddr {
+        InterfaceAddr {
+            label: label.map(Into::into),
+            ..interface_addr(addr, 0)
+        }
+    }
+
+    fn search_label(addrs: &[InterfaceAddr], label: &str) -> (Option<Ipv4Addr>, Vec<String>) {
+        let mut available = Vec::new();
+        let found = addrs
+            .iter()
+            .cloned()
+            .try_for_each(|addr| match_label(addr, label, &mut available));
+        (found.break_value(), available)
+    }
+
+    #[test]
+    fn label_matches_exactly() {
+        let addrs = [
+            labeled("192.168.1.1", Some("eth0")),
+            labeled("192.168.1.2", Some("eth0:mgmt")),
+            labeled("192.168.1.3", Some("eth0:mgmt2")),
+        ];
+
+        let (found, _) = search_label(&addrs, "eth0:mgmt");
+        assert_eq!(found, Some(Ipv4Addr::new(192, 168, 1, 2)));
+        let (found, _) = search_label(&addrs, "eth0");
+        assert_eq!(found, Some(Ipv4Addr::new(192, 168, 1, 1)));
+        let (found, available) = search_label(&addrs, "eth0:mg");
+        assert_eq!(found, None);
+        assert_eq!(available, ["eth0", "eth0:mgmt", "eth0:mgmt2"]);
+    }
+
+    #[test]
+    fn available_labels_are_unique() {
+        // Labels repeat across addresses that aren't adjacent.
+        let addrs = [
+            labeled("192.168.1.1", Some("eth0")),
+            labeled("192.168.1.2", Some("eth0:0")),
+            labeled("2a01:4f8::1", None),
+            labeled("192.168.1.3", Some("eth0")),
+            labeled("10.0.0.1", Some("eth0:0")),
+            labeled("10.0.0.2", Some("eth0:1")),
+        ];
+
+        let (found, available) = search_label(&addrs, "eth0:mgmt");
+        assert_eq!(found, None);
+        assert_eq!(available, ["eth0", "eth0:0", "eth0:1"]);
+    }
+
+    #[test]
+    fn ipv6_addresses_have_no_label() {
+        let addrs = [labeled("2a01:4f8::1", None), labeled("fe80::1", None)];
+
+        let (found, available) = search_label(&addrs, "eth0");
+        assert_eq!(found, None);
+        assert!(available.is_empty());
+    }
+
+    fn ip_prefix(addr: &str, len: u8) -> IpPrefix {
+        IpPrefix {
+            addr: addr.parse().unwrap(),
+            len,
+        }
+    }
+
+    #[test]
+    fn addresses_convert_to_prefixes() {
+        let ipv6: Ipv6Addr = "2a01:4f8::1".parse().unwrap();
+        let ipv4: Ipv4Addr = "192.168.77.1".parse().unwrap();
+
+        assert_eq!(IpPrefix::from(ipv6), ip_prefix("2a01:4f8::1", 128));
+        assert_eq!(IpPrefix::from(ipv4), ip_prefix("192.168.77.1", 32));
+        assert_eq!(IpPrefix::from(IpAddr::V6(ipv6)), IpPrefix::from(ipv6));
+        assert_eq!(IpPrefix::from(IpAddr::V4(ipv4)), IpPrefix::from(ipv4));
+        assert_eq!(IpPrefix::from((ipv6, 64)), ip_prefix("2a01:4f8::1", 64));
+        assert_eq!(IpPrefix::from((ipv4, 24)), ip_prefix("192.168.77.1", 24));
+
+        let mut addr = interface_addr("2a01:4f8::1", 0);
+        assert_eq!(IpPrefix::from(&addr), ip_prefix("2a01:4f8::1", 64));
+        addr.prefix_len = 200;
+        assert_eq!(IpPrefix::from(&addr), ip_prefix("2a01:4f8::1", 200));
+    }
+
+    #[test]
+    fn prefixes_contain_their_family_only() {
+        #[rustfmt::skip]
+        let table = [
+            (ip_prefix("2a01:4f8::", 32), "2a01:4f8:1::5", true),
+            (ip_prefix("2a01:4f8::", 32), "2a01:4f9::5", false),
+            (ip_prefix("2a01:4f8::1", 200), "2a01:4f8::1", true),
+            (ip_prefix("2a01:4f8::1", 200), "2a01:4f8::2", false),
+            (ip_prefix("::", 0), "fe80::1", true),
+            (ip_prefix("::", 0), "192.168.77.1", false),
+            (ip_prefix("192.168.0.0", 16), "192.168.77.1", true),
+            (ip_prefix("192.168.0.0", 16), "192.169.0.1", false),
+            (ip_prefix("192.168.77.1", 33), "192.168.77.1", true),
+            (ip_prefix("0.0.0.0", 0), "8.8.8.8", true),
+            (ip_prefix("0.0.0.0", 0), "::ffff:8.8.8.8", false),
+        ];
+
+        for (prefix, addr, expected) in table {
+            let addr = addr.parse().unwrap();
+            assert_eq!(prefix.contains(addr), expected, "{:?} {}", prefix, addr);
+        }
+    }
+
+    #[test]
+    fn prefixes_of_the_other_family_are_rejected() {
+        let ipv6 = ip_prefix("2a01:4f8::", 32);
+        let ipv4 = ip_prefix("192.168.0.0", 16);
+
+        assert_eq!(ipv6.ipv6().unwrap(), ("2a01:4f8::".parse().unwrap(), 32));
+        assert_eq!(ipv4.ipv4().unwrap(), ("192.168.0.0".parse().unwrap(), 16));
+        assert!(matches!(
+            ipv6.ipv4(),
+            Err(Error::WrongIpVer(IpVersion::V4, addr)) if addr == ipv6.addr
+        ));
+        assert!(matches!(
+            ipv4.ipv6(),
+            Err(Error::WrongIpVer(IpVersion::V6, addr)) if addr == ipv4.addr
+        ));
+
+        // Before anything is enumerated.
+        let query = IpQuery::new("nonexistent0");
+        assert!(matches!(
+            query.ipv6_in_prefix(ipv4),
+            Err(Error::WrongIpVer(..))
+        ));
+        assert!(matches!(
+            query.verify_ipv6_within(ipv4),
+            Err(Error::WrongIpVer(..))
+        ));
+        assert!(matches!(
+            query.ipv4_in_subnet(ipv6),
+            Err(Error::WrongIpVer(..))
+        ));
+    }
+
+    fn suggestion(addrs: Vec<InterfaceAddr>, prefix: &str, len: u8) -> Option<IpAddr> {
+        let prefix = prefix.parse().unwrap();
+        addrs
+            .into_iter()
+            .filter(|addr| is_suggestion(addr, prefix, len, OptimisticDad::default()))
+            .min_by(deterministic_order)
+            .map(|addr| addr.addr)
+    }
+
+    #[test]
+    fn suggestions_are_usable_guas_within_the_prefix() {
+        let prefix = "2a01:4f8:1::".parse().unwrap();
+        let is = |addr, flags| {
+            is_suggestion(
+                &interface_addr(addr, flags),
+                prefix,
+                48,
+                OptimisticDad::default(),
+            )
+        };
+
+        assert!(is("2a01:4f8:1::5", 0));
+        assert!(is("2a01:4f8:1:ffff::5", IFA_F_TEMPORARY));
+        assert!(is("2a01:4f8:1::5", IFA_F_DEPRECATED));
+        assert!(!is("2a01:4f8:2::5", 0));
+        assert!(!is("2a01:4f8:1::5", IFA_F_TENTATIVE));
+        assert!(!is("2a01:4f8:1::5", IFA_F_DADFAILED));
+        assert!(!is("192.168.1.1", 0));
+    }
+
+    #[test]
+    fn suggestions_exclude_non_global_addresses() {
+        let within = |addr| {
+            is_suggestion(
+                &interface_addr(addr, 0),
+                "::".parse().unwrap(),
+                0,
+                OptimisticDad::default(),
+            )
+        };
+
+        assert!(within("2a01:4f8::1"));
+        assert!(!within("fe80::1"));
+        assert!(!within("fd00::1"));
+        assert!(!within("::1"));
+        assert!(!within("2001:db8::1"));
+    }
+
+    #[test]
+    fn optimistic_suggestions_follow_the_policy() {
+        let prefix = "2a01:4f8:1::".parse().unwrap();
+        let addr = interface_addr("2a01:4f8:1::5", IFA_F_OPTIMISTIC);
+
+        assert!(is_suggestion(&addr, prefix, 48, OptimisticDad::Accept));
+        assert!(is_suggestion(
+            &addr,
+            prefix,
+            48,
+            OptimisticDad::AcceptAndFlag
+        ));
+        assert!(!is_suggestion(&addr, prefix, 48, OptimisticDad::Reject));
+    }
+
+    #[test]
+    fn suggestion_is_the_first_in_deterministic_order() {
+        let addrs = vec![
+            interface_addr("2a01:4f8:1::20", IFA_F_DEPRECATED),
+            interface_addr("2a01:4f8:1::10", IFA_F_TEMPORARY),
+            interface_addr("2a01:4f8:2::1", IFA_F_PERMANENT),
+            interface_addr("2a01:4f8:1::30", IFA_F_PERMANENT),
+            interface_addr("2a01:4f8:1::2", IFA_F_TENTATIVE),
+        ];
+
+        assert_eq!(
+            suggestion(addrs.clone(), "2a01:4f8:1::", 48),
+            ips(&["2a01:4f8:1::30"]).pop()
+        );
+        assert_eq!(
+            suggestion(addrs.clone(), "2a01:4f8:2::", 48),
+            ips(&["2a01:4f8:2::1"]).pop()
+        );
+        assert_eq!(suggestion(addrs, "2a01:4f8:3::", 48), None);
+    }
+
+    #[test]
+    fn suggestion_respects_odd_prefix_lengths() {
+        // 2a01:4f8:0:1::/64 is in 2a01:4f8::/63, but not in 2a01:4f8::/64
+        // or 2a01:4f8:0:0:8000::/65.
+        let addrs = vec![
+            interface_addr("2a01:4f8:0:1::5", 0),
+            interface_addr("2a01:4f8:0:0:8000::5", 0),
+            interface_addr("2a01:4f8:0:2::5", 0),
+        ];
+
+        assert_eq!(
+            suggestion(addrs.clone(), "2a01:4f8::", 63),
+            ips(&["2a01:4f8:0:0:8000::5"]).pop()
+        );
+        assert_eq!(
+            suggestion(addrs.clone(), "2a01:4f8:0:1::", 63),
+            ips(&["2a01:4f8:0:0:8000::5"]).pop()
+        );
+        assert_eq!(
+            suggestion(addrs.clone(), "2a01:4f8:0:1::", 64),
+            ips(&["2a01:4f8:0:1::5"]).pop()
+        );
+        assert_eq!(suggestion(addrs.clone(), "2a01:4f8::", 65), None);
+        assert_eq!(
+            suggestion(addrs, "2a01:4f8:0:0:8000::", 65),
+            ips(&["2a01:4f8:0:0:8000::5"]).pop()
+        );
+    }
+
+    fn enumeration_failure(errno: i32) -> Result<bool> {
+        Err(io::Error::from_raw_os_error(errno).into())
+    }
+
+    fn unexpected_probe() -> Result<IpAddr> {
+        panic!("probed although the addresses were enumerated");
+    }
+
+    #[test]
+    fn enumeration_is_used_if_available() {
+        assert!(found_or_probe(Ok(true), unexpected_probe).unwrap());
+        assert!(!found_or_probe(Ok(false), unexpected_probe).unwrap());
+    }
+
+    #[test]
+    fn probe_is_used_if_enumeration_is_unavailable() {
+        let addr = || Ok("2a01:4f8::1".parse().unwrap());
+        let miss = || Err(Error::NoGua("fd00::1".parse().unwrap()));
+
+        for errno in [libc::EPERM, libc::EACCES, libc::ENOENT] {
+            assert!(found_or_probe(enumeration_failure(errno), addr).unwrap());
+            assert!(!found_or_probe(enumeration_failure(errno), miss).unwrap());
+        }
+    }
+
+    #[test]
+    fn hard_enumeration_failures_are_errors() {
+        for errno in [libc::ENODEV, libc::ENOBUFS, libc::EINVAL] {
+            let result = found_or_probe(enumeration_failure(errno), unexpected_probe);
+            assert_eq!(result.unwrap_err().raw_os_error(), Some(errno));
+        }
+
+        let result = found_or_probe(
+            Err(Error::TooManyAddresses {
+                interface: None,
+                limit: MAX_ADDRESSES,
+            }),
+            unexpected_probe,
+        );
+        assert!(matches!(result, Err(Error::TooManyAddresses { .. })));
+    }
+
+    #[test]
+    fn hard_probe_failures_are_errors() {
+        let probe = || Err(io::Error::from_raw_os_error(libc::ENODEV).into());
+        let result = found_or_probe(enumeration_failure(libc::EPERM), probe);
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ENODEV));
+
+        let probe = || {
+            Err(Error::Timeout {
+                interface: None,
+                operation: "probe",
+            })
+        };
+        let result = found_or_probe(enumeration_failure(libc::EPERM), probe);
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+    }
+
+    /// The number of addresses of the stress tests.
+    const STRESS_ADDRESSES: usize = 50_000;
+
+    /// Tracks the peak of the heap memory allocated on the measuring
+    /// thread, other tests run concurrently.
+    struct Peak;
+
+    thread_local! {
+        static MEASURING: Cell<bool> = const { Cell::new(false) };
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn track(delta: isize) {
+        let _ = MEASURING.try_with(|measuring| {
+            if measuring.get() {
+                let live = LIVE.with(|live| {
+                    live.set(live.get() + delta);
+                    live.get()
+                });
+                PEAK.with(|peak| peak.set(peak.get().max(live)));
+            }
+        });
+    }
+
+    // SAFETY: Every call is forwarded to the system allocator.
+    unsafe impl GlobalAlloc for Peak {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            track(layout.size() as isize);
+            // SAFETY: The caller upholds the contract of `alloc`.
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            track(-(layout.size() as isize));
+            // SAFETY: The caller upholds the contract of `dealloc`.
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Peak = Peak;
+
+    /// Get the result of the closure and the peak of the heap memory
+    /// it allocated on this thread beyond what was allocated before.
+    fn peak_memory<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        LIVE.with(|live| live.set(0));
+        PEAK.with(|peak| peak.set(0));
+        MEASURING.with(|measuring| measuring.set(true));
+        let result = f();
+        MEASURING.with(|measuring| measuring.set(false));
+
+        (result, PEAK.with(|peak| peak.get()) as usize)
+    }
+
+    /// The `n`th address of the stress tests, `2a01:4f8:<n / 64k>::<n % 64k>/64`.
+    fn stress_addr(n: usize) -> Ipv6Addr {
+        Ipv6Addr::from(0x2a01_04f8_u128 << 96 | (n as u128 >> 16) << 64 | n as u128 & 0xffff)
+    }
+
+    /// Let the fake kernel dump [`STRESS_ADDRESSES`] addresses
+    /// on the next enumeration.
+    fn stress_dump() -> std::thread::JoinHandle<usize> {
+        netlink::mock::dump((0..STRESS_ADDRESSES).map(|n| {
+            let addr = netlink::Addr {
+                index: 2,
+                prefix_len: 64,
+                flags: IFA_F_PERMANENT,
+                local: Some(stress_addr(n).into()),
+                ..Default::default()
+            };
+            (netlink::RTM_NEWADDR, addr.to_payload())
+        }))
+    }
+
+    /// The memory materializing the addresses would take at least.
+    fn materialized() -> usize {
+        STRESS_ADDRESSES * mem::size_of::<InterfaceAddr>()
+    }
+
+    #[test]
+    fn prefix_lookup_exits_early() {
+        let kernel = stress_dump();
+        let prefix = stress_addr(100);
+
+        let (found, peak) = peak_memory(|| IpQuery::any_interface().ipv6_in_prefix((prefix, 128)));
+        assert_eq!(found.unwrap(), prefix);
+
+        // The rest of the dump stays unread, the fake kernel gives up
+        // once its socket buffer is full.
+        let sent = kernel.join().unwrap();
+        assert!(sent < STRESS_ADDRESSES / 2, "{} addresses sent", sent);
+        assert!(peak < materialized() / 10, "peak of {} bytes", peak);
+    }
+
+    #[test]
+    fn prefix_lookup_streams_the_whole_dump() {
+        let kernel = stress_dump();
+        let last = stress_addr(STRESS_ADDRESSES - 1);
+
+        let (found, peak) = peak_memory(|| IpQuery::any_interface().ipv6_in_prefix((last, 128)));
+        assert_eq!(found.unwrap(), last);
+
+        assert_eq!(kernel.join().unwrap(), STRESS_ADDRESSES);
+        assert!(peak < materialized() / 10, "peak of {} bytes", peak);
+    }
+
+    #[test]
+    fn prefix_lookup_without_match() {
+        let kernel = stress_dump();
+        let prefix: Ipv6Addr = "2a01:4f9::".parse().unwrap();
+
+        let (found, peak) = peak_memory(|| IpQuery::any_interface().ipv6_in_prefix((prefix, 32)));
+        assert!(
+            matches!(
+                found,
+                Err(Error::NoAddress {
+                    family: IpVersion::V6,
+                    ..
+                })
+            ),
+            "{:?}",
+            found
+        );
+
+        assert_eq!(kernel.join().unwrap(), STRESS_ADDRESSES);
+        assert!(peak < materialized() / 10, "peak of {} bytes", peak);
+    }
+
+    #[test]
+    fn collecting_stops_at_the_cap() {
+        let kernel = stress_dump();
+
+        let result = addresses(None);
+        assert!(
+            matches!(
+                result,
+                Err(Error::TooManyAddresses {
+                    limit: MAX_ADDRESSES,
+                    ..
+                })
+            ),
+            "{:?}",
+            result
+        );
+
+        let sent = kernel.join().unwrap();
+        assert!(sent < STRESS_ADDRESSES, "{} addresses sent", sent);
+    }
+
+    /// Let the fake kernel dump the given addresses of the given interfaces
+    /// on the next enumeration.
+    fn owners_dump(addrs: &[(u32, &str, u8)]) -> std::thread::JoinHandle<usize> {
+        let msgs: Vec<_> = addrs
+            .iter()
+            .map(|&(index, addr, prefix_len)| {
+                let addr = netlink::Addr {
+                    index,
+                    prefix_len,
+                    flags: IFA_F_PERMANENT,
+                    local: Some(addr.parse().unwrap()),
+                    ..Default::default()
+                };
+                (netlink::RTM_NEWADDR, addr.to_payload())
+            })
+            .collect();
+
+        netlink::mock::dump(msgs.into_iter())
+    }
+
+    fn owners_of(addr: &str, dump: &[(u32, &str, u8)]) -> Vec<u32> {
+        let kernel = owners_dump(dump);
+        let owners = owner_indexes(addr.parse().unwrap()).unwrap();
+        kernel.join().unwrap();
+        owners
+    }
+
+    #[rustfmt::skip]
+    const SHARED: &[(u32, &str, u8)] = &[
+        (1, "::1",          128),
+        (1, "127.0.0.1",    8),
+        (7, "2a01:4f8::1",  64),
+        (3, "2a01:4f8::1",  128),
+        (5, "2a01:4f8::1",  64),
+        (5, "2a01:4f8::2",  64),
+        (7, "10.0.0.1",     24),
+        (7, "10.0.0.1",     16),
+        (3, "10.0.0.1",     32),
+        (3, "::a00:1",      128),
+    ];
+
+    #[test]
+    fn owners_are_matched_by_address() {
+        assert_eq!(owners_of("2a01:4f8::1", SHARED), [3, 5, 7]);
+        assert_eq!(owners_of("2a01:4f8::2", SHARED), [5]);
+        assert!(owners_of("2a01:4f8::3", SHARED).is_empty());
+        assert_eq!(owners_of("::1", SHARED), [1]);
+        assert_eq!(owners_of("127.0.0.1", SHARED), [1]);
+    }
+
+    #[test]
+    fn owners_are_listed_once() {
+        // Also assigned with a different prefix on the same interface.
+        assert_eq!(owners_of("10.0.0.1", SHARED), [3, 7]);
+    }
+
+    #[test]
+    fn mapped_addresses_are_ipv4_owners() {
+        assert_eq!(owners_of("::ffff:10.0.0.1", SHARED), [3, 7]);
+        // IPv4-compatible addresses are IPv6 addresses.
+        assert_eq!(owners_of("::10.0.0.1", SHARED), [3]);
+    }
+
+    #[test]
+    fn unspecified_addresses_have_no_owners() {
+        // Answered without enumerating, there is no fake kernel to ask.
+        for addr in ["::", "0.0.0.0", "::ffff:0.0.0.0"] {
+            assert!(owner_indexes(addr.parse().unwrap()).unwrap().is_empty());
+        }
+    }
+}
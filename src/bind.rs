@@ -0,0 +1,191 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use crate::{Destination, InterfaceAddr, Lenient, ScopedIpv6Addr};
+
+/// Conversion of an address returned by this crate into a socket
+/// address to bind to, e.g.
+/// `TcpListener::bind(query.ipv6_unicast_global()?.to_bind_addr(8080))`.
+///
+/// IPv6 link-local addresses can only be bound with the scope id
+/// of their interface. Types that know it set it, plain [`Ipv6Addr`]s
+/// and [`IpAddr`]s can't and use 0, so binding them fails.
+/// Use [`IpQuery::ipv6_unicast_link_local_all`](crate::IpQuery::ipv6_unicast_link_local_all)
+/// to get link-local addresses with their scope id.
+pub trait ToBindAddr {
+    /// Get the socket address to bind to for the given port.
+    fn to_bind_addr(&self, port: u16) -> SocketAddr;
+}
+
+impl ToBindAddr for Ipv6Addr {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        SocketAddrV6::new(*self, port, 0, 0).into()
+    }
+}
+
+impl ToBindAddr for Ipv4Addr {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        SocketAddrV4::new(*self, port).into()
+    }
+}
+
+impl ToBindAddr for IpAddr {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        SocketAddr::new(*self, port)
+    }
+}
+
+impl ToBindAddr for ScopedIpv6Addr {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        self.socket_addr(port).into()
+    }
+}
+
+/// Uses the interface as the scope id of IPv6 link-local addresses
+/// and drops the prefix length.
+impl ToBindAddr for InterfaceAddr {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        match self.addr {
+            IpAddr::V6(ipv6) if ipv6.is_unicast_link_local() => {
+                SocketAddrV6::new(ipv6, port, 0, self.index).into()
+            }
+            addr => SocketAddr::new(addr, port),
+        }
+    }
+}
+
+impl ToBindAddr for Destination {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        match self.addr {
+            IpAddr::V6(ipv6) => SocketAddrV6::new(ipv6, port, 0, self.scope_id).into(),
+            IpAddr::V4(ipv4) => SocketAddrV4::new(ipv4, port).into(),
+        }
+    }
+}
+
+/// Binds the address regardless of whether it is of the requested scope.
+impl<T: ToBindAddr> ToBindAddr for Lenient<T> {
+    fn to_bind_addr(&self, port: u16) -> SocketAddr {
+        self.addr.to_bind_addr(port)
+    }
+}
+
+impl From<ScopedIpv6Addr> for SocketAddrV6 {
+    fn from(addr: ScopedIpv6Addr) -> Self {
+        addr.socket_addr(0)
+    }
+}
+
+impl From<ScopedIpv6Addr> for SocketAddr {
+    fn from(addr: ScopedIpv6Addr) -> Self {
+        addr.socket_addr(0).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK_LOCAL: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+    const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+    fn interface_addr(addr: IpAddr) -> InterfaceAddr {
+        InterfaceAddr {
+            index: 3,
+            addr,
+            prefix_len: 64,
+            flags: 0,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    fn v6(addr: Ipv6Addr, port: u16, scope_id: u32) -> SocketAddr {
+        SocketAddrV6::new(addr, port, 0, scope_id).into()
+    }
+
+    #[test]
+    fn plain_addresses() {
+        assert_eq!(GUA.to_bind_addr(8080), v6(GUA, 8080, 0));
+        assert_eq!(
+            PRIVATE.to_bind_addr(8080),
+            SocketAddr::new(PRIVATE.into(), 8080)
+        );
+        assert_eq!(IpAddr::V6(GUA).to_bind_addr(0), v6(GUA, 0, 0));
+        assert!(IpAddr::V4(PRIVATE).to_bind_addr(53).is_ipv4());
+
+        // Plain addresses don't know the zone of link-local addresses.
+        assert_eq!(LINK_LOCAL.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 0));
+        assert_eq!(
+            IpAddr::V6(LINK_LOCAL).to_bind_addr(8080),
+            v6(LINK_LOCAL, 8080, 0)
+        );
+    }
+
+    #[test]
+    fn scope_ids_are_carried_over() {
+        let scoped = ScopedIpv6Addr {
+            addr: LINK_LOCAL,
+            scope_id: 3,
+        };
+        assert_eq!(scoped.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 3));
+        assert_eq!(
+            SocketAddrV6::from(scoped),
+            SocketAddrV6::new(LINK_LOCAL, 0, 0, 3)
+        );
+        assert_eq!(SocketAddr::from(scoped), v6(LINK_LOCAL, 0, 3));
+
+        let addr = interface_addr(LINK_LOCAL.into());
+        assert_eq!(addr.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 3));
+
+        let dest = Destination {
+            addr: LINK_LOCAL.into(),
+            scope_id: 5,
+        };
+        assert_eq!(dest.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 5));
+    }
+
+    #[test]
+    fn interfaces_are_only_zones_of_link_local_addresses() {
+        // The prefix length and the interface are dropped.
+        let addr = interface_addr(GUA.into());
+        assert_eq!(addr.to_bind_addr(8080), v6(GUA, 8080, 0));
+
+        let addr = interface_addr(PRIVATE.into());
+        assert_eq!(
+            addr.to_bind_addr(8080),
+            SocketAddr::new(PRIVATE.into(), 8080)
+        );
+    }
+
+    #[test]
+    fn destinations_keep_their_scope_id() {
+        // Unlike interface addresses, destinations carry any zone given.
+        let dest = Destination {
+            addr: GUA.into(),
+            scope_id: 5,
+        };
+        assert_eq!(dest.to_bind_addr(0), v6(GUA, 0, 5));
+
+        let dest = Destination {
+            addr: PRIVATE.into(),
+            scope_id: 5,
+        };
+        assert_eq!(dest.to_bind_addr(0), SocketAddr::new(PRIVATE.into(), 0));
+    }
+
+    #[test]
+    fn lenient_binds_unclassified_addresses() {
+        let lenient = Lenient::new(LINK_LOCAL, false);
+        assert_eq!(lenient.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 0));
+
+        let lenient = Lenient::new(
+            ScopedIpv6Addr {
+                addr: LINK_LOCAL,
+                scope_id: 3,
+            },
+            true,
+        );
+        assert_eq!(lenient.to_bind_addr(8080), v6(LINK_LOCAL, 8080, 3));
+    }
+}
@@ -0,0 +1,207 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::addrs::{self, InterfaceAddr};
+use crate::netlink::Netlink;
+use crate::{Error, IpQuery, IpVersion, ProbeProtocol, Result};
+
+impl InterfaceAddr {
+    /// Get the directed broadcast address of the subnet of an IPv4
+    /// address, or `None` for IPv6 addresses and IPv4 prefixes
+    /// without one, i.e. /31 (RFC 3021) and /32.
+    pub fn ipv4_broadcast(&self) -> Option<Ipv4Addr> {
+        match self.addr {
+            IpAddr::V4(ipv4) if self.prefix_len < 31 => Some(Ipv4Addr::from_bits(
+                ipv4.to_bits() | u32::MAX >> self.prefix_len,
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl IpQuery<'_> {
+    /// Get the source address used for sending broadcasts, e.g. for
+    /// Wake-on-LAN or discovery protocols, and the directed broadcast
+    /// address of its subnet.
+    ///
+    /// The probe socket has `SO_BROADCAST` set and is connected to
+    /// the directed broadcast address of the primary IPv4 address
+    /// of the interface. If that fails, or if the query isn't bound
+    /// to an interface, the limited broadcast address
+    /// `255.255.255.255` is probed instead.
+    ///
+    /// Fails with [`Error::NoAddress`] if the interface has no IPv4
+    /// address and with [`Error::NoBroadcast`] if it doesn't support
+    /// broadcasts, e.g. point-to-point interfaces, or if the prefix
+    /// of the address has no broadcast address.
+    pub fn ipv4_broadcast_source(&self) -> Result<(Ipv4Addr, Ipv4Addr)> {
+        let no_broadcast = || Error::NoBroadcast {
+            interface: self.interface_name(),
+        };
+
+        let primary = match self.interface {
+            Some(interface) => {
+                if !supports_broadcast(interface)? {
+                    return Err(no_broadcast());
+                }
+
+                let primary = addrs::find_address(Some(interface), |addr| {
+                    addr.addr.is_ipv4() && !addr.is_secondary()
+                })?
+                .ok_or_else(|| Error::NoAddress {
+                    interface: self.interface_name(),
+                    family: IpVersion::V4,
+                })?;
+                Some(primary.ipv4_broadcast().ok_or_else(no_broadcast)?)
+            }
+            None => None,
+        };
+
+        let directed = primary.and_then(|broadcast| self.broadcast_probe(broadcast).ok());
+        let source = match directed {
+            Some(source) => source,
+            None => self.broadcast_probe(Ipv4Addr::BROADCAST)?,
+        };
+
+        let broadcast = match primary {
+            Some(broadcast) => broadcast,
+            None => addrs::find(source.into())?
+                .and_then(|addr| addr.ipv4_broadcast())
+                .ok_or_else(no_broadcast)?,
+        };
+
+        Ok((source, broadcast))
+    }
+
+    /// Probe the source address towards the broadcast address.
+    fn broadcast_probe(&self, broadcast: Ipv4Addr) -> Result<Ipv4Addr> {
+        let dest = SocketAddr::new(broadcast.into(), 0);
+        let ip = self
+            .clone()
+            .protocol(ProbeProtocol::Udp)
+            .probe_with(dest, None, |socket| socket.set_broadcast(true))?;
+        self.check_unspecified(ip, IpVersion::V4)?;
+
+        match ip {
+            IpAddr::V4(ipv4) => Ok(ipv4),
+            IpAddr::V6(_) => Err(Error::WrongIpVer(IpVersion::V4, ip)),
+        }
+    }
+}
+
+/// Report whether the interface has `IFF_BROADCAST` set
+/// and isn't point-to-point. Interfaces are assumed to support
+/// broadcasts if netlink isn't permitted.
+fn supports_broadcast(interface: &str) -> Result<bool> {
+    let index = crate::if_index(interface)?;
+
+    let links = match Netlink::open().and_then(|mut netlink| netlink.links()) {
+        Ok(links) => links,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(links
+        .into_iter()
+        .find(|link| link.index == index)
+        .is_none_or(|link| {
+            link.flags & libc::IFF_BROADCAST as u32 != 0
+                && link.flags & libc::IFF_POINTOPOINT as u32 == 0
+        }))
+}
+
+/// Get the source address used for sending broadcasts on the given
+/// interface and its directed broadcast address.
+/// See [`IpQuery::ipv4_broadcast_source`] for details.
+pub fn ipv4_broadcast_source(interface: &str) -> Result<(Ipv4Addr, Ipv4Addr)> {
+    IpQuery::new(interface).ipv4_broadcast_source()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::netlink::{self, Link};
+
+    fn interface_addr(addr: &str, prefix_len: u8) -> InterfaceAddr {
+        InterfaceAddr {
+            index: 2,
+            addr: addr.parse().unwrap(),
+            prefix_len,
+            flags: 0,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    #[test]
+    fn directed_broadcast_addresses() {
+        #[rustfmt::skip]
+        let cases = [
+            ("192.168.77.1",  24, Some("192.168.77.255")),
+            ("192.168.77.1",  30, Some("192.168.77.3")),
+            ("10.1.2.3",      8,  Some("10.255.255.255")),
+            ("10.1.2.3",      0,  Some("255.255.255.255")),
+            ("192.168.77.0",  31, None),
+            ("192.168.77.1",  32, None),
+            ("2a01:4f8::1",   64, None),
+        ];
+
+        for (addr, prefix_len, expected) in cases {
+            assert_eq!(
+                interface_addr(addr, prefix_len).ipv4_broadcast(),
+                expected.map(|broadcast| broadcast.parse().unwrap()),
+                "{addr}/{prefix_len}"
+            );
+        }
+    }
+
+    /// Let the fake kernel dump `lo` with the given flags.
+    fn mock_lo(flags: libc::c_int) -> thread::JoinHandle<usize> {
+        let link = Link {
+            index: 1,
+            flags: (libc::IFF_UP | flags) as u32,
+            name: Some("lo".into()),
+            ..Default::default()
+        };
+        netlink::mock::dump([(netlink::RTM_NEWLINK, link.to_payload())].into_iter())
+    }
+
+    #[test]
+    fn broadcast_support_by_flags() {
+        #[rustfmt::skip]
+        let cases = [
+            (libc::IFF_BROADCAST,                        true),
+            (libc::IFF_BROADCAST | libc::IFF_MULTICAST,  true),
+            (libc::IFF_BROADCAST | libc::IFF_POINTOPOINT, false),
+            (libc::IFF_POINTOPOINT,                      false),
+            (libc::IFF_LOOPBACK,                         false),
+        ];
+
+        for (flags, expected) in cases {
+            let kernel = mock_lo(flags);
+            assert_eq!(supports_broadcast("lo").unwrap(), expected, "{flags:#x}");
+            kernel.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn unlisted_interfaces_are_assumed_to_support_broadcasts() {
+        let kernel = netlink::mock::dump(std::iter::empty());
+        assert!(supports_broadcast("lo").unwrap());
+        kernel.join().unwrap();
+    }
+
+    #[test]
+    fn point_to_point_interfaces_have_no_broadcast() {
+        let kernel = mock_lo(libc::IFF_POINTOPOINT | libc::IFF_NOARP);
+        let result = IpQuery::new("lo").ipv4_broadcast_source();
+        kernel.join().unwrap();
+
+        assert!(
+            matches!(&result, Err(Error::NoBroadcast { interface }) if interface.as_deref() == Some("lo")),
+            "{:?}",
+            result
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use socket2::Type;
+
+use crate::{Error, IpQuery, IpVersion, Ipv4Scope, Ipv6Scope, Result, Scope, StatsSnapshot};
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    addr: IpAddr,
+    validated: Instant,
+}
+
+/// A probe that concurrent requests for the same scope share.
+#[derive(Debug, Default)]
+struct Flight {
+    /// The result and when it was available, `None` while probing.
+    result: Mutex<Option<(Result<IpAddr>, Instant)>>,
+    done: Condvar,
+}
+
+impl Flight {
+    fn is_shareable(&self, window: Duration, now: Instant) -> bool {
+        match &*self.result.lock().unwrap() {
+            Some((_, finished)) => now.saturating_duration_since(*finished) < window,
+            None => true,
+        }
+    }
+
+    fn finish(&self, result: &Result<IpAddr>, now: Instant) {
+        *self.result.lock().unwrap() = Some((result.clone(), now));
+        self.done.notify_all();
+    }
+
+    fn wait(&self) -> Result<IpAddr> {
+        let result = self.result.lock().unwrap();
+        let result = self
+            .done
+            .wait_while(result, |result| result.is_none())
+            .unwrap();
+
+        match &*result {
+            Some((result, _)) => result.clone(),
+            None => unreachable!("flight finished without result"),
+        }
+    }
+}
+
+/// Fails the flight if the probe panics, so that the waiters don't hang.
+struct Leader<'a>(&'a Flight, Instant);
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.0.finish(
+            &Err(io::Error::other("shared probe panicked").into()),
+            self.1,
+        );
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<Scope, Entry>,
+    flights: HashMap<Scope, Arc<Flight>>,
+    /// Incremented by [`CachedQuery::invalidate`] so that probes
+    /// started before don't store their results.
+    generation: u64,
+}
+
+/// An [`IpQuery`] that remembers the address of each scope
+/// after probing it once.
+///
+/// Cached addresses are served indefinitely by default, which breaks
+/// on renumbering. Use [`Watcher`](crate::Watcher) to call
+/// [`CachedQuery::invalidate`] on changes, or enable
+/// [`CachedQuery::revalidate_after`] where it isn't available.
+/// Errors are never cached.
+///
+/// Concurrent requests for a scope that isn't cached share a single
+/// probe, e.g. when many threads query after an invalidation.
+/// All of them get the same address or error. See
+/// [`CachedQuery::coalesce_window`] to also share it with requests
+/// that arrive shortly after the probe.
+#[derive(Debug)]
+pub struct CachedQuery<'a> {
+    query: IpQuery<'a>,
+    revalidate_after: Option<Duration>,
+    coalesce_window: Duration,
+    state: Mutex<State>,
+}
+
+impl<'a> CachedQuery<'a> {
+    /// Create a new cache in front of the given query.
+    pub fn new(query: IpQuery<'a>) -> Self {
+        Self {
+            query,
+            revalidate_after: None,
+            coalesce_window: Duration::ZERO,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Check cached addresses using [`IpQuery::validate_source`]
+    /// before serving them if they haven't been checked for the given
+    /// duration. Addresses that fail the check, or whose check fails,
+    /// are probed again instead.
+    pub fn revalidate_after(mut self, revalidate_after: Duration) -> Self {
+        self.revalidate_after = Some(revalidate_after);
+        self
+    }
+
+    /// Also share the result of a probe with requests that arrive
+    /// within the given duration after it finished, so that a burst of
+    /// requests only probes once even if it fails. A few milliseconds
+    /// are usually enough. Zero by default.
+    pub fn coalesce_window(mut self, coalesce_window: Duration) -> Self {
+        self.coalesce_window = coalesce_window;
+        self
+    }
+
+    /// Forget all cached addresses. Requests that are waiting for
+    /// a probe that is in progress still get its result,
+    /// later requests probe again.
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.flights.clear();
+        state.generation += 1;
+    }
+
+    /// Get the cached address of the given scope,
+    /// probing it like [`IpQuery::get`] if there is none.
+    pub fn get(&self, scope: Scope) -> Result<IpAddr> {
+        let now = self.query.now();
+        let cached = self.state.lock().unwrap().entries.get(&scope).copied();
+
+        if let Some(entry) = cached {
+            let stale = self
+                .revalidate_after
+                .is_some_and(|after| now.saturating_duration_since(entry.validated) >= after);
+
+            if !stale || matches!(self.query.validate_source(entry.addr), Ok(true)) {
+                self.store(scope, entry.addr, now);
+                self.query.count_cache(scope, true);
+                return Ok(entry.addr);
+            }
+        }
+
+        self.query.count_cache(scope, false);
+        self.probe(scope)
+    }
+
+    /// Probe the scope, or wait for a probe in progress to finish.
+    fn probe(&self, scope: Scope) -> Result<IpAddr> {
+        let (flight, generation) = {
+            let mut state = self.state.lock().unwrap();
+            match state.flights.get(&scope) {
+                Some(flight) if flight.is_shareable(self.coalesce_window, self.query.now()) => {
+                    let flight = flight.clone();
+                    drop(state);
+                    return flight.wait();
+                }
+                _ => {
+                    let flight = Arc::<Flight>::default();
+                    state.flights.insert(scope, flight.clone());
+                    (flight, state.generation)
+                }
+            }
+        };
+
+        let leader = Leader(&flight, self.query.now());
+        let result = self.query.get(scope);
+        let now = self.query.now();
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            match result {
+                Ok(addr) => state.entries.insert(
+                    scope,
+                    Entry {
+                        addr,
+                        validated: now,
+                    },
+                ),
+                Err(_) => state.entries.remove(&scope),
+            };
+        }
+        drop(state);
+
+        leader.0.finish(&result, now);
+        mem::forget(leader);
+        result
+    }
+
+    /// Get the cached IPv6 address of the given scope.
+    /// See [`CachedQuery::get`].
+    ///
+    /// Like the typed getters of [`IpQuery`], this fails with
+    /// [`Error::GotMappedV4`] if [`IpQuery::unmap_v4`] answered
+    /// the scope with an IPv4 address.
+    pub fn ipv6(&self, scope: Ipv6Scope) -> Result<Ipv6Addr> {
+        match self.get(scope.into())? {
+            IpAddr::V6(ipv6) => Ok(ipv6),
+            IpAddr::V4(mapped) => Err(Error::GotMappedV4 { mapped }),
+        }
+    }
+
+    /// Get the cached IPv4 address of the given scope.
+    /// See [`CachedQuery::get`].
+    pub fn ipv4(&self, scope: Ipv4Scope) -> Result<Ipv4Addr> {
+        match self.get(scope.into())? {
+            IpAddr::V4(ipv4) => Ok(ipv4),
+            ip => Err(Error::WrongIpVer(IpVersion::V4, ip)),
+        }
+    }
+
+    /// Get a copy of the statistics of the underlying query,
+    /// including the cache hits and misses.
+    /// See [`IpQuery::collect_stats`].
+    pub fn stats(&self) -> StatsSnapshot {
+        self.query.stats()
+    }
+
+    fn store(&self, scope: Scope, addr: IpAddr, validated: Instant) {
+        self.state
+            .lock()
+            .unwrap()
+            .entries
+            .insert(scope, Entry { addr, validated });
+    }
+}
+
+impl IpQuery<'_> {
+    /// Check whether the address can still be used as a source address
+    /// by binding a socket to it, which is much cheaper than probing.
+    /// Returns `false` if the address isn't assigned (or usable) anymore.
+    ///
+    /// The address isn't required to belong to the interface,
+    /// and IPv4 addresses always pass if `ip_nonlocal_bind` is enabled.
+    pub fn validate_source(&self, addr: IpAddr) -> Result<bool> {
+        let bind_addr = match addr {
+            IpAddr::V6(ipv6) if ipv6.is_unicast_link_local() => {
+                SocketAddrV6::new(ipv6, 0, 0, self.if_index()?).into()
+            }
+            addr => SocketAddr::new(addr, 0),
+        };
+
+        let socket = self.open_socket(bind_addr, Type::DGRAM)?;
+        match socket.bind(&bind_addr.into()) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AddrNotAvailable => Ok(false),
+            Err(e) => Err(Error::IoError(e.into())),
+        }
+    }
+}
+
+/// Check whether the address can still be used as a source address
+/// on the given interface. See [`IpQuery::validate_source`] for details.
+pub fn validate_source(interface: &str, addr: IpAddr) -> Result<bool> {
+    IpQuery::new(interface).validate_source(addr)
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A source of monotonic time for deadlines, cache ages and waiting,
+/// set with [`IpQuery::clock`](crate::IpQuery::clock).
+///
+/// Deadlines are always points in time of the clock, so they aren't
+/// affected by changes of the wall clock, e.g. by NTP or across
+/// suspend. Timeouts of sockets are enforced by the kernel and always
+/// run in real time, the clock only determines how much of the
+/// deadline remains for them.
+pub trait Clock: Send + Sync {
+    /// Get the current point in time.
+    fn now(&self) -> Instant;
+
+    /// Block for the given duration.
+    fn sleep(&self, duration: Duration);
+
+    /// Get the current wall-clock time. It is only used for timestamps
+    /// reported to the caller, e.g. in the [statistics](crate::ScopeStats),
+    /// never for deadlines.
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The default [`Clock`], using [`Instant`] and [`thread::sleep`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// The clock of a query.
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self(Arc::new(clock))
+    }
+
+    pub fn get(&self) -> &dyn Clock {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Clock")
+    }
+}
@@ -0,0 +1,232 @@
+//! The original interface-first functions, for migrating
+//! to [`IpQuery`] one call site at a time.
+//!
+//! Importing `preferred_ip::compat::*` keeps existing code compiling
+//! with deprecation warnings pointing to the builder methods.
+//! The functions call the builder methods of the default configuration
+//! and convert their errors into the original [`Error`],
+//! whose messages are unchanged.
+//! The builder methods return [`crate::Error`] instead.
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use crate::IpQuery;
+
+/// The errors that can occur when trying to get IP address information.
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    WrongIpVer(String, IpAddr),
+    NoLinkLocal(Ipv6Addr),
+    NoUla(Ipv6Addr),
+    NoGua(Ipv6Addr),
+    NoV4LL(Ipv4Addr),
+    NoPrivate(Ipv4Addr, Ipv4Addr, Ipv4Addr),
+    NoGlobal(Ipv4Addr),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => {
+                write!(fmt, "can't get ip address: io error: {}", e)
+            }
+            Self::WrongIpVer(want, got) => {
+                write!(fmt, "wrong ip version: expected {}, got {}", want, got)
+            }
+            Self::NoLinkLocal(ip) => {
+                write!(fmt, "ipv6 address {} is not a link-local address", ip)
+            }
+            Self::NoUla(ip) => write!(fmt, "ipv6 address {} is not a ula", ip),
+            Self::NoGua(ip) => write!(fmt, "ipv6 address {} is not a gua", ip),
+            Self::NoV4LL(ip) => {
+                write!(fmt, "ipv4 address {} is not a link-local address", ip)
+            }
+            Self::NoPrivate(a, b, c) => {
+                write!(fmt, "none of {}, {} and {} are private ipv4", a, b, c)
+            }
+            Self::NoGlobal(ip) => {
+                write!(fmt, "ipv4 address {} is not a global address", ip)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+/// An alias for `std::result::Result` that uses `Error` as its error variant.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<crate::Error> for Error {
+    /// Convert the errors of the builder methods into the original ones.
+    /// Whatever the kernel reports as unreachable is `ENETUNREACH`,
+    /// a missing source address is `EADDRNOTAVAIL`, and the errors
+    /// the original functions didn't have are wrapped into I/O errors.
+    fn from(err: crate::Error) -> Self {
+        match err {
+            crate::Error::IoError(e) => Self::IoError(owned(e)),
+            crate::Error::WrongIpVer(want, got) => Self::WrongIpVer(want.to_string(), got),
+            crate::Error::NoLinkLocal(ip) => Self::NoLinkLocal(ip),
+            crate::Error::NoUla(ip) => Self::NoUla(ip),
+            crate::Error::NoGua(ip) => Self::NoGua(ip),
+            crate::Error::NoV4LL(ip) => Self::NoV4LL(ip),
+            crate::Error::NoPrivate(a, b, c) => Self::NoPrivate(a, b, c),
+            crate::Error::NoGlobal(ip) => Self::NoGlobal(ip),
+            crate::Error::NoRoute { .. } => {
+                Self::IoError(io::Error::from_raw_os_error(libc::ENETUNREACH))
+            }
+            crate::Error::NoAddress { .. } => {
+                Self::IoError(io::Error::from_raw_os_error(libc::EADDRNOTAVAIL))
+            }
+            err => Self::IoError(io::Error::other(err)),
+        }
+    }
+}
+
+/// Take the I/O error out of the error of a builder method,
+/// or make a copy of it if the error was cloned.
+fn owned(err: Arc<io::Error>) -> io::Error {
+    Arc::try_unwrap(err).unwrap_or_else(|err| match err.raw_os_error() {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => io::Error::new(err.kind(), err.to_string()),
+    })
+}
+
+/// Get the (preferred outgoing) IPv6 link-local address
+/// of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv6_unicast_link_local()` instead")]
+pub fn ipv6_unicast_link_local(interface: &str) -> Result<Ipv6Addr> {
+    Ok(IpQuery::new(interface).ipv6_unicast_link_local()?)
+}
+
+/// Get the preferred outgoing IPv6 ULA of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv6_unique_local()` instead")]
+pub fn ipv6_unique_local(interface: &str) -> Result<Ipv6Addr> {
+    Ok(IpQuery::new(interface).ipv6_unique_local()?)
+}
+
+/// Get the preferred outgoing IPv6 GUA of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv6_unicast_global()` instead")]
+pub fn ipv6_unicast_global(interface: &str) -> Result<Ipv6Addr> {
+    Ok(IpQuery::new(interface).ipv6_unicast_global()?)
+}
+
+/// Get the (preferred outgoing) IPv4 link-local address
+/// of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv4_link_local()` instead")]
+pub fn ipv4_link_local(interface: &str) -> Result<Ipv4Addr> {
+    Ok(IpQuery::new(interface).ipv4_link_local()?)
+}
+
+/// Get the preferred outgoing IPv4 private address
+/// of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv4_private()` instead")]
+pub fn ipv4_private(interface: &str) -> Result<Ipv4Addr> {
+    Ok(IpQuery::new(interface).ipv4_private()?)
+}
+
+/// Get the preferred outgoing IPv4 global address
+/// of the given interface.
+#[deprecated(note = "use `IpQuery::new(interface).ipv4_global()` instead")]
+pub fn ipv4_global(interface: &str) -> Result<Ipv4Addr> {
+    Ok(IpQuery::new(interface).ipv4_global()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_unchanged() {
+        let v4 = |addr: &str| addr.parse::<Ipv4Addr>().unwrap();
+        let v6 = |addr: &str| addr.parse::<Ipv6Addr>().unwrap();
+
+        #[rustfmt::skip]
+        let cases = [
+            (Error::IoError(io::Error::from_raw_os_error(libc::ENODEV)),
+                "can't get ip address: io error: No such device (os error 19)"),
+            (Error::IoError(io::Error::other("oops")),
+                "can't get ip address: io error: oops"),
+            (Error::WrongIpVer("IPv6".into(), "192.168.77.1".parse().unwrap()),
+                "wrong ip version: expected IPv6, got 192.168.77.1"),
+            (Error::WrongIpVer("IPv4".into(), "2a01:4f8::1".parse().unwrap()),
+                "wrong ip version: expected IPv4, got 2a01:4f8::1"),
+            (Error::NoLinkLocal(v6("2a01:4f8::1")),
+                "ipv6 address 2a01:4f8::1 is not a link-local address"),
+            (Error::NoUla(v6("fe80::1")), "ipv6 address fe80::1 is not a ula"),
+            (Error::NoGua(v6("fd00::1")), "ipv6 address fd00::1 is not a gua"),
+            (Error::NoV4LL(v4("10.0.0.1")),
+                "ipv4 address 10.0.0.1 is not a link-local address"),
+            (Error::NoPrivate(v4("100.64.0.1"), v4("100.64.0.1"), v4("100.64.0.1")),
+                "none of 100.64.0.1, 100.64.0.1 and 100.64.0.1 are private ipv4"),
+            (Error::NoGlobal(v4("192.168.77.1")),
+                "ipv4 address 192.168.77.1 is not a global address"),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn builder_errors_convert() {
+        let v6 = "2a01:4f8::1".parse().unwrap();
+        let v4 = "192.168.77.1".parse().unwrap();
+        let os = |code| crate::Error::IoError(Arc::new(io::Error::from_raw_os_error(code)));
+
+        #[rustfmt::skip]
+        let cases = [
+            (os(libc::ENODEV),
+                "can't get ip address: io error: No such device (os error 19)"),
+            (crate::Error::WrongIpVer(crate::IpVersion::V6, IpAddr::V4(v4)),
+                "wrong ip version: expected IPv6, got 192.168.77.1"),
+            (crate::Error::NoLinkLocal(v6), "ipv6 address 2a01:4f8::1 is not a link-local address"),
+            (crate::Error::NoUla(v6), "ipv6 address 2a01:4f8::1 is not a ula"),
+            (crate::Error::NoGua(v6), "ipv6 address 2a01:4f8::1 is not a gua"),
+            (crate::Error::NoV4LL(v4), "ipv4 address 192.168.77.1 is not a link-local address"),
+            (crate::Error::NoPrivate(v4, v4, v4),
+                "none of 192.168.77.1, 192.168.77.1 and 192.168.77.1 are private ipv4"),
+            (crate::Error::NoGlobal(v4), "ipv4 address 192.168.77.1 is not a global address"),
+            (crate::Error::NoRoute { interface: Some("veth0".into()), dest: IpAddr::V6(v6) },
+                "can't get ip address: io error: Network is unreachable (os error 101)"),
+            (crate::Error::NoAddress { interface: None, family: crate::IpVersion::V4 },
+                "can't get ip address: io error: Cannot assign requested address (os error 99)"),
+            (crate::Error::FamilyDisabled(crate::IpVersion::V6),
+                "can't get ip address: io error: IPv6 is disabled on this system"),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(Error::from(err).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn shared_io_errors_keep_their_code() {
+        let shared = Arc::new(io::Error::from_raw_os_error(libc::ENETUNREACH));
+        let _clone = Arc::clone(&shared);
+        let err = Error::from(crate::Error::IoError(shared));
+        assert!(
+            matches!(&err, Error::IoError(e) if e.raw_os_error() == Some(libc::ENETUNREACH)),
+            "{:?}",
+            err
+        );
+
+        let err = Error::from(crate::Error::IoError(Arc::new(io::Error::other("oops"))));
+        assert_eq!(err.to_string(), "can't get ip address: io error: oops");
+    }
+
+    #[test]
+    fn io_errors_convert() {
+        let err = Error::from(io::Error::from_raw_os_error(libc::ENETUNREACH));
+        assert!(matches!(&err, Error::IoError(e) if e.raw_os_error() == Some(libc::ENETUNREACH)));
+    }
+}
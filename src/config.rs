@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::name::invalid_reason;
+use crate::{Backend, Error, IpQuery, OptimisticDad, ProbeProtocol, Result, SourcePreference};
+
+/// The options of an [`IpQuery`] in a form that can be read
+/// from configuration files. All fields are optional.
+///
+/// Enumerations are written in kebab case,
+/// e.g. `protocol = "tcp"` or `optimistic-dad = "accept-and-flag"`.
+/// Observers can only be set on the builder.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProbeConfig {
+    /// The interface to query, or any interface if unset.
+    pub interface: Option<String>,
+    /// See [`IpQuery::timeout`].
+    pub timeout_ms: Option<u64>,
+    /// See [`IpQuery::total_timeout`]. The deadline is set
+    /// when the query is created from the configuration.
+    pub total_timeout_ms: Option<u64>,
+    /// `udp` or `tcp`, see [`IpQuery::protocol`].
+    pub protocol: Option<String>,
+    /// `socket`, `procfs` or `network-manager`, see [`IpQuery::backend`].
+    pub backend: Option<String>,
+    /// See [`IpQuery::unmap_v4`].
+    pub unmap_v4: bool,
+    /// See [`IpQuery::deterministic`].
+    pub deterministic: bool,
+    /// See [`IpQuery::verify_owner`].
+    pub verify_owner: bool,
+    /// `reject`, `accept` or `accept-and-flag`,
+    /// see [`IpQuery::optimistic_dad`].
+    pub optimistic_dad: Option<String>,
+    /// See [`IpQuery::min_preferred_lifetime`].
+    pub min_preferred_lifetime_secs: Option<u64>,
+    /// Any of `temporary`, `public`, `care-of` and `home`,
+    /// see [`IpQuery::prefer_source`].
+    pub source_preferences: Vec<String>,
+    /// See [`IpQuery::collect_stats`].
+    pub collect_stats: bool,
+}
+
+impl ProbeConfig {
+    /// Check the configuration, reporting all invalid fields at once.
+    pub fn validate(&self) -> Result<()> {
+        self.parse().map(|_| ())
+    }
+
+    fn parse(&self) -> Result<Parsed> {
+        let mut errors = Vec::new();
+
+        if let Some(interface) = &self.interface {
+            if let Some(reason) = invalid_reason(interface) {
+                errors.push(format!(
+                    "interface: invalid interface name {:?}: {}",
+                    interface, reason
+                ));
+            }
+        }
+
+        let protocol = self.protocol.as_deref().and_then(|protocol| {
+            let parsed = match protocol {
+                "udp" => Some(ProbeProtocol::Udp),
+                "tcp" => Some(ProbeProtocol::Tcp),
+                _ => None,
+            };
+
+            if parsed.is_none() {
+                errors.push(format!("protocol: unknown protocol `{}`", protocol));
+            }
+            parsed
+        });
+
+        let backend = self.backend.as_deref().and_then(|backend| {
+            let parsed = match backend {
+                "socket" => Some(Backend::Socket),
+                "procfs" => Some(Backend::Procfs),
+                #[cfg(feature = "networkmanager")]
+                "network-manager" => Some(Backend::NetworkManager),
+                #[cfg(not(feature = "networkmanager"))]
+                "network-manager" => {
+                    errors.push("backend: networkmanager support isn't enabled".into());
+                    return None;
+                }
+                _ => None,
+            };
+
+            if parsed.is_none() {
+                errors.push(format!("backend: unknown backend `{}`", backend));
+            }
+            parsed
+        });
+
+        let optimistic_dad = self.optimistic_dad.as_deref().and_then(|policy| {
+            let parsed = match policy {
+                "reject" => Some(OptimisticDad::Reject),
+                "accept" => Some(OptimisticDad::Accept),
+                "accept-and-flag" => Some(OptimisticDad::AcceptAndFlag),
+                _ => None,
+            };
+
+            if parsed.is_none() {
+                errors.push(format!("optimistic-dad: unknown policy `{}`", policy));
+            }
+            parsed
+        });
+
+        let source_preferences = self
+            .source_preferences
+            .iter()
+            .filter_map(|preference| {
+                let parsed = match preference.as_str() {
+                    "temporary" => Some(SourcePreference::Temporary),
+                    "public" => Some(SourcePreference::Public),
+                    "care-of" => Some(SourcePreference::CareOf),
+                    "home" => Some(SourcePreference::Home),
+                    _ => None,
+                };
+
+                if parsed.is_none() {
+                    errors.push(format!(
+                        "source-preferences: unknown preference `{}`",
+                        preference
+                    ));
+                }
+                parsed
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(Parsed {
+                protocol,
+                backend,
+                optimistic_dad,
+                source_preferences,
+            })
+        } else {
+            Err(Error::InvalidConfig(errors))
+        }
+    }
+}
+
+struct Parsed {
+    protocol: Option<ProbeProtocol>,
+    backend: Option<Backend>,
+    optimistic_dad: Option<OptimisticDad>,
+    source_preferences: Vec<SourcePreference>,
+}
+
+impl<'a> IpQuery<'a> {
+    /// Create a query from a configuration.
+    /// Fails with [`Error::InvalidConfig`] listing all problems
+    /// if the configuration is invalid.
+    pub fn from_config(config: &'a ProbeConfig) -> Result<Self> {
+        let parsed = config.parse()?;
+
+        let mut query = IpQuery::with_interface(config.interface.as_deref())
+            .unmap_v4(config.unmap_v4)
+            .deterministic(config.deterministic)
+            .verify_owner(config.verify_owner)
+            .collect_stats(config.collect_stats);
+
+        if let Some(timeout) = config.timeout_ms {
+            query = query.timeout(Duration::from_millis(timeout));
+        }
+        if let Some(timeout) = config.total_timeout_ms {
+            query = query.total_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(protocol) = parsed.protocol {
+            query = query.protocol(protocol);
+        }
+        if let Some(backend) = parsed.backend {
+            query = query.backend(backend);
+        }
+        if let Some(lifetime) = config.min_preferred_lifetime_secs {
+            query = query.min_preferred_lifetime(Duration::from_secs(lifetime));
+        }
+        if let Some(optimistic_dad) = parsed.optimistic_dad {
+            query = query.optimistic_dad(optimistic_dad);
+        }
+        for preference in parsed.source_preferences {
+            query = query.prefer_source(preference);
+        }
+
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_config_sets_every_option() {
+        let config = ProbeConfig {
+            interface: Some("eth0".into()),
+            timeout_ms: Some(1500),
+            total_timeout_ms: Some(10_000),
+            protocol: Some("tcp".into()),
+            backend: Some("procfs".into()),
+            unmap_v4: true,
+            deterministic: true,
+            verify_owner: true,
+            optimistic_dad: Some("reject".into()),
+            min_preferred_lifetime_secs: Some(3600),
+            source_preferences: vec!["temporary".into(), "care-of".into()],
+            collect_stats: true,
+        };
+
+        let query = IpQuery::from_config(&config).unwrap();
+        assert_eq!(query.interface, Some("eth0"));
+        assert_eq!(query.timeout, Some(Duration::from_millis(1500)));
+        assert!(query.deadline.is_some());
+        assert_eq!(query.protocol, ProbeProtocol::Tcp);
+        assert_eq!(query.backend, Some(Backend::Procfs));
+        assert!(query.unmap_v4 && query.deterministic && query.verify_owner);
+        assert_eq!(query.optimistic_dad, OptimisticDad::Reject);
+        assert_eq!(
+            query.min_preferred_lifetime,
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            query.source_preferences,
+            [SourcePreference::Temporary, SourcePreference::CareOf]
+        );
+        assert!(query.stats.is_some());
+    }
+
+    #[test]
+    fn default_config_is_default_query() {
+        let config = ProbeConfig::default();
+        let query = IpQuery::from_config(&config).unwrap();
+        let default = IpQuery::any_interface();
+
+        assert_eq!(query.interface, default.interface);
+        assert_eq!(query.timeout, default.timeout);
+        assert_eq!(query.deadline, None);
+        assert_eq!(query.protocol, default.protocol);
+        assert_eq!(query.backend, default.backend);
+        assert_eq!(query.optimistic_dad, default.optimistic_dad);
+        assert_eq!(query.min_preferred_lifetime, None);
+        assert!(query.source_preferences.is_empty());
+        assert!(query.stats.is_none());
+    }
+
+    #[test]
+    fn later_source_preferences_win() {
+        let config = ProbeConfig {
+            source_preferences: vec!["temporary".into(), "home".into(), "public".into()],
+            ..Default::default()
+        };
+
+        let query = IpQuery::from_config(&config).unwrap();
+        assert_eq!(
+            query.source_preferences,
+            [SourcePreference::Home, SourcePreference::Public]
+        );
+    }
+}
@@ -0,0 +1,541 @@
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::fd::AsRawFd;
+use std::str::FromStr;
+
+use socket2::{Socket, Type};
+
+use crate::netlink::Netlink;
+use crate::{addrs, if_index, Error, IpQuery, IpVersion, ProbeProtocol, Result};
+
+/// A destination to get the source address for,
+/// optionally with the zone (scope id) of an IPv6 address.
+///
+/// Link-local destinations need a zone to be reachable.
+/// It can be given as part of the destination, e.g. by parsing
+/// `fe80::1%eth0` or `fe80::1%2` or by converting a [`SocketAddrV6`],
+/// or implicitly by binding the query to an interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Destination {
+    pub addr: IpAddr,
+    /// The interface index of the zone, 0 if there is none.
+    pub scope_id: u32,
+}
+
+impl Destination {
+    fn needs_zone(&self) -> bool {
+        match self.addr {
+            IpAddr::V4(_) => false,
+            IpAddr::V6(ipv6) => ipv6.is_unicast_link_local(),
+        }
+    }
+}
+
+impl From<IpAddr> for Destination {
+    fn from(addr: IpAddr) -> Self {
+        Self { addr, scope_id: 0 }
+    }
+}
+
+impl From<Ipv4Addr> for Destination {
+    fn from(addr: Ipv4Addr) -> Self {
+        IpAddr::V4(addr).into()
+    }
+}
+
+impl From<Ipv6Addr> for Destination {
+    fn from(addr: Ipv6Addr) -> Self {
+        IpAddr::V6(addr).into()
+    }
+}
+
+impl From<SocketAddrV4> for Destination {
+    fn from(addr: SocketAddrV4) -> Self {
+        (*addr.ip()).into()
+    }
+}
+
+impl From<SocketAddrV6> for Destination {
+    fn from(addr: SocketAddrV6) -> Self {
+        Self {
+            addr: IpAddr::V6(*addr.ip()),
+            scope_id: addr.scope_id(),
+        }
+    }
+}
+
+impl From<SocketAddr> for Destination {
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(addr) => addr.into(),
+            SocketAddr::V6(addr) => addr.into(),
+        }
+    }
+}
+
+impl FromStr for Destination {
+    type Err = Error;
+
+    /// Parse an IP address, optionally followed by `%` and a zone.
+    /// The zone can be an interface name or index. Names are resolved
+    /// to an index immediately, so the interface has to exist.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidDestination(s.into());
+
+        let (addr, zone) = match s.split_once('%') {
+            Some((addr, zone)) => (addr, Some(zone)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let scope_id = match zone {
+            None => 0,
+            Some(_) if addr.is_ipv4() => return Err(invalid()),
+            Some("") => return Err(invalid()),
+            Some(zone) => match zone.parse() {
+                Ok(index) => index,
+                Err(_) => if_index(zone)?,
+            },
+        };
+
+        Ok(Self { addr, scope_id })
+    }
+}
+
+impl IpQuery<'_> {
+    /// Resolve a destination to a socket address,
+    /// taking the zone from the query's interface if it has none.
+    pub(crate) fn dest_socket_addr(&self, dest: Destination, port: u16) -> Result<SocketAddr> {
+        let ipv6 = match dest.addr {
+            IpAddr::V4(ipv4) => return Ok(SocketAddrV4::new(ipv4, port).into()),
+            IpAddr::V6(ipv6) => ipv6,
+        };
+
+        let if_index = self.if_index()?;
+        if dest.scope_id != 0 && if_index != 0 && dest.scope_id != if_index {
+            return Err(Error::ZoneMismatch {
+                interface: self.interface.unwrap_or_default().into(),
+                scope_id: dest.scope_id,
+            });
+        }
+
+        let scope_id = if dest.scope_id != 0 {
+            dest.scope_id
+        } else {
+            if_index
+        };
+
+        if dest.needs_zone() && scope_id == 0 {
+            return Err(Error::NoZone(ipv6));
+        }
+
+        Ok(SocketAddrV6::new(ipv6, port, 0, scope_id).into())
+    }
+
+    /// Get the source address used for sending to the given destination.
+    ///
+    /// If the query is bound to an interface, the zone of the destination
+    /// has to match it. Link-local destinations need a zone
+    /// unless the query is bound to an interface.
+    pub fn preferred_source_for(&self, dest: impl Into<Destination>) -> Result<IpAddr> {
+        self.probe(self.dest_socket_addr(dest.into(), 0)?)
+    }
+
+    /// Get the source addresses used for sending to each of the given
+    /// destinations, in the same order. One destination failing
+    /// doesn't affect the others.
+    ///
+    /// With [`ProbeProtocol::Udp`](crate::ProbeProtocol::Udp),
+    /// a single socket per address family is connected to each destination
+    /// in turn, which is considerably faster than separate probes
+    /// and doesn't use up local ports. Otherwise this is equivalent
+    /// to calling [`IpQuery::preferred_source_for`] for each destination.
+    pub fn preferred_sources_for<D>(&self, dests: &[D]) -> Vec<Result<IpAddr>>
+    where
+        D: Copy + Into<Destination>,
+    {
+        if self.protocol != ProbeProtocol::Udp {
+            return dests
+                .iter()
+                .map(|&dest| self.preferred_source_for(dest))
+                .collect();
+        }
+
+        let mut sockets = [None, None];
+        dests
+            .iter()
+            .map(|&dest| {
+                let dest = self.dest_socket_addr(dest.into(), 0)?;
+                self.observed(dest, None, || self.reconnect(&mut sockets, dest))
+            })
+            .collect()
+    }
+
+    /// Confirm that the given source address can be used for sending
+    /// to the destination with the current routing, e.g. before binding
+    /// a service to it. This is the inverse of
+    /// [`IpQuery::preferred_source_for`]: The probe socket is bound to
+    /// the source address before connecting, so policy rules that select
+    /// on the source are taken into account.
+    ///
+    /// The socket isn't bound to the interface, since that would make
+    /// the kernel ignore routes through other devices, e.g. `prohibit`
+    /// routes. Instead, the address has to be assigned to the interface
+    /// and the route the kernel picked has to use it.
+    ///
+    /// Fails with [`Error::SourceNotAssigned`] if the address isn't
+    /// assigned (to the interface), with [`Error::NoSourceRoute`]
+    /// if there is no route towards the destination from it (through
+    /// the interface) and with [`Error::Prohibited`] if sending is
+    /// prohibited by a route or the firewall. Filtering of the replies,
+    /// e.g. by `rp_filter` on the remote end, can't be detected
+    /// without sending traffic.
+    pub fn confirm_source_route(&self, source: IpAddr, dest: impl Into<Destination>) -> Result<()> {
+        let dest = self.dest_socket_addr(dest.into(), 0)?;
+        if source.is_ipv4() != dest.is_ipv4() {
+            let family = if dest.is_ipv4() {
+                IpVersion::V4
+            } else {
+                IpVersion::V6
+            };
+            return Err(Error::WrongIpVer(family, source));
+        }
+
+        let bind_addr = match (source, dest) {
+            (IpAddr::V6(ipv6), SocketAddr::V6(dest)) if ipv6.is_unicast_link_local() => {
+                SocketAddrV6::new(ipv6, 0, 0, dest.scope_id()).into()
+            }
+            _ => SocketAddr::new(source, 0),
+        };
+
+        if self.interface.is_some()
+            && addrs::find_address(self.interface, |addr| addr.addr == source)?.is_none()
+        {
+            return Err(Error::SourceNotAssigned {
+                interface: self.interface_name(),
+                source,
+            });
+        }
+
+        let unbound = IpQuery {
+            interface: None,
+            ..self.clone()
+        };
+        let result = self.observed(dest, None, || {
+            let local_addr = unbound.probe_socket(dest, |socket| socket.bind(&bind_addr.into()))?;
+            Ok(local_addr.ip())
+        });
+
+        match result {
+            Ok(_) if self.interface.is_none() => Ok(()),
+            Ok(_) => {
+                // Link-local destinations are ambiguous without their zone.
+                let zone = match dest {
+                    SocketAddr::V6(dest) if dest.scope_id() != 0 => Some(dest.scope_id()),
+                    _ => None,
+                };
+
+                let route = Netlink::open()?.route_get_from(dest.ip(), source, zone)?;
+                if route.oif == Some(self.if_index()?) {
+                    Ok(())
+                } else {
+                    Err(self.no_source_route(source, dest.ip()))
+                }
+            }
+            Err(e) => Err(self.source_route_error(e, source, dest.ip())),
+        }
+    }
+
+    /// Classify the failure of binding to the source and connecting.
+    fn source_route_error(&self, e: Error, source: IpAddr, dest: IpAddr) -> Error {
+        match e {
+            Error::NoRoute { .. } => self.no_source_route(source, dest),
+            Error::IoError(e) => match e.raw_os_error() {
+                Some(libc::EADDRNOTAVAIL) => Error::SourceNotAssigned {
+                    interface: self.interface_name(),
+                    source,
+                },
+                Some(libc::EINVAL | libc::ENETUNREACH) => self.no_source_route(source, dest),
+                Some(libc::EACCES | libc::EPERM) => Error::Prohibited {
+                    interface: self.interface_name(),
+                    source,
+                    dest,
+                },
+                _ => Error::IoError(e),
+            },
+            e => e,
+        }
+    }
+
+    fn no_source_route(&self, source: IpAddr, dest: IpAddr) -> Error {
+        Error::NoSourceRoute {
+            interface: self.interface_name(),
+            source,
+            dest,
+        }
+    }
+
+    /// Connect the socket of the destination's family to it,
+    /// creating the socket first if there is none yet.
+    fn reconnect(&self, sockets: &mut [Option<Socket>; 2], dest: SocketAddr) -> Result<IpAddr> {
+        self.probe_timeout("probe")?;
+
+        let socket = match &mut sockets[usize::from(dest.is_ipv6())] {
+            Some(socket) => {
+                // Dissolving the association also unbinds the socket
+                // from its interface, which has to be restored.
+                let device = socket.device()?;
+                disconnect(socket)?;
+                if device.is_some() {
+                    socket.bind_device(device.as_deref())?;
+                }
+                socket
+            }
+            empty => empty.insert(self.open_socket(dest, Type::DGRAM)?),
+        };
+
+        socket
+            .connect(&dest.into())
+            .map_err(|e| self.connect_error(e, dest.ip()))?;
+
+        let local_addr = socket.local_addr()?.as_socket().unwrap();
+        Ok(local_addr.ip())
+    }
+}
+
+/// Dissolve the association of a connected UDP socket,
+/// resetting its local address and port so that
+/// the next connect selects them again.
+fn disconnect(socket: &Socket) -> io::Result<()> {
+    let addr = libc::sockaddr {
+        sa_family: libc::AF_UNSPEC as libc::sa_family_t,
+        sa_data: [0; 14],
+    };
+
+    // SAFETY: `addr` is a valid `sockaddr` of the given size
+    // and the file descriptor is owned by the socket.
+    let res = unsafe {
+        libc::connect(
+            socket.as_raw_fd(),
+            &addr,
+            mem::size_of_val(&addr) as libc::socklen_t,
+        )
+    };
+
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Get the source address used for sending to the given destination
+/// on the given interface, or on any interface if `None`.
+/// See [`IpQuery::preferred_source_for`] for details.
+pub fn preferred_source_for(
+    interface: Option<&str>,
+    dest: impl Into<Destination>,
+) -> Result<IpAddr> {
+    IpQuery::with_interface(interface).preferred_source_for(dest)
+}
+
+/// Confirm that the given source address can be used for sending
+/// to the destination on the given interface.
+/// See [`IpQuery::confirm_source_route`] for details.
+pub fn confirm_source_route(interface: &str, source: IpAddr, dest: IpAddr) -> Result<()> {
+    IpQuery::new(interface).confirm_source_route(source, dest)
+}
+
+/// Get the source addresses used for sending to each of the given
+/// destinations on the given interface, or on any interface if `None`.
+/// See [`IpQuery::preferred_sources_for`] for details.
+pub fn preferred_sources_for<D>(interface: Option<&str>, dests: &[D]) -> Vec<Result<IpAddr>>
+where
+    D: Copy + Into<Destination>,
+{
+    IpQuery::with_interface(interface).preferred_sources_for(dests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dest(addr: &str, scope_id: u32) -> Destination {
+        Destination {
+            addr: addr.parse().unwrap(),
+            scope_id,
+        }
+    }
+
+    #[test]
+    fn parse_destinations() {
+        assert_eq!(
+            "fe80::1".parse::<Destination>().unwrap(),
+            dest("fe80::1", 0)
+        );
+        assert_eq!(
+            "fe80::1%2".parse::<Destination>().unwrap(),
+            dest("fe80::1", 2)
+        );
+        assert_eq!(
+            "fe80::1%lo".parse::<Destination>().unwrap(),
+            dest("fe80::1", 1)
+        );
+        assert_eq!(
+            "2a01:4f8::1%3".parse::<Destination>().unwrap(),
+            dest("2a01:4f8::1", 3)
+        );
+        assert_eq!(
+            "192.0.2.1".parse::<Destination>().unwrap(),
+            dest("192.0.2.1", 0)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_destinations() {
+        for invalid in [
+            "",
+            "%1",
+            "fe80::1%",
+            "192.0.2.1%1",
+            "192.0.2.1%lo",
+            "[fe80::1]",
+            "fe80::g",
+        ] {
+            let result = invalid.parse::<Destination>();
+            assert!(
+                matches!(result, Err(Error::InvalidDestination(ref s)) if s == invalid),
+                "{:?}: {:?}",
+                invalid,
+                result
+            );
+        }
+
+        let result = "fe80::1%nonexistent0".parse::<Destination>();
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ENODEV));
+    }
+
+    #[test]
+    fn convert_socket_addrs() {
+        let scoped = SocketAddrV6::new("fe80::1".parse().unwrap(), 53, 0, 7);
+        assert_eq!(Destination::from(scoped), dest("fe80::1", 7));
+        assert_eq!(
+            Destination::from(SocketAddr::V6(scoped)),
+            dest("fe80::1", 7)
+        );
+
+        let ipv4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 53);
+        assert_eq!(
+            Destination::from(SocketAddr::V4(ipv4)),
+            dest("192.0.2.1", 0)
+        );
+    }
+
+    #[test]
+    fn zone_of_bound_query() {
+        let query = IpQuery::new("lo");
+
+        let addr = query.dest_socket_addr(dest("fe80::1", 0), 53).unwrap();
+        assert_eq!(addr, "[fe80::1%1]:53".parse().unwrap());
+        let addr = query.dest_socket_addr(dest("fe80::1", 1), 0).unwrap();
+        assert_eq!(addr, "[fe80::1%1]:0".parse().unwrap());
+
+        // Other IPv6 destinations get the zone too,
+        // the kernel ignores it for them.
+        let addr = query.dest_socket_addr(dest("2a01:4f8::1", 0), 0).unwrap();
+        assert_eq!(addr, "[2a01:4f8::1%1]:0".parse().unwrap());
+        let addr = query.dest_socket_addr(dest("192.0.2.1", 0), 0).unwrap();
+        assert_eq!(addr, "192.0.2.1:0".parse().unwrap());
+
+        let result = query.dest_socket_addr(dest("fe80::1", 2), 0);
+        assert!(
+            matches!(
+                result,
+                Err(Error::ZoneMismatch { ref interface, scope_id: 2 }) if interface == "lo"
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn zone_of_unbound_query() {
+        let query = IpQuery::any_interface();
+
+        let addr = query.dest_socket_addr(dest("fe80::1", 2), 0).unwrap();
+        assert_eq!(addr, "[fe80::1%2]:0".parse().unwrap());
+        let addr = query.dest_socket_addr(dest("2a01:4f8::1", 0), 0).unwrap();
+        assert_eq!(addr, "[2a01:4f8::1]:0".parse().unwrap());
+
+        let result = query.dest_socket_addr(dest("fe80::1", 0), 0);
+        assert!(
+            matches!(result, Err(Error::NoZone(ip)) if ip == "fe80::1".parse::<Ipv6Addr>().unwrap()),
+            "{:?}",
+            result
+        );
+    }
+
+    fn os(errno: i32) -> Error {
+        io::Error::from_raw_os_error(errno).into()
+    }
+
+    fn classify(e: Error) -> Error {
+        let source = "2a01:4f8::1".parse().unwrap();
+        let dest = "2a01:4f8:1::1".parse().unwrap();
+        IpQuery::new("eth0").source_route_error(e, source, dest)
+    }
+
+    #[test]
+    fn source_route_errors_are_classified_by_errno() {
+        let source: IpAddr = "2a01:4f8::1".parse().unwrap();
+        let dest: IpAddr = "2a01:4f8:1::1".parse().unwrap();
+        let interface = Some(String::from("eth0"));
+
+        assert!(matches!(
+            classify(os(libc::EADDRNOTAVAIL)),
+            Error::SourceNotAssigned { interface: ref i, source: s } if *i == interface && s == source
+        ));
+        for errno in [libc::EINVAL, libc::ENETUNREACH] {
+            assert!(matches!(
+                classify(os(errno)),
+                Error::NoSourceRoute { interface: ref i, source: s, dest: d }
+                    if *i == interface && s == source && d == dest
+            ));
+        }
+        for errno in [libc::EACCES, libc::EPERM] {
+            assert!(matches!(
+                classify(os(errno)),
+                Error::Prohibited { interface: ref i, source: s, dest: d }
+                    if *i == interface && s == source && d == dest
+            ));
+        }
+    }
+
+    #[test]
+    fn unreachable_destinations_have_no_source_route() {
+        let e = Error::NoRoute {
+            interface: None,
+            dest: "2a01:4f8:1::1".parse().unwrap(),
+        };
+        assert!(matches!(classify(e), Error::NoSourceRoute { .. }));
+    }
+
+    #[test]
+    fn other_errors_are_kept() {
+        for errno in [libc::ENODEV, libc::ECONNREFUSED, libc::ENOBUFS] {
+            assert_eq!(classify(os(errno)).raw_os_error(), Some(errno));
+            assert!(matches!(classify(os(errno)), Error::IoError(_)));
+        }
+
+        // The errno of a failing factory isn't the kernel's verdict.
+        let e = Error::SocketFactory(io::Error::from_raw_os_error(libc::EACCES).into());
+        assert!(matches!(classify(e), Error::SocketFactory(_)));
+
+        let e = Error::Timeout {
+            interface: None,
+            operation: "connect",
+        };
+        assert!(matches!(classify(e), Error::Timeout { .. }));
+    }
+}
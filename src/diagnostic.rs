@@ -0,0 +1,285 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::observe::{self, Observer};
+use crate::{
+    Backend, Error, FallbackKind, IpQuery, Ipv4Scope, Ipv6Scope, ProbeObserver, Result, Scope,
+};
+
+/// What the kernel reported for all scopes of [`IpQuery::get_all`],
+/// e.g. for support bundles. See [`IpQuery::get_all_diagnostic`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct DiagnosticReport {
+    pub interface: Option<String>,
+    pub scopes: Vec<ScopeDiagnostic>,
+}
+
+/// The probes and the outcome of a single scope.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct ScopeDiagnostic {
+    /// Serialized in the form of [`get_by_spec`](crate::get_by_spec),
+    /// e.g. `ipv6-gua`.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_display"))]
+    pub scope: Scope,
+    /// The raw probes towards each of the destinations of the scope.
+    pub probes: Vec<ProbeDiagnostic>,
+    /// The address the getter of the scope returned after classification,
+    /// or its error message.
+    pub outcome: std::result::Result<IpAddr, String>,
+    /// The backend that produced the outcome.
+    pub backend: Backend,
+    /// The fallbacks the getter used.
+    pub fallbacks: Vec<FallbackKind>,
+}
+
+/// A single probe exactly as reported by the kernel.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct ProbeDiagnostic {
+    pub dest: SocketAddr,
+    /// The local address of the probe socket including the
+    /// port, flow info and scope id, or the error message.
+    pub local: std::result::Result<SocketAddr, String>,
+    pub duration: Duration,
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_display<S: serde::Serializer>(
+    value: &impl std::fmt::Display,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Records the fallbacks of a getter, forwarding all hooks
+/// to the observer the query would otherwise use.
+struct Recorder {
+    inner: Option<Observer>,
+    fallbacks: Arc<Mutex<Vec<FallbackKind>>>,
+}
+
+impl Recorder {
+    fn inner(&self) -> Option<&dyn ProbeObserver> {
+        match &self.inner {
+            Some(observer) => Some(observer.get()),
+            None => observe::global(),
+        }
+    }
+}
+
+impl ProbeObserver for Recorder {
+    fn on_probe_start(&self, interface: Option<&str>, dest: SocketAddr, scope: Option<Scope>) {
+        if let Some(inner) = self.inner() {
+            inner.on_probe_start(interface, dest, scope);
+        }
+    }
+
+    fn on_probe_end(
+        &self,
+        interface: Option<&str>,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        outcome: std::result::Result<IpAddr, &Error>,
+        duration: Duration,
+    ) {
+        if let Some(inner) = self.inner() {
+            inner.on_probe_end(interface, dest, scope, outcome, duration);
+        }
+    }
+
+    fn on_fallback(&self, interface: Option<&str>, kind: FallbackKind) {
+        self.fallbacks.lock().unwrap().push(kind);
+
+        if let Some(inner) = self.inner() {
+            inner.on_fallback(interface, kind);
+        }
+    }
+}
+
+impl IpQuery<'_> {
+    /// Like [`IpQuery::get_all`], but report the raw probes and
+    /// the outcome of every scope instead of only the addresses.
+    ///
+    /// Each destination of a scope is probed once more on its own,
+    /// so the raw probes can differ from what the getter saw
+    /// if the addresses change in between. Errors of individual
+    /// scopes are part of the report. Only a deadline that expires
+    /// before the first scope is probed fails the whole operation.
+    pub fn get_all_diagnostic(&self) -> Result<DiagnosticReport> {
+        self.probe_timeout("diagnostic")?;
+
+        Ok(DiagnosticReport {
+            interface: self.interface_name(),
+            scopes: scopes(self.interface.is_some())
+                .into_iter()
+                .map(|scope| self.diagnose(scope))
+                .collect(),
+        })
+    }
+
+    fn diagnose(&self, scope: Scope) -> ScopeDiagnostic {
+        let probes = scope
+            .probe_dests()
+            .iter()
+            .map(|&dest| {
+                let start = self.now();
+                let local = self.probe_socket(dest, |_| Ok(()));
+
+                ProbeDiagnostic {
+                    dest,
+                    local: local.map_err(|e| e.to_string()),
+                    duration: self.now().saturating_duration_since(start),
+                }
+            })
+            .collect();
+
+        let fallbacks = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            inner: self.observer.clone(),
+            fallbacks: fallbacks.clone(),
+        };
+
+        let outcome = self.clone().observer(recorder).get(scope);
+        let fallbacks = fallbacks.lock().unwrap().clone();
+
+        ScopeDiagnostic {
+            scope,
+            probes,
+            outcome: outcome.map_err(|e| e.to_string()),
+            backend: backend_of(&fallbacks, self.backend),
+            fallbacks,
+        }
+    }
+}
+
+/// Get the scopes of [`IpQuery::get_all`] in the order they are probed.
+/// Link-local IPv6 addresses need an interface.
+fn scopes(bound: bool) -> Vec<Scope> {
+    let mut scopes = Vec::new();
+    if bound {
+        scopes.push(Scope::V6(Ipv6Scope::UnicastLinkLocal));
+    }
+    scopes.extend([
+        Scope::V6(Ipv6Scope::UniqueLocal),
+        Scope::V6(Ipv6Scope::UnicastGlobal),
+        Scope::V4(Ipv4Scope::LinkLocal),
+        Scope::V4(Ipv4Scope::Private),
+        Scope::V4(Ipv4Scope::Global),
+    ]);
+
+    scopes
+}
+
+/// Get the backend that produced an outcome: The last fallback
+/// to another backend, or the configured one if there was none.
+fn backend_of(fallbacks: &[FallbackKind], configured: Option<Backend>) -> Backend {
+    fallbacks
+        .iter()
+        .rev()
+        .find_map(|kind| match kind {
+            #[cfg(feature = "networkmanager")]
+            FallbackKind::NetworkManager => Some(Backend::NetworkManager),
+            FallbackKind::Procfs => Some(Backend::Procfs),
+            _ => None,
+        })
+        .unwrap_or(configured.unwrap_or(Backend::Socket))
+}
+
+/// Get a [`DiagnosticReport`] for the given interface,
+/// or for any interface if `None`.
+/// See [`IpQuery::get_all_diagnostic`] for details.
+pub fn get_all_diagnostic(interface: Option<&str>) -> Result<DiagnosticReport> {
+    IpQuery::with_interface(interface).get_all_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_local_ipv6_needs_an_interface() {
+        let bound = scopes(true);
+        let unbound = scopes(false);
+
+        assert_eq!(bound[0], Scope::V6(Ipv6Scope::UnicastLinkLocal));
+        assert_eq!(bound[1..], unbound);
+        assert!(!unbound.contains(&Scope::V6(Ipv6Scope::UnicastLinkLocal)));
+        assert_eq!(unbound.len(), 5);
+    }
+
+    #[test]
+    fn backend_is_the_last_fallback() {
+        assert_eq!(backend_of(&[], None), Backend::Socket);
+        assert_eq!(backend_of(&[], Some(Backend::Procfs)), Backend::Procfs);
+        assert_eq!(
+            backend_of(&[FallbackKind::Procfs], Some(Backend::Socket)),
+            Backend::Procfs
+        );
+        assert_eq!(
+            backend_of(
+                &[FallbackKind::Procfs, FallbackKind::UnmapV4],
+                Some(Backend::Socket)
+            ),
+            Backend::Procfs
+        );
+    }
+
+    #[test]
+    fn other_fallbacks_keep_the_backend() {
+        let fallbacks = [
+            FallbackKind::OptimisticRejected,
+            FallbackKind::ShortLifetime,
+            FallbackKind::UnmapV4,
+        ];
+
+        assert_eq!(backend_of(&fallbacks, None), Backend::Socket);
+        assert_eq!(
+            backend_of(&fallbacks, Some(Backend::Procfs)),
+            Backend::Procfs
+        );
+    }
+
+    #[cfg(feature = "networkmanager")]
+    #[test]
+    fn networkmanager_fallback_is_reported() {
+        let fallbacks = [FallbackKind::Procfs, FallbackKind::NetworkManager];
+        assert_eq!(backend_of(&fallbacks, None), Backend::NetworkManager);
+        assert_eq!(backend_of(&fallbacks[..1], None), Backend::Procfs);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn report_serialization() {
+        let report = DiagnosticReport {
+            interface: Some("eth0".into()),
+            scopes: vec![ScopeDiagnostic {
+                scope: Scope::V6(Ipv6Scope::UnicastGlobal),
+                probes: vec![ProbeDiagnostic {
+                    dest: "[2001:4860:4860::8888]:0".parse().unwrap(),
+                    local: Ok("[2a01:4f8::1]:40000".parse().unwrap()),
+                    duration: Duration::from_millis(3),
+                }],
+                outcome: Err("no global address".into()),
+                backend: Backend::Socket,
+                fallbacks: vec![FallbackKind::Procfs],
+            }],
+        };
+
+        let json = serde_json::to_value(report).unwrap();
+        let scope = &json["scopes"][0];
+        assert_eq!(json["interface"], "eth0");
+        assert_eq!(scope["scope"], "ipv6-gua");
+        assert_eq!(scope["probes"][0]["dest"], "[2001:4860:4860::8888]:0");
+        assert_eq!(scope["probes"][0]["local"]["Ok"], "[2a01:4f8::1]:40000");
+        assert_eq!(scope["outcome"]["Err"], "no global address");
+    }
+}
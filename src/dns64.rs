@@ -0,0 +1,495 @@
+//! Discovery of the NAT64 prefix of a DNS64 resolver (RFC 7050).
+//!
+//! A DNS64 resolver synthesizes AAAA records for the IPv4-only name
+//! `ipv4only.arpa` by embedding its well-known addresses 192.0.0.170
+//! and 192.0.0.171 into the NAT64 prefix as described in RFC 6052.
+//! Locating them in the synthesized addresses reveals the prefix.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::time::Duration;
+
+use socket2::Type;
+
+use crate::{resolv, Error, IpQuery, Result};
+
+const WELL_KNOWN_ADDRS: [Ipv4Addr; 2] =
+    [Ipv4Addr::new(192, 0, 0, 170), Ipv4Addr::new(192, 0, 0, 171)];
+
+/// The prefix lengths of RFC 6052, in the order they are tried.
+const PREFIX_LENS: [u8; 6] = [96, 64, 56, 48, 40, 32];
+
+/// The default time to wait for a nameserver to answer,
+/// matching the default of `resolv.conf`.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const QTYPE_AAAA: u16 = 28;
+const QCLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Extract the IPv4 address embedded in an IPv6 address
+/// with a prefix of the given length according to RFC 6052.
+/// Returns `None` if bits 64 to 71 aren't zero.
+fn embedded_ipv4(addr: Ipv6Addr, prefix_len: u8) -> Option<Ipv4Addr> {
+    let octets = addr.octets();
+
+    // Bits 64 to 71 are skipped and must be zero.
+    if prefix_len < 96 && octets[8] != 0 {
+        return None;
+    }
+
+    let start = usize::from(prefix_len / 8);
+    let mut ipv4 = [0; 4];
+    for (octet, i) in ipv4.iter_mut().zip((start..16).filter(|&i| i != 8)) {
+        *octet = octets[i];
+    }
+
+    Some(ipv4.into())
+}
+
+/// Mask an IPv6 address to the given prefix length.
+fn mask(addr: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0);
+    Ipv6Addr::from(u128::from(addr) & mask)
+}
+
+/// Get the NAT64 prefix and its length from the addresses a DNS64
+/// resolver synthesized for `ipv4only.arpa`, or `None` if none of them
+/// embed a well-known address.
+///
+/// If a well-known address could be embedded at several prefix lengths,
+/// only the lengths at which all addresses embed one are considered,
+/// as described in RFC 7050 section 3. Of those, the longest is used.
+pub fn nat64_prefix(synthesized: &[Ipv6Addr]) -> Option<(Ipv6Addr, u8)> {
+    let (first, _) = synthesized.split_first()?;
+
+    let prefix_len = PREFIX_LENS.into_iter().find(|&prefix_len| {
+        synthesized.iter().all(|&addr| {
+            embedded_ipv4(addr, prefix_len).is_some_and(|ipv4| WELL_KNOWN_ADDRS.contains(&ipv4))
+                && mask(addr, prefix_len) == mask(*first, prefix_len)
+        })
+    })?;
+
+    Some((mask(*first, prefix_len), prefix_len))
+}
+
+/// Build a recursive AAAA query for `ipv4only.arpa`.
+fn build_query(id: u16) -> Vec<u8> {
+    let mut query = Vec::with_capacity(31);
+
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00]); // RD
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // QDCOUNT 1
+
+    for label in ["ipv4only", "arpa"] {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend_from_slice(&QTYPE_AAAA.to_be_bytes());
+    query.extend_from_slice(&QCLASS_IN.to_be_bytes());
+    query
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed dns response")
+}
+
+/// Skip a possibly compressed domain name, returning the offset after it.
+fn skip_name(msg: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(malformed)?;
+        match len {
+            0 => return Ok(pos + 1),
+            // A compression pointer ends the name.
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len => pos += 1 + usize::from(len),
+        }
+    }
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> io::Result<u16> {
+    let bytes = msg.get(pos..pos + 2).ok_or_else(malformed)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Parse the AAAA records of the answer section of a response.
+/// Returns `None` if the response isn't meant for the query.
+fn parse_response(msg: &[u8], id: u16) -> io::Result<Option<Vec<Ipv6Addr>>> {
+    if msg.len() < 12 || read_u16(msg, 0)? != id || msg[2] & 0x80 == 0 {
+        return Ok(None);
+    }
+
+    match msg[3] & 0x0f {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(Some(Vec::new())),
+        rcode => {
+            return Err(io::Error::other(format!(
+                "nameserver failed with rcode {}",
+                rcode
+            )))
+        }
+    }
+
+    let qdcount = read_u16(msg, 4)?;
+    let ancount = read_u16(msg, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(msg, pos)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..ancount {
+        pos = skip_name(msg, pos)?;
+
+        let ty = read_u16(msg, pos)?;
+        let class = read_u16(msg, pos + 2)?;
+        let len = usize::from(read_u16(msg, pos + 8)?);
+        pos += 10;
+
+        let data = msg.get(pos..pos + len).ok_or_else(malformed)?;
+        if ty == QTYPE_AAAA && class == QCLASS_IN {
+            let octets: [u8; 16] = data.try_into().map_err(|_| malformed())?;
+            addrs.push(octets.into());
+        }
+
+        pos += len;
+    }
+
+    Ok(Some(addrs))
+}
+
+/// Get a query id that differs between calls.
+fn query_id() -> u16 {
+    // Every `RandomState` has different keys.
+    RandomState::new().build_hasher().finish() as u16
+}
+
+impl IpQuery<'_> {
+    fn query_dns64(&self, nameserver: IpAddr) -> Result<Vec<Ipv6Addr>> {
+        let dest = self.dest_socket_addr(nameserver.into(), 53)?;
+        let timeout = self.probe_timeout("dns64 query")?.unwrap_or(QUERY_TIMEOUT);
+
+        let socket = self.open_socket(dest, Type::DGRAM)?;
+        socket
+            .connect(&dest.into())
+            .map_err(|e| self.connect_error(e, nameserver))?;
+
+        let socket = UdpSocket::from(socket);
+        let id = query_id();
+        socket.send(&build_query(id))?;
+
+        let deadline = self.now() + timeout;
+        let mut buf = [0; 512];
+        loop {
+            let remaining = deadline
+                .checked_duration_since(self.now())
+                .filter(|remaining| !remaining.is_zero())
+                .ok_or_else(|| Error::Timeout {
+                    interface: self.interface_name(),
+                    operation: "dns64 query",
+                })?;
+            socket.set_read_timeout(Some(remaining))?;
+
+            let start = self.now();
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Let clocks that only advance when sleeping
+                    // reach the deadline as well.
+                    let elapsed = self.now().saturating_duration_since(start);
+                    self.active_clock().sleep(remaining.saturating_sub(elapsed));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if let Some(addrs) = parse_response(&buf[..len], id)? {
+                return Ok(addrs);
+            }
+        }
+    }
+
+    /// Detect whether the network provides DNS64 by resolving
+    /// `ipv4only.arpa` as described in RFC 7050 and get its NAT64 prefix,
+    /// or `None` if no AAAA records are synthesized.
+    ///
+    /// The nameservers configured in `/etc/resolv.conf` are tried in order
+    /// until one of them answers. Each of them gets the per-probe timeout,
+    /// or five seconds if there is none. The query is sent from a socket
+    /// bound to the interface, so it can be mocked using
+    /// [`IpQuery::socket_factory`]. Use [`nat64_prefix`] to get
+    /// the prefix length as well.
+    pub fn detect_dns64(&self) -> Result<Option<Ipv6Addr>> {
+        let mut last_err = None;
+
+        for nameserver in resolv::nameservers()? {
+            match self.query_dns64(nameserver) {
+                Ok(addrs) => return Ok(nat64_prefix(&addrs).map(|(prefix, _)| prefix)),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap())
+    }
+}
+
+/// Detect DNS64 on the given interface, or on any interface if `None`,
+/// and get its NAT64 prefix. See [`IpQuery::detect_dns64`] for details.
+pub fn detect_dns64(interface: Option<&str>) -> Result<Option<Ipv6Addr>> {
+    IpQuery::with_interface(interface).detect_dns64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv6(addr: &str) -> Ipv6Addr {
+        addr.parse().unwrap()
+    }
+
+    /// Build a response to `build_query(id)` with the given rcode
+    /// and answers of the given types, using a compression pointer
+    /// to the question for their names.
+    fn response(id: u16, rcode: u8, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut msg = build_query(id);
+        msg[2] |= 0x80;
+        msg[3] = 0x80 | rcode;
+        msg[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+
+        for (ty, data) in answers {
+            msg.extend_from_slice(&[0xc0, 0x0c]);
+            msg.extend_from_slice(&ty.to_be_bytes());
+            msg.extend_from_slice(&QCLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&3600u32.to_be_bytes());
+            msg.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            msg.extend_from_slice(data);
+        }
+
+        msg
+    }
+
+    #[test]
+    fn embedded_ipv4_rfc6052_examples() {
+        // The examples of RFC 6052 section 2.4 for 192.0.2.33.
+        let examples = [
+            ("2001:db8:c000:221::", 32),
+            ("2001:db8:1c0:2:21::", 40),
+            ("2001:db8:122:c000:2:2100::", 48),
+            ("2001:db8:122:3c0:0:221::", 56),
+            ("2001:db8:122:344:c0:2:2100:0", 64),
+            ("2001:db8:122:344::c000:221", 96),
+        ];
+
+        for (addr, prefix_len) in examples {
+            assert_eq!(
+                embedded_ipv4(ipv6(addr), prefix_len),
+                Some(Ipv4Addr::new(192, 0, 2, 33)),
+                "{}/{}",
+                addr,
+                prefix_len
+            );
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4_requires_zero_u_octet() {
+        assert_eq!(
+            embedded_ipv4(ipv6("2001:db8:122:344:c0ff:2:2100:0"), 64),
+            None
+        );
+        assert_eq!(embedded_ipv4(ipv6("2001:db8:c000:221:ff00::"), 32), None);
+        // The octet is part of the prefix at /96.
+        assert_eq!(
+            embedded_ipv4(ipv6("2001:db8:122:344:ff00::c000:221"), 96),
+            Some(Ipv4Addr::new(192, 0, 2, 33))
+        );
+    }
+
+    #[test]
+    fn mask_prefix() {
+        let addr = ipv6("2001:db8:122:344:c0:2:2100:ffff");
+        assert_eq!(mask(addr, 0), Ipv6Addr::UNSPECIFIED);
+        assert_eq!(mask(addr, 32), ipv6("2001:db8::"));
+        assert_eq!(mask(addr, 40), ipv6("2001:db8:100::"));
+        assert_eq!(mask(addr, 56), ipv6("2001:db8:122:300::"));
+        assert_eq!(mask(addr, 128), addr);
+    }
+
+    #[test]
+    fn nat64_prefix_of_well_known_prefix() {
+        let synthesized = [ipv6("64:ff9b::c000:aa"), ipv6("64:ff9b::c000:ab")];
+        assert_eq!(nat64_prefix(&synthesized), Some((ipv6("64:ff9b::"), 96)));
+        assert_eq!(
+            nat64_prefix(&synthesized[1..]),
+            Some((ipv6("64:ff9b::"), 96))
+        );
+    }
+
+    #[test]
+    fn nat64_prefix_at_every_length() {
+        let cases = [
+            (
+                ["2001:db8:c000:aa::", "2001:db8:c000:ab::"],
+                "2001:db8::",
+                32,
+            ),
+            (
+                ["2001:db8:1c0:0:aa::", "2001:db8:1c0:0:ab::"],
+                "2001:db8:100::",
+                40,
+            ),
+            (
+                ["2001:db8:122:c000:0:aa00::", "2001:db8:122:c000:0:ab00::"],
+                "2001:db8:122::",
+                48,
+            ),
+            (
+                ["2001:db8:122:3c0:0:aa::", "2001:db8:122:3c0:0:ab::"],
+                "2001:db8:122:300::",
+                56,
+            ),
+            (
+                [
+                    "2001:db8:122:344:c0:0:aa00:0",
+                    "2001:db8:122:344:c0:0:ab00:0",
+                ],
+                "2001:db8:122:344::",
+                64,
+            ),
+            (
+                ["2001:db8:122:344::c000:aa", "2001:db8:122:344::c000:ab"],
+                "2001:db8:122:344::",
+                96,
+            ),
+        ];
+
+        for (synthesized, prefix, prefix_len) in cases {
+            let synthesized = synthesized.map(ipv6);
+            assert_eq!(
+                nat64_prefix(&synthesized),
+                Some((ipv6(prefix), prefix_len)),
+                "{:?}",
+                synthesized
+            );
+        }
+    }
+
+    #[test]
+    fn nat64_prefix_of_ambiguous_addresses() {
+        // Both embed 192.0.0.170 at /32 and /96, so the longest wins.
+        let ambiguous = ipv6("2001:db8:c000:aa::c000:aa");
+        assert_eq!(
+            nat64_prefix(&[ambiguous]),
+            Some((ipv6("2001:db8:c000:aa::"), 96))
+        );
+
+        // Only /32 is consistent with the second address.
+        let synthesized = [ambiguous, ipv6("2001:db8:c000:ab::")];
+        assert_eq!(nat64_prefix(&synthesized), Some((ipv6("2001:db8::"), 32)));
+    }
+
+    #[test]
+    fn nat64_prefix_without_synthesis() {
+        assert_eq!(nat64_prefix(&[]), None);
+        // Real AAAA records of an IPv4-only name don't exist,
+        // but misconfigured resolvers return arbitrary ones.
+        assert_eq!(nat64_prefix(&[ipv6("2001:db8::1")]), None);
+        assert_eq!(nat64_prefix(&[ipv6("64:ff9b::808:808")]), None);
+        // An address of another NAT64 prefix spoils the result.
+        let mixed = [ipv6("64:ff9b::c000:aa"), ipv6("2001:db8:122:344::c000:ab")];
+        assert_eq!(nat64_prefix(&mixed), None);
+        let foreign = [ipv6("64:ff9b::c000:aa"), ipv6("2001:db8::1")];
+        assert_eq!(nat64_prefix(&foreign), None);
+    }
+
+    #[test]
+    fn query_format() {
+        assert_eq!(
+            build_query(0x1234),
+            [
+                0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 8, b'i',
+                b'p', b'v', b'4', b'o', b'n', b'l', b'y', 4, b'a', b'r', b'p', b'a', 0, 0x00, 0x1c,
+                0x00, 0x01,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_synthesized_answers() {
+        let wka1 = ipv6("64:ff9b::c000:aa").octets();
+        let wka2 = ipv6("64:ff9b::c000:ab").octets();
+        // A CNAME to skip.
+        let cname = [3, b'f', b'o', b'o', 0xc0, 0x0c];
+        let msg = response(
+            7,
+            0,
+            &[(5, &cname), (QTYPE_AAAA, &wka1), (QTYPE_AAAA, &wka2)],
+        );
+
+        assert_eq!(
+            parse_response(&msg, 7).unwrap(),
+            Some(vec![ipv6("64:ff9b::c000:aa"), ipv6("64:ff9b::c000:ab")])
+        );
+    }
+
+    #[test]
+    fn parse_ignores_foreign_messages() {
+        let msg = response(7, 0, &[]);
+        assert_eq!(parse_response(&msg, 8).unwrap(), None);
+        // The query itself isn't a response.
+        assert_eq!(parse_response(&build_query(7), 7).unwrap(), None);
+        assert_eq!(parse_response(&msg[..11], 7).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_rcodes() {
+        assert_eq!(
+            parse_response(&response(7, 0, &[]), 7).unwrap(),
+            Some(Vec::new())
+        );
+        assert_eq!(
+            parse_response(&response(7, RCODE_NXDOMAIN, &[]), 7).unwrap(),
+            Some(Vec::new())
+        );
+
+        let err = parse_response(&response(7, 2, &[]), 7).unwrap_err();
+        assert_eq!(err.to_string(), "nameserver failed with rcode 2");
+    }
+
+    #[test]
+    fn parse_malformed_answers() {
+        let wka = ipv6("64:ff9b::c000:aa").octets();
+        let msg = response(7, 0, &[(QTYPE_AAAA, &wka)]);
+
+        for len in 12..msg.len() {
+            let err = parse_response(&msg[..len], 7).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", len);
+        }
+
+        // AAAA records must be 16 bytes long.
+        let msg = response(7, 0, &[(QTYPE_AAAA, &wka[..4])]);
+        assert_eq!(
+            parse_response(&msg, 7).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn query_ids_differ() {
+        let ids: std::collections::HashSet<_> = (0..64).map(|_| query_id()).collect();
+        // Random ids collide rarely.
+        assert!(ids.len() >= 60, "{:?}", ids);
+    }
+}
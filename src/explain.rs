@@ -0,0 +1,374 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::addrs::InterfaceAddr;
+use crate::{FallbackKind, IpQuery, OptimisticDad, Result, Scope};
+
+/// How an address was chosen by [`IpQuery::get_explained`],
+/// e.g. to find out why an unexpected address was returned.
+///
+/// The [`Display`](fmt::Display) implementation renders
+/// a readable report with one line per step.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct Explanation {
+    pub interface: Option<String>,
+    /// Serialized in the form of [`get_by_spec`](crate::get_by_spec),
+    /// e.g. `ipv6-gua`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::diagnostic::serialize_display")
+    )]
+    pub scope: Scope,
+    /// The decisions in the order they were made.
+    pub steps: Vec<Step>,
+}
+
+/// A single decision of the address selection.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Step {
+    /// A probe towards the destination and the source address
+    /// the kernel chose, or the error message.
+    Probe {
+        dest: SocketAddr,
+        source: std::result::Result<IpAddr, String>,
+    },
+    /// A fallback from probing.
+    Fallback(FallbackKind),
+    /// An assigned address that was considered instead of
+    /// or in addition to the probe result.
+    Candidate { addr: IpAddr, verdict: Verdict },
+    /// The candidate that was chosen. The rank is `None` if the backend
+    /// doesn't report the address properties, e.g. NetworkManager.
+    Chosen { addr: IpAddr, rank: Option<Rank> },
+    /// The address the getter of the scope returned.
+    Result { addr: IpAddr },
+}
+
+/// Whether a candidate passed the filters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Verdict {
+    /// The candidate is usable and of the requested scope.
+    Accepted(Rank),
+    /// Duplicate address detection failed.
+    DadFailed,
+    /// Duplicate address detection is still in progress.
+    Tentative,
+    /// The address is optimistic and rejected
+    /// by [`OptimisticDad::Reject`].
+    Optimistic,
+    /// The address isn't of the requested scope.
+    OutOfScope,
+    /// The remaining preferred lifetime of the address is shorter than
+    /// [`IpQuery::min_preferred_lifetime`].
+    ShortLifetime(Duration),
+}
+
+impl Verdict {
+    /// Filter an enumerated address like the getters do.
+    pub(crate) fn of(
+        addr: &InterfaceAddr,
+        optimistic_dad: OptimisticDad,
+        matches: impl Fn(&IpAddr) -> bool,
+    ) -> Self {
+        if addr.is_dad_failed() {
+            Self::DadFailed
+        } else if addr.is_optimistic() && optimistic_dad == OptimisticDad::Reject {
+            Self::Optimistic
+        } else if addr.is_tentative() && !addr.is_optimistic() {
+            Self::Tentative
+        } else if !matches(&addr.addr) {
+            Self::OutOfScope
+        } else {
+            Self::Accepted(Rank::of(addr))
+        }
+    }
+
+    pub(crate) fn is_accepted(self) -> bool {
+        matches!(self, Self::Accepted(_))
+    }
+}
+
+/// The properties of a candidate that decide between the accepted ones.
+/// Addresses that aren't deprecated win, then stable IPv6 addresses
+/// before temporary ones, then the numerically lowest address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct Rank {
+    pub deprecated: bool,
+    pub temporary: bool,
+}
+
+impl Rank {
+    pub(crate) fn of(addr: &InterfaceAddr) -> Self {
+        Self {
+            deprecated: addr.is_deprecated(),
+            temporary: addr.is_temporary(),
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.interface {
+            Some(interface) => writeln!(fmt, "{} on interface {}:", self.scope, interface)?,
+            None => writeln!(fmt, "{} on any interface:", self.scope)?,
+        }
+
+        for step in &self.steps {
+            writeln!(fmt, "  {}", step)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Probe {
+                dest,
+                source: Ok(source),
+            } => write!(fmt, "probe towards {}: kernel chose {}", dest.ip(), source),
+            Self::Probe {
+                dest,
+                source: Err(e),
+            } => write!(fmt, "probe towards {}: {}", dest.ip(), e),
+            Self::Fallback(kind) => write!(fmt, "fallback: {:?}", kind),
+            Self::Candidate { addr, verdict } => write!(fmt, "candidate {}: {}", addr, verdict),
+            Self::Chosen {
+                addr,
+                rank: Some(rank),
+            } => write!(fmt, "chose {} ({})", addr, rank),
+            Self::Chosen { addr, rank: None } => write!(fmt, "chose {}", addr),
+            Self::Result { addr } => write!(fmt, "result: {}", addr),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Accepted(rank) => write!(fmt, "accepted ({})", rank),
+            Self::DadFailed => write!(fmt, "rejected, dad failed"),
+            Self::Tentative => write!(fmt, "rejected, tentative"),
+            Self::Optimistic => write!(fmt, "rejected, optimistic"),
+            Self::OutOfScope => write!(fmt, "rejected, out of scope"),
+            Self::ShortLifetime(remaining) => {
+                write!(fmt, "rejected, only preferred for {:?}", remaining)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lifetime = if self.deprecated {
+            "deprecated"
+        } else {
+            "preferred"
+        };
+        let kind = if self.temporary {
+            "temporary"
+        } else {
+            "stable"
+        };
+
+        write!(fmt, "{}, {}", lifetime, kind)
+    }
+}
+
+/// Collects the steps of a query run by [`IpQuery::get_explained`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Trace(Arc<Mutex<Vec<Step>>>);
+
+impl IpQuery<'_> {
+    /// Record a step if the query is explained.
+    pub(crate) fn trace(&self, step: impl FnOnce() -> Step) {
+        if let Some(trace) = &self.trace {
+            trace.0.lock().unwrap().push(step());
+        }
+    }
+
+    /// Like [`IpQuery::get`], but also explain how the address was chosen.
+    ///
+    /// The explanation records the decisions of the getter as it makes
+    /// them: the probes, the fallbacks and, if the probe result isn't
+    /// used as is, the enumerated candidates with the filters that
+    /// rejected them and the properties that ranked the accepted ones.
+    pub fn get_explained(&self, scope: Scope) -> Result<(IpAddr, Explanation)> {
+        let trace = Trace::default();
+        let mut query = self.clone();
+        query.trace = Some(trace.clone());
+
+        let addr = query.get(scope)?;
+        query.trace(|| Step::Result { addr });
+
+        let steps = trace.0.lock().unwrap().clone();
+        Ok((
+            addr,
+            Explanation {
+                interface: self.interface_name(),
+                scope,
+                steps,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ipv6Scope;
+
+    fn addr(addr: &str, flags: u32) -> InterfaceAddr {
+        InterfaceAddr {
+            index: 2,
+            addr: addr.parse().unwrap(),
+            prefix_len: 64,
+            flags,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    fn is_gua(ip: &IpAddr) -> bool {
+        matches!(ip, IpAddr::V6(ipv6) if ipv6.is_unicast_global())
+    }
+
+    const STABLE: Rank = Rank {
+        deprecated: false,
+        temporary: false,
+    };
+
+    #[test]
+    fn verdicts() {
+        #[rustfmt::skip]
+        let cases = [
+            (addr("2a01:4f8::1", 0x80), OptimisticDad::Reject, Verdict::Accepted(STABLE)),
+            (addr("2a01:4f8::1", 0x01), OptimisticDad::Reject, Verdict::Accepted(Rank { deprecated: false, temporary: true })),
+            (addr("2a01:4f8::1", 0x20), OptimisticDad::Reject, Verdict::Accepted(Rank { deprecated: true, temporary: false })),
+            (addr("2a01:4f8::1", 0x08), OptimisticDad::Accept, Verdict::DadFailed),
+            (addr("2a01:4f8::1", 0x48), OptimisticDad::Accept, Verdict::DadFailed),
+            (addr("2a01:4f8::1", 0x44), OptimisticDad::Reject, Verdict::Optimistic),
+            (addr("2a01:4f8::1", 0x44), OptimisticDad::Accept, Verdict::Accepted(STABLE)),
+            (addr("2a01:4f8::1", 0x40), OptimisticDad::Accept, Verdict::Tentative),
+            (addr("fd00::1", 0x80), OptimisticDad::Accept, Verdict::OutOfScope),
+            (addr("fd00::1", 0x40), OptimisticDad::Accept, Verdict::Tentative),
+        ];
+
+        for (candidate, optimistic_dad, verdict) in cases {
+            assert_eq!(
+                Verdict::of(&candidate, optimistic_dad, is_gua),
+                verdict,
+                "{:?} {:?}",
+                candidate,
+                optimistic_dad
+            );
+        }
+    }
+
+    #[test]
+    fn report_of_every_step() {
+        let explanation = Explanation {
+            interface: None,
+            scope: Scope::V6(Ipv6Scope::UnicastGlobal),
+            steps: vec![
+                Step::Probe {
+                    dest: "[2000::]:0".parse().unwrap(),
+                    source: Err("permission denied".into()),
+                },
+                Step::Fallback(FallbackKind::Procfs),
+                Step::Candidate {
+                    addr: "2a01:4f8::1".parse().unwrap(),
+                    verdict: Verdict::DadFailed,
+                },
+                Step::Candidate {
+                    addr: "2a01:4f8::2".parse().unwrap(),
+                    verdict: Verdict::Optimistic,
+                },
+                Step::Candidate {
+                    addr: "2a01:4f8::3".parse().unwrap(),
+                    verdict: Verdict::ShortLifetime(Duration::from_secs(90)),
+                },
+                Step::Candidate {
+                    addr: "2a01:4f8::4".parse().unwrap(),
+                    verdict: Verdict::Accepted(Rank {
+                        deprecated: true,
+                        temporary: true,
+                    }),
+                },
+                Step::Chosen {
+                    addr: "2a01:4f8::4".parse().unwrap(),
+                    rank: Some(Rank {
+                        deprecated: true,
+                        temporary: true,
+                    }),
+                },
+                Step::Result {
+                    addr: "2a01:4f8::4".parse().unwrap(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            explanation.to_string(),
+            "\
+ipv6-gua on any interface:
+  probe towards 2000::: permission denied
+  fallback: Procfs
+  candidate 2a01:4f8::1: rejected, dad failed
+  candidate 2a01:4f8::2: rejected, optimistic
+  candidate 2a01:4f8::3: rejected, only preferred for 90s
+  candidate 2a01:4f8::4: accepted (deprecated, temporary)
+  chose 2a01:4f8::4 (deprecated, temporary)
+  result: 2a01:4f8::4
+"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_shape() {
+        let explanation = Explanation {
+            interface: Some("eth0".into()),
+            scope: Scope::V4(crate::Ipv4Scope::Private),
+            steps: vec![
+                Step::Probe {
+                    dest: "10.0.0.0:0".parse().unwrap(),
+                    source: Ok("192.168.77.1".parse().unwrap()),
+                },
+                Step::Candidate {
+                    addr: "192.168.77.1".parse().unwrap(),
+                    verdict: Verdict::Accepted(STABLE),
+                },
+                Step::Chosen {
+                    addr: "192.168.77.1".parse().unwrap(),
+                    rank: None,
+                },
+            ],
+        };
+
+        let json = serde_json::to_value(explanation).unwrap();
+        assert_eq!(json["interface"], "eth0");
+        assert_eq!(json["scope"], "ipv4-private");
+        assert_eq!(json["steps"][0]["probe"]["dest"], "10.0.0.0:0");
+        assert_eq!(json["steps"][0]["probe"]["source"]["Ok"], "192.168.77.1");
+        assert_eq!(
+            json["steps"][1]["candidate"]["verdict"]["accepted"],
+            serde_json::json!({ "deprecated": false, "temporary": false })
+        );
+        assert_eq!(json["steps"][2]["chosen"]["rank"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use socket2::{Domain, Socket, Type};
+
+/// A socket provided by a [`SocketFactory`].
+#[derive(Debug)]
+pub enum ProvidedSocket {
+    /// The socket isn't bound to an interface yet.
+    /// It is bound to the interface of the query, if any.
+    Unbound(Socket),
+    /// Binding the socket to the right interface or network
+    /// is already taken care of, e.g. by `Network.bindSocket` on Android
+    /// or by a privileged helper. The interface of the query
+    /// is only used for scope ids and error messages.
+    Bound(Socket),
+}
+
+/// Creates the probe sockets of an [`IpQuery`](crate::IpQuery)
+/// instead of [`Socket::new`]. Called with the domain and type
+/// the probe needs.
+///
+/// Whatever the factory returns, the query sets `IPV6_V6ONLY` on IPv6
+/// sockets, the [source preferences](crate::IpQuery::prefer_source),
+/// the outgoing interface for multicast probes and non-blocking mode
+/// for TCP probes, and then connects the socket.
+/// The factory must not connect it. Options it sets are kept
+/// unless one of the above overrides them.
+///
+/// The source preferences of IPv6 sockets are always set, even if the
+/// query has none, and [unbound](ProvidedSocket::Unbound) sockets are
+/// unbound from their interface if the query isn't bound to one.
+/// This way, a factory can reuse sockets across queries without
+/// leaking these options between them. Unbinding a socket
+/// requires `CAP_NET_RAW`, so pools should be kept per interface.
+///
+/// Errors of the factory fail the query with
+/// [`Error::SocketFactory`](crate::Error::SocketFactory), except for
+/// `EAFNOSUPPORT`, which means that the family is disabled like it does
+/// for [`Socket::new`] and fails it with
+/// [`Error::FamilyDisabled`](crate::Error::FamilyDisabled).
+pub trait SocketFactory: Send + Sync {
+    fn socket(&self, domain: Domain, ty: Type) -> io::Result<ProvidedSocket>;
+}
+
+impl<F> SocketFactory for F
+where
+    F: Fn(Domain, Type) -> io::Result<ProvidedSocket> + Send + Sync,
+{
+    fn socket(&self, domain: Domain, ty: Type) -> io::Result<ProvidedSocket> {
+        self(domain, ty)
+    }
+}
+
+/// The socket factory of a query.
+#[derive(Clone)]
+pub(crate) struct Factory(Arc<dyn SocketFactory>);
+
+impl Factory {
+    pub fn new(factory: impl SocketFactory + 'static) -> Self {
+        Self(Arc::new(factory))
+    }
+
+    pub fn get(&self) -> &dyn SocketFactory {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for Factory {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Factory")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closures_are_factories() {
+        let factory = Factory::new(|domain, ty| match (domain, ty) {
+            (Domain::IPV6, Type::DGRAM) => Socket::new(domain, ty, None).map(ProvidedSocket::Bound),
+            _ => Err(io::Error::from(io::ErrorKind::Unsupported)),
+        });
+
+        let shared = factory.clone();
+        assert!(matches!(
+            shared.get().socket(Domain::IPV6, Type::DGRAM),
+            Ok(ProvidedSocket::Bound(_))
+        ));
+        assert_eq!(
+            factory
+                .get()
+                .socket(Domain::IPV4, Type::DGRAM)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(format!("{:?}", factory), "Factory");
+    }
+}
@@ -0,0 +1,270 @@
+//! Bindings for Kotlin and Swift generated by [UniFFI](https://mozilla.github.io/uniffi-rs/).
+//!
+//! Addresses are passed as strings in their usual textual form.
+//! All functions block while probing, so they should be called
+//! from a background thread rather than the UI thread.
+//! Errors are raised as a single exception type carrying an
+//! [`ErrorKind`] and the message of the underlying [`Error`].
+
+use std::fmt;
+use std::net::AddrParseError;
+
+pub use crate::ErrorKind;
+use crate::{AddressReport, Error};
+
+/// The exception type of the bindings.
+#[derive(Debug, uniffi::Error)]
+pub enum FfiError {
+    Failed { kind: ErrorKind, message: String },
+}
+
+impl FfiError {
+    /// Get the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Failed { kind, .. } => *kind,
+        }
+    }
+}
+
+impl std::error::Error for FfiError {}
+
+impl fmt::Display for FfiError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed { message, .. } => write!(fmt, "{}", message),
+        }
+    }
+}
+
+impl From<Error> for FfiError {
+    fn from(err: Error) -> Self {
+        Self::Failed {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// An [`AddressReport`] with the addresses as strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct FfiAddressReport {
+    pub ipv6_unicast_link_local: Option<String>,
+    pub ipv6_unique_local: Option<String>,
+    pub ipv6_unicast_global: Option<String>,
+    pub ipv4_link_local: Option<String>,
+    pub ipv4_private: Option<String>,
+    pub ipv4_global: Option<String>,
+    pub incomplete: bool,
+}
+
+fn to_string<T: ToString>(addr: Option<T>) -> Option<String> {
+    addr.map(|addr| addr.to_string())
+}
+
+fn parse<T: std::str::FromStr<Err = AddrParseError>>(
+    addr: &Option<String>,
+) -> Result<Option<T>, AddrParseError> {
+    addr.as_deref().map(str::parse).transpose()
+}
+
+impl From<AddressReport> for FfiAddressReport {
+    fn from(report: AddressReport) -> Self {
+        Self {
+            ipv6_unicast_link_local: to_string(report.ipv6_unicast_link_local),
+            ipv6_unique_local: to_string(report.ipv6_unique_local),
+            ipv6_unicast_global: to_string(report.ipv6_unicast_global),
+            ipv4_link_local: to_string(report.ipv4_link_local),
+            ipv4_private: to_string(report.ipv4_private),
+            ipv4_global: to_string(report.ipv4_global),
+            incomplete: report.incomplete,
+        }
+    }
+}
+
+impl TryFrom<&FfiAddressReport> for AddressReport {
+    type Error = AddrParseError;
+
+    /// Parse the addresses back.
+    fn try_from(report: &FfiAddressReport) -> Result<Self, Self::Error> {
+        Ok(Self {
+            ipv6_unicast_link_local: parse(&report.ipv6_unicast_link_local)?,
+            ipv6_unique_local: parse(&report.ipv6_unique_local)?,
+            ipv6_unicast_global: parse(&report.ipv6_unicast_global)?,
+            ipv4_link_local: parse(&report.ipv4_link_local)?,
+            ipv4_private: parse(&report.ipv4_private)?,
+            ipv4_global: parse(&report.ipv4_global)?,
+            incomplete: report.incomplete,
+        })
+    }
+}
+
+/// Get the (preferred outgoing) IPv6 link-local address of the interface.
+#[uniffi::export]
+pub fn ipv6_unicast_link_local(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv6_unicast_link_local(&interface)?.to_string())
+}
+
+/// Get the preferred outgoing IPv6 ULA of the interface.
+#[uniffi::export]
+pub fn ipv6_unique_local(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv6_unique_local(&interface)?.to_string())
+}
+
+/// Get the preferred outgoing IPv6 GUA of the interface.
+#[uniffi::export]
+pub fn ipv6_unicast_global(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv6_unicast_global(&interface)?.to_string())
+}
+
+/// Get the (preferred outgoing) IPv4 link-local address of the interface.
+#[uniffi::export]
+pub fn ipv4_link_local(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv4_link_local(&interface)?.to_string())
+}
+
+/// Get the preferred outgoing IPv4 private address of the interface.
+#[uniffi::export]
+pub fn ipv4_private(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv4_private(&interface)?.to_string())
+}
+
+/// Get the preferred outgoing IPv4 global address of the interface.
+#[uniffi::export]
+pub fn ipv4_global(interface: String) -> Result<String, FfiError> {
+    Ok(crate::ipv4_global(&interface)?.to_string())
+}
+
+/// Get the preferred outgoing addresses of all scopes of the interface,
+/// or of any interface if `None`.
+#[uniffi::export]
+pub fn get_all(interface: Option<String>) -> Result<FfiAddressReport, FfiError> {
+    Ok(crate::get_all(interface.as_deref())?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::IpVersion;
+
+    fn full_report() -> AddressReport {
+        AddressReport {
+            ipv6_unicast_link_local: Some(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ipv6_unique_local: Some(Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1)),
+            ipv6_unicast_global: Some(Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1)),
+            ipv4_link_local: Some(Ipv4Addr::new(169, 254, 1, 1)),
+            ipv4_private: Some(Ipv4Addr::new(192, 168, 1, 1)),
+            ipv4_global: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            incomplete: false,
+        }
+    }
+
+    #[test]
+    fn report_to_strings() {
+        let report = FfiAddressReport::from(full_report());
+
+        assert_eq!(
+            report,
+            FfiAddressReport {
+                ipv6_unicast_link_local: Some("fe80::1".into()),
+                ipv6_unique_local: Some("fd00:dead::1".into()),
+                ipv6_unicast_global: Some("2a01:4f8::1".into()),
+                ipv4_link_local: Some("169.254.1.1".into()),
+                ipv4_private: Some("192.168.1.1".into()),
+                ipv4_global: Some("1.1.1.1".into()),
+                incomplete: false,
+            }
+        );
+    }
+
+    #[test]
+    fn report_round_trip() {
+        let full = full_report();
+        assert_eq!(
+            AddressReport::try_from(&FfiAddressReport::from(full)).unwrap(),
+            full
+        );
+
+        let partial = AddressReport {
+            ipv6_unique_local: full.ipv6_unique_local,
+            ipv4_private: full.ipv4_private,
+            incomplete: true,
+            ..Default::default()
+        };
+        let ffi = FfiAddressReport::from(partial);
+        assert_eq!(ffi.ipv6_unicast_global, None);
+        assert!(ffi.incomplete);
+        assert_eq!(AddressReport::try_from(&ffi).unwrap(), partial);
+
+        let empty = FfiAddressReport::default();
+        assert_eq!(
+            AddressReport::try_from(&empty).unwrap(),
+            AddressReport::default()
+        );
+    }
+
+    #[test]
+    fn invalid_report_addresses() {
+        let invalid = [
+            FfiAddressReport {
+                ipv6_unicast_global: Some("2a01:4f8::g".into()),
+                ..Default::default()
+            },
+            // Addresses of the wrong family don't parse.
+            FfiAddressReport {
+                ipv6_unique_local: Some("192.168.1.1".into()),
+                ..Default::default()
+            },
+            FfiAddressReport {
+                ipv4_private: Some("::ffff:192.168.1.1".into()),
+                ..Default::default()
+            },
+            FfiAddressReport {
+                ipv4_global: Some(String::new()),
+                ..Default::default()
+            },
+        ];
+
+        for report in invalid {
+            assert!(AddressReport::try_from(&report).is_err(), "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn errors_carry_kind_and_message() {
+        let errors = [
+            Error::NoAddress {
+                interface: Some("eth0".into()),
+                family: IpVersion::V6,
+            },
+            Error::NoGua(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+            Error::IoError(io::Error::from_raw_os_error(libc::ENODEV).into()),
+            Error::NoScopes,
+        ];
+
+        for err in errors {
+            let kind = err.kind();
+            let message = err.to_string();
+
+            let ffi = FfiError::from(err);
+            assert_eq!(ffi.kind(), kind);
+            assert_eq!(ffi.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn getters_raise_errors() {
+        let err = ipv4_private("nonexistent0".into()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(
+            err.to_string(),
+            Error::from(io::Error::from_raw_os_error(libc::ENODEV)).to_string()
+        );
+
+        let err = get_all(Some("nonexistent0".into())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+    }
+}
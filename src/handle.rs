@@ -0,0 +1,303 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, UdpSocket};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::{if_index, if_name, IpQuery, Result, Scope};
+
+struct Inner {
+    name: String,
+    index: AtomicU32,
+}
+
+/// An interface that is known to exist, with the getters
+/// of [`IpQuery`] as methods.
+///
+/// [`InterfaceHandle::open`] fails right away if there is no interface
+/// of the given name, rather than at the first probe. The handle
+/// caches the index of the interface and binds the probe sockets by it,
+/// so renaming the interface doesn't affect the getters. If a getter
+/// fails and the name refers to another index by now, e.g. because
+/// the interface was deleted and created again, the new index is cached
+/// and the getter is retried once. If the interface is gone,
+/// they fail with `ENODEV`.
+///
+/// Clones share the same cache. The handle is `Send` and `Sync`,
+/// so it can be kept in shared state.
+#[derive(Clone)]
+pub struct InterfaceHandle(Arc<Inner>);
+
+impl InterfaceHandle {
+    /// Open a handle to the interface of the given name,
+    /// failing with `ENODEV` if there is none.
+    pub fn open(name: &str) -> Result<Self> {
+        let index = if_index(name)?;
+
+        Ok(Self(Arc::new(Inner {
+            name: name.into(),
+            index: AtomicU32::new(index),
+        })))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Get the index of the interface as of opening the handle
+    /// or the last time it was resolved again. The new index of an
+    /// interface that was created again is only picked up once a getter
+    /// fails.
+    pub fn index(&self) -> u32 {
+        self.0.index.load(Ordering::Relaxed)
+    }
+
+    /// Get a query for the interface, e.g. to configure it
+    /// before running it. It uses the cached index,
+    /// but its getters aren't retried.
+    pub fn query(&self) -> IpQuery<'_> {
+        IpQuery {
+            if_index: NonZeroU32::new(self.index()),
+            ..IpQuery::new(&self.0.name)
+        }
+    }
+
+    /// Run the getter, resolving the index again
+    /// and retrying once if the interface disappeared.
+    ///
+    /// Sockets bound to an index that no longer exists fail to route
+    /// rather than with `ENODEV`, so any failure is checked against
+    /// the current index of the name.
+    fn retried<T>(&self, getter: impl Fn(&IpQuery<'_>) -> Result<T>) -> Result<T> {
+        let err = match getter(&self.query()) {
+            Err(err) => err,
+            result => return result,
+        };
+        let cached = self.index();
+
+        match if_index(&self.0.name) {
+            Ok(index) if index != cached => {
+                self.0.index.store(index, Ordering::Relaxed);
+                getter(&self.query())
+            }
+            // Neither the name nor the cached index exist anymore.
+            Err(e) if if_name(cached).is_none() => Err(e),
+            // The interface is the same or was renamed.
+            _ => Err(err),
+        }
+    }
+
+    /// Get the (preferred outgoing) IPv6 link-local address
+    /// of the interface.
+    pub fn ipv6_unicast_link_local(&self) -> Result<Ipv6Addr> {
+        self.retried(|query| query.ipv6_unicast_link_local())
+    }
+
+    /// Get the preferred outgoing IPv6 ULA of the interface.
+    pub fn ipv6_unique_local(&self) -> Result<Ipv6Addr> {
+        self.retried(|query| query.ipv6_unique_local())
+    }
+
+    /// Get the preferred outgoing IPv6 GUA of the interface.
+    pub fn ipv6_unicast_global(&self) -> Result<Ipv6Addr> {
+        self.retried(|query| query.ipv6_unicast_global())
+    }
+
+    /// Get the (preferred outgoing) IPv4 link-local address
+    /// of the interface.
+    pub fn ipv4_link_local(&self) -> Result<Ipv4Addr> {
+        self.retried(|query| query.ipv4_link_local())
+    }
+
+    /// Get the preferred outgoing IPv4 private address
+    /// of the interface.
+    pub fn ipv4_private(&self) -> Result<Ipv4Addr> {
+        self.retried(|query| query.ipv4_private())
+    }
+
+    /// Get the preferred outgoing IPv4 global address
+    /// of the interface.
+    pub fn ipv4_global(&self) -> Result<Ipv4Addr> {
+        self.retried(|query| query.ipv4_global())
+    }
+
+    /// Get the preferred outgoing address of the given scope.
+    pub fn get(&self, scope: Scope) -> Result<IpAddr> {
+        self.retried(|query| query.get(scope))
+    }
+
+    /// Bind a new UDP socket to the preferred outgoing address
+    /// of the given scope on the interface and the given port.
+    /// See [`IpQuery::bind_udp`] for details.
+    pub fn bind_udp(&self, scope: Scope, port: u16) -> Result<UdpSocket> {
+        self.retried(|query| query.bind_udp(scope, port))
+    }
+
+    /// Bind a new TCP listener to the preferred outgoing address
+    /// of the given scope on the interface and the given port.
+    /// See [`IpQuery::bind_tcp_listener`] for details.
+    pub fn bind_tcp_listener(&self, scope: Scope, port: u16, backlog: i32) -> Result<TcpListener> {
+        self.retried(|query| query.bind_tcp_listener(scope, port, backlog))
+    }
+}
+
+impl fmt::Debug for InterfaceHandle {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("InterfaceHandle")
+            .field("name", &self.0.name)
+            .field("index", &self.index())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::io;
+
+    use crate::Error;
+
+    fn enodev() -> Error {
+        Error::IoError(io::Error::from_raw_os_error(libc::ENODEV).into())
+    }
+
+    fn handle(name: &str, index: u32) -> InterfaceHandle {
+        InterfaceHandle(Arc::new(Inner {
+            name: name.into(),
+            index: AtomicU32::new(index),
+        }))
+    }
+
+    #[test]
+    fn open_fails_fast() {
+        let err = InterfaceHandle::open("nonexistent0").unwrap_err();
+        assert!(
+            matches!(&err, Error::IoError(e) if e.raw_os_error() == Some(libc::ENODEV)),
+            "{:?}",
+            err
+        );
+
+        let err = InterfaceHandle::open("lo\0").unwrap_err();
+        assert!(
+            matches!(&err, Error::IoError(e) if e.kind() == io::ErrorKind::InvalidInput),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn open_caches_index() {
+        let lo = InterfaceHandle::open("lo").unwrap();
+        assert_eq!(lo.name(), "lo");
+        assert_eq!(lo.index(), if_index("lo").unwrap());
+        assert_eq!(lo.query().if_index, NonZeroU32::new(lo.index()));
+        assert_eq!(
+            format!("{:?}", lo),
+            format!("InterfaceHandle {{ name: \"lo\", index: {} }}", lo.index())
+        );
+    }
+
+    #[test]
+    fn clones_share_the_cache() {
+        let lo = handle("lo", 9999);
+        let clone = lo.clone();
+
+        clone.retried(|_| Err::<(), _>(enodev())).unwrap_err();
+        assert_eq!(lo.index(), if_index("lo").unwrap());
+    }
+
+    #[test]
+    fn stale_index_is_resolved_again() {
+        let lo = handle("lo", 9999);
+        let seen = RefCell::new(Vec::new());
+
+        let result = lo.retried(|query| {
+            seen.borrow_mut().push(query.if_index.map(NonZeroU32::get));
+            match seen.borrow().len() {
+                1 => Err(enodev()),
+                _ => Ok(()),
+            }
+        });
+
+        let index = if_index("lo").unwrap();
+        assert!(result.is_ok());
+        assert_eq!(*seen.borrow(), [Some(9999), Some(index)]);
+        assert_eq!(lo.index(), index);
+    }
+
+    #[test]
+    fn retries_once() {
+        let lo = handle("lo", 9999);
+        let calls = RefCell::new(0);
+
+        let result = lo.retried(|_| {
+            *calls.borrow_mut() += 1;
+            Err::<(), _>(enodev())
+        });
+
+        assert!(matches!(result, Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::ENODEV)));
+        assert_eq!(*calls.borrow(), 2);
+    }
+
+    #[test]
+    fn vanished_interface_fails_with_enodev() {
+        let gone = handle("nonexistent0", 9999);
+        let calls = RefCell::new(0);
+
+        let result = gone.retried(|_| {
+            *calls.borrow_mut() += 1;
+            Err::<(), _>(Error::IoError(
+                io::Error::from_raw_os_error(libc::ENETUNREACH).into(),
+            ))
+        });
+
+        assert!(matches!(result, Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::ENODEV)));
+        assert_eq!(*calls.borrow(), 1);
+        assert_eq!(gone.index(), 9999);
+    }
+
+    #[test]
+    fn errors_of_current_index_are_not_retried() {
+        let lo = handle("lo", if_index("lo").unwrap());
+        let calls = RefCell::new(0);
+
+        let result = lo.retried(|_| {
+            *calls.borrow_mut() += 1;
+            Err::<(), _>(Error::IoError(
+                io::Error::from_raw_os_error(libc::ENETUNREACH).into(),
+            ))
+        });
+
+        assert!(
+            matches!(result, Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::ENETUNREACH))
+        );
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn renamed_interface_keeps_the_error() {
+        // The name is gone, but the cached index still exists.
+        let renamed = handle("nonexistent0", if_index("lo").unwrap());
+        let calls = RefCell::new(0);
+
+        let result = renamed.retried(|_| {
+            *calls.borrow_mut() += 1;
+            Err::<(), _>(Error::IoError(
+                io::Error::from_raw_os_error(libc::ENETUNREACH).into(),
+            ))
+        });
+
+        assert!(
+            matches!(result, Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::ENETUNREACH))
+        );
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn handles_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<InterfaceHandle>();
+    }
+}
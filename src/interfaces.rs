@@ -0,0 +1,331 @@
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::netlink::Netlink;
+use crate::{if_index, observe, AddressReport, IpQuery, Result};
+
+/// A network interface as reported by the kernel.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Interface {
+    pub index: u32,
+    pub name: String,
+    /// The `IFF_*` flags of the interface.
+    pub flags: u32,
+    /// The kind of virtual interfaces, e.g. `vlan`, `bridge` or `veth`.
+    /// `None` for physical interfaces and loopback.
+    pub kind: Option<String>,
+    /// How the IPv6 link-local and SLAAC addresses of the interface
+    /// are generated, `None` if the kernel doesn't report it,
+    /// e.g. if it has no IPv6 support.
+    pub ipv6_addr_gen_mode: Option<AddrGenMode>,
+}
+
+/// How the kernel generates the interface identifiers of the IPv6
+/// link-local and SLAAC addresses of an interface,
+/// as configured by the `addr_gen_mode` sysctl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddrGenMode {
+    /// Derived from the MAC address (modified EUI-64).
+    Eui64,
+    /// No addresses are generated.
+    None,
+    /// Stable privacy addresses derived from `stable_secret` (RFC 7217).
+    StablePrivacy,
+    /// Like [`AddrGenMode::StablePrivacy`], but with a random secret.
+    Random,
+    /// A mode this crate doesn't know about.
+    Unknown(u8),
+}
+
+impl From<u8> for AddrGenMode {
+    fn from(mode: u8) -> Self {
+        match mode {
+            0 => Self::Eui64,
+            1 => Self::None,
+            2 => Self::StablePrivacy,
+            3 => Self::Random,
+            mode => Self::Unknown(mode),
+        }
+    }
+}
+
+impl Interface {
+    /// Report whether the interface is administratively up.
+    pub fn is_up(&self) -> bool {
+        self.flags & libc::IFF_UP as u32 != 0
+    }
+
+    /// Report whether the interface is operationally up.
+    pub fn is_running(&self) -> bool {
+        self.flags & libc::IFF_RUNNING as u32 != 0
+    }
+
+    /// Report whether this is a loopback interface.
+    pub fn is_loopback(&self) -> bool {
+        self.flags & libc::IFF_LOOPBACK as u32 != 0
+    }
+
+    /// Report whether this is a virtual interface of a known kind.
+    pub fn is_virtual(&self) -> bool {
+        self.kind.is_some()
+    }
+}
+
+/// Get the network interfaces, ordered by index.
+pub fn interfaces() -> Result<Vec<Interface>> {
+    let mut interfaces: Vec<_> = Netlink::open()?
+        .links()?
+        .into_iter()
+        .filter_map(|link| {
+            Some(Interface {
+                index: link.index,
+                name: link.name?,
+                flags: link.flags,
+                kind: link.kind,
+                ipv6_addr_gen_mode: link.addr_gen_mode.map(AddrGenMode::from),
+            })
+        })
+        .collect();
+
+    interfaces.sort_by_key(|interface| interface.index);
+    Ok(interfaces)
+}
+
+impl IpQuery<'_> {
+    /// Get the [`AddressReport`] of every interface, ordered by index.
+    /// The interface of the query is ignored, all other options apply.
+    /// See [`IpQuery::all_preferred_with`].
+    pub fn all_preferred(&self) -> Result<Vec<(Interface, Result<AddressReport>)>> {
+        self.all_preferred_with(|_| true, |_, _, _, _| {})
+    }
+
+    /// Like [`IpQuery::all_preferred`], but only probe the interfaces
+    /// `filter` accepts, e.g. to skip virtual or down interfaces,
+    /// and report progress.
+    ///
+    /// The interfaces are probed concurrently by a few worker threads.
+    /// `progress` is called on the worker thread as each interface
+    /// completes, with the number of completed and accepted interfaces,
+    /// the name of the interface and its result. If it panics, the panic
+    /// is reported by the panic hook as usual and otherwise ignored.
+    pub fn all_preferred_with(
+        &self,
+        filter: impl Fn(&Interface) -> bool,
+        progress: impl Fn(usize, usize, &str, &Result<AddressReport>) + Sync,
+    ) -> Result<Vec<(Interface, Result<AddressReport>)>> {
+        let interfaces: Vec<_> = interfaces()?.into_iter().filter(filter).collect();
+
+        let total = interfaces.len();
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(total);
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let results = Mutex::new((0..total).map(|_| None).collect::<Vec<_>>());
+
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(interface) = interfaces.get(i) else {
+                        break;
+                    };
+
+                    let query = IpQuery {
+                        interface: Some(&interface.name),
+                        if_index: None,
+                        ..self.clone()
+                    };
+                    let report = query.get_all();
+
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    observe::isolate(|| progress(done, total, &interface.name, &report));
+
+                    results.lock().unwrap()[i] = Some(report);
+                });
+            }
+        });
+
+        let results = results.into_inner().unwrap();
+        Ok(interfaces
+            .into_iter()
+            .zip(results)
+            .map(|(interface, report)| (interface, report.unwrap()))
+            .collect())
+    }
+}
+
+/// Get the [`AddressReport`] of every interface.
+/// See [`IpQuery::all_preferred`] for details.
+pub fn all_preferred() -> Result<Vec<(Interface, Result<AddressReport>)>> {
+    IpQuery::any_interface().all_preferred()
+}
+
+/// Get the [`AddressReport`] of the interfaces `filter` accepts,
+/// reporting progress. See [`IpQuery::all_preferred_with`] for details.
+pub fn all_preferred_with(
+    filter: impl Fn(&Interface) -> bool,
+    progress: impl Fn(usize, usize, &str, &Result<AddressReport>) + Sync,
+) -> Result<Vec<(Interface, Result<AddressReport>)>> {
+    IpQuery::any_interface().all_preferred_with(filter, progress)
+}
+
+/// Get the IPv6 address generation mode of the given interface.
+/// Fails with [`io::ErrorKind::Unsupported`] if the kernel
+/// doesn't report it, e.g. if it has no IPv6 support.
+pub fn ipv6_addr_gen_mode(interface: &str) -> Result<AddrGenMode> {
+    let index = if_index(interface)?;
+
+    interfaces()?
+        .into_iter()
+        .find(|link| link.index == index)
+        .and_then(|link| link.ipv6_addr_gen_mode)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ipv6 address generation mode not reported",
+            )
+            .into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::netlink::{self, Link};
+
+    const UP: u32 = libc::IFF_UP as u32;
+
+    /// Let the fake kernel dump `mock0` to `mock9` on the next
+    /// enumeration, every other one up. None of them exists,
+    /// so probing them fails quickly.
+    fn mock_links() -> thread::JoinHandle<usize> {
+        netlink::mock::dump((0..10).map(|n| {
+            let link = Link {
+                index: 1000 + n,
+                flags: if n % 2 == 0 { UP } else { 0 },
+                name: Some(format!("mock{}", n)),
+                ..Default::default()
+            };
+            (netlink::RTM_NEWLINK, link.to_payload())
+        }))
+    }
+
+    #[test]
+    fn progress_is_reported_once_per_interface() {
+        let kernel = mock_links();
+        let calls = Mutex::new(Vec::new());
+
+        let results = IpQuery::any_interface()
+            .all_preferred_with(
+                |_| true,
+                |done, total, interface, report| {
+                    calls
+                        .lock()
+                        .unwrap()
+                        .push((done, total, interface.to_owned(), report.is_ok()));
+                },
+            )
+            .unwrap();
+        kernel.join().unwrap();
+
+        let calls = calls.into_inner().unwrap();
+        assert_eq!(calls.len(), 10);
+        assert!(calls.iter().all(|&(_, total, ..)| total == 10));
+        let done: BTreeSet<_> = calls.iter().map(|&(done, ..)| done).collect();
+        assert_eq!(done, (1..=10).collect());
+        let names: BTreeSet<_> = calls.iter().map(|(_, _, name, _)| name.clone()).collect();
+        assert_eq!(names, (0..10).map(|n| format!("mock{}", n)).collect());
+
+        // The results are in interface order and agree with the progress.
+        let indexes: Vec<_> = results
+            .iter()
+            .map(|(interface, _)| interface.index)
+            .collect();
+        assert_eq!(indexes, (1000..1010).collect::<Vec<_>>());
+        for (interface, report) in &results {
+            let (.., ok) = calls
+                .iter()
+                .find(|(_, _, name, _)| *name == interface.name)
+                .unwrap();
+            assert_eq!(report.is_ok(), *ok, "{}", interface.name);
+        }
+    }
+
+    #[test]
+    fn filtered_interfaces_are_not_probed() {
+        let kernel = mock_links();
+        let calls = Mutex::new(Vec::new());
+
+        let results = IpQuery::any_interface()
+            .all_preferred_with(Interface::is_up, |done, total, interface, _| {
+                calls
+                    .lock()
+                    .unwrap()
+                    .push((done, total, interface.to_owned()));
+            })
+            .unwrap();
+        kernel.join().unwrap();
+
+        let names: Vec<_> = results
+            .iter()
+            .map(|(interface, _)| &interface.name[..])
+            .collect();
+        assert_eq!(names, ["mock0", "mock2", "mock4", "mock6", "mock8"]);
+
+        let mut calls = calls.into_inner().unwrap();
+        calls.sort();
+        assert_eq!(calls.len(), 5);
+        assert!(calls
+            .iter()
+            .all(|(_, total, name)| *total == 5 && names.contains(&&name[..])));
+    }
+
+    #[test]
+    fn nothing_accepted() {
+        let kernel = mock_links();
+        let calls = AtomicUsize::new(0);
+
+        let results = IpQuery::any_interface()
+            .all_preferred_with(
+                |_| false,
+                |_, _, _, _| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                },
+            )
+            .unwrap();
+        kernel.join().unwrap();
+
+        assert!(results.is_empty());
+        assert_eq!(calls.into_inner(), 0);
+    }
+
+    #[test]
+    fn panicking_progress_doesnt_poison_the_result() {
+        let kernel = mock_links();
+        let calls = AtomicUsize::new(0);
+
+        let results = IpQuery::any_interface()
+            .all_preferred_with(
+                |_| true,
+                |done, _, _, _| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    if done % 3 == 0 {
+                        panic!("progress panicked");
+                    }
+                },
+            )
+            .unwrap();
+        kernel.join().unwrap();
+
+        // Every interface still completes and is reported.
+        assert_eq!(calls.into_inner(), 10);
+        assert_eq!(results.len(), 10);
+    }
+}
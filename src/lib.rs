@@ -1,25 +1,546 @@
 #![feature(ip)]
 
+use std::ffi::CString;
 use std::fmt;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::net::{ToSocketAddrs, UdpSocket};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Ipv6MulticastScope};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, UdpSocket};
+use std::num::NonZeroU32;
+use std::ops::ControlFlow;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use socket2::{Domain, Socket, Type};
 
+mod addrs;
+mod bind;
+mod broadcast;
+mod cache;
+mod clock;
+pub mod compat;
+#[cfg(feature = "serde")]
+mod config;
+mod dest;
+mod diagnostic;
+#[cfg(feature = "dns64")]
+mod dns64;
+mod explain;
+mod factory;
+#[cfg(feature = "uniffi")]
+pub mod ffi;
+mod handle;
+mod interfaces;
+mod name;
+#[cfg(feature = "ipnet")]
+mod net;
+mod netlink;
+#[cfg(feature = "networkmanager")]
+pub mod networkmanager;
+mod observe;
+mod plan;
+mod privacy;
+mod procfs;
+mod ranges;
+mod rank;
+mod resolv;
+mod route;
+mod spec;
+mod stats;
+#[cfg(all(any(test, feature = "test-support"), target_os = "linux"))]
+pub mod test_support;
+mod v6mostly;
+mod watch;
+
+pub use addrs::{
+    address_owners, has_ipv4_global, has_ipv4_link_local, has_ipv4_private,
+    has_ipv6_unicast_global, has_ipv6_unicast_link_local, has_ipv6_unique_local,
+    interface_addresses, ipv4_by_label, ipv4_in_subnet, ipv6_in_prefix,
+    ipv6_unicast_link_local_all, verify_ipv6_within, InterfaceAddr, IpPrefix, PrefixVerdict,
+    ScopedIpv6Addr, MAX_ADDRESSES,
+};
+pub use bind::ToBindAddr;
+pub use broadcast::ipv4_broadcast_source;
+pub use cache::{validate_source, CachedQuery};
+pub use clock::{Clock, MonotonicClock};
+#[cfg(feature = "serde")]
+pub use config::ProbeConfig;
+pub use dest::{confirm_source_route, preferred_source_for, preferred_sources_for, Destination};
+pub use diagnostic::{get_all_diagnostic, DiagnosticReport, ProbeDiagnostic, ScopeDiagnostic};
+#[cfg(feature = "dns64")]
+pub use dns64::{detect_dns64, nat64_prefix};
+pub use explain::{Explanation, Rank, Step, Verdict};
+pub use factory::{ProvidedSocket, SocketFactory};
+pub use handle::InterfaceHandle;
+pub use interfaces::{
+    all_preferred, all_preferred_with, interfaces, ipv6_addr_gen_mode, AddrGenMode, Interface,
+};
+pub use name::{sanitize_interface_name, InterfaceName};
+pub use observe::{set_global_observer, FallbackKind, ProbeObserver};
+pub use plan::{PlannedProbe, ProbePlan, SocketPlan};
+pub use privacy::{ipv6_privacy_policy, PrivacyPolicy};
+pub use ranges::{range_kind, RangeKind, SPECIAL_RANGES_V4, SPECIAL_RANGES_V6};
+pub use rank::{address_labels, rank_sources};
+pub use resolv::{parse_resolv_conf, preferred_source_for_dns};
+pub use route::{
+    default_routes, detect_multipath, preferred_source_via, rank_interfaces, DefaultRoute,
+    InterfaceRanking, MultipathReport,
+};
+pub use spec::get_by_spec;
+pub use stats::{ScopeStats, StatsSnapshot};
+pub use v6mostly::{ipv4_suppressed, V6MostlySignals};
+pub use watch::{wait_for_ipv6_global_change, ChangeOutcome, WatchEvent, Watcher};
+
+/// The socket library used by [`SocketFactory`].
+pub use socket2;
+
+/// The prefix types accepted and returned with the `ipnet` feature.
+#[cfg(feature = "ipnet")]
+pub use ipnet;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+const PROBE_IPV6_LINK_LOCAL: SocketAddr = probe_v6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0));
+const PROBE_IPV6_UNIQUE_LOCAL: SocketAddr = probe_v6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0));
+const PROBE_IPV6_GLOBAL: SocketAddr = probe_v6(Ipv6Addr::new(0x2000, 0, 0, 0, 0, 0, 0, 0));
+const PROBE_IPV6_LOOPBACK: SocketAddr = probe_v6(Ipv6Addr::LOCALHOST);
+const PROBE_IPV4_LINK_LOCAL: SocketAddr = probe_v4(Ipv4Addr::new(169, 254, 0, 0));
+const PROBE_IPV4_PRIVATE: [SocketAddr; 3] = [
+    probe_v4(Ipv4Addr::new(10, 0, 0, 0)),
+    probe_v4(Ipv4Addr::new(172, 16, 0, 0)),
+    probe_v4(Ipv4Addr::new(192, 168, 0, 0)),
+];
+const PROBE_IPV4_GLOBAL: SocketAddr = probe_v4(Ipv4Addr::UNSPECIFIED);
+const PROBE_IPV4_LOOPBACK: SocketAddr = probe_v4(Ipv4Addr::LOCALHOST);
+
+const fn probe_v6(ip: Ipv6Addr) -> SocketAddr {
+    SocketAddr::V6(SocketAddrV6::new(ip, 0, 0, 0))
+}
+
+const fn probe_v4(ip: Ipv4Addr) -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(ip, 0))
+}
+
+/// An IP protocol version. IPv4 is ordered before IPv6.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    fn of(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => Self::V4,
+            IpAddr::V6(_) => Self::V6,
+        }
+    }
+
+    fn unspecified(self) -> IpAddr {
+        match self {
+            Self::V4 => Ipv4Addr::UNSPECIFIED.into(),
+            Self::V6 => Ipv6Addr::UNSPECIFIED.into(),
+        }
+    }
+}
+
+impl fmt::Display for IpVersion {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V4 => write!(fmt, "IPv4"),
+            Self::V6 => write!(fmt, "IPv6"),
+        }
+    }
+}
+
+/// The IPv6 address scopes that can be queried.
+///
+/// Scopes are ordered from the narrowest to the widest,
+/// followed by loopback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Ipv6Scope {
+    UnicastLinkLocal,
+    UniqueLocal,
+    UnicastGlobal,
+    /// `::1`, which is only found on loopback interfaces.
+    Loopback,
+}
+
+/// The IPv4 address scopes that can be queried,
+/// ordered like [`Ipv6Scope`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Ipv4Scope {
+    LinkLocal,
+    Private,
+    Global,
+    /// `127.0.0.0/8`, which is only found on loopback interfaces.
+    Loopback,
+}
+
+impl Ipv6Scope {
+    /// The IPv4 scope that serves the same purpose,
+    /// treating ULAs like private addresses.
+    fn ipv4_counterpart(self) -> Ipv4Scope {
+        match self {
+            Self::UnicastLinkLocal => Ipv4Scope::LinkLocal,
+            Self::UniqueLocal => Ipv4Scope::Private,
+            Self::UnicastGlobal => Ipv4Scope::Global,
+            Self::Loopback => Ipv4Scope::Loopback,
+        }
+    }
+}
+
+/// An address scope of either IP version.
+///
+/// IPv6 scopes are ordered before IPv4 scopes,
+/// which is the order [`IpQuery::get_all`] probes them in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    V6(Ipv6Scope),
+    V4(Ipv4Scope),
+}
+
+impl Scope {
+    fn version(self) -> IpVersion {
+        match self {
+            Self::V6(_) => IpVersion::V6,
+            Self::V4(_) => IpVersion::V4,
+        }
+    }
+
+    /// Get the destinations that are probed for this scope.
+    fn probe_dests(self) -> &'static [SocketAddr] {
+        match self {
+            Self::V6(Ipv6Scope::UnicastLinkLocal) => &[PROBE_IPV6_LINK_LOCAL],
+            Self::V6(Ipv6Scope::UniqueLocal) => &[PROBE_IPV6_UNIQUE_LOCAL],
+            Self::V6(Ipv6Scope::UnicastGlobal) => &[PROBE_IPV6_GLOBAL],
+            Self::V6(Ipv6Scope::Loopback) => &[PROBE_IPV6_LOOPBACK],
+            Self::V4(Ipv4Scope::LinkLocal) => &[PROBE_IPV4_LINK_LOCAL],
+            Self::V4(Ipv4Scope::Private) => &PROBE_IPV4_PRIVATE,
+            Self::V4(Ipv4Scope::Global) => &[PROBE_IPV4_GLOBAL],
+            Self::V4(Ipv4Scope::Loopback) => &[PROBE_IPV4_LOOPBACK],
+        }
+    }
+
+    /// Report whether the address is of this scope,
+    /// by the most specific special-purpose range it is within.
+    ///
+    /// Global addresses are those outside the ranges that aren't
+    /// globally reachable, like [`Ipv4Addr::is_global`] and
+    /// [`Ipv6Addr::is_unicast_global`]. IPv6 translation and tunneling
+    /// ranges such as NAT64 and 6to4 are global.
+    fn contains(self, ip: &IpAddr) -> bool {
+        use RangeKind::*;
+
+        let kind = range_kind(*ip);
+        match (self, ip) {
+            (Self::V6(Ipv6Scope::UnicastLinkLocal), IpAddr::V6(_))
+            | (Self::V4(Ipv4Scope::LinkLocal), IpAddr::V4(_)) => kind == Some(LinkLocal),
+            (Self::V6(Ipv6Scope::UniqueLocal), IpAddr::V6(_)) => kind == Some(UniqueLocal),
+            (Self::V6(Ipv6Scope::UnicastGlobal), IpAddr::V6(_)) => !matches!(
+                kind,
+                Some(
+                    Unspecified
+                        | Loopback
+                        | LinkLocal
+                        | UniqueLocal
+                        | Documentation
+                        | Benchmarking
+                        | Multicast
+                )
+            ),
+            (Self::V6(Ipv6Scope::Loopback), IpAddr::V6(_))
+            | (Self::V4(Ipv4Scope::Loopback), IpAddr::V4(_)) => kind == Some(Loopback),
+            (Self::V4(Ipv4Scope::Private), IpAddr::V4(_)) => kind == Some(Private),
+            (Self::V4(Ipv4Scope::Global), IpAddr::V4(_)) => !matches!(
+                kind,
+                Some(
+                    Unspecified
+                        | Private
+                        | SharedAddressSpace
+                        | Loopback
+                        | LinkLocal
+                        | ProtocolAssignments
+                        | ServiceContinuity
+                        | DummyIpv4
+                        | Nat64Discovery
+                        | Documentation
+                        | Benchmarking
+                        | Reserved
+                        | Broadcast
+                )
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl From<Ipv6Scope> for Scope {
+    fn from(scope: Ipv6Scope) -> Self {
+        Self::V6(scope)
+    }
+}
+
+impl From<Ipv4Scope> for Scope {
+    fn from(scope: Ipv4Scope) -> Self {
+        Self::V4(scope)
+    }
+}
+
+/// The transport protocol of the probe sockets.
+///
+/// Policy routing can select routes by protocol,
+/// so the preferred source address may differ between them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ProbeProtocol {
+    /// Connect a datagram socket. This is the default.
+    #[default]
+    Udp,
+    /// Start a non-blocking connect on a stream socket.
+    /// The socket is closed before the handshake completes.
+    Tcp,
+}
+
+/// A preference for the kind of IPv6 source address (RFC 5014),
+/// set via `IPV6_ADDR_PREFERENCES` on the probe sockets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum SourcePreference {
+    /// Prefer temporary (privacy) addresses.
+    Temporary,
+    /// Prefer stable (public) addresses.
+    Public,
+    /// Prefer the Mobile IPv6 care-of address. Most kernels reject this
+    /// unless they were built with Mobile IPv6 support.
+    CareOf,
+    /// Prefer the Mobile IPv6 home address.
+    Home,
+}
+
+impl SourcePreference {
+    /// The `IPV6_PREFER_SRC_*` flag of the preference.
+    fn flag(self) -> u32 {
+        let flag = match self {
+            Self::Temporary => libc::IPV6_PREFER_SRC_TMP,
+            Self::Public => libc::IPV6_PREFER_SRC_PUBLIC,
+            Self::CareOf => libc::IPV6_PREFER_SRC_COA,
+            Self::Home => libc::IPV6_PREFER_SRC_HOME,
+        };
+
+        flag as u32
+    }
+
+    /// The `IPV6_ADDR_PREFERENCES` value of the given preferences.
+    fn flags(preferences: &[Self]) -> u32 {
+        preferences
+            .iter()
+            .fold(0, |flags, preference| flags | preference.flag())
+    }
+
+    /// The preference that is mutually exclusive with this one.
+    fn opposite(self) -> Self {
+        match self {
+            Self::Temporary => Self::Public,
+            Self::Public => Self::Temporary,
+            Self::CareOf => Self::Home,
+            Self::Home => Self::CareOf,
+        }
+    }
+}
+
+/// How to treat IPv6 addresses that are still undergoing optimistic
+/// duplicate address detection (RFC 4429).
+///
+/// Optimistic addresses are intentionally usable before DAD completes,
+/// so the kernel may choose them as the source address.
+/// Addresses that failed DAD are never used regardless of the policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OptimisticDad {
+    /// Fall back to another usable address of the requested scope
+    /// if the kernel chose an optimistic one.
+    Reject,
+    /// Use optimistic addresses like any other.
+    Accept,
+    /// Use optimistic addresses and report them in
+    /// [`Lenient::optimistic`]. This is the default.
+    #[default]
+    AcceptAndFlag,
+}
+
+/// The ways of obtaining address information.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Backend {
+    /// Connect a socket bound to the interface and read its local address.
+    Socket,
+    /// Query the addresses NetworkManager has configured over D-Bus.
+    /// The kernel's preference isn't known to NetworkManager,
+    /// so the first address of the requested scope is used.
+    #[cfg(feature = "networkmanager")]
+    NetworkManager,
+    /// Parse `/proc/net`. This is a last resort of lower fidelity:
+    /// The kernel's preference isn't known, so the lowest
+    /// usable address of the requested scope is used,
+    /// and IPv4 addresses are only found if they have a connected route.
+    Procfs,
+}
+
+impl Backend {
+    /// The fallback that is reported when this backend is tried
+    /// after another one failed.
+    fn fallback_kind(self) -> Option<FallbackKind> {
+        match self {
+            Self::Socket => None,
+            #[cfg(feature = "networkmanager")]
+            Self::NetworkManager => Some(FallbackKind::NetworkManager),
+            Self::Procfs => Some(FallbackKind::Procfs),
+        }
+    }
+
+    /// Report whether the next backend is tried after this one
+    /// failed with the error. Probing falls back if it isn't permitted.
+    fn falls_back_on(self, err: &Error) -> bool {
+        match self {
+            Self::Socket => match err {
+                Error::IoError(e) => e.kind() == io::ErrorKind::PermissionDenied,
+                Error::SandboxRestricted { .. } => true,
+                _ => false,
+            },
+            #[cfg(feature = "networkmanager")]
+            Self::NetworkManager => true,
+            Self::Procfs => false,
+        }
+    }
+}
+
 /// The errors that can occur when trying to get IP address information.
-#[derive(Debug)]
+///
+/// Errors can be cloned, e.g. to hand the result of a shared probe
+/// to every waiting caller, so the I/O errors are reference counted.
+#[derive(Clone, Debug)]
 pub enum Error {
-    IoError(std::io::Error),
-    WrongIpVer(String, IpAddr),
+    IoError(Arc<io::Error>),
+    WrongIpVer(IpVersion, IpAddr),
     NoLinkLocal(Ipv6Addr),
     NoUla(Ipv6Addr),
     NoGua(Ipv6Addr),
     NoV4LL(Ipv4Addr),
     NoPrivate(Ipv4Addr, Ipv4Addr, Ipv4Addr),
     NoGlobal(Ipv4Addr),
+    NoLoopback(IpAddr),
+    LoopbackInterface {
+        interface: String,
+        scope: Scope,
+    },
+    Timeout {
+        interface: Option<String>,
+        operation: &'static str,
+    },
+    NoAddress {
+        interface: Option<String>,
+        family: IpVersion,
+    },
+    NoRoute {
+        interface: Option<String>,
+        dest: IpAddr,
+    },
+    AddrInUse {
+        interface: Option<String>,
+        addr: SocketAddr,
+    },
+    AddrNotAvailable {
+        interface: Option<String>,
+        addr: IpAddr,
+    },
+    SourceNotAssigned {
+        interface: Option<String>,
+        source: IpAddr,
+    },
+    NoSourceRoute {
+        interface: Option<String>,
+        source: IpAddr,
+        dest: IpAddr,
+    },
+    Prohibited {
+        interface: Option<String>,
+        source: IpAddr,
+        dest: IpAddr,
+    },
+    ForeignSource {
+        interface: Option<String>,
+        addr: IpAddr,
+        owner: Option<String>,
+    },
+    NotMulticast(IpAddr),
+    NoBroadcast {
+        interface: Option<String>,
+    },
+    ShortLifetime {
+        interface: Option<String>,
+        addr: IpAddr,
+        remaining: Duration,
+    },
+    NoNameservers,
+    NoScopes,
+    GotMappedV4 {
+        mapped: Ipv4Addr,
+    },
+    SocketFactory(Arc<io::Error>),
+    SandboxRestricted {
+        syscall: &'static str,
+        source: Arc<io::Error>,
+    },
+    FamilyDisabled(IpVersion),
+    TooManyAddresses {
+        interface: Option<String>,
+        limit: usize,
+    },
+    InvalidSpec {
+        spec: String,
+        token: String,
+        expected: &'static [&'static str],
+    },
+    InvalidDestination(String),
+    InvalidInterfaceName {
+        name: String,
+        reason: &'static str,
+    },
+    NoZone(Ipv6Addr),
+    ZoneMismatch {
+        interface: String,
+        scope_id: u32,
+    },
+    NoLabel {
+        interface: Option<String>,
+        label: String,
+        available: Vec<String>,
+    },
+    SourcePreferenceRejected {
+        interface: Option<String>,
+        preferences: Vec<SourcePreference>,
+    },
+    #[cfg(feature = "serde")]
+    InvalidConfig(Vec<String>),
+    #[cfg(feature = "networkmanager")]
+    NetworkManager(zbus::Error),
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IoError(e) | Self::SocketFactory(e) => Some(&**e),
+            Self::SandboxRestricted { source, .. } => Some(&**source),
+            #[cfg(feature = "networkmanager")]
+            Self::NetworkManager(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -44,123 +565,2632 @@ impl fmt::Display for Error {
             Self::NoGlobal(ip) => {
                 write!(fmt, "ipv4 address {} is not a global address", ip)
             }
+            Self::NoLoopback(ip) => write!(fmt, "{} is not a loopback address", ip),
+            Self::LoopbackInterface { interface, scope } => match scope {
+                Scope::V6(scope) => write!(
+                    fmt,
+                    "loopback interface {} has no ipv6 {} address",
+                    interface, scope
+                ),
+                Scope::V4(scope) => write!(
+                    fmt,
+                    "loopback interface {} has no ipv4 {} address",
+                    interface, scope
+                ),
+            },
+            Self::Timeout {
+                interface,
+                operation,
+            } => write!(fmt, "{} on {} timed out", operation, On(interface)),
+            Self::NoAddress { interface, family } => {
+                write!(fmt, "no usable {} address on {}", family, On(interface))
+            }
+            Self::NoRoute { interface, dest } => write!(
+                fmt,
+                "no route towards probe network {} on {}",
+                dest,
+                On(interface)
+            ),
+            Self::AddrInUse { interface, addr } => {
+                write!(fmt, "{} is already in use on {}", addr, On(interface))
+            }
+            Self::AddrNotAvailable { interface, addr } => write!(
+                fmt,
+                "address {} is no longer available on {}",
+                addr,
+                On(interface)
+            ),
+            Self::SourceNotAssigned { interface, source } => write!(
+                fmt,
+                "source address {} is not assigned on {}",
+                source,
+                On(interface)
+            ),
+            Self::NoSourceRoute {
+                interface,
+                source,
+                dest,
+            } => write!(
+                fmt,
+                "no route towards {} from {} on {}",
+                dest,
+                source,
+                On(interface)
+            ),
+            Self::Prohibited {
+                interface,
+                source,
+                dest,
+            } => write!(
+                fmt,
+                "sending to {} from {} on {} is prohibited",
+                dest,
+                source,
+                On(interface)
+            ),
+            Self::ForeignSource {
+                interface,
+                addr,
+                owner: Some(owner),
+            } => write!(
+                fmt,
+                "source address {} chosen on {} belongs to interface {}",
+                addr,
+                On(interface),
+                owner
+            ),
+            Self::ForeignSource {
+                interface,
+                addr,
+                owner: None,
+            } => write!(
+                fmt,
+                "source address {} chosen on {} isn't assigned to any interface",
+                addr,
+                On(interface)
+            ),
+            Self::NotMulticast(ip) => {
+                write!(fmt, "{} is not a multicast address", ip)
+            }
+            Self::NoBroadcast { interface } => {
+                write!(fmt, "no ipv4 broadcast address on {}", On(interface))
+            }
+            Self::ShortLifetime {
+                interface,
+                addr,
+                remaining,
+            } => write!(
+                fmt,
+                "no address with a long enough preferred lifetime on {}, {} is only preferred for {:?}",
+                On(interface),
+                addr,
+                remaining
+            ),
+            Self::NoNameservers => write!(fmt, "no nameservers are configured"),
+            Self::NoScopes => write!(fmt, "no scopes to try were given"),
+            Self::GotMappedV4 { mapped } => write!(
+                fmt,
+                "ipv6 probe returned ipv4-mapped address of {}, use the ipv4 getters",
+                mapped
+            ),
+            Self::SocketFactory(e) => write!(fmt, "socket factory failed: {}", e),
+            Self::SandboxRestricted { syscall, source } => write!(
+                fmt,
+                "{}() is restricted, e.g. by a seccomp sandbox: {}, \
+                 consider the procfs backend",
+                syscall, source
+            ),
+            Self::FamilyDisabled(family) => {
+                write!(fmt, "{} is disabled on this system", family)
+            }
+            Self::TooManyAddresses { interface, limit } => {
+                write!(fmt, "more than {} addresses on {}", limit, On(interface))
+            }
+            Self::InvalidSpec {
+                spec,
+                token,
+                expected,
+            } => match expected {
+                [expected] => write!(
+                    fmt,
+                    "invalid token `{}` in `{}`, expected {}",
+                    token, spec, expected
+                ),
+                _ => write!(
+                    fmt,
+                    "invalid token `{}` in `{}`, expected one of {}",
+                    token,
+                    spec,
+                    expected.join(", ")
+                ),
+            },
+            Self::InvalidDestination(dest) => write!(fmt, "invalid destination {}", dest),
+            Self::InvalidInterfaceName { name, reason } => {
+                write!(fmt, "invalid interface name {:?}: {}", name, reason)
+            }
+            Self::NoZone(ip) => write!(fmt, "link-local destination {} needs a zone", ip),
+            Self::ZoneMismatch {
+                interface,
+                scope_id,
+            } => write!(
+                fmt,
+                "zone {} of destination doesn't match interface {}",
+                scope_id, interface
+            ),
+            Self::NoLabel {
+                interface,
+                label,
+                available,
+            } => write!(
+                fmt,
+                "no ipv4 address labeled {} on {} (available: {})",
+                label,
+                On(interface),
+                available.join(", ")
+            ),
+            Self::SourcePreferenceRejected {
+                interface,
+                preferences,
+            } => write!(
+                fmt,
+                "kernel rejected source preferences {:?} on {}",
+                preferences,
+                On(interface)
+            ),
+            #[cfg(feature = "serde")]
+            Self::InvalidConfig(errors) => {
+                write!(fmt, "invalid probe config: {}", errors.join("; "))
+            }
+            #[cfg(feature = "networkmanager")]
+            Self::NetworkManager(e) => {
+                write!(fmt, "can't query networkmanager: d-bus error: {}", e)
+            }
         }
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Self::IoError(err)
+impl Error {
+    /// Get the OS error code underlying the error, if any.
+    /// The [source](std::error::Error::source) chain
+    /// is searched for the first [`io::Error`] that has one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+        while let Some(err) = source {
+            source = match err.downcast_ref::<io::Error>() {
+                Some(e) => match e.raw_os_error() {
+                    Some(errno) => return Some(errno),
+                    // The source of a custom `io::Error` skips its payload.
+                    None => e.get_ref().map(|e| e as _),
+                },
+                None => err.source(),
+            };
+        }
+
+        None
+    }
+
+    /// Report whether retrying the operation later may succeed.
+    ///
+    /// Timeouts, missing routes, addresses that aren't usable yet
+    /// (e.g. because they are still tentative), not assigned
+    /// or no longer available and addresses that are in use are
+    /// transient, as are the OS errors `ENETUNREACH`, `EHOSTUNREACH`,
+    /// `EAGAIN`, `EINTR`, `ENOBUFS`, `ETIMEDOUT`, `EADDRNOTAVAIL`
+    /// and `EADDRINUSE`. Everything else, e.g. `ENODEV`, `EPERM`,
+    /// `EINVAL` or an address of the wrong scope, is not.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Timeout { .. }
+            | Self::NoAddress { .. }
+            | Self::NoRoute { .. }
+            | Self::NoSourceRoute { .. }
+            | Self::AddrInUse { .. }
+            | Self::AddrNotAvailable { .. }
+            | Self::SourceNotAssigned { .. } => true,
+            _ => matches!(
+                self.raw_os_error(),
+                Some(
+                    libc::ENETUNREACH
+                        | libc::EHOSTUNREACH
+                        | libc::EAGAIN
+                        | libc::EINTR
+                        | libc::ENOBUFS
+                        | libc::ETIMEDOUT
+                        | libc::EADDRNOTAVAIL
+                        | libc::EADDRINUSE
+                )
+            ),
+        }
+    }
+
+    /// Get the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from(self)
+    }
+
+    /// Report whether the error only means that there is
+    /// no address of the requested scope, e.g. because
+    /// its family is disabled.
+    fn is_scope_miss(&self) -> bool {
+        matches!(
+            self,
+            Self::NoLinkLocal(_)
+                | Self::NoUla(_)
+                | Self::NoGua(_)
+                | Self::NoV4LL(_)
+                | Self::NoPrivate(..)
+                | Self::NoGlobal(_)
+                | Self::NoLoopback(_)
+                | Self::LoopbackInterface { .. }
+                | Self::NoAddress { .. }
+                | Self::NoRoute { .. }
+                | Self::FamilyDisabled(_)
+        )
+    }
+}
+
+/// The kind of an [`Error`], as returned by [`Error::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ErrorKind {
+    Io,
+    WrongIpVersion,
+    /// The kernel chose an address of a different scope.
+    WrongScope,
+    Timeout,
+    NoAddress,
+    NoRoute,
+    AddrInUse,
+    AddrNotAvailable,
+    InvalidInput,
+    Other,
+}
+
+impl From<&Error> for ErrorKind {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::IoError(_) | Error::SocketFactory(_) | Error::SandboxRestricted { .. } => {
+                Self::Io
+            }
+            Error::WrongIpVer(..) | Error::GotMappedV4 { .. } => Self::WrongIpVersion,
+            Error::NoLinkLocal(_)
+            | Error::NoUla(_)
+            | Error::NoGua(_)
+            | Error::NoV4LL(_)
+            | Error::NoPrivate(..)
+            | Error::NoGlobal(_)
+            | Error::NoLoopback(_)
+            | Error::LoopbackInterface { .. } => Self::WrongScope,
+            Error::Timeout { .. } => Self::Timeout,
+            Error::NoAddress { .. }
+            | Error::NoBroadcast { .. }
+            | Error::ShortLifetime { .. }
+            | Error::NoLabel { .. }
+            | Error::FamilyDisabled(_) => Self::NoAddress,
+            Error::NoRoute { .. } | Error::NoSourceRoute { .. } => Self::NoRoute,
+            Error::AddrInUse { .. } => Self::AddrInUse,
+            Error::AddrNotAvailable { .. } | Error::SourceNotAssigned { .. } => {
+                Self::AddrNotAvailable
+            }
+            Error::NotMulticast(_)
+            | Error::NoScopes
+            | Error::InvalidDestination(_)
+            | Error::InvalidInterfaceName { .. }
+            | Error::NoZone(_)
+            | Error::ZoneMismatch { .. }
+            | Error::InvalidSpec { .. } => Self::InvalidInput,
+            #[cfg(feature = "serde")]
+            Error::InvalidConfig(_) => Self::InvalidInput,
+            Error::NoNameservers
+            | Error::SourcePreferenceRejected { .. }
+            | Error::Prohibited { .. }
+            | Error::ForeignSource { .. }
+            | Error::TooManyAddresses { .. } => Self::Other,
+            #[cfg(feature = "networkmanager")]
+            Error::NetworkManager(_) => Self::Other,
+        }
+    }
+}
+
+/// Formats an optional interface name for error messages.
+struct On<'a>(&'a Option<String>);
+
+impl fmt::Display for On<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(interface) => write!(fmt, "interface {}", interface),
+            None => write!(fmt, "any interface"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::IoError(Arc::new(err))
     }
 }
 
 /// An alias for `std::result::Result` that uses `Error` as its error variant.
 pub type Result<T> = std::result::Result<T, Error>;
 
-fn ipv6(interface: &str, network: &str) -> Result<Ipv6Addr> {
-    let socket = Socket::new(Domain::IPV6, Type::DGRAM, None)?;
-    let sock_addr = (network, 0).to_socket_addrs()?.next().unwrap();
+/// A builder for configuring how IP address information is obtained.
+///
+/// The free functions of this crate are shorthands for
+/// `IpQuery::new(interface)` with the default configuration.
+#[derive(Clone, Debug, Default)]
+pub struct IpQuery<'a> {
+    interface: Option<&'a str>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    protocol: ProbeProtocol,
+    backend: Option<Backend>,
+    unmap_v4: bool,
+    deterministic: bool,
+    verify_owner: bool,
+    optimistic_dad: OptimisticDad,
+    min_preferred_lifetime: Option<Duration>,
+    observer: Option<observe::Observer>,
+    clock: Option<clock::SharedClock>,
+    source_preferences: Vec<SourcePreference>,
+    socket_factory: Option<factory::Factory>,
+    #[cfg(feature = "networkmanager")]
+    nm_bus: Option<networkmanager::SharedBus>,
+    trace: Option<explain::Trace>,
+    stats: Option<stats::Stats>,
+    /// The index of the interface, if it is known in advance.
+    /// Sockets are bound by index rather than by name then.
+    pub(crate) if_index: Option<NonZeroU32>,
+}
 
-    socket.bind_device(Some(interface.as_bytes()))?;
-    socket.connect(&sock_addr.into())?;
+impl<'a> IpQuery<'a> {
+    /// Create a new query for the given interface.
+    pub fn new(interface: &'a str) -> Self {
+        Self::with_interface(Some(interface))
+    }
 
-    let udp: UdpSocket = socket.into();
-    let ip = udp.local_addr()?.ip();
+    /// Create a new query that isn't bound to any interface,
+    /// leaving the choice of the outgoing interface to the kernel.
+    pub fn any_interface() -> Self {
+        Self::default()
+    }
 
-    match ip {
-        IpAddr::V4(_) => Err(Error::WrongIpVer("IPv6".into(), ip)),
-        IpAddr::V6(ipv6) => Ok(ipv6),
+    fn with_interface(interface: Option<&'a str>) -> Self {
+        Self {
+            interface,
+            ..Default::default()
+        }
     }
-}
 
-/// Get the (preferred outgoing) IPv6 link-local address
-/// of the given interface.
-pub fn ipv6_unicast_link_local(interface: &str) -> Result<Ipv6Addr> {
-    let ipv6 = ipv6(interface, "fe80::")?;
+    fn interface_name(&self) -> Option<String> {
+        self.interface.map(Into::into)
+    }
 
-    if ipv6.is_unicast_link_local() {
-        Ok(ipv6)
-    } else {
-        Err(Error::NoLinkLocal(ipv6))
+    fn deadline_expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| self.now() >= deadline)
     }
-}
 
-/// Get the preferred outgoing IPv6 ULA of the given interface.
-pub fn ipv6_unique_local(interface: &str) -> Result<Ipv6Addr> {
-    let ipv6 = ipv6(interface, "fc00::")?;
+    /// Set a deadline for each individual probe operation,
+    /// including the time a [socket factory](IpQuery::socket_factory)
+    /// takes to provide its socket. A zero timeout is treated as no timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+        self
+    }
 
-    if ipv6.is_unique_local() {
-        Ok(ipv6)
-    } else {
-        Err(Error::NoUla(ipv6))
+    /// Set a deadline for the entire operation. Operations that perform
+    /// multiple probes check it before starting each of them
+    /// and every probe is limited to the time that remains,
+    /// or to the per-probe timeout if that is shorter.
+    /// The deadline is a point in time of the [clock](IpQuery::clock).
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
     }
-}
 
-/// Get the preferred outgoing IPv6 GUA of the given interface.
-pub fn ipv6_unicast_global(interface: &str) -> Result<Ipv6Addr> {
-    let ipv6 = ipv6(interface, "2000::")?;
+    /// Set a deadline for the entire operation relative to now.
+    /// A zero timeout is treated as no deadline.
+    /// See [`IpQuery::deadline`] for details.
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = (!timeout.is_zero()).then(|| self.now() + timeout);
+        self
+    }
 
-    if ipv6.is_unicast_global() {
-        Ok(ipv6)
-    } else {
-        Err(Error::NoGua(ipv6))
+    /// Set the transport protocol of the probe sockets.
+    pub fn protocol(mut self, protocol: ProbeProtocol) -> Self {
+        self.protocol = protocol;
+        self
     }
-}
 
-fn ipv4(interface: &str, network: &str) -> Result<Ipv4Addr> {
-    let socket = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
-    let sock_addr = (network, 0).to_socket_addrs()?.next().unwrap();
+    /// Only use the given backend. By default the socket backend is used,
+    /// falling back to NetworkManager (if enabled), then to procfs
+    /// if binding the socket to the interface or creating it
+    /// isn't permitted.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
 
-    socket.bind_device(Some(interface.as_bytes()))?;
-    socket.connect(&sock_addr.into())?;
+    /// Make [`IpQuery::get`] answer IPv6 requests with the IPv4 address
+    /// of the corresponding scope if the IPv6 probe yields
+    /// an IPv4-mapped address, instead of failing with
+    /// [`Error::GotMappedV4`]. The typed IPv6 getters always fail.
+    pub fn unmap_v4(mut self, unmap_v4: bool) -> Self {
+        self.unmap_v4 = unmap_v4;
+        self
+    }
 
-    let udp: UdpSocket = socket.into();
-    let ip = udp.local_addr()?.ip();
+    /// Instead of the kernel's choice, which can change e.g. across reboots,
+    /// return the first usable address of the requested scope
+    /// on the interface after sorting them: Addresses that aren't
+    /// deprecated come first, then stable IPv6 addresses before temporary
+    /// ones, then the numerically lowest address.
+    /// Has no effect if the query isn't bound to an interface.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
 
-    match ip {
-        IpAddr::V4(ipv4) => Ok(ipv4),
-        IpAddr::V6(_) => Err(Error::WrongIpVer("IPv4".into(), ip)),
+    /// Check that the address the kernel chose is assigned to the interface,
+    /// which it may not be e.g. with the weak host model or some tunnels,
+    /// failing with [`Error::ForeignSource`] otherwise. Addresses that are
+    /// also assigned to other interfaces, e.g. anycast addresses, pass.
+    /// Has no effect if the query isn't bound to an interface
+    /// or if the address is taken from the addresses of the interface
+    /// anyway, e.g. by the procfs backend.
+    pub fn verify_owner(mut self, verify_owner: bool) -> Self {
+        self.verify_owner = verify_owner;
+        self
     }
-}
 
-/// Get the (preferred outgoing) IPv4 link-local address
-/// of the given interface.
-pub fn ipv4_link_local(interface: &str) -> Result<Ipv4Addr> {
-    let ipv4 = ipv4(interface, "169.254.0.0")?;
+    /// Set how to treat IPv6 addresses that are still undergoing
+    /// optimistic duplicate address detection. See [`OptimisticDad`].
+    pub fn optimistic_dad(mut self, optimistic_dad: OptimisticDad) -> Self {
+        self.optimistic_dad = optimistic_dad;
+        self
+    }
 
-    if ipv4.is_link_local() {
-        Ok(ipv4)
-    } else {
-        Err(Error::NoV4LL(ipv4))
+    /// Reject addresses whose remaining preferred lifetime is shorter
+    /// than the given duration, e.g. because they are embedded
+    /// in certificates. Addresses with an infinite lifetime always qualify.
+    ///
+    /// If the kernel chooses such an address, the first qualifying
+    /// address of the scope on the interface in the order of
+    /// [`IpQuery::deterministic`] is returned instead. If there is none,
+    /// the query fails with [`Error::ShortLifetime`] naming the rejected
+    /// address with the longest remaining lifetime. The lifetimes are
+    /// only known for addresses enumerated via netlink, so the procfs
+    /// and NetworkManager backends don't reject any addresses.
+    /// A zero duration disables the check.
+    pub fn min_preferred_lifetime(mut self, min: Duration) -> Self {
+        self.min_preferred_lifetime = Some(min).filter(|min| !min.is_zero());
+        self
     }
-}
 
-/// Get the preferred outgoing IPv4 private address
-/// of the given interface.
-pub fn ipv4_private(interface: &str) -> Result<Ipv4Addr> {
-    let a = ipv4(interface, "10.0.0.0")?;
-    let b = ipv4(interface, "172.16.0.0")?;
-    let c = ipv4(interface, "192.168.0.0")?;
-
-    if c.is_private() {
-        Ok(c)
-    } else if b.is_private() {
-        Ok(b)
-    } else if a.is_private() {
-        Ok(a)
-    } else {
-        Err(Error::NoPrivate(a, b, c))
+    /// Ask the kernel to prefer the given kind of IPv6 source address.
+    /// Can be combined with preferences of the other kind, e.g.
+    /// [`SourcePreference::Temporary`] and [`SourcePreference::Home`].
+    /// Replaces an opposite preference that was set before.
+    pub fn prefer_source(mut self, preference: SourcePreference) -> Self {
+        self.source_preferences
+            .retain(|&set| set != preference && set != preference.opposite());
+        self.source_preferences.push(preference);
+        self
     }
-}
 
-/// Get the preferred outgoing IPv4 global address
-/// of the given interface.
-pub fn ipv4_global(interface: &str) -> Result<Ipv4Addr> {
-    let ipv4 = ipv4(interface, "0.0.0.0")?;
+    /// Create the probe sockets with the given factory.
+    /// See [`SocketFactory`] for which options are set by whom.
+    /// Sockets for [`IpQuery::bind_udp`] and
+    /// [`IpQuery::bind_tcp_listener`] are not affected.
+    pub fn socket_factory(mut self, factory: impl SocketFactory + 'static) -> Self {
+        self.socket_factory = Some(factory::Factory::new(factory));
+        self
+    }
 
-    if ipv4.is_global() {
-        Ok(ipv4)
-    } else {
-        Err(Error::NoGlobal(ipv4))
+    /// Query NetworkManager over the given bus instead of connecting
+    /// to the system bus each time, e.g. to share one connection
+    /// between queries or to fake NetworkManager in tests.
+    #[cfg(feature = "networkmanager")]
+    pub fn networkmanager_bus(
+        mut self,
+        bus: impl networkmanager::NmBus + Send + Sync + 'static,
+    ) -> Self {
+        self.nm_bus = Some(networkmanager::SharedBus::new(bus));
+        self
+    }
+
+    /// Notify the given observer about the probes of this query
+    /// instead of the global one. See [`ProbeObserver`].
+    pub fn observer(mut self, observer: impl ProbeObserver + 'static) -> Self {
+        self.observer = Some(observe::Observer::new(observer));
+        self
+    }
+
+    /// Use the given clock for deadlines, cache ages and waiting
+    /// instead of [`MonotonicClock`], e.g. the `MockClock`
+    /// of the `test-support` feature in tests.
+    /// Set it before [`IpQuery::total_timeout`],
+    /// which reads the clock immediately.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(clock::SharedClock::new(clock));
+        self
+    }
+
+    fn active_clock(&self) -> &dyn Clock {
+        match &self.clock {
+            Some(clock) => clock.get(),
+            None => &MonotonicClock,
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.active_clock().now()
+    }
+
+    fn active_observer(&self) -> Option<&dyn ProbeObserver> {
+        match &self.observer {
+            Some(observer) => Some(observer.get()),
+            None => observe::global(),
+        }
+    }
+
+    fn fallback(&self, kind: FallbackKind) {
+        self.trace(|| Step::Fallback(kind));
+
+        if let Some(observer) = self.active_observer() {
+            observe::isolate(|| observer.on_fallback(self.interface, kind));
+        }
+    }
+
+    fn probe_timeout(&self, operation: &'static str) -> Result<Option<Duration>> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Ok(self.timeout),
+        };
+
+        let remaining = deadline
+            .checked_duration_since(self.now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| Error::Timeout {
+                interface: self.interface_name(),
+                operation,
+            })?;
+
+        Ok(Some(match self.timeout {
+            Some(timeout) => timeout.min(remaining),
+            None => remaining,
+        }))
+    }
+
+    fn probe(&self, dest: SocketAddr) -> Result<IpAddr> {
+        self.probe_with(dest, None, |_| Ok(()))
+    }
+
+    /// Probe the source address towards the destination,
+    /// notifying the observer. `scope` is the scope that is queried, if any.
+    fn probe_with(
+        &self,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        setup: impl FnOnce(&Socket) -> io::Result<()>,
+    ) -> Result<IpAddr> {
+        self.observed(dest, scope, || {
+            self.probe_socket(dest, setup).map(|local| local.ip())
+        })
+    }
+
+    /// Run a probe towards the destination, notifying the observer.
+    fn observed(
+        &self,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        probe: impl FnOnce() -> Result<IpAddr>,
+    ) -> Result<IpAddr> {
+        let trace = |result: &Result<IpAddr>| {
+            self.trace(|| Step::Probe {
+                dest,
+                source: result.as_ref().copied().map_err(|e| e.to_string()),
+            });
+        };
+
+        let Some(observer) = self.active_observer() else {
+            let result = probe();
+            trace(&result);
+            return result;
+        };
+
+        observe::isolate(|| observer.on_probe_start(self.interface, dest, scope));
+        let start = self.now();
+
+        let result = probe();
+        let outcome = result.as_ref().copied();
+        let duration = self.now().saturating_duration_since(start);
+        trace(&result);
+
+        observe::isolate(|| observer.on_probe_end(self.interface, dest, scope, outcome, duration));
+        result
+    }
+
+    /// Probe the local address towards the destination.
+    fn probe_socket(
+        &self,
+        dest: SocketAddr,
+        setup: impl FnOnce(&Socket) -> io::Result<()>,
+    ) -> Result<SocketAddr> {
+        let start = self.now();
+        let timeout = self.probe_timeout("probe")?;
+
+        let ty = match self.protocol {
+            ProbeProtocol::Udp => Type::DGRAM,
+            ProbeProtocol::Tcp => Type::STREAM,
+        };
+
+        let socket = self.open_socket(dest, ty)?;
+        setup(&socket)?;
+
+        // Socket factories may block, e.g. on a privileged helper,
+        // so the time they took counts towards the timeout.
+        let timeout = match timeout {
+            Some(timeout) => Some(
+                timeout
+                    .checked_sub(self.now().saturating_duration_since(start))
+                    .filter(|remaining| !remaining.is_zero())
+                    .ok_or_else(|| Error::Timeout {
+                        interface: self.interface_name(),
+                        operation: "socket",
+                    })?,
+            ),
+            None => None,
+        };
+
+        match self.protocol {
+            ProbeProtocol::Udp => match timeout {
+                Some(timeout) => socket.connect_timeout(&dest.into(), timeout),
+                None => socket.connect(&dest.into()),
+            },
+            ProbeProtocol::Tcp => {
+                // The source address is assigned as soon as the SYN is sent.
+                // Never wait for the handshake.
+                socket.set_nonblocking(true)?;
+                socket.connect(&dest.into()).or_else(|e| {
+                    if e.raw_os_error() == Some(libc::EINPROGRESS) {
+                        Ok(())
+                    } else {
+                        Err(e)
+                    }
+                })
+            }
+        }
+        .map_err(|e| self.connect_error(e, dest.ip()))?;
+
+        Ok(socket.local_addr()?.as_socket().unwrap())
+    }
+
+    /// Create a socket for sending to the destination, bound to the
+    /// query's interface and with its socket options applied.
+    fn open_socket(&self, dest: SocketAddr, ty: Type) -> Result<Socket> {
+        let domain = Domain::for_address(dest);
+        let options = self.socket_options(dest);
+        let (socket, bound) = match &self.socket_factory {
+            Some(factory) => match factory.get().socket(domain, ty) {
+                Ok(ProvidedSocket::Unbound(socket)) => (socket, false),
+                Ok(ProvidedSocket::Bound(socket)) => (socket, true),
+                Err(e) if e.raw_os_error() == Some(libc::EAFNOSUPPORT) => {
+                    return Err(Error::FamilyDisabled(IpVersion::of(dest.ip())));
+                }
+                Err(e) => return Err(Error::SocketFactory(e.into())),
+            },
+            None => match Socket::new(domain, ty, None) {
+                Ok(socket) => (socket, false),
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    return Err(Error::SandboxRestricted {
+                        syscall: "socket",
+                        source: e.into(),
+                    })
+                }
+                Err(e) if e.raw_os_error() == Some(libc::EAFNOSUPPORT) => {
+                    return Err(Error::FamilyDisabled(IpVersion::of(dest.ip())));
+                }
+                Err(e) => return Err(Error::IoError(e.into())),
+            },
+        };
+
+        // `IPV6_V6ONLY` can't be changed once the socket had a local port,
+        // so it's only set where needed.
+        if options.only_v6 && !(options.provided && socket.only_v6()?) {
+            socket.set_only_v6(true)?;
+        }
+        match options.bind_device.filter(|_| !bound) {
+            Some(_) => self.bind_device(&socket, domain)?,
+            None if options.clear_device && !bound && socket.device()?.is_some() => {
+                socket.bind_device(None)?
+            }
+            None => {}
+        }
+        if options.source_preferences.is_some() {
+            self.set_source_preferences(&socket)?;
+        }
+
+        Ok(socket)
+    }
+
+    /// Bind the socket to the interface of the query,
+    /// by its cached index if there is one.
+    fn bind_device(&self, socket: &Socket, domain: Domain) -> io::Result<()> {
+        match self.interface {
+            Some(_) if self.if_index.is_some() => match domain {
+                Domain::IPV6 => socket.bind_device_by_index_v6(self.if_index),
+                _ => socket.bind_device_by_index_v4(self.if_index),
+            },
+            interface => socket.bind_device(interface.map(str::as_bytes)),
+        }
+    }
+
+    /// Decide how to set up the socket for sending to the destination.
+    fn socket_options(&self, dest: SocketAddr) -> plan::SocketOptions<'_> {
+        let provided = self.socket_factory.is_some();
+
+        // Provided sockets may have been used by a query with different
+        // options before, so they are reset.
+        plan::SocketOptions {
+            family: IpVersion::of(dest.ip()),
+            protocol: self.protocol,
+            provided,
+            only_v6: dest.is_ipv6(),
+            bind_device: self.interface,
+            clear_device: provided && self.interface.is_none(),
+            source_preferences: (dest.is_ipv6()
+                && (provided || !self.source_preferences.is_empty()))
+            .then_some(&self.source_preferences[..]),
+        }
+    }
+
+    /// The backends in the order they are tried.
+    fn backends(&self) -> &[Backend] {
+        #[cfg(feature = "networkmanager")]
+        const DEFAULT: &[Backend] = &[Backend::Socket, Backend::NetworkManager, Backend::Procfs];
+        #[cfg(not(feature = "networkmanager"))]
+        const DEFAULT: &[Backend] = &[Backend::Socket, Backend::Procfs];
+
+        match &self.backend {
+            Some(backend) => std::slice::from_ref(backend),
+            None => DEFAULT,
+        }
+    }
+
+    /// Report whether the probe result is replaced by the first usable
+    /// address of the interface in deterministic order.
+    fn ranks_by_enumeration(&self) -> bool {
+        self.deterministic && self.interface.is_some()
+    }
+
+    /// The scopes [`IpQuery::get_all`] reports, in the order it probes them.
+    fn report_scopes(&self) -> Vec<Scope> {
+        let link_local = self
+            .interface
+            .is_some()
+            .then_some(Scope::V6(Ipv6Scope::UnicastLinkLocal));
+
+        link_local
+            .into_iter()
+            .chain([
+                Scope::V6(Ipv6Scope::UniqueLocal),
+                Scope::V6(Ipv6Scope::UnicastGlobal),
+                Scope::V4(Ipv4Scope::LinkLocal),
+                Scope::V4(Ipv4Scope::Private),
+                Scope::V4(Ipv4Scope::Global),
+            ])
+            .collect()
+    }
+
+    fn set_source_preferences(&self, socket: &Socket) -> Result<()> {
+        let flags = SourcePreference::flags(&self.source_preferences);
+
+        set_addr_preferences(socket, flags).map_err(|e| self.source_preference_error(e))
+    }
+
+    fn source_preference_error(&self, err: io::Error) -> Error {
+        if err.raw_os_error() == Some(libc::EINVAL) {
+            Error::SourcePreferenceRejected {
+                interface: self.interface_name(),
+                preferences: self.source_preferences.clone(),
+            }
+        } else {
+            Error::IoError(err.into())
+        }
+    }
+
+    fn connect_error(&self, err: io::Error, dest: IpAddr) -> Error {
+        match err.kind() {
+            io::ErrorKind::TimedOut => Error::Timeout {
+                interface: self.interface_name(),
+                operation: "connect",
+            },
+            io::ErrorKind::NetworkUnreachable | io::ErrorKind::HostUnreachable => Error::NoRoute {
+                interface: self.interface_name(),
+                dest,
+            },
+            // IPv6 sockets aren't connected without a source address,
+            // and connecting to 0.0.0.0 requires an IPv4 address.
+            io::ErrorKind::AddrNotAvailable => Error::NoAddress {
+                interface: self.interface_name(),
+                family: IpVersion::of(dest),
+            },
+            io::ErrorKind::InvalidInput if dest.is_unspecified() => Error::NoAddress {
+                interface: self.interface_name(),
+                family: IpVersion::of(dest),
+            },
+            _ => Error::IoError(err.into()),
+        }
+    }
+
+    fn check_unspecified(&self, ip: IpAddr, family: IpVersion) -> Result<()> {
+        if ip.is_unspecified() {
+            Err(Error::NoAddress {
+                interface: self.interface_name(),
+                family,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn source(&self, dest: SocketAddr, scope: Scope) -> Result<IpAddr> {
+        let backends = self.backends();
+        let mut result = self.backend_source(backends[0], dest, scope);
+
+        for pair in backends.windows(2) {
+            let (previous, backend) = (pair[0], pair[1]);
+            match &result {
+                Err(e) if previous.falls_back_on(e) => {}
+                _ => break,
+            }
+
+            if let Some(kind) = backend.fallback_kind() {
+                self.fallback(kind);
+            }
+            result = self.backend_source(backend, dest, scope);
+        }
+
+        let ip = result?;
+        self.check_unspecified(ip, scope.version())?;
+        Ok(ip)
+    }
+
+    fn backend_source(&self, backend: Backend, dest: SocketAddr, scope: Scope) -> Result<IpAddr> {
+        let family = scope.version();
+        let matches = |ip: &IpAddr| scope.contains(ip);
+
+        match backend {
+            Backend::Socket => self.socket_source(dest, scope),
+            #[cfg(feature = "networkmanager")]
+            Backend::NetworkManager => self.networkmanager_source(family, matches),
+            Backend::Procfs => self.procfs_source(family, matches),
+        }
+    }
+
+    fn socket_source(&self, dest: SocketAddr, scope: Scope) -> Result<IpAddr> {
+        let ip = self.probe_with(dest, Some(scope), |_| Ok(()))?;
+
+        let probed = if self.optimistic_dad == OptimisticDad::Reject
+            || self.min_preferred_lifetime.is_some()
+        {
+            addrs::find(ip)?
+        } else {
+            None
+        };
+        let rejected = match probed {
+            Some(addr) if self.optimistic_dad == OptimisticDad::Reject && addr.is_optimistic() => {
+                Some(FallbackKind::OptimisticRejected)
+            }
+            Some(addr) if self.short_lifetime(&addr).is_some() => Some(FallbackKind::ShortLifetime),
+            _ => None,
+        };
+        if let Some(kind) = rejected {
+            self.fallback(kind);
+        }
+
+        if rejected.is_some() || self.ranks_by_enumeration() {
+            let matches = |ip: &IpAddr| scope.contains(ip);
+            match self.usable_source(matches)? {
+                Some(ip) => Ok(ip),
+                None if rejected.is_none() => Ok(ip),
+                None => match self.longest_lived(matches)? {
+                    Some(addr) => Err(Error::ShortLifetime {
+                        interface: self.interface_name(),
+                        addr: addr.addr,
+                        remaining: addr.preferred_lifetime.unwrap_or_default(),
+                    }),
+                    None => Ok(scope.version().unspecified()),
+                },
+            }
+        } else {
+            self.check_owner(ip)?;
+            Ok(ip)
+        }
+    }
+
+    fn verifies_owner(&self) -> bool {
+        self.verify_owner && self.interface.is_some()
+    }
+
+    /// Make sure the address is assigned to the interface
+    /// if [`IpQuery::verify_owner`] is enabled.
+    fn check_owner(&self, ip: IpAddr) -> Result<()> {
+        let Some(interface) = self.interface.filter(|_| self.verifies_owner()) else {
+            return Ok(());
+        };
+
+        // Missing and mapped addresses are reported by the callers.
+        if ip.is_unspecified() || ip.to_canonical() != ip {
+            return Ok(());
+        }
+
+        let index = self.if_index()?;
+        let mut owners = Vec::new();
+        addrs::try_for_each_address(None, |addr| {
+            if addr.addr == ip {
+                owners.push(addr.index);
+            }
+            ControlFlow::<()>::Continue(())
+        })?;
+
+        foreign_owner(index, &owners).map_or(Ok(()), |owner| {
+            Err(Error::ForeignSource {
+                interface: Some(interface.into()),
+                addr: ip,
+                owner: owner.and_then(if_name),
+            })
+        })
+    }
+
+    fn usable_source(&self, matches: impl Fn(&IpAddr) -> bool) -> Result<Option<IpAddr>> {
+        let chosen = addrs::min_address(self.interface, |addr| self.candidate(addr, &matches))?;
+        Ok(chosen.map(|addr| self.chosen(&addr)))
+    }
+
+    /// Get the remaining preferred lifetime of the address
+    /// if it is shorter than [`IpQuery::min_preferred_lifetime`].
+    fn short_lifetime(&self, addr: &addrs::InterfaceAddr) -> Option<Duration> {
+        let min = self.min_preferred_lifetime?;
+        addr.preferred_lifetime.filter(|&remaining| remaining < min)
+    }
+
+    /// Get the candidate with the longest remaining preferred lifetime
+    /// among those that were only rejected because of it.
+    fn longest_lived(
+        &self,
+        matches: impl Fn(&IpAddr) -> bool,
+    ) -> Result<Option<addrs::InterfaceAddr>> {
+        let mut longest: Option<addrs::InterfaceAddr> = None;
+        addrs::try_for_each_address(self.interface, |addr| {
+            let short = Verdict::of(&addr, self.optimistic_dad, &matches).is_accepted()
+                && self.short_lifetime(&addr).is_some();
+            if short
+                && longest
+                    .as_ref()
+                    .is_none_or(|longest| addr.preferred_lifetime > longest.preferred_lifetime)
+            {
+                longest = Some(addr);
+            }
+
+            ControlFlow::<()>::Continue(())
+        })?;
+
+        Ok(longest)
+    }
+
+    /// Filter an enumerated candidate, recording the verdict.
+    fn candidate(&self, addr: &addrs::InterfaceAddr, matches: impl Fn(&IpAddr) -> bool) -> bool {
+        let verdict = self.verdict(addr, matches);
+        self.trace(|| Step::Candidate {
+            addr: addr.addr,
+            verdict,
+        });
+
+        verdict.is_accepted()
+    }
+
+    /// Filter an enumerated candidate. The lifetime is only checked
+    /// for candidates that are usable and of the scope.
+    fn verdict(&self, addr: &addrs::InterfaceAddr, matches: impl Fn(&IpAddr) -> bool) -> Verdict {
+        match Verdict::of(addr, self.optimistic_dad, matches) {
+            Verdict::Accepted(rank) => self
+                .short_lifetime(addr)
+                .map_or(Verdict::Accepted(rank), Verdict::ShortLifetime),
+            verdict => verdict,
+        }
+    }
+
+    /// Record the chosen candidate.
+    fn chosen(&self, addr: &addrs::InterfaceAddr) -> IpAddr {
+        self.trace(|| Step::Chosen {
+            addr: addr.addr,
+            rank: Some(Rank::of(addr)),
+        });
+
+        addr.addr
+    }
+
+    fn lenient_ipv6(&self, ipv6: Ipv6Addr, classified: bool) -> Lenient<Ipv6Addr> {
+        let optimistic = self.optimistic_dad == OptimisticDad::AcceptAndFlag
+            && addrs::find(ipv6.into())
+                .ok()
+                .flatten()
+                .is_some_and(|addr| addr.is_optimistic());
+
+        Lenient {
+            addr: ipv6,
+            classified,
+            optimistic,
+            privacy_consistent: None,
+            shared_with: Vec::new(),
+        }
+    }
+
+    fn procfs_source(
+        &self,
+        family: IpVersion,
+        matches: impl Fn(&IpAddr) -> bool,
+    ) -> Result<IpAddr> {
+        procfs::addresses(self.interface)?
+            .into_iter()
+            .filter(|addr| self.candidate(addr, &matches))
+            .min_by(addrs::deterministic_order)
+            .map(|addr| self.chosen(&addr))
+            .ok_or_else(|| Error::NoAddress {
+                interface: self.interface_name(),
+                family,
+            })
+    }
+
+    #[cfg(feature = "networkmanager")]
+    fn networkmanager_source(
+        &self,
+        family: IpVersion,
+        matches: impl Fn(&IpAddr) -> bool,
+    ) -> Result<IpAddr> {
+        let addrs = match &self.nm_bus {
+            Some(bus) => networkmanager::addresses(bus.get(), self.interface, family)?,
+            None => {
+                let bus = networkmanager::DBus::system()?;
+                networkmanager::addresses(&bus, self.interface, family)?
+            }
+        };
+
+        addrs
+            .iter()
+            .find(|ip| matches(ip))
+            .or(addrs.first())
+            .copied()
+            .inspect(|&addr| self.trace(|| Step::Chosen { addr, rank: None }))
+            .ok_or_else(|| Error::NoAddress {
+                interface: self.interface_name(),
+                family,
+            })
+    }
+
+    fn probe_ipv6(&self, dest: SocketAddr, scope: Ipv6Scope) -> Result<Ipv6Addr> {
+        let ip = self.source(dest, scope.into())?;
+
+        match ip {
+            IpAddr::V4(_) => Err(Error::WrongIpVer(IpVersion::V6, ip)),
+            IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+                Some(mapped) => Err(Error::GotMappedV4 { mapped }),
+                None => Ok(ipv6),
+            },
+        }
+    }
+
+    /// Probe the given scope. Looking up whether the address is
+    /// optimistic or shared requires netlink dumps, so strict getters
+    /// skip it.
+    fn ipv6_scoped(&self, scope: Ipv6Scope, detailed: bool) -> Result<Lenient<Ipv6Addr>> {
+        let dest = Scope::V6(scope).probe_dests()[0];
+        let ipv6 = self.probe_ipv6(dest, scope)?;
+        let classified = Scope::V6(scope).contains(&ipv6.into());
+
+        if detailed {
+            Ok(self.shared(self.lenient_ipv6(ipv6, classified)))
+        } else {
+            Ok(Lenient::new(ipv6, classified))
+        }
+    }
+
+    /// Report the other interfaces the address is assigned to.
+    fn shared<T: Copy + Into<IpAddr>>(&self, mut lenient: Lenient<T>) -> Lenient<T> {
+        let addr = lenient.addr.into();
+        let link_local = match addr {
+            IpAddr::V4(ipv4) => ipv4.is_link_local(),
+            IpAddr::V6(ipv6) => ipv6.is_unicast_link_local(),
+        };
+        if link_local {
+            return lenient;
+        }
+
+        let owners = addrs::address_owners(addr).unwrap_or_default();
+        lenient.shared_with = shared_with(self.interface, owners);
+        lenient
+    }
+
+    /// Get the (preferred outgoing) IPv6 link-local address
+    /// of the interface.
+    pub fn ipv6_unicast_link_local(&self) -> Result<Ipv6Addr> {
+        self.ipv6_strict(Ipv6Scope::UnicastLinkLocal, Error::NoLinkLocal)
+    }
+
+    /// Like [`IpQuery::ipv6_unicast_link_local`],
+    /// but doesn't fail if the address isn't link-local.
+    pub fn ipv6_unicast_link_local_lenient(&self) -> Result<Lenient<Ipv6Addr>> {
+        self.ipv6_scoped(Ipv6Scope::UnicastLinkLocal, true)
+    }
+
+    /// Get the preferred outgoing IPv6 ULA of the interface.
+    pub fn ipv6_unique_local(&self) -> Result<Ipv6Addr> {
+        self.ipv6_strict(Ipv6Scope::UniqueLocal, Error::NoUla)
+    }
+
+    /// Like [`IpQuery::ipv6_unique_local`],
+    /// but doesn't fail if the address isn't a ULA.
+    pub fn ipv6_unique_local_lenient(&self) -> Result<Lenient<Ipv6Addr>> {
+        self.ipv6_scoped(Ipv6Scope::UniqueLocal, true)
+    }
+
+    /// Get the preferred outgoing IPv6 GUA of the interface.
+    pub fn ipv6_unicast_global(&self) -> Result<Ipv6Addr> {
+        self.ipv6_strict(Ipv6Scope::UnicastGlobal, Error::NoGua)
+    }
+
+    /// Like [`IpQuery::ipv6_unicast_global`],
+    /// but doesn't fail if the address isn't a GUA.
+    /// Also reports whether the address is consistent
+    /// with the [privacy policy](ipv6_privacy_policy) of its interface.
+    pub fn ipv6_unicast_global_lenient(&self) -> Result<Lenient<Ipv6Addr>> {
+        let mut lenient = self.ipv6_scoped(Ipv6Scope::UnicastGlobal, true)?;
+        lenient.privacy_consistent = self.privacy_consistent(lenient.addr);
+        Ok(lenient)
+    }
+
+    /// Get the IPv6 loopback address `::1` if the interface
+    /// is a loopback interface.
+    pub fn ipv6_loopback(&self) -> Result<Ipv6Addr> {
+        self.ipv6_strict(Ipv6Scope::Loopback, |ipv6| Error::NoLoopback(ipv6.into()))
+    }
+
+    fn ipv6_strict(&self, scope: Ipv6Scope, err: fn(Ipv6Addr) -> Error) -> Result<Ipv6Addr> {
+        let result = self
+            .ipv6_scoped(scope, false)
+            .and_then(|lenient| lenient.strict(err))
+            .map_err(|e| self.loopback_miss(scope.into(), e));
+        self.count(scope.into(), result)
+    }
+
+    /// Explain a scope miss on a loopback interface,
+    /// which only has loopback addresses.
+    fn loopback_miss(&self, scope: Scope, err: Error) -> Error {
+        match self.interface {
+            Some(_) if matches!(err, Error::FamilyDisabled(_)) => err,
+            Some(interface) if err.is_scope_miss() && is_loopback(interface) => {
+                Error::LoopbackInterface {
+                    interface: interface.into(),
+                    scope,
+                }
+            }
+            _ => err,
+        }
+    }
+
+    fn probe_ipv4(&self, dest: SocketAddr, scope: Ipv4Scope) -> Result<Ipv4Addr> {
+        let ip = self.source(dest, scope.into())?;
+
+        match ip {
+            IpAddr::V4(ipv4) => Ok(ipv4),
+            IpAddr::V6(_) => Err(Error::WrongIpVer(IpVersion::V4, ip)),
+        }
+    }
+
+    /// Get the (preferred outgoing) IPv4 link-local address
+    /// of the interface.
+    pub fn ipv4_link_local(&self) -> Result<Ipv4Addr> {
+        let result = self
+            .ipv4_link_local_scoped()
+            .and_then(|lenient| lenient.strict(Error::NoV4LL))
+            .map_err(|e| self.loopback_miss(Ipv4Scope::LinkLocal.into(), e));
+        self.count(Ipv4Scope::LinkLocal.into(), result)
+    }
+
+    /// Like [`IpQuery::ipv4_link_local`],
+    /// but doesn't fail if the address isn't link-local.
+    pub fn ipv4_link_local_lenient(&self) -> Result<Lenient<Ipv4Addr>> {
+        self.ipv4_link_local_scoped()
+            .map(|lenient| self.shared(lenient))
+    }
+
+    fn ipv4_link_local_scoped(&self) -> Result<Lenient<Ipv4Addr>> {
+        let dest = Scope::V4(Ipv4Scope::LinkLocal).probe_dests()[0];
+        let ipv4 = self.probe_ipv4(dest, Ipv4Scope::LinkLocal)?;
+        Ok(Lenient::new(
+            ipv4,
+            Scope::from(Ipv4Scope::LinkLocal).contains(&ipv4.into()),
+        ))
+    }
+
+    fn ipv4_private_candidates(&self) -> Result<[Ipv4Addr; 3]> {
+        let dests = Scope::V4(Ipv4Scope::Private).probe_dests();
+        let a = self.probe_ipv4(dests[0], Ipv4Scope::Private)?;
+        let b = self.probe_ipv4(dests[1], Ipv4Scope::Private)?;
+        let c = self.probe_ipv4(dests[2], Ipv4Scope::Private)?;
+
+        Ok([a, b, c])
+    }
+
+    /// Get the preferred outgoing IPv4 private address
+    /// of the interface.
+    pub fn ipv4_private(&self) -> Result<Ipv4Addr> {
+        let result = self.ipv4_private_uncounted();
+        self.count(Ipv4Scope::Private.into(), result)
+    }
+
+    fn ipv4_private_uncounted(&self) -> Result<Ipv4Addr> {
+        let [a, b, c] = self
+            .ipv4_private_candidates()
+            .map_err(|e| self.loopback_miss(Ipv4Scope::Private.into(), e))?;
+
+        let private = |ipv4: Ipv4Addr| Scope::from(Ipv4Scope::Private).contains(&ipv4.into());
+        if private(c) {
+            Ok(c)
+        } else if private(b) {
+            Ok(b)
+        } else if private(a) {
+            Ok(a)
+        } else {
+            Err(self.loopback_miss(Ipv4Scope::Private.into(), Error::NoPrivate(a, b, c)))
+        }
+    }
+
+    /// Like [`IpQuery::ipv4_private`],
+    /// but doesn't fail if none of the addresses are private.
+    /// In that case the address used towards 192.168.0.0 is returned.
+    pub fn ipv4_private_lenient(&self) -> Result<Lenient<Ipv4Addr>> {
+        let [a, b, c] = self.ipv4_private_candidates()?;
+
+        let lenient = [c, b, a]
+            .into_iter()
+            .find(|&ipv4| Scope::from(Ipv4Scope::Private).contains(&ipv4.into()))
+            .map(|ipv4| Lenient::new(ipv4, true))
+            .unwrap_or(Lenient::new(c, false));
+        Ok(self.shared(lenient))
+    }
+
+    /// Get the preferred outgoing IPv4 global address
+    /// of the interface.
+    pub fn ipv4_global(&self) -> Result<Ipv4Addr> {
+        let result = self
+            .ipv4_global_scoped()
+            .and_then(|lenient| lenient.strict(Error::NoGlobal))
+            .map_err(|e| self.loopback_miss(Ipv4Scope::Global.into(), e));
+        self.count(Ipv4Scope::Global.into(), result)
+    }
+
+    /// Like [`IpQuery::ipv4_global`],
+    /// but doesn't fail if the address isn't global.
+    pub fn ipv4_global_lenient(&self) -> Result<Lenient<Ipv4Addr>> {
+        self.ipv4_global_scoped()
+            .map(|lenient| self.shared(lenient))
+    }
+
+    fn ipv4_global_scoped(&self) -> Result<Lenient<Ipv4Addr>> {
+        let dest = Scope::V4(Ipv4Scope::Global).probe_dests()[0];
+        let ipv4 = self.probe_ipv4(dest, Ipv4Scope::Global)?;
+        Ok(Lenient::new(
+            ipv4,
+            Scope::from(Ipv4Scope::Global).contains(&ipv4.into()),
+        ))
+    }
+
+    /// Get the IPv4 loopback address, usually `127.0.0.1`,
+    /// if the interface is a loopback interface.
+    pub fn ipv4_loopback(&self) -> Result<Ipv4Addr> {
+        let result = self
+            .probe_ipv4(
+                Scope::V4(Ipv4Scope::Loopback).probe_dests()[0],
+                Ipv4Scope::Loopback,
+            )
+            .and_then(|ipv4| {
+                Lenient::new(ipv4, ipv4.is_loopback()).strict(|ipv4| Error::NoLoopback(ipv4.into()))
+            });
+        self.count(Ipv4Scope::Loopback.into(), result)
+    }
+
+    /// Get the preferred outgoing IPv6 address of the given scope.
+    pub fn ipv6(&self, scope: Ipv6Scope) -> Result<Ipv6Addr> {
+        match scope {
+            Ipv6Scope::UnicastLinkLocal => self.ipv6_unicast_link_local(),
+            Ipv6Scope::UniqueLocal => self.ipv6_unique_local(),
+            Ipv6Scope::UnicastGlobal => self.ipv6_unicast_global(),
+            Ipv6Scope::Loopback => self.ipv6_loopback(),
+        }
+    }
+
+    /// Get the preferred outgoing IPv4 address of the given scope.
+    pub fn ipv4(&self, scope: Ipv4Scope) -> Result<Ipv4Addr> {
+        match scope {
+            Ipv4Scope::LinkLocal => self.ipv4_link_local(),
+            Ipv4Scope::Private => self.ipv4_private(),
+            Ipv4Scope::Global => self.ipv4_global(),
+            Ipv4Scope::Loopback => self.ipv4_loopback(),
+        }
+    }
+
+    /// Get the preferred outgoing IPv6 address of the first of the
+    /// given scopes that has one, trying them in order.
+    /// Hard errors are returned immediately. If none of the scopes
+    /// has an address, the error of the last one is returned.
+    pub fn ipv6_first_of(&self, scopes: &[Ipv6Scope]) -> Result<(Ipv6Scope, Ipv6Addr)> {
+        first_of(scopes, |scope| self.ipv6(scope))
+    }
+
+    /// Get the preferred outgoing IPv4 address of the first of the
+    /// given scopes that has one, trying them in order.
+    /// See [`IpQuery::ipv6_first_of`] for details.
+    pub fn ipv4_first_of(&self, scopes: &[Ipv4Scope]) -> Result<(Ipv4Scope, Ipv4Addr)> {
+        first_of(scopes, |scope| self.ipv4(scope))
+    }
+
+    /// Get the preferred outgoing address of the given scope.
+    pub fn get(&self, scope: Scope) -> Result<IpAddr> {
+        match scope {
+            Scope::V6(scope) => match self.ipv6(scope) {
+                Err(Error::GotMappedV4 { .. }) if self.unmap_v4 => {
+                    self.fallback(FallbackKind::UnmapV4);
+                    self.ipv4(scope.ipv4_counterpart()).map(IpAddr::V4)
+                }
+                result => result.map(IpAddr::V6),
+            },
+            Scope::V4(scope) => self.ipv4(scope).map(IpAddr::V4),
+        }
+    }
+
+    fn if_index(&self) -> Result<u32> {
+        match self.if_index {
+            Some(index) if self.interface.is_some() => Ok(index.get()),
+            _ => self.interface.map_or(Ok(0), if_index),
+        }
+    }
+
+    /// Get the source address used for sending
+    /// to the given IPv6 multicast group.
+    ///
+    /// Groups of link-local scope yield the link-local address,
+    /// groups of wider scope yield an address of a wider scope.
+    pub fn ipv6_multicast_source(&self, group: Ipv6Addr) -> Result<Ipv6Addr> {
+        if !group.is_multicast() {
+            return Err(Error::NotMulticast(group.into()));
+        }
+
+        let index = self.if_index()?;
+        let scope_id = match group.multicast_scope() {
+            Some(Ipv6MulticastScope::InterfaceLocal | Ipv6MulticastScope::LinkLocal) => index,
+            _ => 0,
+        };
+
+        let dest = SocketAddrV6::new(group, 0, 0, scope_id).into();
+        let ip = self
+            .clone()
+            .protocol(ProbeProtocol::Udp)
+            .probe_with(dest, None, |socket| socket.set_multicast_if_v6(index))?;
+        self.check_unspecified(ip, IpVersion::V6)?;
+
+        match ip {
+            IpAddr::V4(_) => Err(Error::WrongIpVer(IpVersion::V6, ip)),
+            IpAddr::V6(ipv6) => Ok(ipv6),
+        }
+    }
+
+    /// Get the source address used for sending
+    /// to the given IPv4 multicast group.
+    ///
+    /// Groups in `224.0.0.0/24` yield the first address of at most
+    /// link scope, other groups the first address of global scope.
+    pub fn ipv4_multicast_source(&self, group: Ipv4Addr) -> Result<Ipv4Addr> {
+        if !group.is_multicast() {
+            return Err(Error::NotMulticast(group.into()));
+        }
+
+        let index = self.if_index()?;
+
+        let dest = SocketAddr::new(group.into(), 0);
+        let ip = self
+            .clone()
+            .protocol(ProbeProtocol::Udp)
+            .probe_with(dest, None, |socket| set_multicast_if_v4_n(socket, index))?;
+        self.check_unspecified(ip, IpVersion::V4)?;
+
+        match ip {
+            IpAddr::V4(ipv4) => Ok(ipv4),
+            IpAddr::V6(_) => Err(Error::WrongIpVer(IpVersion::V4, ip)),
+        }
+    }
+
+    /// Get the preferred outgoing addresses of all scopes.
+    ///
+    /// Scopes without an address are `None` rather than errors,
+    /// other errors are returned. If the deadline expires
+    /// the scopes that weren't probed yet are `None`
+    /// and [`AddressReport::incomplete`] is set.
+    /// The IPv6 link-local address is only probed
+    /// if the query is bound to an interface.
+    pub fn get_all(&self) -> Result<AddressReport> {
+        let mut report = AddressReport::default();
+
+        for scope in self.report_scopes() {
+            match scope {
+                Scope::V6(scope) => {
+                    let ipv6 = self.report_scope(&mut report, || self.ipv6(scope))?;
+                    match scope {
+                        Ipv6Scope::UnicastLinkLocal => report.ipv6_unicast_link_local = ipv6,
+                        Ipv6Scope::UniqueLocal => report.ipv6_unique_local = ipv6,
+                        Ipv6Scope::UnicastGlobal => report.ipv6_unicast_global = ipv6,
+                        Ipv6Scope::Loopback => {}
+                    }
+                }
+                Scope::V4(scope) => {
+                    let ipv4 = self.report_scope(&mut report, || self.ipv4(scope))?;
+                    match scope {
+                        Ipv4Scope::LinkLocal => report.ipv4_link_local = ipv4,
+                        Ipv4Scope::Private => report.ipv4_private = ipv4,
+                        Ipv4Scope::Global => report.ipv4_global = ipv4,
+                        Ipv4Scope::Loopback => {}
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn report_scope<T>(
+        &self,
+        report: &mut AddressReport,
+        probe: impl FnOnce() -> Result<T>,
+    ) -> Result<Option<T>> {
+        if report.incomplete {
+            return Ok(None);
+        }
+
+        match probe() {
+            Ok(addr) => Ok(Some(addr)),
+            Err(e) if e.is_scope_miss() => Ok(None),
+            Err(Error::Timeout { .. }) if self.deadline_expired() => {
+                report.incomplete = true;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Summarize the IP connectivity. See [`Connectivity`] for the rules.
+    pub fn connectivity(&self) -> Result<Connectivity> {
+        match Connectivity::from(&self.get_all()?) {
+            Connectivity::V6Only if self.ipv4_suppressed()? => Ok(Connectivity::V6Mostly),
+            connectivity => Ok(connectivity),
+        }
+    }
+
+    fn bind_preferred(
+        &self,
+        scope: Scope,
+        port: u16,
+        ty: Type,
+        setup: impl FnOnce(&Socket) -> io::Result<()>,
+    ) -> Result<Socket> {
+        let addr = match self.get(scope)? {
+            IpAddr::V6(ipv6) if ipv6.is_unicast_link_local() => {
+                SocketAddrV6::new(ipv6, port, 0, self.if_index()?).into()
+            }
+            ip => SocketAddr::new(ip, port),
+        };
+
+        let domain = Domain::for_address(addr);
+        let socket = Socket::new(domain, ty, None)?;
+        self.bind_device(&socket, domain)?;
+        setup(&socket)?;
+
+        socket.bind(&addr.into()).map_err(|e| match e.kind() {
+            io::ErrorKind::AddrInUse => Error::AddrInUse {
+                interface: self.interface_name(),
+                addr,
+            },
+            io::ErrorKind::AddrNotAvailable => Error::AddrNotAvailable {
+                interface: self.interface_name(),
+                addr: addr.ip(),
+            },
+            _ => Error::IoError(e.into()),
+        })?;
+
+        Ok(socket)
+    }
+
+    /// Bind a new UDP socket to the preferred outgoing address
+    /// of the given scope and the given port.
+    /// The socket is also bound to the interface.
+    pub fn bind_udp(&self, scope: Scope, port: u16) -> Result<UdpSocket> {
+        let socket = self.bind_preferred(scope, port, Type::DGRAM, |_| Ok(()))?;
+        Ok(socket.into())
+    }
+
+    /// Bind a new TCP listener to the preferred outgoing address
+    /// of the given scope and the given port.
+    /// The socket is also bound to the interface
+    /// and has `SO_REUSEADDR` set.
+    pub fn bind_tcp_listener(&self, scope: Scope, port: u16, backlog: i32) -> Result<TcpListener> {
+        let socket = self.bind_preferred(scope, port, Type::STREAM, |socket| {
+            socket.set_reuse_address(true)
+        })?;
+
+        socket.listen(backlog)?;
+        Ok(socket.into())
+    }
+}
+
+/// The preferred outgoing addresses of all scopes,
+/// as returned by [`get_all`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AddressReport {
+    pub ipv6_unicast_link_local: Option<Ipv6Addr>,
+    pub ipv6_unique_local: Option<Ipv6Addr>,
+    pub ipv6_unicast_global: Option<Ipv6Addr>,
+    pub ipv4_link_local: Option<Ipv4Addr>,
+    pub ipv4_private: Option<Ipv4Addr>,
+    pub ipv4_global: Option<Ipv4Addr>,
+    /// Whether the deadline expired before all scopes were probed.
+    pub incomplete: bool,
+}
+
+/// A summary of the available IP connectivity.
+///
+/// IPv6 counts as usable if there is a GUA or a ULA
+/// and IPv4 counts as usable if there is a global or a private address.
+/// Link-local addresses alone don't make a family usable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    DualStack,
+    /// IPv6 only, with IPv4 suppressed in an IPv6-mostly network.
+    /// Only reported by [`IpQuery::connectivity`],
+    /// see [`V6MostlySignals::ipv4_suppressed`].
+    V6Mostly,
+    V6Only,
+    V4Only,
+    None,
+}
+
+impl From<&AddressReport> for Connectivity {
+    fn from(report: &AddressReport) -> Self {
+        let ipv6 = report.ipv6_unicast_global.is_some() || report.ipv6_unique_local.is_some();
+        let ipv4 = report.ipv4_global.is_some() || report.ipv4_private.is_some();
+
+        match (ipv6, ipv4) {
+            (true, true) => Self::DualStack,
+            (true, false) => Self::V6Only,
+            (false, true) => Self::V4Only,
+            (false, false) => Self::None,
+        }
+    }
+}
+
+/// The source addresses the kernel chose for UDP and TCP
+/// traffic towards the same destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProbeConsistency {
+    pub udp: IpAddr,
+    pub tcp: IpAddr,
+}
+
+impl ProbeConsistency {
+    /// Report whether both protocols use the same source address.
+    pub fn is_consistent(&self) -> bool {
+        self.udp == self.tcp
+    }
+}
+
+/// An address returned by one of the `*_lenient` methods of [`IpQuery`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lenient<T> {
+    /// The address the kernel chose.
+    pub addr: T,
+    /// Whether the address is of the requested scope.
+    pub classified: bool,
+    /// Whether the address is still undergoing optimistic
+    /// duplicate address detection. Only set for IPv6 addresses
+    /// with [`OptimisticDad::AcceptAndFlag`].
+    pub optimistic: bool,
+    /// Whether the address is temporary exactly if it should be
+    /// according to the [`PrivacyPolicy`] of its interface,
+    /// overridden by the [source preferences](IpQuery::prefer_source)
+    /// of the query. Only set for IPv6 GUAs, `None` if the policy
+    /// or the address properties can't be read.
+    pub privacy_consistent: Option<bool>,
+    /// The other interfaces the address is assigned to, ordered by index,
+    /// e.g. in anycast or VRRP setups. See [`address_owners`].
+    /// If the query isn't bound to an interface, all of them
+    /// if there is more than one. Link-local addresses are only unique
+    /// per link, so they are never reported as shared. Empty if
+    /// the addresses can't be enumerated.
+    pub shared_with: Vec<String>,
+}
+
+impl<T> Lenient<T> {
+    fn new(addr: T, classified: bool) -> Self {
+        Self {
+            addr,
+            classified,
+            optimistic: false,
+            privacy_consistent: None,
+            shared_with: Vec::new(),
+        }
+    }
+
+    fn strict(self, err: impl FnOnce(T) -> Error) -> Result<T> {
+        if self.classified {
+            Ok(self.addr)
+        } else {
+            Err(err(self.addr))
+        }
+    }
+}
+
+/// Get the (preferred outgoing) IPv6 link-local address
+/// of the given interface.
+pub fn ipv6_unicast_link_local(interface: &str) -> Result<Ipv6Addr> {
+    IpQuery::new(interface).ipv6_unicast_link_local()
+}
+
+/// Get the preferred outgoing IPv6 ULA of the given interface.
+pub fn ipv6_unique_local(interface: &str) -> Result<Ipv6Addr> {
+    IpQuery::new(interface).ipv6_unique_local()
+}
+
+/// Get the preferred outgoing IPv6 GUA of the given interface.
+pub fn ipv6_unicast_global(interface: &str) -> Result<Ipv6Addr> {
+    IpQuery::new(interface).ipv6_unicast_global()
+}
+
+/// Get the (preferred outgoing) IPv4 link-local address
+/// of the given interface.
+pub fn ipv4_link_local(interface: &str) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_link_local()
+}
+
+/// Get the preferred outgoing IPv4 private address
+/// of the given interface.
+pub fn ipv4_private(interface: &str) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_private()
+}
+
+/// Get the preferred outgoing IPv4 global address
+/// of the given interface.
+pub fn ipv4_global(interface: &str) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_global()
+}
+
+/// Get the source address used for sending
+/// to the given IPv6 multicast group on the given interface.
+/// See [`IpQuery::ipv6_multicast_source`] for details.
+pub fn ipv6_multicast_source(interface: &str, group: Ipv6Addr) -> Result<Ipv6Addr> {
+    IpQuery::new(interface).ipv6_multicast_source(group)
+}
+
+/// Get the source address used for sending
+/// to the given IPv4 multicast group on the given interface.
+pub fn ipv4_multicast_source(interface: &str, group: Ipv4Addr) -> Result<Ipv4Addr> {
+    IpQuery::new(interface).ipv4_multicast_source(group)
+}
+
+/// Get the preferred outgoing IPv6 address of the first of the
+/// given scopes that has one on the given interface.
+/// See [`IpQuery::ipv6_first_of`] for details.
+pub fn ipv6_first_of(interface: &str, scopes: &[Ipv6Scope]) -> Result<(Ipv6Scope, Ipv6Addr)> {
+    IpQuery::new(interface).ipv6_first_of(scopes)
+}
+
+/// Get the preferred outgoing IPv4 address of the first of the
+/// given scopes that has one on the given interface.
+/// See [`IpQuery::ipv6_first_of`] for details.
+pub fn ipv4_first_of(interface: &str, scopes: &[Ipv4Scope]) -> Result<(Ipv4Scope, Ipv4Addr)> {
+    IpQuery::new(interface).ipv4_first_of(scopes)
+}
+
+/// Get the preferred outgoing addresses of all scopes
+/// of the given interface, or of any interface if `None`.
+/// See [`IpQuery::get_all`] for details.
+pub fn get_all(interface: Option<&str>) -> Result<AddressReport> {
+    IpQuery::with_interface(interface).get_all()
+}
+
+/// Summarize the IP connectivity of the given interface,
+/// or of any interface if `None`. See [`Connectivity`] for the rules.
+pub fn connectivity(interface: Option<&str>) -> Result<Connectivity> {
+    IpQuery::with_interface(interface).connectivity()
+}
+
+/// Bind a new UDP socket to the preferred outgoing address
+/// of the given scope on the given interface and the given port.
+/// The socket is also bound to the interface.
+pub fn bind_preferred_udp(interface: &str, scope: Scope, port: u16) -> Result<UdpSocket> {
+    IpQuery::new(interface).bind_udp(scope, port)
+}
+
+/// Bind a new TCP listener to the preferred outgoing address
+/// of the given scope on the given interface and the given port.
+/// The socket is also bound to the interface
+/// and has `SO_REUSEADDR` set.
+pub fn bind_preferred_tcp_listener(
+    interface: &str,
+    scope: Scope,
+    port: u16,
+    backlog: i32,
+) -> Result<TcpListener> {
+    IpQuery::new(interface).bind_tcp_listener(scope, port, backlog)
+}
+
+/// Compare the source addresses the kernel chooses for UDP and TCP traffic
+/// towards the given destination on the given interface.
+///
+/// The destination can have a zone like in [`IpQuery::preferred_source_for`].
+pub fn probe_consistency(
+    interface: &str,
+    dest: impl Into<Destination>,
+) -> Result<ProbeConsistency> {
+    let query = IpQuery::new(interface);
+    let dest = query.dest_socket_addr(dest.into(), 0)?;
+
+    Ok(ProbeConsistency {
+        udp: query.clone().protocol(ProbeProtocol::Udp).probe(dest)?,
+        tcp: query.protocol(ProbeProtocol::Tcp).probe(dest)?,
+    })
+}
+
+fn first_of<S: Copy, T>(scopes: &[S], get: impl Fn(S) -> Result<T>) -> Result<(S, T)> {
+    let mut last_err = Error::NoScopes;
+
+    for &scope in scopes {
+        match get(scope) {
+            Ok(addr) => return Ok((scope, addr)),
+            Err(e) if e.is_scope_miss() => last_err = e,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(last_err)
+}
+
+fn if_index(interface: &str) -> Result<u32> {
+    let name = CString::new(interface).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+    // SAFETY: `name` is a valid NUL-terminated string.
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+
+    if index == 0 {
+        Err(io::Error::last_os_error().into())
+    } else {
+        Ok(index)
+    }
+}
+
+/// Report whether the interface is a loopback interface.
+/// Interfaces whose flags can't be read aren't.
+fn is_loopback(interface: &str) -> bool {
+    let Ok(socket) = Socket::new(Domain::IPV4, Type::DGRAM, None) else {
+        return false;
+    };
+
+    // SAFETY: `ifreq` is plain old data.
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    if interface.len() >= req.ifr_name.len() {
+        return false;
+    }
+    for (dst, &src) in req.ifr_name.iter_mut().zip(interface.as_bytes()) {
+        *dst = src as libc::c_char;
+    }
+
+    // SAFETY: `req` is a valid `ifreq` with a NUL-terminated name
+    // and the file descriptor is owned by the socket.
+    let ret = unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCGIFFLAGS, &mut req) };
+
+    // SAFETY: SIOCGIFFLAGS initialized the flags.
+    ret == 0 && i32::from(unsafe { req.ifr_ifru.ifru_flags }) & libc::IFF_LOOPBACK != 0
+}
+
+/// Decide whether an address assigned to the given interfaces
+/// is foreign to the one with the given index. If so, returns the
+/// lowest of the owning interfaces, if any. Shared addresses that
+/// are also assigned to the interface aren't foreign.
+fn foreign_owner(index: u32, owners: &[u32]) -> Option<Option<u32>> {
+    if owners.contains(&index) {
+        None
+    } else {
+        Some(owners.iter().copied().min())
+    }
+}
+
+/// Select the owners of an address that share it with the interface,
+/// all of them if there is more than one and no interface.
+fn shared_with(interface: Option<&str>, owners: Vec<String>) -> Vec<String> {
+    match interface {
+        Some(interface) => owners
+            .into_iter()
+            .filter(|owner| owner != interface)
+            .collect(),
+        None if owners.len() > 1 => owners,
+        None => Vec::new(),
+    }
+}
+
+/// Look up the name of the interface with the given index.
+fn if_name(index: u32) -> Option<String> {
+    let mut name = [0; libc::IF_NAMESIZE];
+
+    // SAFETY: `name` has room for the longest name and the NUL terminator.
+    let ret = unsafe { libc::if_indextoname(index, name.as_mut_ptr()) };
+    if ret.is_null() {
+        return None;
+    }
+
+    // SAFETY: if_indextoname wrote a NUL-terminated string.
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Build the `ip_mreqn` selecting the interface with the given index.
+fn mreqn_by_index(index: u32) -> io::Result<libc::ip_mreqn> {
+    Ok(libc::ip_mreqn {
+        imr_multiaddr: libc::in_addr { s_addr: 0 },
+        imr_address: libc::in_addr { s_addr: 0 },
+        // The index is a native-endian `int`, unlike the addresses,
+        // which are in network byte order.
+        imr_ifindex: libc::c_int::try_from(index)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
+    })
+}
+
+/// Set `IP_MULTICAST_IF` by interface index. An index of 0 resets it.
+fn set_multicast_if_v4_n(socket: &Socket, index: u32) -> io::Result<()> {
+    let mreqn = mreqn_by_index(index)?;
+
+    // SAFETY: `mreqn` is a valid `ip_mreqn` and the length matches its size.
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_IF,
+            &mreqn as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&mreqn) as libc::socklen_t,
+        )
+    };
+
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn set_addr_preferences(socket: &Socket, flags: u32) -> io::Result<()> {
+    let flags = flags as libc::c_int;
+
+    // SAFETY: `flags` is a valid `c_int` and the length matches its size.
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_ADDR_PREFERENCES,
+            &flags as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&flags) as libc::socklen_t,
+        )
+    };
+
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Report whether IPv6 sockets can be created, which they can't
+/// if IPv6 is disabled system-wide, e.g. by booting with `ipv6.disable=1`.
+/// Getters of disabled families fail with [`Error::FamilyDisabled`].
+/// The check is only made once.
+pub fn ipv6_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| family_available(Domain::IPV6))
+}
+
+/// Report whether IPv4 sockets can be created.
+/// See [`ipv6_available`] for details.
+pub fn ipv4_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| family_available(Domain::IPV4))
+}
+
+fn family_available(domain: Domain) -> bool {
+    match Socket::new(domain, Type::DGRAM, None) {
+        Ok(_) => true,
+        Err(e) => e.raw_os_error() != Some(libc::EAFNOSUPPORT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Addresses around the bounds of every special-purpose range,
+    /// and a few outside of them.
+    fn classified_addrs() -> Vec<IpAddr> {
+        let v4 = SPECIAL_RANGES_V4.iter().flat_map(|&(prefix, len, _)| {
+            let first = u32::from(prefix);
+            let last = first | u32::MAX.checked_shr(len.into()).unwrap_or(0);
+            [first.wrapping_sub(1), first, last, last.wrapping_add(1)]
+                .map(|ipv4| IpAddr::from(Ipv4Addr::from(ipv4)))
+        });
+        let v6 = SPECIAL_RANGES_V6.iter().flat_map(|&(prefix, len, _)| {
+            let first = u128::from(prefix);
+            let last = first | u128::MAX.checked_shr(len.into()).unwrap_or(0);
+            [first.wrapping_sub(1), first, last, last.wrapping_add(1)]
+                .map(|ipv6| IpAddr::from(Ipv6Addr::from(ipv6)))
+        });
+        let outside =
+            ["1.1.1.1", "8.8.8.8", "2a01:4f8::1", "2600::1"].map(|ip| ip.parse().unwrap());
+
+        v4.chain(v6).chain(outside).collect()
+    }
+
+    #[test]
+    fn scopes_agree_with_std() {
+        for ip in classified_addrs() {
+            let (v6, v4) = match ip {
+                IpAddr::V6(ipv6) => (
+                    [
+                        ipv6.is_unicast_link_local(),
+                        ipv6.is_unique_local(),
+                        ipv6.is_unicast_global(),
+                        ipv6.is_loopback(),
+                    ],
+                    [false; 4],
+                ),
+                IpAddr::V4(ipv4) => (
+                    [false; 4],
+                    [
+                        ipv4.is_link_local(),
+                        ipv4.is_private(),
+                        ipv4.is_global(),
+                        ipv4.is_loopback(),
+                    ],
+                ),
+            };
+
+            let scopes = [
+                Ipv6Scope::UnicastLinkLocal,
+                Ipv6Scope::UniqueLocal,
+                Ipv6Scope::UnicastGlobal,
+                Ipv6Scope::Loopback,
+            ];
+            for (scope, expected) in scopes.into_iter().zip(v6) {
+                assert_eq!(Scope::V6(scope).contains(&ip), expected, "{ip} {scope:?}");
+            }
+            let scopes = [
+                Ipv4Scope::LinkLocal,
+                Ipv4Scope::Private,
+                Ipv4Scope::Global,
+                Ipv4Scope::Loopback,
+            ];
+            for (scope, expected) in scopes.into_iter().zip(v4) {
+                assert_eq!(Scope::V4(scope).contains(&ip), expected, "{ip} {scope:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn connectivity_from_report() {
+        let ll6 = Some(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let ula = Some(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+        let gua = Some(Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1));
+        let ll4 = Some(Ipv4Addr::new(169, 254, 1, 1));
+        let private = Some(Ipv4Addr::new(192, 168, 1, 1));
+        let global = Some(Ipv4Addr::new(1, 1, 1, 1));
+
+        #[rustfmt::skip]
+        let table = [
+            // ll6, ula, gua, ll4, private, global
+            ((None, None, None, None, None, None), Connectivity::None),
+            ((ll6, None, None, ll4, None, None), Connectivity::None),
+            ((ll6, ula, None, None, None, None), Connectivity::V6Only),
+            ((ll6, None, gua, ll4, None, None), Connectivity::V6Only),
+            ((None, None, None, None, private, None), Connectivity::V4Only),
+            ((ll6, None, None, None, None, global), Connectivity::V4Only),
+            ((None, ula, None, None, private, None), Connectivity::DualStack),
+            ((ll6, ula, gua, ll4, private, global), Connectivity::DualStack),
+        ];
+
+        for ((ll6, ula, gua, ll4, private, global), expected) in table {
+            let report = AddressReport {
+                ipv6_unicast_link_local: ll6,
+                ipv6_unique_local: ula,
+                ipv6_unicast_global: gua,
+                ipv4_link_local: ll4,
+                ipv4_private: private,
+                ipv4_global: global,
+                incomplete: false,
+            };
+            assert_eq!(Connectivity::from(&report), expected, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn connectivity_of_incomplete_report() {
+        // Scopes that weren't probed don't count.
+        let report = AddressReport {
+            ipv6_unique_local: Some(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+            incomplete: true,
+            ..Default::default()
+        };
+        assert_eq!(Connectivity::from(&report), Connectivity::V6Only);
+    }
+
+    fn miss(scope: Ipv6Scope) -> Error {
+        match scope {
+            Ipv6Scope::UnicastLinkLocal => Error::NoLinkLocal(Ipv6Addr::UNSPECIFIED),
+            Ipv6Scope::UniqueLocal => Error::NoUla(Ipv6Addr::UNSPECIFIED),
+            Ipv6Scope::UnicastGlobal => Error::NoGua(Ipv6Addr::UNSPECIFIED),
+            Ipv6Scope::Loopback => Error::NoLoopback(Ipv6Addr::UNSPECIFIED.into()),
+        }
+    }
+
+    /// Run `first_of` over per-scope results, recording the scopes tried.
+    fn first_of_mocked(
+        scopes: &[Ipv6Scope],
+        result: impl Fn(Ipv6Scope) -> Result<Ipv6Addr>,
+    ) -> (Result<(Ipv6Scope, Ipv6Addr)>, Vec<Ipv6Scope>) {
+        let tried = std::cell::RefCell::new(Vec::new());
+        let found = first_of(scopes, |scope| {
+            tried.borrow_mut().push(scope);
+            result(scope)
+        });
+        (found, tried.into_inner())
+    }
+
+    const FALLBACK: [Ipv6Scope; 3] = [
+        Ipv6Scope::UnicastGlobal,
+        Ipv6Scope::UniqueLocal,
+        Ipv6Scope::UnicastLinkLocal,
+    ];
+
+    #[test]
+    fn first_of_returns_first_hit() {
+        let ula = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+
+        let (found, tried) = first_of_mocked(&FALLBACK, |scope| match scope {
+            Ipv6Scope::UnicastGlobal => Err(miss(scope)),
+            _ => Ok(ula),
+        });
+        assert_eq!(found.unwrap(), (Ipv6Scope::UniqueLocal, ula));
+        assert_eq!(tried, FALLBACK[..2]);
+    }
+
+    #[test]
+    fn first_of_skips_scope_misses() {
+        let skipped = [
+            Error::NoAddress {
+                interface: Some("eth0".into()),
+                family: IpVersion::V6,
+            },
+            Error::NoRoute {
+                interface: Some("eth0".into()),
+                dest: "2001:4860:4860::8888".parse().unwrap(),
+            },
+            Error::FamilyDisabled(IpVersion::V6),
+        ];
+
+        for err in skipped {
+            let lla = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+            let kind = err.kind();
+            let err = std::cell::Cell::new(Some(err));
+
+            let (found, tried) = first_of_mocked(&FALLBACK, |scope| match scope {
+                Ipv6Scope::UnicastGlobal => Err(err.take().unwrap()),
+                Ipv6Scope::UniqueLocal => Err(miss(scope)),
+                _ => Ok(lla),
+            });
+            assert_eq!(
+                found.unwrap(),
+                (Ipv6Scope::UnicastLinkLocal, lla),
+                "{:?}",
+                kind
+            );
+            assert_eq!(tried, FALLBACK);
+        }
+    }
+
+    #[test]
+    fn first_of_aborts_on_hard_errors() {
+        let hard = [
+            io::ErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound,
+            io::ErrorKind::InvalidInput,
+        ];
+
+        for kind in hard {
+            let (found, tried) = first_of_mocked(&FALLBACK, |scope| match scope {
+                Ipv6Scope::UnicastGlobal => Err(miss(scope)),
+                Ipv6Scope::UniqueLocal => Err(io::Error::from(kind).into()),
+                _ => Ok(Ipv6Addr::LOCALHOST),
+            });
+            assert!(
+                matches!(found, Err(Error::IoError(ref e)) if e.kind() == kind),
+                "{:?}",
+                found
+            );
+            assert_eq!(tried, FALLBACK[..2]);
+        }
+
+        let (found, tried) = first_of_mocked(&FALLBACK, |_| {
+            Err(Error::Timeout {
+                interface: None,
+                operation: "probe",
+            })
+        });
+        assert!(matches!(found, Err(Error::Timeout { .. })), "{:?}", found);
+        assert_eq!(tried, FALLBACK[..1]);
+    }
+
+    #[test]
+    fn first_of_returns_last_miss() {
+        let (found, tried) = first_of_mocked(&FALLBACK, |scope| Err(miss(scope)));
+        assert!(matches!(found, Err(Error::NoLinkLocal(_))), "{:?}", found);
+        assert_eq!(tried, FALLBACK);
+    }
+
+    #[test]
+    fn first_of_rejects_empty_scopes() {
+        let (found, tried) = first_of_mocked(&[], |_| Ok(Ipv6Addr::LOCALHOST));
+        let err = found.unwrap_err();
+        assert!(matches!(err, Error::NoScopes));
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(tried.is_empty());
+    }
+
+    #[test]
+    fn source_preference_flags() {
+        use SourcePreference::*;
+
+        // The values of include/uapi/linux/in6.h.
+        #[rustfmt::skip]
+        let table: [(&[SourcePreference], u32); 7] = [
+            (&[], 0),
+            (&[Temporary], 0x0001),
+            (&[Public], 0x0002),
+            (&[CareOf], 0x0004),
+            (&[Home], 0x0400),
+            (&[Temporary, Home], 0x0401),
+            (&[Public, CareOf], 0x0006),
+        ];
+
+        for (preferences, flags) in table {
+            assert_eq!(
+                SourcePreference::flags(preferences),
+                flags,
+                "{:?}",
+                preferences
+            );
+        }
+    }
+
+    #[test]
+    fn opposite_source_preferences_replace_each_other() {
+        use SourcePreference::*;
+
+        let query = IpQuery::any_interface()
+            .prefer_source(Temporary)
+            .prefer_source(Home)
+            .prefer_source(Public)
+            .prefer_source(Home);
+        assert_eq!(query.source_preferences, [Public, Home]);
+
+        let query = query.prefer_source(CareOf).prefer_source(Temporary);
+        assert_eq!(query.source_preferences, [CareOf, Temporary]);
+        assert_eq!(SourcePreference::flags(&query.source_preferences), 0x0005);
+
+        for preference in [Temporary, Public, CareOf, Home] {
+            assert_eq!(preference.opposite().opposite(), preference);
+            assert_eq!(preference.flag() & preference.opposite().flag(), 0);
+        }
+    }
+
+    #[test]
+    fn rejected_source_preferences() {
+        let query = IpQuery::new("eth0")
+            .prefer_source(SourcePreference::CareOf)
+            .prefer_source(SourcePreference::Temporary);
+
+        let err = query.source_preference_error(io::Error::from_raw_os_error(libc::EINVAL));
+        assert!(
+            matches!(
+                err,
+                Error::SourcePreferenceRejected { ref interface, ref preferences }
+                    if interface.as_deref() == Some("eth0")
+                        && preferences == &[SourcePreference::CareOf, SourcePreference::Temporary]
+            ),
+            "{:?}",
+            err
+        );
+
+        let err = query.source_preference_error(io::Error::from_raw_os_error(libc::ENOPROTOOPT));
+        assert_eq!(err.raw_os_error(), Some(libc::ENOPROTOOPT));
+    }
+
+    fn os(errno: i32) -> io::Error {
+        io::Error::from_raw_os_error(errno)
+    }
+
+    #[test]
+    fn mreqn_index_is_native_endian() {
+        for index in [0, 1, 255, 256, 300, 0x0102_0304, i32::MAX as u32] {
+            let mreqn = mreqn_by_index(index).unwrap();
+            assert_eq!(mreqn.imr_ifindex.to_ne_bytes(), index.to_ne_bytes());
+            assert_eq!(mreqn.imr_ifindex as u32, index);
+            assert_eq!(mreqn.imr_multiaddr.s_addr, 0);
+            assert_eq!(mreqn.imr_address.s_addr, 0);
+        }
+
+        // Indexes are positive `int`s, so larger ones must not wrap.
+        for index in [i32::MAX as u32 + 1, u32::MAX] {
+            let err = mreqn_by_index(index).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn multicast_if_v4_by_index() {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+        let lo = if_index("lo").unwrap();
+
+        set_multicast_if_v4_n(&socket, lo).unwrap();
+        set_multicast_if_v4_n(&socket, 0).unwrap();
+
+        // The kernel looks the index up, so a swapped one is refused.
+        let swapped = lo.swap_bytes();
+        assert!(if_name(swapped).is_none());
+        let err = set_multicast_if_v4_n(&socket, swapped).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EADDRNOTAVAIL));
+    }
+
+    #[test]
+    fn raw_os_error_of_variants() {
+        let addr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+        #[rustfmt::skip]
+        let table = [
+            (Error::IoError(os(libc::ENODEV).into()), Some(libc::ENODEV)),
+            (Error::SocketFactory(os(libc::EACCES).into()), Some(libc::EACCES)),
+            (Error::SandboxRestricted { syscall: "socket", source: os(libc::EPERM).into() }, Some(libc::EPERM)),
+            // Custom errors are searched for a wrapped OS error.
+            (Error::IoError(io::Error::other(os(libc::ENETUNREACH)).into()), Some(libc::ENETUNREACH)),
+            (
+                Error::SocketFactory(io::Error::new(
+                    io::ErrorKind::ConnectionReset,
+                    io::Error::other(os(libc::EAGAIN)),
+                ).into()),
+                Some(libc::EAGAIN),
+            ),
+            (Error::IoError(io::Error::other("helper went away").into()), None),
+            (Error::from(io::Error::from(io::ErrorKind::TimedOut)), None),
+            (Error::Timeout { interface: None, operation: "probe" }, None),
+            (Error::NoRoute { interface: None, dest: addr }, None),
+            (Error::NoGua(Ipv6Addr::UNSPECIFIED), None),
+            (Error::FamilyDisabled(IpVersion::V6), None),
+            (Error::NoScopes, None),
+        ];
+
+        for (err, expected) in table {
+            assert_eq!(err.raw_os_error(), expected, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn transient_errors() {
+        let addr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let socket_addr = SocketAddr::new(addr, 80);
+
+        #[rustfmt::skip]
+        let table = [
+            (Error::Timeout { interface: None, operation: "probe" }, true),
+            (Error::NoAddress { interface: None, family: IpVersion::V6 }, true),
+            (Error::NoRoute { interface: None, dest: addr }, true),
+            (Error::NoSourceRoute { interface: None, source: addr, dest: addr }, true),
+            (Error::AddrInUse { interface: None, addr: socket_addr }, true),
+            (Error::AddrNotAvailable { interface: None, addr }, true),
+            (Error::SourceNotAssigned { interface: None, source: addr }, true),
+            (Error::IoError(os(libc::ENETUNREACH).into()), true),
+            (Error::IoError(os(libc::EHOSTUNREACH).into()), true),
+            (Error::IoError(os(libc::EAGAIN).into()), true),
+            (Error::IoError(os(libc::EINTR).into()), true),
+            (Error::IoError(os(libc::ENOBUFS).into()), true),
+            (Error::IoError(os(libc::ETIMEDOUT).into()), true),
+            (Error::IoError(os(libc::EADDRNOTAVAIL).into()), true),
+            (Error::IoError(os(libc::EADDRINUSE).into()), true),
+            (Error::SocketFactory(os(libc::EAGAIN).into()), true),
+            (Error::IoError(io::Error::other(os(libc::ENETUNREACH)).into()), true),
+            (Error::IoError(os(libc::ENODEV).into()), false),
+            (Error::IoError(os(libc::EPERM).into()), false),
+            (Error::IoError(os(libc::EINVAL).into()), false),
+            (Error::IoError(os(libc::EACCES).into()), false),
+            (Error::IoError(io::Error::other("helper went away").into()), false),
+            (Error::SandboxRestricted { syscall: "socket", source: os(libc::EPERM).into() }, false),
+            (Error::Prohibited { interface: None, source: addr, dest: addr }, false),
+            (Error::NoGua(Ipv6Addr::UNSPECIFIED), false),
+            (Error::WrongIpVer(IpVersion::V4, addr), false),
+            (Error::GotMappedV4 { mapped: Ipv4Addr::LOCALHOST }, false),
+            (Error::FamilyDisabled(IpVersion::V6), false),
+            (Error::NoZone(Ipv6Addr::UNSPECIFIED), false),
+            (Error::InvalidDestination("::1%".into()), false),
+            (Error::NoScopes, false),
+            (Error::NoNameservers, false),
+        ];
+
+        for (err, expected) in table {
+            assert_eq!(err.is_transient(), expected, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn foreign_owners() {
+        #[rustfmt::skip]
+        let cases: [(&[u32], Option<Option<u32>>); 6] = [
+            (&[3], None),
+            // Shared with other interfaces, e.g. anycast.
+            (&[5, 3, 1], None),
+            (&[5], Some(Some(5))),
+            (&[7, 5], Some(Some(5))),
+            (&[5, 5], Some(Some(5))),
+            (&[], Some(None)),
+        ];
+
+        for (owners, foreign) in cases {
+            assert_eq!(foreign_owner(3, owners), foreign, "{:?}", owners);
+        }
+    }
+
+    /// Let the fake kernel dump the given addresses and run
+    /// the owner check of a query bound to interface 7.
+    fn check_owner(ip: IpAddr, owners: &[(u32, &str)]) -> Result<()> {
+        let msgs: Vec<_> = owners
+            .iter()
+            .map(|&(index, addr)| {
+                let addr = netlink::Addr {
+                    index,
+                    prefix_len: 64,
+                    flags: 0x80,
+                    local: Some(addr.parse().unwrap()),
+                    ..Default::default()
+                };
+                (netlink::RTM_NEWADDR, addr.to_payload())
+            })
+            .collect();
+        let kernel = netlink::mock::dump(msgs.into_iter());
+
+        let query = IpQuery {
+            if_index: NonZeroU32::new(7),
+            ..IpQuery::new("mock0").verify_owner(true)
+        };
+        let result = query.check_owner(ip);
+        kernel.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn owner_is_checked_against_the_dump() {
+        let ip: IpAddr = "2a01:4f8::1".parse().unwrap();
+
+        check_owner(ip, &[(7, "fd00::1"), (7, "2a01:4f8::1")]).unwrap();
+        // Anycast: the address is also assigned to `lo`.
+        check_owner(ip, &[(1, "2a01:4f8::1"), (7, "2a01:4f8::1")]).unwrap();
+
+        let result = check_owner(ip, &[(7, "fd00::1"), (1, "2a01:4f8::1")]);
+        assert!(
+            matches!(&result, Err(Error::ForeignSource { interface, addr, owner })
+                if interface.as_deref() == Some("mock0")
+                    && *addr == ip
+                    && owner.as_deref() == Some("lo")),
+            "{:?}",
+            result
+        );
+
+        let result = check_owner(ip, &[(7, "fd00::1"), (9, "2a01:4f8::2")]);
+        assert!(
+            matches!(result, Err(Error::ForeignSource { owner: None, .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "source address 2a01:4f8::1 chosen on interface mock0 isn't assigned to any interface"
+        );
+    }
+
+    #[test]
+    fn owner_check_is_opt_in() {
+        let ip: IpAddr = "2a01:4f8::1".parse().unwrap();
+
+        // Neither enumerates, so no fake kernel is needed.
+        IpQuery::new("mock0").check_owner(ip).unwrap();
+        IpQuery::any_interface()
+            .verify_owner(true)
+            .check_owner(ip)
+            .unwrap();
+        // Missing and mapped addresses are left to the callers.
+        let query = IpQuery::new("mock0").verify_owner(true);
+        query.check_owner(Ipv6Addr::UNSPECIFIED.into()).unwrap();
+        query
+            .check_owner("::ffff:192.168.1.1".parse().unwrap())
+            .unwrap();
+    }
+
+    // The flags of the synthetic candidates.
+    const TEMPORARY: u32 = 0x01;
+    const OPTIMISTIC: u32 = 0x04;
+    const DAD_FAILED: u32 = 0x08;
+    const DEPRECATED: u32 = 0x20;
+    const TENTATIVE: u32 = 0x40;
+
+    /// A candidate with the given remaining preferred lifetime in seconds,
+    /// `None` for an infinite one.
+    fn short_lived(addr: &str, flags: u32, lifetime: Option<u32>) -> netlink::Addr {
+        netlink::Addr {
+            index: 7,
+            prefix_len: 64,
+            flags,
+            local: Some(addr.parse().unwrap()),
+            lifetimes: Some((lifetime.unwrap_or(u32::MAX), u32::MAX)),
+            ..Default::default()
+        }
+    }
+
+    /// Let the fake kernel dump the candidates on the next enumeration.
+    fn candidates(addrs: &[netlink::Addr]) -> thread::JoinHandle<usize> {
+        let msgs: Vec<_> = addrs
+            .iter()
+            .map(|addr| (netlink::RTM_NEWADDR, addr.to_payload()))
+            .collect();
+        netlink::mock::dump(msgs.into_iter())
+    }
+
+    fn gua(ip: &IpAddr) -> bool {
+        Scope::from(Ipv6Scope::UnicastGlobal).contains(ip)
+    }
+
+    #[test]
+    fn lifetimes_are_checked_after_the_other_filters() {
+        let query = IpQuery::any_interface()
+            .optimistic_dad(OptimisticDad::Reject)
+            .min_preferred_lifetime(Duration::from_secs(300));
+        let verdict = |addr: netlink::Addr| {
+            let addr = addrs::InterfaceAddr::from_netlink(&addr).unwrap();
+            query.verdict(&addr, gua)
+        };
+        let rank = |deprecated, temporary| {
+            Verdict::Accepted(Rank {
+                deprecated,
+                temporary,
+            })
+        };
+
+        #[rustfmt::skip]
+        let cases = [
+            (short_lived("2a01:4f8::1", DAD_FAILED, Some(10)),              Verdict::DadFailed),
+            (short_lived("2a01:4f8::1", OPTIMISTIC | TENTATIVE, Some(10)),  Verdict::Optimistic),
+            (short_lived("2a01:4f8::1", TENTATIVE, Some(10)),               Verdict::Tentative),
+            (short_lived("fd00::1", 0, Some(10)),                           Verdict::OutOfScope),
+            (short_lived("2a01:4f8::1", 0, Some(10)),                       Verdict::ShortLifetime(Duration::from_secs(10))),
+            (short_lived("2a01:4f8::1", TEMPORARY, Some(299)),              Verdict::ShortLifetime(Duration::from_secs(299))),
+            (short_lived("2a01:4f8::1", 0, Some(300)),                      rank(false, false)),
+            (short_lived("2a01:4f8::1", 0, None),                           rank(false, false)),
+            (short_lived("2a01:4f8::1", DEPRECATED, None),                  rank(true, false)),
+        ];
+
+        for (addr, expected) in cases {
+            assert_eq!(verdict(addr.clone()), expected, "{:?}", addr);
+        }
+
+        // Without a minimum, short lifetimes don't matter.
+        let addr = short_lived("2a01:4f8::1", 0, Some(0));
+        let addr = addrs::InterfaceAddr::from_netlink(&addr).unwrap();
+        let unlimited = IpQuery::any_interface().min_preferred_lifetime(Duration::ZERO);
+        assert_eq!(unlimited.verdict(&addr, gua), rank(false, false));
+    }
+
+    #[test]
+    fn replacements_are_ranked_among_long_lived_candidates() {
+        let kernel = candidates(&[
+            // The best rank, but about to be deprecated.
+            short_lived("2a01:4f8::1", 0, Some(60)),
+            short_lived("2a01:4f8::2", DEPRECATED, None),
+            short_lived("2a01:4f8::3", TEMPORARY, Some(3600)),
+            short_lived("2a01:4f8::9", 0, Some(3600)),
+            // Lower, but filtered for other reasons.
+            short_lived("2a01:4f8::", DAD_FAILED, None),
+            short_lived("fd00::1", 0, None),
+        ]);
+
+        let query = IpQuery::any_interface().min_preferred_lifetime(Duration::from_secs(300));
+        let chosen = query.usable_source(gua).unwrap();
+        kernel.join().unwrap();
+        assert_eq!(chosen, Some("2a01:4f8::9".parse().unwrap()));
+    }
+
+    #[test]
+    fn temporary_and_deprecated_candidates_are_last_resorts() {
+        let kernel = candidates(&[
+            short_lived("2a01:4f8::1", 0, Some(60)),
+            short_lived("2a01:4f8::3", DEPRECATED, None),
+            short_lived("2a01:4f8::2", TEMPORARY, Some(3600)),
+        ]);
+
+        let query = IpQuery::any_interface().min_preferred_lifetime(Duration::from_secs(300));
+        let chosen = query.usable_source(gua).unwrap();
+        kernel.join().unwrap();
+        assert_eq!(chosen, Some("2a01:4f8::2".parse().unwrap()));
+    }
+
+    #[test]
+    fn longest_lived_rejection_is_reported() {
+        let kernel = candidates(&[
+            short_lived("2a01:4f8::1", 0, Some(10)),
+            short_lived("2a01:4f8::2", 0, Some(60)),
+            short_lived("2a01:4f8::3", TEMPORARY, Some(30)),
+            // Longer lived, but not candidates anyway.
+            short_lived("2a01:4f8::4", DAD_FAILED, Some(200)),
+            short_lived("fd00::1", 0, Some(200)),
+        ]);
+
+        let query = IpQuery::any_interface().min_preferred_lifetime(Duration::from_secs(300));
+        let longest = query.longest_lived(gua).unwrap().unwrap();
+        kernel.join().unwrap();
+        assert_eq!(longest.addr, "2a01:4f8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(longest.preferred_lifetime, Some(Duration::from_secs(60)));
+
+        let kernel = candidates(&[short_lived("2a01:4f8::1", 0, None)]);
+        assert!(query.longest_lived(gua).unwrap().is_none());
+        kernel.join().unwrap();
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&name| name.into()).collect()
+    }
+
+    #[test]
+    fn shared_addresses_of_bound_queries() {
+        let owners = names(&["veth0", "vrrp0", "vrrp1"]);
+        assert_eq!(
+            shared_with(Some("veth0"), owners.clone()),
+            ["vrrp0", "vrrp1"]
+        );
+        assert_eq!(shared_with(Some("vrrp1"), owners), ["veth0", "vrrp0"]);
+
+        assert!(shared_with(Some("veth0"), names(&["veth0"])).is_empty());
+        assert!(shared_with(Some("veth0"), Vec::new()).is_empty());
+        // A source of another interface is shared with all its owners.
+        assert_eq!(shared_with(Some("veth0"), names(&["veth1"])), ["veth1"]);
+    }
+
+    #[test]
+    fn shared_addresses_of_unbound_queries() {
+        assert!(shared_with(None, Vec::new()).is_empty());
+        assert!(shared_with(None, names(&["veth0"])).is_empty());
+        assert_eq!(
+            shared_with(None, names(&["veth0", "vrrp0"])),
+            ["veth0", "vrrp0"]
+        );
     }
 }
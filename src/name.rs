@@ -0,0 +1,193 @@
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+
+use crate::{interfaces, Error, Result};
+
+/// The maximum length of an interface name on Linux, in bytes,
+/// without the terminating NUL that `IFNAMSIZ` includes.
+const MAX_NAME_LEN: usize = libc::IFNAMSIZ - 1;
+
+/// An interface name that passed [`sanitize_interface_name`].
+///
+/// It dereferences to `&str`, so it can be passed to
+/// [`IpQuery::new`](crate::IpQuery::new) and the free functions
+/// like any other interface name.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InterfaceName(String);
+
+impl InterfaceName {
+    /// Get the validated name as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Make sure that an interface of this name exists by looking it up
+    /// in the interface list, failing with `ENODEV` otherwise.
+    pub fn verify_exists(self) -> Result<Self> {
+        if interfaces()?
+            .iter()
+            .any(|interface| interface.name == self.0)
+        {
+            Ok(self)
+        } else {
+            Err(io::Error::from_raw_os_error(libc::ENODEV).into())
+        }
+    }
+}
+
+impl Deref for InterfaceName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for InterfaceName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for InterfaceName {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(fmt)
+    }
+}
+
+/// Get the reason why the name isn't a valid interface name, if any.
+pub(crate) fn invalid_reason(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("empty")
+    } else if name.len() > MAX_NAME_LEN {
+        Some("too long")
+    } else if name == "." || name == ".." {
+        Some("reserved")
+    } else if name.contains(['/', ':']) {
+        Some("contains a path or alias separator")
+    } else if name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Some("contains whitespace or control characters")
+    } else {
+        None
+    }
+}
+
+/// Validate an interface name from untrusted input without passing it
+/// to the kernel, failing with [`Error::InvalidInterfaceName`].
+///
+/// The rules are those of Linux, the only supported platform:
+/// Names are at most 15 bytes long, aren't empty, `.` or `..`
+/// and don't contain `/`, `:` or whitespace. Control characters
+/// are rejected as well, even though the kernel accepts some of them.
+/// The name doesn't have to exist, see [`InterfaceName::verify_exists`].
+pub fn sanitize_interface_name(input: &str) -> Result<InterfaceName> {
+    match invalid_reason(input) {
+        Some(reason) => Err(Error::InvalidInterfaceName {
+            name: input.into(),
+            reason,
+        }),
+        None => Ok(InterfaceName(input.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The checks of `dev_valid_name` in the kernel.
+    fn kernel_accepts(name: &[u8]) -> bool {
+        !name.is_empty()
+            && name.len() < libc::IFNAMSIZ
+            && name != b"."
+            && name != b".."
+            && !name
+                .iter()
+                .any(|&b| b == b'/' || b == b':' || b == 0 || b.is_ascii_whitespace() || b == 0x0b)
+    }
+
+    fn check(input: &str) {
+        match sanitize_interface_name(input) {
+            Ok(name) => {
+                assert!(kernel_accepts(input.as_bytes()), "{:?}", input);
+                assert!(!input.chars().any(char::is_control), "{:?}", input);
+                assert_eq!(name.as_str(), input);
+            }
+            Err(Error::InvalidInterfaceName { name, reason }) => {
+                assert_eq!(name, input);
+                assert_eq!(Some(reason), invalid_reason(input));
+            }
+            Err(e) => panic!("{:?}: {}", input, e),
+        }
+    }
+
+    #[test]
+    fn name_rules() {
+        #[rustfmt::skip]
+        let cases = [
+            ("eth0",             None),
+            ("wlp3s0",           None),
+            ("veth-a_b.1@x",     None),
+            ("äöü",              None),
+            ("a23456789012345",  None),
+            ("a234567890123456", Some("too long")),
+            ("äöüäöüäö",         Some("too long")),
+            ("",                 Some("empty")),
+            (".",                Some("reserved")),
+            ("..",               Some("reserved")),
+            ("...",              None),
+            ("../etc",           Some("contains a path or alias separator")),
+            ("eth0:1",           Some("contains a path or alias separator")),
+            ("eth 0",            Some("contains whitespace or control characters")),
+            ("eth0\n",           Some("contains whitespace or control characters")),
+            ("eth\u{0}",         Some("contains whitespace or control characters")),
+            ("eth\u{7f}",        Some("contains whitespace or control characters")),
+            ("eth\u{a0}",        Some("contains whitespace or control characters")),
+            ("eth\u{2028}",      Some("contains whitespace or control characters")),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(invalid_reason(input), expected, "{:?}", input);
+            check(input);
+        }
+    }
+
+    #[test]
+    fn random_names_are_decided_like_the_kernel() {
+        let mut rng = fastrand::Rng::with_seed(0x1f_5e_ed);
+        for _ in 0..100_000 {
+            let len = rng.usize(..24);
+            // Mostly ASCII, to get past the length check often.
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| match rng.u8(..4) {
+                    0 => rng.u8(..),
+                    1 => *rng.choice(b"/:. \t\n\0").unwrap(),
+                    _ => rng.alphanumeric() as u8,
+                })
+                .collect();
+            let input = String::from_utf8_lossy(&bytes);
+            check(&input);
+
+            // Valid ASCII names are exactly those the kernel takes,
+            // apart from the control characters it doesn't check for.
+            if input.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+                assert_eq!(
+                    invalid_reason(&input).is_none(),
+                    kernel_accepts(input.as_bytes()),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn random_unicode_never_panics() {
+        let mut rng = fastrand::Rng::with_seed(0xc4a2);
+        for _ in 0..100_000 {
+            let len = rng.usize(..8);
+            let input: String = (0..len).map(|_| rng.char(..)).collect();
+            check(&input);
+        }
+    }
+}
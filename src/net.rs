@@ -0,0 +1,162 @@
+//! Conversions to and from the prefix types of the `ipnet` crate.
+
+use std::net::IpAddr;
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+
+use crate::{InterfaceAddr, IpPrefix};
+
+impl InterfaceAddr {
+    /// Get the address together with its prefix length.
+    /// Lengths above the size of the address are treated as its size.
+    pub fn net(&self) -> IpNet {
+        IpPrefix::from(self).net()
+    }
+}
+
+impl From<&InterfaceAddr> for IpNet {
+    fn from(addr: &InterfaceAddr) -> Self {
+        addr.net()
+    }
+}
+
+impl From<InterfaceAddr> for IpNet {
+    fn from(addr: InterfaceAddr) -> Self {
+        addr.net()
+    }
+}
+
+impl IpPrefix {
+    /// Get the prefix as an [`IpNet`].
+    /// Lengths above the size of the address are treated as its size.
+    pub fn net(&self) -> IpNet {
+        match self.addr {
+            IpAddr::V4(ipv4) => Ipv4Net::new_assert(ipv4, self.len.min(32)).into(),
+            IpAddr::V6(ipv6) => Ipv6Net::new_assert(ipv6, self.len.min(128)).into(),
+        }
+    }
+}
+
+impl From<IpPrefix> for IpNet {
+    fn from(prefix: IpPrefix) -> Self {
+        prefix.net()
+    }
+}
+
+impl From<IpNet> for IpPrefix {
+    fn from(net: IpNet) -> Self {
+        (net.addr(), net.prefix_len()).into()
+    }
+}
+
+impl From<Ipv6Net> for IpPrefix {
+    fn from(net: Ipv6Net) -> Self {
+        (net.addr(), net.prefix_len()).into()
+    }
+}
+
+impl From<Ipv4Net> for IpPrefix {
+    fn from(net: Ipv4Net) -> Self {
+        (net.addr(), net.prefix_len()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface_addr(addr: &str, prefix_len: u8) -> InterfaceAddr {
+        InterfaceAddr {
+            index: 2,
+            addr: addr.parse().unwrap(),
+            prefix_len,
+            flags: 0,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    #[test]
+    fn interface_addr_to_net() {
+        let ipv6 = interface_addr("2a01:4f8::1", 64);
+        assert_eq!(ipv6.net(), "2a01:4f8::1/64".parse::<IpNet>().unwrap());
+        assert_eq!(IpNet::from(&ipv6).trunc(), "2a01:4f8::/64".parse().unwrap());
+
+        let ipv4 = interface_addr("192.168.77.1", 24);
+        assert_eq!(IpNet::from(ipv4), "192.168.77.1/24".parse().unwrap());
+    }
+
+    #[test]
+    fn interface_addr_to_net_clamps_prefix_len() {
+        let ipv6 = interface_addr("2a01:4f8::1", 200);
+        assert_eq!(ipv6.net(), "2a01:4f8::1/128".parse::<IpNet>().unwrap());
+
+        let ipv4 = interface_addr("192.168.77.1", 33);
+        assert_eq!(ipv4.net(), "192.168.77.1/32".parse::<IpNet>().unwrap());
+    }
+
+    fn prefix(addr: &str, len: u8) -> IpPrefix {
+        (addr.parse::<IpAddr>().unwrap(), len).into()
+    }
+
+    #[test]
+    fn nets_to_prefixes() {
+        let ipv6: Ipv6Net = "2a01:4f8::1/64".parse().unwrap();
+        assert_eq!(IpPrefix::from(ipv6), prefix("2a01:4f8::1", 64));
+        assert_eq!(IpPrefix::from(IpNet::V6(ipv6)), prefix("2a01:4f8::1", 64));
+
+        let ipv4: Ipv4Net = "192.168.77.0/24".parse().unwrap();
+        assert_eq!(IpPrefix::from(ipv4), prefix("192.168.77.0", 24));
+        assert_eq!(IpPrefix::from(IpNet::V4(ipv4)), prefix("192.168.77.0", 24));
+    }
+
+    #[test]
+    fn prefixes_to_nets() {
+        let cases = [
+            (prefix("2a01:4f8::1", 64), "2a01:4f8::1/64"),
+            (prefix("2a01:4f8::1", 200), "2a01:4f8::1/128"),
+            (prefix("192.168.77.1", 24), "192.168.77.1/24"),
+            (prefix("192.168.77.1", 33), "192.168.77.1/32"),
+            (prefix("0.0.0.0", 0), "0.0.0.0/0"),
+        ];
+
+        for (prefix, net) in cases {
+            let net: IpNet = net.parse().unwrap();
+            assert_eq!(IpNet::from(prefix), net, "{:?}", prefix);
+            // Clamping aside, converting back is lossless.
+            assert_eq!(IpPrefix::from(net).net(), net);
+        }
+    }
+
+    #[test]
+    fn prefixes_contain_like_nets() {
+        let addrs = [
+            "2a01:4f8::1",
+            "2a01:4f8:0:1::1",
+            "fe80::1",
+            "192.168.77.1",
+            "10.0.0.1",
+        ];
+        let prefixes = [
+            prefix("2a01:4f8::", 64),
+            prefix("2a01:4f8::", 32),
+            prefix("::", 0),
+            prefix("192.168.0.0", 16),
+            prefix("0.0.0.0", 0),
+            prefix("10.0.0.1", 32),
+        ];
+
+        for prefix in prefixes {
+            for addr in addrs {
+                let addr: IpAddr = addr.parse().unwrap();
+                assert_eq!(
+                    prefix.contains(addr),
+                    prefix.net().contains(&addr),
+                    "{:?} {}",
+                    prefix,
+                    addr
+                );
+            }
+        }
+    }
+}
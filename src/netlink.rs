@@ -0,0 +1,1336 @@
+//! A minimal rtnetlink client covering the requests this crate needs.
+
+use std::io::{self, Read};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use std::os::fd::AsRawFd;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+const NETLINK_ROUTE: i32 = 0;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+const NLM_F_REQUEST: u16 = 0x01;
+const NLM_F_DUMP: u16 = 0x300;
+
+pub(crate) const RTM_NEWLINK: u16 = 16;
+pub(crate) const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+pub(crate) const RTM_NEWADDR: u16 = 20;
+pub(crate) const RTM_DELADDR: u16 = 21;
+const RTM_GETADDR: u16 = 22;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_GETROUTE: u16 = 26;
+const RTM_NEWADDRLABEL: u16 = 72;
+const RTM_GETADDRLABEL: u16 = 74;
+
+pub(crate) const RTMGRP_LINK: u32 = 0x01;
+pub(crate) const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+pub(crate) const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+
+const RTM_F_FIB_MATCH: u32 = 0x2000;
+
+const RTA_DST: u16 = 1;
+const RTA_SRC: u16 = 2;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PRIORITY: u16 = 6;
+const RTA_PREFSRC: u16 = 7;
+const RTA_MULTIPATH: u16 = 9;
+const RTA_TABLE: u16 = 15;
+
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_AF_SPEC: u16 = 26;
+const IFLA_INET6_ADDR_GEN_MODE: u16 = 8;
+const IFLA_INFO_KIND: u16 = 1;
+
+const IFAL_ADDRESS: u16 = 1;
+const IFAL_LABEL: u16 = 2;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+const IFA_BROADCAST: u16 = 4;
+const IFA_CACHEINFO: u16 = 6;
+const IFA_FLAGS: u16 = 8;
+
+pub(crate) const RTN_UNICAST: u8 = 1;
+
+pub(crate) const AF_INET: u8 = libc::AF_INET as u8;
+pub(crate) const AF_INET6: u8 = libc::AF_INET6 as u8;
+
+const IFADDRMSG_LEN: usize = 8;
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRLBLMSG_LEN: usize = 12;
+const RTMSG_LEN: usize = 12;
+const RTNH_LEN: usize = 8;
+
+/// A route as reported by the kernel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Route {
+    pub dst: Option<IpAddr>,
+    pub dst_len: u8,
+    pub table: u32,
+    /// The `RTN_*` type of the route.
+    pub ty: u8,
+    pub oif: Option<u32>,
+    pub gateway: Option<IpAddr>,
+    pub metric: Option<u32>,
+    pub prefsrc: Option<IpAddr>,
+    /// The outgoing interfaces of the nexthops of a multipath route.
+    pub multipath: Vec<u32>,
+}
+
+/// An interface address as reported by the kernel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Addr {
+    pub index: u32,
+    pub prefix_len: u8,
+    pub scope: u8,
+    pub flags: u32,
+    pub local: Option<IpAddr>,
+    pub address: Option<IpAddr>,
+    pub broadcast: Option<IpAddr>,
+    pub label: Option<String>,
+    /// The remaining preferred and valid lifetimes in seconds.
+    pub lifetimes: Option<(u32, u32)>,
+}
+
+/// A network interface as reported by the kernel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Link {
+    pub index: u32,
+    /// The `IFF_*` flags of the interface.
+    pub flags: u32,
+    pub name: Option<String>,
+    /// The kind of virtual interfaces, e.g. `vlan` or `bridge`.
+    pub kind: Option<String>,
+    /// The `IN6_ADDR_GEN_MODE_*` of the interface.
+    pub addr_gen_mode: Option<u8>,
+}
+
+/// An entry of the IPv6 address label table (RFC 6724).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct AddrLabel {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    /// The interface the entry is restricted to, 0 for all interfaces.
+    pub index: u32,
+    pub label: u32,
+}
+
+/// A netlink message, without the header.
+#[derive(Clone, Debug)]
+pub(crate) struct Message {
+    pub ty: u16,
+    pub payload: Vec<u8>,
+}
+
+/// The size of the receive buffer, large enough for any datagram
+/// the kernel sends in reply to dumps.
+const RECV_BUF_LEN: usize = 65536;
+
+/// A `NETLINK_ROUTE` socket.
+pub(crate) struct Netlink {
+    socket: Socket,
+    seq: u32,
+    /// The receive buffer, allocated on the first receive
+    /// and reused for every datagram after it.
+    buf: Vec<u8>,
+}
+
+impl Netlink {
+    /// Open a new socket. It is bound automatically when sending.
+    pub fn open() -> io::Result<Self> {
+        #[cfg(test)]
+        if let Some(socket) = mock::take() {
+            return Ok(Self {
+                socket,
+                seq: 0,
+                buf: Vec::new(),
+            });
+        }
+
+        let socket = Socket::new(
+            Domain::from(libc::AF_NETLINK),
+            Type::RAW,
+            Some(Protocol::from(NETLINK_ROUTE)),
+        )?;
+
+        Ok(Self {
+            socket,
+            seq: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Open a new socket that receives the notifications
+    /// of the given `RTMGRP_*` multicast groups.
+    pub fn subscribe(groups: u32) -> io::Result<Self> {
+        let netlink = Self::open()?;
+
+        // SAFETY: An all-zero `sockaddr_nl` is valid.
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = groups;
+
+        // SAFETY: `addr` is a valid `sockaddr_nl` of the given size
+        // and the file descriptor is owned by the socket.
+        let ret = unsafe {
+            libc::bind(
+                netlink.socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+
+        if ret == 0 {
+            Ok(netlink)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Set how long receiving notifications blocks, `None` for no limit.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Receive the notifications in the next datagram.
+    /// Fails with `ENOBUFS` if notifications were dropped
+    /// because the receive buffer overflowed.
+    pub fn recv_notifications(&mut self) -> io::Result<Vec<Message>> {
+        let n = self.read()?;
+
+        Ok(messages(&self.buf[..n])
+            .filter(|&(ty, ..)| ty != NLMSG_DONE && ty != NLMSG_ERROR)
+            .map(|(ty, _, payload)| Message {
+                ty,
+                payload: payload.to_vec(),
+            })
+            .collect())
+    }
+
+    fn send(&mut self, ty: u16, flags: u16, payload: &[u8]) -> io::Result<u32> {
+        self.seq = self.seq.wrapping_add(1);
+
+        let mut buf = Vec::with_capacity(NLMSG_HDRLEN + payload.len());
+        buf.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&(flags | NLM_F_REQUEST).to_ne_bytes());
+        buf.extend_from_slice(&self.seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(payload);
+
+        self.socket.send(&buf)?;
+        Ok(self.seq)
+    }
+
+    /// Read the next datagram into the receive buffer
+    /// and return its length.
+    fn read(&mut self) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            self.buf = vec![0; RECV_BUF_LEN];
+        }
+
+        (&self.socket).read(&mut self.buf)
+    }
+
+    /// Receive the messages in the next datagram that belong to
+    /// the given request, converting errors to `io::Error`.
+    /// The flag is set once the request is done, which can be
+    /// in the same datagram as the last messages.
+    /// Notifications on subscribed sockets are discarded.
+    fn recv(&mut self, seq: u32) -> io::Result<(Vec<Message>, bool)> {
+        let n = loop {
+            match self.read() {
+                // Notifications were lost, but the reply is still pending.
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {}
+                result => break result?,
+            }
+        };
+        let mut msgs = Vec::new();
+
+        for (ty, msg_seq, payload) in messages(&self.buf[..n]) {
+            if msg_seq != seq {
+                continue;
+            }
+
+            match ty {
+                NLMSG_DONE => return Ok((msgs, true)),
+                NLMSG_ERROR => {
+                    let errno = payload
+                        .get(..4)
+                        .map(|errno| i32::from_ne_bytes(errno.try_into().unwrap()))
+                        .unwrap_or(-libc::EINVAL);
+
+                    if errno == 0 {
+                        return Ok((msgs, true));
+                    } else {
+                        return Err(io::Error::from_raw_os_error(-errno));
+                    }
+                }
+                _ => msgs.push(Message {
+                    ty,
+                    payload: payload.to_vec(),
+                }),
+            }
+        }
+
+        Ok((msgs, false))
+    }
+
+    /// Send a request and return the first message of the reply.
+    pub fn request(&mut self, ty: u16, payload: &[u8]) -> io::Result<Message> {
+        let seq = self.send(ty, 0, payload)?;
+
+        loop {
+            match self.recv(seq)? {
+                (mut msgs, _) if !msgs.is_empty() => return Ok(msgs.remove(0)),
+                (_, false) => {}
+                (_, true) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            }
+        }
+    }
+
+    /// Send a dump request and return all messages of the reply.
+    pub fn dump(&mut self, ty: u16, payload: &[u8]) -> io::Result<Vec<Message>> {
+        let mut msgs = Vec::new();
+        self.dump_each(ty, payload, |msg| {
+            msgs.push(msg);
+            ControlFlow::<()>::Continue(())
+        })?;
+
+        Ok(msgs)
+    }
+
+    /// Send a dump request and pass the messages of the reply to `f`
+    /// as they arrive, until it breaks. Only one datagram is held
+    /// in memory at a time. The rest of the reply is left unread
+    /// after a break, so the socket must not be used for other
+    /// requests afterwards.
+    pub fn dump_each<B>(
+        &mut self,
+        ty: u16,
+        payload: &[u8],
+        mut f: impl FnMut(Message) -> ControlFlow<B>,
+    ) -> io::Result<Option<B>> {
+        let seq = self.send(ty, NLM_F_DUMP, payload)?;
+
+        loop {
+            let (part, done) = self.recv(seq)?;
+            for msg in part {
+                if let ControlFlow::Break(b) = f(msg) {
+                    return Ok(Some(b));
+                }
+            }
+
+            if done {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Dump the addresses of the given family, or of all families if `None`.
+    pub fn addrs(&mut self, family: Option<u8>) -> io::Result<Vec<Addr>> {
+        let mut addrs = Vec::new();
+        self.addrs_each(family, |addr| {
+            addrs.push(addr);
+            ControlFlow::<()>::Continue(())
+        })?;
+
+        Ok(addrs)
+    }
+
+    /// Dump the addresses of the given family, or of all families if `None`,
+    /// passing them to `f` until it breaks. See [`Netlink::dump_each`].
+    pub fn addrs_each<B>(
+        &mut self,
+        family: Option<u8>,
+        mut f: impl FnMut(Addr) -> ControlFlow<B>,
+    ) -> io::Result<Option<B>> {
+        let ifaddrmsg = [family.unwrap_or(0), 0, 0, 0, 0, 0, 0, 0];
+
+        self.dump_each(RTM_GETADDR, &ifaddrmsg, |msg| {
+            match parse_addr_msg(&msg.payload).filter(|_| msg.ty == RTM_NEWADDR) {
+                Some(addr) => f(addr),
+                None => ControlFlow::Continue(()),
+            }
+        })
+    }
+
+    /// Dump the interfaces.
+    pub fn links(&mut self) -> io::Result<Vec<Link>> {
+        let ifinfomsg = [0; IFINFOMSG_LEN];
+
+        Ok(self
+            .dump(RTM_GETLINK, &ifinfomsg)?
+            .iter()
+            .filter(|msg| msg.ty == RTM_NEWLINK)
+            .filter_map(|msg| parse_link_msg(&msg.payload))
+            .collect())
+    }
+
+    /// Dump the IPv6 address label table.
+    pub fn addr_labels(&mut self) -> io::Result<Vec<AddrLabel>> {
+        let ifaddrlblmsg = [AF_INET6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        Ok(self
+            .dump(RTM_GETADDRLABEL, &ifaddrlblmsg)?
+            .iter()
+            .filter(|msg| msg.ty == RTM_NEWADDRLABEL)
+            .filter_map(|msg| parse_addrlabel_msg(&msg.payload))
+            .collect())
+    }
+
+    /// Dump the routes of all tables of the given family.
+    pub fn routes(&mut self, family: u8) -> io::Result<Vec<Route>> {
+        Ok(self
+            .dump(RTM_GETROUTE, &rtmsg(family, 0, 0))?
+            .iter()
+            .filter(|msg| msg.ty == RTM_NEWROUTE)
+            .filter_map(|msg| parse_route(&msg.payload))
+            .collect())
+    }
+
+    /// Look up the route the kernel uses towards the given destination,
+    /// optionally constrained to the given outgoing interface.
+    /// If `fib_match` is set the matching routing table entry
+    /// is returned instead of the resolved route.
+    pub fn route_get(
+        &mut self,
+        dest: IpAddr,
+        oif: Option<u32>,
+        fib_match: bool,
+    ) -> io::Result<Route> {
+        self.lookup_route(dest, None, oif, fib_match)
+    }
+
+    /// Look up the route the kernel uses towards the given destination
+    /// for packets from the given source address,
+    /// optionally constrained to the given outgoing interface.
+    pub fn route_get_from(
+        &mut self,
+        dest: IpAddr,
+        source: IpAddr,
+        oif: Option<u32>,
+    ) -> io::Result<Route> {
+        self.lookup_route(dest, Some(source), oif, false)
+    }
+
+    fn lookup_route(
+        &mut self,
+        dest: IpAddr,
+        source: Option<IpAddr>,
+        oif: Option<u32>,
+        fib_match: bool,
+    ) -> io::Result<Route> {
+        let payload = route_request(dest, source, oif, fib_match);
+        let msg = self.request(RTM_GETROUTE, &payload)?;
+        if msg.ty != RTM_NEWROUTE {
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+
+        parse_route(&msg.payload).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+    }
+}
+
+/// Encode the payload of an `RTM_GETROUTE` lookup.
+fn route_request(
+    dest: IpAddr,
+    source: Option<IpAddr>,
+    oif: Option<u32>,
+    fib_match: bool,
+) -> Vec<u8> {
+    let octets = |addr| match addr {
+        IpAddr::V4(ipv4) => ipv4.octets().to_vec(),
+        IpAddr::V6(ipv6) => ipv6.octets().to_vec(),
+    };
+
+    let family = if dest.is_ipv4() { AF_INET } else { AF_INET6 };
+    let addr = octets(dest);
+    let flags = if fib_match { RTM_F_FIB_MATCH } else { 0 };
+
+    let mut payload = rtmsg(family, (addr.len() * 8) as u8, flags);
+    push_attr(&mut payload, RTA_DST, &addr);
+    if let Some(source) = source {
+        push_attr(&mut payload, RTA_SRC, &octets(source));
+    }
+    if let Some(oif) = oif {
+        push_attr(&mut payload, RTA_OIF, &oif.to_ne_bytes());
+    }
+
+    payload
+}
+
+fn rtmsg(family: u8, dst_len: u8, flags: u32) -> Vec<u8> {
+    let mut buf = vec![family, dst_len, 0, 0, 0, 0, 0, 0];
+    buf.extend_from_slice(&flags.to_ne_bytes());
+    buf
+}
+
+const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn push_attr(buf: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    buf.extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+    buf.extend_from_slice(&ty.to_ne_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(align(buf.len()), 0);
+}
+
+/// Iterate over the netlink messages in a datagram
+/// as `(type, sequence number, payload)`.
+pub(crate) fn messages(mut buf: &[u8]) -> impl Iterator<Item = (u16, u32, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = u32::from_ne_bytes(buf.get(..4)?.try_into().unwrap()) as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return None;
+        }
+
+        let ty = u16::from_ne_bytes(buf[4..6].try_into().unwrap());
+        let seq = u32::from_ne_bytes(buf[8..12].try_into().unwrap());
+        let payload = &buf[NLMSG_HDRLEN..len];
+
+        buf = buf.get(align(len)..).unwrap_or_default();
+        Some((ty, seq, payload))
+    })
+}
+
+/// Iterate over the route attributes in a buffer as `(type, data)`.
+pub(crate) fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = u16::from_ne_bytes(buf.get(..2)?.try_into().unwrap()) as usize;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+
+        // The upper bits are the nested and byte order flags.
+        let ty = u16::from_ne_bytes(buf[2..4].try_into().unwrap()) & 0x3fff;
+        let data = &buf[4..len];
+
+        buf = buf.get(align(len)..).unwrap_or_default();
+        Some((ty, data))
+    })
+}
+
+/// Parse a NUL-terminated string attribute.
+fn parse_string(data: &[u8]) -> String {
+    let string = data.split(|&b| b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(string).into_owned()
+}
+
+pub(crate) fn parse_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().unwrap()))
+}
+
+pub(crate) fn parse_addr(data: &[u8]) -> Option<IpAddr> {
+    match data.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(data).unwrap()).into()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(data).unwrap()).into()),
+        _ => None,
+    }
+}
+
+/// Parse the payload of an `RTM_NEWROUTE` message.
+pub(crate) fn parse_route(payload: &[u8]) -> Option<Route> {
+    let header = payload.get(..RTMSG_LEN)?;
+
+    let mut route = Route {
+        dst_len: header[1],
+        table: header[4].into(),
+        ty: header[7],
+        ..Default::default()
+    };
+
+    for (ty, data) in attrs(&payload[RTMSG_LEN..]) {
+        match ty {
+            RTA_DST => route.dst = parse_addr(data),
+            RTA_OIF => route.oif = parse_u32(data),
+            RTA_GATEWAY => route.gateway = parse_addr(data),
+            RTA_PRIORITY => route.metric = parse_u32(data),
+            RTA_PREFSRC => route.prefsrc = parse_addr(data),
+            RTA_TABLE => route.table = parse_u32(data).unwrap_or(route.table),
+            RTA_MULTIPATH => route.multipath = parse_multipath(data),
+            _ => {}
+        }
+    }
+
+    Some(route)
+}
+
+fn parse_multipath(mut data: &[u8]) -> Vec<u32> {
+    let mut oifs = Vec::new();
+
+    while let Some(header) = data.get(..RTNH_LEN) {
+        let len = u16::from_ne_bytes(header[..2].try_into().unwrap()) as usize;
+        if len < RTNH_LEN {
+            break;
+        }
+
+        oifs.push(parse_u32(&header[4..]).unwrap());
+        data = data.get(align(len)..).unwrap_or_default();
+    }
+
+    oifs
+}
+
+/// Parse the payload of an `RTM_NEWADDR` message.
+pub(crate) fn parse_addr_msg(payload: &[u8]) -> Option<Addr> {
+    let header = payload.get(..IFADDRMSG_LEN)?;
+
+    let mut addr = Addr {
+        prefix_len: header[1],
+        flags: header[2].into(),
+        scope: header[3],
+        index: parse_u32(&header[4..])?,
+        ..Default::default()
+    };
+
+    for (ty, data) in attrs(&payload[IFADDRMSG_LEN..]) {
+        match ty {
+            IFA_ADDRESS => addr.address = parse_addr(data),
+            IFA_LOCAL => addr.local = parse_addr(data),
+            IFA_BROADCAST => addr.broadcast = parse_addr(data),
+            IFA_LABEL => addr.label = Some(parse_string(data)),
+            IFA_CACHEINFO => {
+                addr.lifetimes = parse_u32(data).zip(data.get(4..).and_then(parse_u32));
+            }
+            // Supersedes the 8 bit flags in the header.
+            IFA_FLAGS => addr.flags = parse_u32(data).unwrap_or(addr.flags),
+            _ => {}
+        }
+    }
+
+    Some(addr)
+}
+
+/// Parse the payload of an `RTM_NEWLINK` or `RTM_DELLINK` message.
+pub(crate) fn parse_link_msg(payload: &[u8]) -> Option<Link> {
+    let header = payload.get(..IFINFOMSG_LEN)?;
+
+    let mut link = Link {
+        index: parse_u32(&header[4..])?,
+        flags: parse_u32(&header[8..])?,
+        ..Default::default()
+    };
+
+    for (ty, data) in attrs(&payload[IFINFOMSG_LEN..]) {
+        match ty {
+            IFLA_IFNAME => link.name = Some(parse_string(data)),
+            IFLA_LINKINFO => {
+                link.kind = attrs(data)
+                    .find(|&(ty, _)| ty == IFLA_INFO_KIND)
+                    .map(|(_, data)| parse_string(data));
+            }
+            IFLA_AF_SPEC => {
+                link.addr_gen_mode = attrs(data)
+                    .find(|&(family, _)| family == libc::AF_INET6 as u16)
+                    .and_then(|(_, inet6)| {
+                        attrs(inet6).find(|&(ty, _)| ty == IFLA_INET6_ADDR_GEN_MODE)
+                    })
+                    .and_then(|(_, data)| data.first().copied());
+            }
+            _ => {}
+        }
+    }
+
+    Some(link)
+}
+
+/// Parse the payload of an `RTM_NEWADDRLABEL` message.
+pub(crate) fn parse_addrlabel_msg(payload: &[u8]) -> Option<AddrLabel> {
+    let header = payload.get(..IFADDRLBLMSG_LEN)?;
+
+    let mut prefix = None;
+    let mut label = None;
+    for (ty, data) in attrs(&payload[IFADDRLBLMSG_LEN..]) {
+        match ty {
+            IFAL_ADDRESS => prefix = <[u8; 16]>::try_from(data).ok().map(Ipv6Addr::from),
+            IFAL_LABEL => label = parse_u32(data),
+            _ => {}
+        }
+    }
+
+    Some(AddrLabel {
+        prefix: prefix?,
+        prefix_len: header[2],
+        index: parse_u32(&header[4..])?,
+        label: label?,
+    })
+}
+
+#[cfg(test)]
+impl Link {
+    /// Encode the name and flags as the payload of a link message.
+    pub(crate) fn to_payload(&self) -> Vec<u8> {
+        let mut payload = vec![libc::AF_UNSPEC as u8, 0, 0, 0];
+        payload.extend_from_slice(&self.index.to_ne_bytes());
+        payload.extend_from_slice(&self.flags.to_ne_bytes());
+        payload.extend_from_slice(&u32::MAX.to_ne_bytes());
+        if let Some(name) = &self.name {
+            push_attr(&mut payload, IFLA_IFNAME, format!("{}\0", name).as_bytes());
+        }
+        payload
+    }
+}
+
+#[cfg(test)]
+impl Addr {
+    /// Encode the local address, flags and lifetimes as the payload
+    /// of an address message.
+    pub(crate) fn to_payload(&self) -> Vec<u8> {
+        let family = match self.local {
+            Some(IpAddr::V4(_)) => AF_INET,
+            _ => AF_INET6,
+        };
+
+        let mut payload = vec![family, self.prefix_len, self.flags as u8, self.scope];
+        payload.extend_from_slice(&self.index.to_ne_bytes());
+        match self.local {
+            Some(IpAddr::V4(ipv4)) => push_attr(&mut payload, IFA_LOCAL, &ipv4.octets()),
+            Some(IpAddr::V6(ipv6)) => push_attr(&mut payload, IFA_LOCAL, &ipv6.octets()),
+            None => {}
+        }
+        push_attr(&mut payload, IFA_FLAGS, &self.flags.to_ne_bytes());
+        if let Some((preferred, valid)) = self.lifetimes {
+            let mut cacheinfo = [0; 16];
+            cacheinfo[..4].copy_from_slice(&preferred.to_ne_bytes());
+            cacheinfo[4..8].copy_from_slice(&valid.to_ne_bytes());
+            push_attr(&mut payload, IFA_CACHEINFO, &cacheinfo);
+        }
+        payload
+    }
+}
+
+/// Replies replayed by a fake kernel, for tests that need
+/// more than a test network can provide.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::cell::RefCell;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    const NLM_F_MULTI: u16 = 0x02;
+
+    /// The size of the datagrams of the fake kernel,
+    /// about what the kernel uses for dumps.
+    const DATAGRAM_LEN: usize = 32768;
+
+    thread_local! {
+        static NEXT: RefCell<Option<Socket>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn take() -> Option<Socket> {
+        NEXT.with(|next| next.borrow_mut().take())
+    }
+
+    fn message(ty: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(NLMSG_HDRLEN + payload.len());
+        buf.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&NLM_F_MULTI.to_ne_bytes());
+        buf.extend_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(payload);
+        buf.resize(align(buf.len()), 0);
+        buf
+    }
+
+    /// Answer the first request of the next [`Netlink::open`] on this
+    /// thread with a dump of the given messages, generated lazily.
+    /// The returned thread counts the messages that were sent
+    /// before the dump was done or the socket was closed.
+    pub(crate) fn dump(
+        msgs: impl Iterator<Item = (u16, Vec<u8>)> + Send + 'static,
+    ) -> JoinHandle<usize> {
+        let (ours, kernel) = Socket::pair(Domain::UNIX, Type::DGRAM, None).unwrap();
+        NEXT.with(|next| *next.borrow_mut() = Some(ours));
+
+        thread::spawn(move || {
+            let mut request = [0; 4096];
+            let n = (&kernel).read(&mut request).unwrap();
+            let (_, seq, _) = messages(&request[..n]).next().unwrap();
+
+            let mut sent = 0;
+            let mut datagram = Vec::with_capacity(DATAGRAM_LEN);
+            let mut pending = 0;
+            for (ty, payload) in msgs {
+                let msg = message(ty, seq, &payload);
+                if datagram.len() + msg.len() > DATAGRAM_LEN {
+                    if kernel.send(&datagram).is_err() {
+                        return sent;
+                    }
+                    sent += pending;
+                    datagram.clear();
+                    pending = 0;
+                }
+                datagram.extend_from_slice(&msg);
+                pending += 1;
+            }
+
+            datagram.extend_from_slice(&message(NLMSG_DONE, seq, &0i32.to_ne_bytes()));
+            if kernel.send(&datagram).is_ok() {
+                sent += pending;
+            }
+            sent
+        })
+    }
+}
+
+// The messages were captured on x86_64, so they are little-endian.
+#[cfg(all(test, target_endian = "little"))]
+pub(crate) mod tests {
+    use super::*;
+
+    /// `RTM_NEWADDR` of `192.168.77.2/24 dev veth0 label veth0:mgmt`.
+    #[rustfmt::skip]
+    const NEWADDR_LABELED: &[u8] = &[
+        // ifaddrmsg: AF_INET, /24, IFA_F_SECONDARY | IFA_F_PERMANENT, universe, index 3
+        0x02, 0x18, 0x81, 0x00, 0x03, 0x00, 0x00, 0x00,
+        // IFA_ADDRESS
+        0x08, 0x00, 0x01, 0x00, 0xc0, 0xa8, 0x4d, 0x02,
+        // IFA_LOCAL
+        0x08, 0x00, 0x02, 0x00, 0xc0, 0xa8, 0x4d, 0x02,
+        // IFA_LABEL "veth0:mgmt"
+        0x0f, 0x00, 0x03, 0x00, 0x76, 0x65, 0x74, 0x68,
+        0x30, 0x3a, 0x6d, 0x67, 0x6d, 0x74, 0x00, 0x00,
+        // IFA_FLAGS
+        0x08, 0x00, 0x08, 0x00, 0x81, 0x00, 0x00, 0x00,
+        // IFA_CACHEINFO: forever, forever, created, updated
+        0x14, 0x00, 0x06, 0x00, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0x41, 0x60, 0x0b, 0x00,
+        0x41, 0x60, 0x0b, 0x00,
+    ];
+
+    #[test]
+    fn parse_labeled_addr() {
+        let addr = parse_addr_msg(NEWADDR_LABELED).unwrap();
+
+        assert_eq!(addr.index, 3);
+        assert_eq!(addr.prefix_len, 24);
+        assert_eq!(addr.flags, 0x81);
+        assert_eq!(addr.local, Some(Ipv4Addr::new(192, 168, 77, 2).into()));
+        assert_eq!(addr.address, Some(Ipv4Addr::new(192, 168, 77, 2).into()));
+        assert_eq!(addr.broadcast, None);
+        assert_eq!(addr.label.as_deref(), Some("veth0:mgmt"));
+        assert_eq!(addr.lifetimes, Some((u32::MAX, u32::MAX)));
+    }
+
+    #[test]
+    fn parse_labels() {
+        let mut payload = NEWADDR_LABELED[..IFADDRMSG_LEN].to_vec();
+        push_attr(&mut payload, IFA_LABEL, b"eth0:0");
+        assert_eq!(
+            parse_addr_msg(&payload).unwrap().label.as_deref(),
+            Some("eth0:0")
+        );
+
+        // Invalid UTF-8 is replaced rather than dropping the label.
+        let mut payload = NEWADDR_LABELED[..IFADDRMSG_LEN].to_vec();
+        push_attr(&mut payload, IFA_LABEL, b"eth0:\xff\0");
+        assert_eq!(
+            parse_addr_msg(&payload).unwrap().label.as_deref(),
+            Some("eth0:\u{fffd}")
+        );
+
+        let payload = &NEWADDR_LABELED[..IFADDRMSG_LEN];
+        assert_eq!(parse_addr_msg(payload).unwrap().label, None);
+    }
+
+    /// `RTM_NEWLINK` of `cap0 type veth peer name cap1` with `addrgenmode none`,
+    /// trimmed to the attributes read and a few neighbours. The IPv6
+    /// configuration and statistics of `AF_INET6` are left out.
+    #[rustfmt::skip]
+    const NEWLINK_VETH: &[u8] = &[
+        // ifinfomsg: AF_UNSPEC, ARPHRD_ETHER, index 6, IFF_BROADCAST | IFF_MULTICAST
+        0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00, 0x00,
+        0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // IFLA_IFNAME
+        0x09, 0x00, 0x03, 0x00, 0x63, 0x61, 0x70, 0x30,
+        0x00, 0x00, 0x00, 0x00,
+        // IFLA_MTU
+        0x08, 0x00, 0x04, 0x00, 0xdc, 0x05, 0x00, 0x00,
+        // IFLA_LINKINFO
+        0x10, 0x00, 0x12, 0x00, 0x09, 0x00, 0x01, 0x00,
+        0x76, 0x65, 0x74, 0x68, 0x00, 0x00, 0x00, 0x00,
+        // IFLA_AF_SPEC
+        0xb8, 0x00, 0x1a, 0x00,
+        // AF_INET
+        0x8c, 0x00, 0x02, 0x00, 0x88, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x10, 0x27, 0x00, 0x00, 0xe8, 0x03, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00,
+        // AF_INET6
+        0x28, 0x00, 0x0a, 0x00,
+        // IFLA_INET6_FLAGS
+        0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // IFLA_INET6_CACHEINFO
+        0x14, 0x00, 0x05, 0x00, 0xff, 0xff, 0x00, 0x00,
+        0x21, 0x68, 0x12, 0x00, 0x64, 0xac, 0x00, 0x00,
+        0xe8, 0x03, 0x00, 0x00,
+        // IFLA_INET6_ADDR_GEN_MODE
+        0x05, 0x00, 0x08, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_captured_link() {
+        let link = parse_link_msg(NEWLINK_VETH).unwrap();
+
+        assert_eq!(link.index, 6);
+        assert_eq!(
+            link.flags,
+            (libc::IFF_BROADCAST | libc::IFF_MULTICAST) as u32
+        );
+        assert_eq!(link.name.as_deref(), Some("cap0"));
+        assert_eq!(link.kind.as_deref(), Some("veth"));
+        assert_eq!(link.addr_gen_mode, Some(1));
+    }
+
+    #[test]
+    fn parse_link_without_inet6() {
+        assert_eq!(parse_link_msg(&NEWLINK_VETH[..IFINFOMSG_LEN - 1]), None);
+
+        // Only the AF_INET family is left of IFLA_AF_SPEC.
+        let af_spec = NEWLINK_VETH.len() - 40 - 140 - 4;
+        let mut payload = NEWLINK_VETH[..af_spec].to_vec();
+        push_attr(
+            &mut payload,
+            IFLA_AF_SPEC,
+            &NEWLINK_VETH[af_spec + 4..][..140],
+        );
+        let link = parse_link_msg(&payload).unwrap();
+        assert_eq!(link.kind.as_deref(), Some("veth"));
+        assert_eq!(link.addr_gen_mode, None);
+
+        // A truncated IFLA_AF_SPEC is dropped as a whole.
+        let link = parse_link_msg(&NEWLINK_VETH[..NEWLINK_VETH.len() - 4]).unwrap();
+        assert_eq!(link.name.as_deref(), Some("cap0"));
+        assert_eq!(link.addr_gen_mode, None);
+    }
+
+    /// `RTM_NEWADDRLABEL` of `prefix 2001:db8:77::/48 dev cap0 label 77`.
+    #[rustfmt::skip]
+    const NEWADDRLABEL_DEV: &[u8] = &[
+        // ifaddrlblmsg: AF_INET6, /48, index 6, seq 12
+        0x0a, 0x00, 0x30, 0x00, 0x06, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x00, 0x00,
+        // IFAL_ADDRESS
+        0x14, 0x00, 0x01, 0x00, 0x20, 0x01, 0x0d, 0xb8,
+        0x00, 0x77, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        // IFAL_LABEL
+        0x08, 0x00, 0x02, 0x00, 0x4d, 0x00, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWADDRLABEL` of the default `prefix ::ffff:0.0.0.0/96 label 4`.
+    #[rustfmt::skip]
+    const NEWADDRLABEL_MAPPED: &[u8] = &[
+        // ifaddrlblmsg: AF_INET6, /96, all interfaces, seq 12
+        0x0a, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x00, 0x00,
+        // IFAL_ADDRESS
+        0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
+        0x00, 0x00, 0x00, 0x00,
+        // IFAL_LABEL
+        0x08, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_captured_addrlabels() {
+        assert_eq!(
+            parse_addrlabel_msg(NEWADDRLABEL_DEV),
+            Some(AddrLabel {
+                prefix: "2001:db8:77::".parse().unwrap(),
+                prefix_len: 48,
+                index: 6,
+                label: 77,
+            })
+        );
+        assert_eq!(
+            parse_addrlabel_msg(NEWADDRLABEL_MAPPED),
+            Some(AddrLabel {
+                prefix: "::ffff:0.0.0.0".parse().unwrap(),
+                prefix_len: 96,
+                index: 0,
+                label: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_incomplete_addrlabels() {
+        assert_eq!(
+            parse_addrlabel_msg(&NEWADDRLABEL_DEV[..IFADDRLBLMSG_LEN - 1]),
+            None
+        );
+
+        // Entries without a label or prefix are dropped.
+        let without_label = &NEWADDRLABEL_DEV[..IFADDRLBLMSG_LEN + 20];
+        assert_eq!(parse_addrlabel_msg(without_label), None);
+
+        let mut without_prefix = NEWADDRLABEL_DEV[..IFADDRLBLMSG_LEN].to_vec();
+        push_attr(&mut without_prefix, IFAL_LABEL, &77u32.to_ne_bytes());
+        assert_eq!(parse_addrlabel_msg(&without_prefix), None);
+
+        // So are prefixes of the wrong length.
+        let mut short_prefix = NEWADDRLABEL_DEV[..IFADDRLBLMSG_LEN].to_vec();
+        push_attr(&mut short_prefix, IFAL_ADDRESS, &[0x20, 0x01, 0x0d, 0xb8]);
+        push_attr(&mut short_prefix, IFAL_LABEL, &77u32.to_ne_bytes());
+        assert_eq!(parse_addrlabel_msg(&short_prefix), None);
+    }
+
+    // The routes were captured with wan1 at index 3 and wan2 at index 5.
+
+    /// `RTM_NEWROUTE` of `default via 10.1.0.1 dev wan1 metric 100` from a dump.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_DEFAULT_VIA: &[u8] = &[
+        // rtmsg: AF_INET, /0, main table, boot, universe, unicast
+        0x02, 0x00, 0x00, 0x00, 0xfe, 0x03, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0x64, 0x00, 0x00, 0x00,
+        // RTA_GATEWAY
+        0x08, 0x00, 0x05, 0x00, 0x0a, 0x01, 0x00, 0x01,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWROUTE` of `default via 10.2.0.1 dev wan2 metric 200 src 10.2.0.3`.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_DEFAULT_SRC: &[u8] = &[
+        // rtmsg: AF_INET, /0, main table, boot, universe, unicast
+        0x02, 0x00, 0x00, 0x00, 0xfe, 0x03, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0xc8, 0x00, 0x00, 0x00,
+        // RTA_PREFSRC
+        0x08, 0x00, 0x07, 0x00, 0x0a, 0x02, 0x00, 0x03,
+        // RTA_GATEWAY
+        0x08, 0x00, 0x05, 0x00, 0x0a, 0x02, 0x00, 0x01,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWROUTE` of `default dev wan2 metric 300 table 1000`.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_DEFAULT_TABLE: &[u8] = &[
+        // rtmsg: AF_INET, /0, RT_TABLE_COMPAT, boot, link, unicast
+        0x02, 0x00, 0x00, 0x00, 0xfc, 0x03, 0xfd, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xe8, 0x03, 0x00, 0x00,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0x2c, 0x01, 0x00, 0x00,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWROUTE` of `blackhole default metric 400`.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_BLACKHOLE: &[u8] = &[
+        // rtmsg: AF_INET, /0, main table, boot, universe, blackhole
+        0x02, 0x00, 0x00, 0x00, 0xfe, 0x03, 0x00, 0x06,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0x90, 0x01, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWROUTE` of the prefix route `10.1.0.0/24 dev wan1 src 10.1.0.2`.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_SUBNET: &[u8] = &[
+        // rtmsg: AF_INET, /24, main table, kernel, link, unicast
+        0x02, 0x18, 0x00, 0x00, 0xfe, 0x02, 0xfd, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_DST
+        0x08, 0x00, 0x01, 0x00, 0x0a, 0x01, 0x00, 0x00,
+        // RTA_PREFSRC
+        0x08, 0x00, 0x07, 0x00, 0x0a, 0x01, 0x00, 0x02,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00,
+    ];
+
+    /// `RTM_NEWROUTE` of `default metric 500
+    /// nexthop via 10.1.0.1 dev wan1 nexthop via 10.2.0.1 dev wan2`.
+    #[rustfmt::skip]
+    pub(crate) const DUMP_MULTIPATH: &[u8] = &[
+        // rtmsg: AF_INET, /0, main table, boot, universe, unicast
+        0x02, 0x00, 0x00, 0x00, 0xfe, 0x03, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0xf4, 0x01, 0x00, 0x00,
+        // RTA_MULTIPATH: rtnexthop of wan1 with RTA_GATEWAY,
+        // rtnexthop of wan2 with RTA_GATEWAY
+        0x24, 0x00, 0x09, 0x00, 0x10, 0x00, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x0a, 0x01, 0x00, 0x01, 0x10, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x0a, 0x02, 0x00, 0x01,
+    ];
+
+    /// The response to `ip route get 192.0.2.1 oif wan2`.
+    #[rustfmt::skip]
+    pub(crate) const GET_VIA_WAN2: &[u8] = &[
+        // rtmsg: AF_INET, /32, main table, unspec, universe, unicast, RTM_F_CLONED
+        0x02, 0x20, 0x00, 0x00, 0xfe, 0x00, 0x00, 0x01,
+        0x00, 0x02, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_DST
+        0x08, 0x00, 0x01, 0x00, 0xc0, 0x00, 0x02, 0x01,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00,
+        // RTA_PREFSRC
+        0x08, 0x00, 0x07, 0x00, 0x0a, 0x02, 0x00, 0x03,
+        // RTA_GATEWAY
+        0x08, 0x00, 0x05, 0x00, 0x0a, 0x02, 0x00, 0x01,
+        // RTA_UID
+        0x08, 0x00, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // RTA_CACHEINFO
+        0x24, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// The response to `ip route get 192.0.2.1 oif wan1` without a default
+    /// route through wan1, which treats the destination as on-link.
+    #[rustfmt::skip]
+    pub(crate) const GET_ONLINK: &[u8] = &[
+        // rtmsg: AF_INET, /32, main table, unspec, universe, unicast, RTM_F_CLONED
+        0x02, 0x20, 0x00, 0x00, 0xfe, 0x00, 0x00, 0x01,
+        0x00, 0x02, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_DST
+        0x08, 0x00, 0x01, 0x00, 0xc0, 0x00, 0x02, 0x01,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00,
+        // RTA_PREFSRC
+        0x08, 0x00, 0x07, 0x00, 0x0a, 0x01, 0x00, 0x02,
+        // RTA_UID
+        0x08, 0x00, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // RTA_CACHEINFO
+        0x24, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// The response to `ip route get 2001:db8:ffff::1 oif wan1`
+    /// through `default via fe80::1 dev wan1 metric 1024`.
+    #[rustfmt::skip]
+    pub(crate) const GET_VIA_V6: &[u8] = &[
+        // rtmsg: AF_INET6, /128, from /128, main table, boot, universe, unicast
+        0x0a, 0x80, 0x80, 0x00, 0xfe, 0x03, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_TABLE
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        // RTA_DST
+        0x14, 0x00, 0x01, 0x00, 0x20, 0x01, 0x0d, 0xb8,
+        0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01,
+        // RTA_SRC
+        0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_PREFSRC
+        0x14, 0x00, 0x07, 0x00, 0x20, 0x01, 0x0d, 0xb8,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x02,
+        // RTA_PRIORITY
+        0x08, 0x00, 0x06, 0x00, 0x00, 0x04, 0x00, 0x00,
+        // RTA_GATEWAY
+        0x14, 0x00, 0x05, 0x00, 0xfe, 0x80, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01,
+        // RTA_OIF
+        0x08, 0x00, 0x04, 0x00, 0x03, 0x00, 0x00, 0x00,
+        // RTA_CACHEINFO
+        0x24, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        // RTA_PREF
+        0x05, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    fn v4(addr: &str) -> Option<IpAddr> {
+        Some(IpAddr::V4(addr.parse().unwrap()))
+    }
+
+    #[test]
+    fn parse_dumped_default_routes() {
+        assert_eq!(
+            parse_route(DUMP_DEFAULT_VIA).unwrap(),
+            Route {
+                dst_len: 0,
+                table: 254,
+                ty: RTN_UNICAST,
+                oif: Some(3),
+                gateway: v4("10.1.0.1"),
+                metric: Some(100),
+                ..Default::default()
+            }
+        );
+
+        let route = parse_route(DUMP_DEFAULT_SRC).unwrap();
+        assert_eq!(route.prefsrc, v4("10.2.0.3"));
+        assert_eq!(route.gateway, v4("10.2.0.1"));
+        assert_eq!((route.oif, route.metric), (Some(5), Some(200)));
+
+        // Tables above 255 are only in RTA_TABLE.
+        let route = parse_route(DUMP_DEFAULT_TABLE).unwrap();
+        assert_eq!(route.table, 1000);
+        assert_eq!(route.gateway, None);
+        assert_eq!((route.oif, route.metric), (Some(5), Some(300)));
+
+        let route = parse_route(DUMP_BLACKHOLE).unwrap();
+        assert_eq!(route.ty, 6);
+        assert_eq!((route.oif, route.metric), (None, Some(400)));
+        assert!(route.multipath.is_empty());
+    }
+
+    #[test]
+    fn parse_dumped_prefix_route() {
+        let route = parse_route(DUMP_SUBNET).unwrap();
+        assert_eq!(route.dst, v4("10.1.0.0"));
+        assert_eq!(route.dst_len, 24);
+        assert_eq!(route.prefsrc, v4("10.1.0.2"));
+        assert_eq!(route.metric, None);
+    }
+
+    #[test]
+    fn parse_dumped_multipath_route() {
+        let route = parse_route(DUMP_MULTIPATH).unwrap();
+        assert_eq!(route.oif, None);
+        assert_eq!(route.gateway, None);
+        assert_eq!(route.metric, Some(500));
+        assert_eq!(route.multipath, [3, 5]);
+    }
+
+    #[test]
+    fn parse_constrained_lookups() {
+        let route = parse_route(GET_VIA_WAN2).unwrap();
+        assert_eq!(route.dst, v4("192.0.2.1"));
+        assert_eq!(route.dst_len, 32);
+        assert_eq!(route.oif, Some(5));
+        assert_eq!(route.prefsrc, v4("10.2.0.3"));
+        assert_eq!(route.gateway, v4("10.2.0.1"));
+
+        let route = parse_route(GET_ONLINK).unwrap();
+        assert_eq!(route.oif, Some(3));
+        assert_eq!(route.prefsrc, v4("10.1.0.2"));
+        assert_eq!(route.gateway, None);
+
+        let route = parse_route(GET_VIA_V6).unwrap();
+        assert_eq!(route.dst, Some("2001:db8:ffff::1".parse().unwrap()));
+        assert_eq!(route.dst_len, 128);
+        assert_eq!(route.oif, Some(3));
+        assert_eq!(route.metric, Some(1024));
+        assert_eq!(route.prefsrc, Some("2001:db8:1::2".parse().unwrap()));
+        assert_eq!(route.gateway, Some("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn parse_truncated_routes() {
+        assert_eq!(parse_route(&DUMP_DEFAULT_VIA[..RTMSG_LEN - 1]), None);
+
+        // A truncated attribute is dropped with all that follow it.
+        let route = parse_route(&DUMP_DEFAULT_VIA[..DUMP_DEFAULT_VIA.len() - 2]).unwrap();
+        assert_eq!(route.gateway, v4("10.1.0.1"));
+        assert_eq!(route.oif, None);
+    }
+
+    #[test]
+    fn constrained_lookup_request() {
+        let dest = "192.0.2.1".parse().unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            route_request(dest, None, Some(5), false),
+            [
+                // rtmsg: AF_INET, /32
+                0x02, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+                // RTA_DST
+                0x08, 0x00, 0x01, 0x00, 0xc0, 0x00, 0x02, 0x01,
+                // RTA_OIF
+                0x08, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00,
+            ]
+        );
+
+        let unconstrained = route_request(dest, None, None, false);
+        assert_eq!(unconstrained.len(), RTMSG_LEN + 8);
+        assert!(attrs(&unconstrained[RTMSG_LEN..]).all(|(ty, _)| ty != RTA_OIF));
+    }
+
+    #[test]
+    fn lookup_request_options() {
+        let dest = "2001:db8::1".parse().unwrap();
+        let source = "2001:db8::2".parse().unwrap();
+        let request = route_request(dest, Some(source), Some(3), true);
+
+        assert_eq!(request[..2], [AF_INET6, 128]);
+        assert_eq!(request[8..12], RTM_F_FIB_MATCH.to_ne_bytes());
+        let attrs: Vec<_> = attrs(&request[RTMSG_LEN..]).collect();
+        assert_eq!(attrs.len(), 3);
+        assert_eq!(parse_addr(attrs[0].1), Some(dest));
+        assert_eq!(
+            (attrs[1].0, parse_addr(attrs[1].1)),
+            (RTA_SRC, Some(source))
+        );
+        assert_eq!((attrs[2].0, parse_u32(attrs[2].1)), (RTA_OIF, Some(3)));
+    }
+
+    #[test]
+    fn receive_buffer_is_reused() {
+        let mut netlink = Netlink::open().unwrap();
+        assert!(netlink.buf.is_empty());
+
+        netlink.addrs(None).unwrap();
+        let buf = netlink.buf.as_ptr();
+        assert_eq!(netlink.buf.len(), RECV_BUF_LEN);
+
+        netlink.addrs(None).unwrap();
+        netlink.links().unwrap();
+        assert_eq!(netlink.buf.as_ptr(), buf);
+    }
+}
@@ -0,0 +1,310 @@
+//! A backend that queries NetworkManager over D-Bus.
+//!
+//! This works in sandboxes (e.g. Flatpak) that don't permit
+//! binding sockets to an interface.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+use crate::{Error, IpVersion, Result};
+
+const DESTINATION: &str = "org.freedesktop.NetworkManager";
+const PATH: &str = "/org/freedesktop/NetworkManager";
+const INTERFACE: &str = "org.freedesktop.NetworkManager";
+const DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const ACTIVE_INTERFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+
+/// An object that has an IP configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NmObject {
+    /// A device, identified by its object path.
+    Device(String),
+    /// An active connection, identified by its object path.
+    ActiveConnection(String),
+}
+
+/// The subset of the NetworkManager D-Bus API used by this crate.
+/// Object paths are passed as strings, `/` meaning none.
+pub trait NmBus {
+    /// Get the device that manages the given interface.
+    fn device_by_ip_iface(&self, interface: &str) -> Result<String>;
+    /// Get the primary active connection.
+    fn primary_connection(&self) -> Result<String>;
+    /// Get the `Ip4Config` or `Ip6Config` property of the given object.
+    fn ip_config(&self, object: &NmObject, version: IpVersion) -> Result<String>;
+    /// Get the addresses from the `AddressData` property
+    /// of the given IP configuration.
+    fn address_data(&self, config: &str, version: IpVersion) -> Result<Vec<String>>;
+}
+
+/// The bus of a query.
+#[derive(Clone)]
+pub(crate) struct SharedBus(Arc<dyn NmBus + Send + Sync>);
+
+impl SharedBus {
+    pub fn new(bus: impl NmBus + Send + Sync + 'static) -> Self {
+        Self(Arc::new(bus))
+    }
+
+    pub fn get(&self) -> &(dyn NmBus + Send + Sync) {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for SharedBus {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("NmBus")
+    }
+}
+
+/// The system bus.
+pub struct DBus {
+    conn: Connection,
+}
+
+impl DBus {
+    /// Connect to the system bus.
+    pub fn system() -> Result<Self> {
+        Ok(Self {
+            conn: Connection::system()?,
+        })
+    }
+
+    fn proxy<'a>(&self, path: &'a str, interface: &'a str) -> Result<Proxy<'a>> {
+        Ok(Proxy::new(&self.conn, DESTINATION, path, interface)?)
+    }
+}
+
+impl NmBus for DBus {
+    fn device_by_ip_iface(&self, interface: &str) -> Result<String> {
+        let device: OwnedObjectPath = self
+            .proxy(PATH, INTERFACE)?
+            .call("GetDeviceByIpIface", &(interface,))?;
+
+        Ok(device.as_str().into())
+    }
+
+    fn primary_connection(&self) -> Result<String> {
+        let active: OwnedObjectPath = self
+            .proxy(PATH, INTERFACE)?
+            .get_property("PrimaryConnection")?;
+
+        Ok(active.as_str().into())
+    }
+
+    fn ip_config(&self, object: &NmObject, version: IpVersion) -> Result<String> {
+        let proxy = match object {
+            NmObject::Device(path) => self.proxy(path, DEVICE_INTERFACE)?,
+            NmObject::ActiveConnection(path) => self.proxy(path, ACTIVE_INTERFACE)?,
+        };
+
+        let property = match version {
+            IpVersion::V4 => "Ip4Config",
+            IpVersion::V6 => "Ip6Config",
+        };
+
+        let config: OwnedObjectPath = proxy.get_property(property)?;
+        Ok(config.as_str().into())
+    }
+
+    fn address_data(&self, config: &str, version: IpVersion) -> Result<Vec<String>> {
+        let interface = match version {
+            IpVersion::V4 => "org.freedesktop.NetworkManager.IP4Config",
+            IpVersion::V6 => "org.freedesktop.NetworkManager.IP6Config",
+        };
+
+        let data: Vec<HashMap<String, OwnedValue>> =
+            self.proxy(config, interface)?.get_property("AddressData")?;
+
+        Ok(data
+            .iter()
+            .filter_map(|entry| entry.get("address"))
+            .filter_map(|addr| <&str>::try_from(addr).ok())
+            .map(String::from)
+            .collect())
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(err: zbus::Error) -> Self {
+        Self::NetworkManager(err)
+    }
+}
+
+/// Get the addresses of the given IP version NetworkManager has configured
+/// on the given interface, or on the primary connection if `None`,
+/// in the order NetworkManager reports them.
+/// Addresses that can't be parsed are skipped.
+pub fn addresses(
+    bus: &(impl NmBus + ?Sized),
+    interface: Option<&str>,
+    version: IpVersion,
+) -> Result<Vec<IpAddr>> {
+    let object = match interface {
+        Some(interface) => NmObject::Device(bus.device_by_ip_iface(interface)?),
+        None => NmObject::ActiveConnection(bus.primary_connection()?),
+    };
+
+    if object == NmObject::ActiveConnection("/".into()) {
+        return Ok(Vec::new());
+    }
+
+    let config = bus.ip_config(&object, version)?;
+    if config == "/" {
+        return Ok(Vec::new());
+    }
+
+    Ok(bus
+        .address_data(&config, version)?
+        .iter()
+        .filter_map(|addr| addr.parse().ok())
+        .filter(|addr: &IpAddr| match version {
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// A bus with a single device `eth0` and a primary connection,
+    /// recording the calls made to it.
+    #[derive(Default)]
+    struct FakeBus {
+        primary: Option<&'static str>,
+        ipv4: Vec<&'static str>,
+        ipv6: Vec<&'static str>,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl NmBus for FakeBus {
+        fn device_by_ip_iface(&self, interface: &str) -> Result<String> {
+            self.calls
+                .borrow_mut()
+                .push(format!("device {}", interface));
+            match interface {
+                "eth0" => Ok("/org/freedesktop/NetworkManager/Devices/2".into()),
+                _ => Err(zbus::Error::Failure("No device found".into()).into()),
+            }
+        }
+
+        fn primary_connection(&self) -> Result<String> {
+            self.calls.borrow_mut().push("primary".into());
+            Ok(self.primary.unwrap_or("/").into())
+        }
+
+        fn ip_config(&self, object: &NmObject, version: IpVersion) -> Result<String> {
+            self.calls
+                .borrow_mut()
+                .push(format!("config {:?} {}", object, version));
+            let addrs = match version {
+                IpVersion::V4 => &self.ipv4,
+                IpVersion::V6 => &self.ipv6,
+            };
+            match addrs.is_empty() {
+                true => Ok("/".into()),
+                false => Ok(format!(
+                    "/org/freedesktop/NetworkManager/{}Config/1",
+                    version
+                )),
+            }
+        }
+
+        fn address_data(&self, config: &str, version: IpVersion) -> Result<Vec<String>> {
+            self.calls.borrow_mut().push(format!("data {}", config));
+            let addrs = match version {
+                IpVersion::V4 => &self.ipv4,
+                IpVersion::V6 => &self.ipv6,
+            };
+            Ok(addrs.iter().map(|&addr| addr.into()).collect())
+        }
+    }
+
+    #[test]
+    fn device_addresses() {
+        let bus = FakeBus {
+            ipv6: vec!["2a01:4f8::1", "fe80::1", "192.168.1.1", "garbage"],
+            ..Default::default()
+        };
+
+        let addrs = addresses(&bus, Some("eth0"), IpVersion::V6).unwrap();
+        // Reported order, without unparsable addresses or other families.
+        assert_eq!(
+            addrs,
+            [
+                "2a01:4f8::1".parse::<IpAddr>().unwrap(),
+                "fe80::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            *bus.calls.borrow(),
+            [
+                "device eth0",
+                "config Device(\"/org/freedesktop/NetworkManager/Devices/2\") IPv6",
+                "data /org/freedesktop/NetworkManager/IPv6Config/1",
+            ]
+        );
+    }
+
+    #[test]
+    fn primary_connection_addresses() {
+        let bus = FakeBus {
+            primary: Some("/org/freedesktop/NetworkManager/ActiveConnection/1"),
+            ipv4: vec!["192.168.1.1"],
+            ..Default::default()
+        };
+
+        let addrs = addresses(&bus, None, IpVersion::V4).unwrap();
+        assert_eq!(addrs, ["192.168.1.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(bus.calls.borrow()[0], "primary");
+    }
+
+    #[test]
+    fn no_primary_connection() {
+        let bus = FakeBus {
+            ipv4: vec!["192.168.1.1"],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            addresses(&bus, None, IpVersion::V4).unwrap(),
+            Vec::<IpAddr>::new()
+        );
+        assert_eq!(*bus.calls.borrow(), ["primary"]);
+    }
+
+    #[test]
+    fn no_ip_config() {
+        let bus = FakeBus {
+            ipv4: vec!["192.168.1.1"],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            addresses(&bus, Some("eth0"), IpVersion::V6).unwrap(),
+            Vec::<IpAddr>::new()
+        );
+        assert_eq!(bus.calls.borrow().len(), 2);
+    }
+
+    #[test]
+    fn unknown_device() {
+        let bus = FakeBus::default();
+
+        let result = addresses(&bus, Some("eth1"), IpVersion::V4);
+        assert!(
+            matches!(result, Err(Error::NetworkManager(_))),
+            "{:?}",
+            result
+        );
+    }
+}
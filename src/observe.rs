@@ -0,0 +1,95 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::{Error, Scope};
+
+static GLOBAL_OBSERVER: OnceLock<Box<dyn ProbeObserver>> = OnceLock::new();
+
+/// A fallback from the regular way of getting an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum FallbackKind {
+    /// Probing wasn't permitted, so NetworkManager was asked instead.
+    NetworkManager,
+    /// Probing wasn't permitted, so `/proc/net` was parsed instead.
+    Procfs,
+    /// The kernel chose an optimistic address, which was replaced
+    /// because of [`OptimisticDad::Reject`](crate::OptimisticDad::Reject).
+    OptimisticRejected,
+    /// The kernel chose an address whose remaining preferred lifetime
+    /// is too short, which was replaced because of
+    /// [`IpQuery::min_preferred_lifetime`](crate::IpQuery::min_preferred_lifetime).
+    ShortLifetime,
+    /// An IPv6 getter returned an IPv4-mapped address,
+    /// so the IPv4 counterpart was queried instead.
+    UnmapV4,
+}
+
+/// Hooks that are notified about the probes of an [`IpQuery`](crate::IpQuery),
+/// e.g. to export metrics.
+///
+/// The hooks are called synchronously on the thread running the query.
+/// They can't affect the result: If a hook panics, the panic is
+/// reported by the panic hook as usual and otherwise ignored.
+/// All hooks do nothing by default.
+#[allow(unused_variables)]
+pub trait ProbeObserver: Send + Sync {
+    /// Called before a probe towards `dest` is sent.
+    /// `scope` is the scope that is queried, if any.
+    fn on_probe_start(&self, interface: Option<&str>, dest: SocketAddr, scope: Option<Scope>) {}
+
+    /// Called after a probe completed or failed.
+    fn on_probe_end(
+        &self,
+        interface: Option<&str>,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        outcome: Result<IpAddr, &Error>,
+        duration: Duration,
+    ) {
+    }
+
+    /// Called when a fallback is used.
+    fn on_fallback(&self, interface: Option<&str>, kind: FallbackKind) {}
+}
+
+/// Register an observer for all queries that don't have their own,
+/// including those of the free functions.
+/// Returns `false` if one has already been registered,
+/// in which case the old one is kept.
+pub fn set_global_observer(observer: impl ProbeObserver + 'static) -> bool {
+    GLOBAL_OBSERVER.set(Box::new(observer)).is_ok()
+}
+
+pub(crate) fn global() -> Option<&'static dyn ProbeObserver> {
+    GLOBAL_OBSERVER.get().map(|observer| &**observer)
+}
+
+/// Run a hook, ignoring panics.
+pub(crate) fn isolate(hook: impl FnOnce()) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(hook));
+}
+
+/// The observer of a query.
+#[derive(Clone)]
+pub(crate) struct Observer(Arc<dyn ProbeObserver>);
+
+impl Observer {
+    pub fn new(observer: impl ProbeObserver + 'static) -> Self {
+        Self(Arc::new(observer))
+    }
+
+    pub fn get(&self) -> &dyn ProbeObserver {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Observer")
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{Backend, IpQuery, IpVersion, ProbeProtocol, Scope, SourcePreference};
+
+/// What a query would do, as returned by [`IpQuery::dry_run`],
+/// e.g. to have it reviewed before running it in a hardened environment.
+///
+/// The [`Display`](fmt::Display) implementation renders
+/// a readable summary.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct ProbePlan {
+    pub interface: Option<String>,
+    /// The backends in the order they are tried. Each one is only
+    /// tried if the previous one failed: The socket backend falls back
+    /// if creating or binding its sockets isn't permitted,
+    /// NetworkManager on any error.
+    pub backends: Vec<Backend>,
+    /// The sockets the socket backend creates, one per family.
+    /// Empty if the socket backend isn't used.
+    pub sockets: Vec<SocketPlan>,
+    /// The probes of [`IpQuery::get_all`] in the order they are sent.
+    /// Empty if the socket backend isn't used.
+    pub probes: Vec<PlannedProbe>,
+    /// The timeout of each probe.
+    pub timeout: Option<Duration>,
+    /// Whether the chosen address is replaced by the first usable one
+    /// in deterministic order, see [`IpQuery::deterministic`].
+    pub deterministic: bool,
+    /// Whether optimistic addresses are replaced,
+    /// see [`OptimisticDad::Reject`](crate::OptimisticDad::Reject).
+    pub reject_optimistic: bool,
+    /// The minimum remaining preferred lifetime of the chosen address,
+    /// see [`IpQuery::min_preferred_lifetime`].
+    pub min_preferred_lifetime: Option<Duration>,
+    /// Whether the chosen address must be assigned to the interface,
+    /// see [`IpQuery::verify_owner`].
+    pub verify_owner: bool,
+}
+
+/// A socket the socket backend creates for each probe.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct SocketPlan {
+    /// Serialized as `IPv6` or `IPv4`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::diagnostic::serialize_display")
+    )]
+    pub family: IpVersion,
+    /// UDP sockets are connected, TCP sockets connect without blocking.
+    pub protocol: ProbeProtocol,
+    /// Whether the socket is provided by the
+    /// [socket factory](IpQuery::socket_factory) rather than created.
+    pub provided: bool,
+    /// Whether `IPV6_V6ONLY` is set.
+    pub only_v6: bool,
+    /// The interface the socket is bound to with `SO_BINDTODEVICE`.
+    /// Provided sockets that are already bound aren't bound again.
+    pub bind_device: Option<String>,
+    /// Whether an existing `SO_BINDTODEVICE` of a provided socket
+    /// is removed.
+    pub clear_device: bool,
+    /// The preferences set via `IPV6_ADDR_PREFERENCES`,
+    /// `None` if it isn't set.
+    pub source_preferences: Option<Vec<SourcePreference>>,
+}
+
+/// A probe of a scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct PlannedProbe {
+    /// Serialized in the form of [`get_by_spec`](crate::get_by_spec),
+    /// e.g. `ipv6-gua`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::diagnostic::serialize_display")
+    )]
+    pub scope: Scope,
+    pub dest: SocketAddr,
+}
+
+/// The options of a probe socket, shared by the execution
+/// and [`IpQuery::dry_run`].
+pub(crate) struct SocketOptions<'q> {
+    pub family: IpVersion,
+    pub protocol: ProbeProtocol,
+    pub provided: bool,
+    pub only_v6: bool,
+    pub bind_device: Option<&'q str>,
+    pub clear_device: bool,
+    pub source_preferences: Option<&'q [SourcePreference]>,
+}
+
+impl From<SocketOptions<'_>> for SocketPlan {
+    fn from(options: SocketOptions<'_>) -> Self {
+        Self {
+            family: options.family,
+            protocol: options.protocol,
+            provided: options.provided,
+            only_v6: options.only_v6,
+            bind_device: options.bind_device.map(Into::into),
+            clear_device: options.clear_device,
+            source_preferences: options.source_preferences.map(Into::into),
+        }
+    }
+}
+
+impl IpQuery<'_> {
+    /// Report what [`IpQuery::get_all`] would do without creating
+    /// any sockets or sending anything. The plan is made by the same
+    /// code that makes these decisions when the query runs.
+    ///
+    /// The backends only fall back at runtime, and a socket factory
+    /// may provide sockets that are already bound.
+    pub fn dry_run(&self) -> ProbePlan {
+        let backends = self.backends().to_vec();
+        let probes: Vec<_> = self
+            .report_scopes()
+            .into_iter()
+            .filter(|_| backends.contains(&Backend::Socket))
+            .flat_map(|scope| {
+                scope
+                    .probe_dests()
+                    .iter()
+                    .map(move |&dest| PlannedProbe { scope, dest })
+            })
+            .collect();
+
+        // The sockets are those of the probes, so there are none
+        // without the socket backend either.
+        let mut sockets = Vec::new();
+        for probe in &probes {
+            let options = self.socket_options(probe.dest);
+            if sockets
+                .iter()
+                .all(|socket: &SocketPlan| socket.family != options.family)
+            {
+                sockets.push(options.into());
+            }
+        }
+
+        ProbePlan {
+            interface: self.interface_name(),
+            backends,
+            sockets,
+            probes,
+            timeout: self.timeout,
+            deterministic: self.ranks_by_enumeration(),
+            reject_optimistic: self.optimistic_dad == crate::OptimisticDad::Reject,
+            min_preferred_lifetime: self.min_preferred_lifetime,
+            verify_owner: self.verifies_owner(),
+        }
+    }
+}
+
+impl fmt::Display for ProbePlan {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.interface {
+            Some(interface) => writeln!(fmt, "probe plan for interface {}:", interface)?,
+            None => writeln!(fmt, "probe plan for any interface:")?,
+        }
+
+        let backends: Vec<_> = self.backends.iter().map(backend_name).collect();
+        writeln!(fmt, "  backends: {}", backends.join(", then "))?;
+
+        for socket in &self.sockets {
+            writeln!(fmt, "  {}", socket)?;
+        }
+        for probe in &self.probes {
+            writeln!(fmt, "  {}", probe)?;
+        }
+
+        match self.timeout {
+            Some(timeout) => writeln!(fmt, "  timeout: {:?} per probe", timeout)?,
+            None => writeln!(fmt, "  timeout: none")?,
+        }
+
+        let min_lifetime = self
+            .min_preferred_lifetime
+            .map(|min| format!("preferred for at least {:?}", min));
+        let checks: Vec<_> = [
+            (self.deterministic, "deterministic order"),
+            (self.reject_optimistic, "reject optimistic"),
+            (self.verify_owner, "verify owner"),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| enabled)
+        .map(|(_, check)| check.to_owned())
+        .chain(min_lifetime)
+        .collect();
+        if !checks.is_empty() {
+            writeln!(fmt, "  after probing: {}", checks.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn backend_name(backend: &Backend) -> &'static str {
+    match backend {
+        Backend::Socket => "socket",
+        #[cfg(feature = "networkmanager")]
+        Backend::NetworkManager => "networkmanager",
+        Backend::Procfs => "procfs",
+    }
+}
+
+impl fmt::Display for SocketPlan {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let protocol = match self.protocol {
+            ProbeProtocol::Udp => "udp",
+            ProbeProtocol::Tcp => "tcp",
+        };
+        let origin = if self.provided { "provided" } else { "new" };
+        write!(fmt, "{} socket: {} {}", self.family, origin, protocol)?;
+
+        if self.only_v6 {
+            write!(fmt, ", ipv6 only")?;
+        }
+        match &self.bind_device {
+            Some(interface) => write!(fmt, ", bound to {}", interface)?,
+            None if self.clear_device => write!(fmt, ", device binding cleared")?,
+            None => {}
+        }
+        if let Some(preferences) = &self.source_preferences {
+            write!(fmt, ", source preferences {:?}", preferences)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlannedProbe {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}: towards {}", self.scope, self.dest.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_display() {
+        let plan = IpQuery::new("eth0")
+            .backend(Backend::Socket)
+            .prefer_source(SourcePreference::Temporary)
+            .timeout(Duration::from_secs(2))
+            .verify_owner(true)
+            .dry_run();
+
+        assert_eq!(
+            plan.to_string(),
+            "probe plan for interface eth0:
+  backends: socket
+  IPv6 socket: new udp, ipv6 only, bound to eth0, source preferences [Temporary]
+  IPv4 socket: new udp, bound to eth0
+  ipv6-link-local: towards fe80::
+  ipv6-ula: towards fc00::
+  ipv6-gua: towards 2000::
+  ipv4-link-local: towards 169.254.0.0
+  ipv4-private: towards 10.0.0.0
+  ipv4-private: towards 172.16.0.0
+  ipv4-private: towards 192.168.0.0
+  ipv4-global: towards 0.0.0.0
+  timeout: 2s per probe
+  after probing: verify owner
+"
+        );
+    }
+
+    #[test]
+    fn procfs_plan_display() {
+        let plan = IpQuery::any_interface()
+            .backend(Backend::Procfs)
+            .deterministic(true)
+            .dry_run();
+
+        assert!(plan.probes.is_empty());
+        assert_eq!(
+            plan.to_string(),
+            "probe plan for any interface:
+  backends: procfs
+  timeout: none
+"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::io;
+use std::net::Ipv6Addr;
+use std::path::Path;
+
+use crate::{addrs, if_index, if_name, IpQuery, Result, SourcePreference};
+
+// Network sysctls are those of the namespace of the opening thread.
+const CONF: &str = "/proc/sys/net/ipv6/conf";
+
+/// The IPv6 privacy extensions policy of an interface (RFC 8981),
+/// as configured by the `use_tempaddr` sysctl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrivacyPolicy {
+    /// No temporary addresses are generated (`use_tempaddr` <= 0).
+    Disabled,
+    /// Temporary addresses are generated, but stable addresses
+    /// are preferred as source addresses (`use_tempaddr` = 1).
+    PreferPublic,
+    /// Temporary addresses are generated and preferred
+    /// as source addresses (`use_tempaddr` >= 2).
+    PreferTemporary,
+}
+
+impl PrivacyPolicy {
+    fn from_use_tempaddr(value: i32) -> Self {
+        match value {
+            ..=0 => Self::Disabled,
+            1 => Self::PreferPublic,
+            _ => Self::PreferTemporary,
+        }
+    }
+}
+
+/// Read the policy of the interface from the `conf` directory
+/// at the given root. Interfaces inherit `default` when they are
+/// created and writing `all` updates every interface, so those are
+/// only consulted if the interface has no entry of its own.
+pub(crate) fn privacy_policy_in(root: &Path, interface: &str) -> io::Result<PrivacyPolicy> {
+    for conf in [interface, "default", "all"] {
+        let contents = match fs::read_to_string(root.join(conf).join("use_tempaddr")) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        let value = contents
+            .trim()
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed use_tempaddr"))?;
+        return Ok(PrivacyPolicy::from_use_tempaddr(value));
+    }
+
+    Err(io::ErrorKind::NotFound.into())
+}
+
+impl IpQuery<'_> {
+    /// Report whether the temporary flag of the address matches
+    /// the privacy policy of its interface and the source preferences
+    /// of the query, or `None` if either can't be determined.
+    pub(crate) fn privacy_consistent(&self, ipv6: Ipv6Addr) -> Option<bool> {
+        let addr = addrs::find(ipv6.into()).ok().flatten()?;
+        let policy = privacy_policy_in(Path::new(CONF), &if_name(addr.index)?).ok()?;
+
+        Some(addr.is_temporary() == expects_temporary(policy, &self.source_preferences))
+    }
+}
+
+/// Whether the kernel should choose a temporary address
+/// with the policy and the source preferences of a socket.
+fn expects_temporary(policy: PrivacyPolicy, preferences: &[SourcePreference]) -> bool {
+    let prefers = |preference| preferences.contains(&preference);
+    match policy {
+        PrivacyPolicy::Disabled => false,
+        _ if prefers(SourcePreference::Temporary) => true,
+        _ if prefers(SourcePreference::Public) => false,
+        PrivacyPolicy::PreferPublic => false,
+        PrivacyPolicy::PreferTemporary => true,
+    }
+}
+
+/// Get the IPv6 privacy extensions policy of the given interface
+/// from `/proc/sys/net/ipv6/conf/<interface>/use_tempaddr`.
+pub fn ipv6_privacy_policy(interface: &str) -> Result<PrivacyPolicy> {
+    // Also makes sure that the name can't escape the directory.
+    if_index(interface)?;
+    Ok(privacy_policy_in(Path::new(CONF), interface)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A fake `conf` directory with the given `use_tempaddr` contents,
+    /// removed when dropped.
+    struct FakeConf(PathBuf);
+
+    impl FakeConf {
+        fn new(entries: &[(&str, &str)]) -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+            let root = std::env::temp_dir().join(format!(
+                "preferred-ip-conf-{}-{}",
+                process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            for (conf, contents) in entries {
+                fs::create_dir_all(root.join(conf)).unwrap();
+                fs::write(root.join(conf).join("use_tempaddr"), contents).unwrap();
+            }
+            fs::create_dir_all(&root).unwrap();
+
+            Self(root)
+        }
+
+        fn policy(&self, interface: &str) -> io::Result<PrivacyPolicy> {
+            privacy_policy_in(&self.0, interface)
+        }
+    }
+
+    impl Drop for FakeConf {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn values_of_use_tempaddr() {
+        use PrivacyPolicy::*;
+
+        for (contents, policy) in [
+            ("-1\n", Disabled),
+            ("0\n", Disabled),
+            ("1\n", PreferPublic),
+            ("2\n", PreferTemporary),
+            ("3\n", PreferTemporary),
+            (" 2 ", PreferTemporary),
+        ] {
+            let conf = FakeConf::new(&[("eth0", contents)]);
+            assert_eq!(conf.policy("eth0").unwrap(), policy, "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn interface_entry_comes_first() {
+        let conf = FakeConf::new(&[("eth0", "0\n"), ("default", "2\n"), ("all", "1\n")]);
+        assert_eq!(conf.policy("eth0").unwrap(), PrivacyPolicy::Disabled);
+    }
+
+    #[test]
+    fn missing_interface_falls_back_to_default_then_all() {
+        let conf = FakeConf::new(&[("eth0", "0\n"), ("default", "2\n"), ("all", "1\n")]);
+        assert_eq!(conf.policy("eth1").unwrap(), PrivacyPolicy::PreferTemporary);
+
+        let conf = FakeConf::new(&[("eth0", "0\n"), ("all", "1\n")]);
+        assert_eq!(conf.policy("eth1").unwrap(), PrivacyPolicy::PreferPublic);
+    }
+
+    #[test]
+    fn no_entry_at_all() {
+        let conf = FakeConf::new(&[]);
+        assert_eq!(
+            conf.policy("eth0").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn malformed_entries_fail() {
+        for contents in ["", "yes\n", "1 2\n", "99999999999\n"] {
+            // The fallbacks aren't used for a broken entry.
+            let conf = FakeConf::new(&[("eth0", contents), ("default", "2\n")]);
+            assert_eq!(
+                conf.policy("eth0").unwrap_err().kind(),
+                io::ErrorKind::InvalidData,
+                "{:?}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn preferences_override_the_policy() {
+        use PrivacyPolicy::*;
+        use SourcePreference::*;
+
+        #[rustfmt::skip]
+        let cases: [(PrivacyPolicy, &[SourcePreference], bool); 9] = [
+            (Disabled, &[], false),
+            (Disabled, &[Temporary], false),
+            (PreferPublic, &[], false),
+            (PreferPublic, &[Temporary], true),
+            (PreferPublic, &[CareOf], false),
+            (PreferTemporary, &[], true),
+            (PreferTemporary, &[Public], false),
+            (PreferTemporary, &[Home], true),
+            (PreferTemporary, &[Temporary, CareOf], true),
+        ];
+
+        for (policy, preferences, temporary) in cases {
+            assert_eq!(
+                expects_temporary(policy, preferences),
+                temporary,
+                "{:?} {:?}",
+                policy,
+                preferences
+            );
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! A fallback for environments where netlink and binding to interfaces
+//! are blocked, but `/proc/net` is readable.
+//!
+//! The procfs files don't reveal the kernel's source address selection,
+//! so addresses are chosen by scope only. IPv4 addresses are taken from
+//! the local entries of `/proc/net/fib_trie` and assigned to interfaces
+//! by their connected routes in `/proc/net/route`, which fails for
+//! addresses without one.
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::addrs::InterfaceAddr;
+use crate::{if_index, Result};
+
+// `/proc/net` is the namespace of the main thread,
+// which isn't necessarily the one of the calling thread.
+const IF_INET6: &str = "/proc/thread-self/net/if_inet6";
+const ROUTE: &str = "/proc/thread-self/net/route";
+const FIB_TRIE: &str = "/proc/thread-self/net/fib_trie";
+
+/// An entry of `/proc/net/if_inet6`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Inet6Addr {
+    pub addr: Ipv6Addr,
+    pub index: u32,
+    pub prefix_len: u8,
+    pub flags: u32,
+    pub interface: String,
+}
+
+/// An entry of `/proc/net/route`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Ipv4Route {
+    pub interface: String,
+    pub dest: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub mask: Ipv4Addr,
+}
+
+impl Ipv4Route {
+    fn prefix_len(&self) -> u8 {
+        u32::from(self.mask).leading_ones() as u8
+    }
+
+    fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & u32::from(self.mask) == u32::from(self.dest)
+    }
+}
+
+/// Parse the contents of `/proc/net/if_inet6`,
+/// skipping malformed lines.
+pub(crate) fn parse_if_inet6(contents: &str) -> Vec<Inet6Addr> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+
+            let addr = u128::from_str_radix(fields.next()?, 16).ok()?;
+            let index = u32::from_str_radix(fields.next()?, 16).ok()?;
+            let prefix_len = u8::from_str_radix(fields.next()?, 16).ok()?;
+            let _scope = fields.next()?;
+            let flags = u32::from_str_radix(fields.next()?, 16).ok()?;
+            let interface = fields.next()?.into();
+
+            Some(Inet6Addr {
+                addr: Ipv6Addr::from(addr),
+                index,
+                prefix_len,
+                flags,
+                interface,
+            })
+        })
+        .collect()
+}
+
+/// Parse an address of `/proc/net/route`,
+/// which is printed as a native endian integer.
+fn parse_route_addr(field: &str) -> Option<Ipv4Addr> {
+    let addr = u32::from_str_radix(field, 16).ok()?;
+    Some(Ipv4Addr::from(addr.to_ne_bytes()))
+}
+
+/// Parse the contents of `/proc/net/route`,
+/// skipping the header and malformed lines.
+pub(crate) fn parse_route(contents: &str) -> Vec<Ipv4Route> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+
+            Some(Ipv4Route {
+                interface: (*fields.first()?).into(),
+                dest: parse_route_addr(fields.get(1)?)?,
+                gateway: parse_route_addr(fields.get(2)?)?,
+                mask: parse_route_addr(fields.get(7)?)?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the local IPv4 addresses from the contents of
+/// `/proc/net/fib_trie`, without duplicates.
+pub(crate) fn parse_fib_trie(contents: &str) -> Vec<Ipv4Addr> {
+    let mut addrs = Vec::new();
+    let mut leaf = None;
+
+    for line in contents.lines().map(str::trim) {
+        if let Some(addr) = line.strip_prefix("|-- ") {
+            leaf = addr.parse().ok();
+        } else if line.starts_with("/32 host LOCAL") {
+            if let Some(addr) = leaf.filter(|addr| !addrs.contains(addr)) {
+                addrs.push(addr);
+            }
+        }
+    }
+
+    addrs
+}
+
+/// Get the addresses assigned to the given interface,
+/// or to any interface if `None`.
+/// IPv4 addresses without a connected route are only returned
+/// for `None` and have an index of 0.
+pub(crate) fn addresses(interface: Option<&str>) -> Result<Vec<InterfaceAddr>> {
+    let if_inet6 = fs::read_to_string(IF_INET6).unwrap_or_default();
+    let route = fs::read_to_string(ROUTE)?;
+    let fib_trie = fs::read_to_string(FIB_TRIE)?;
+
+    Ok(assign(
+        interface,
+        parse_if_inet6(&if_inet6),
+        &parse_route(&route),
+        parse_fib_trie(&fib_trie),
+        |interface| if_index(interface).unwrap_or(0),
+    ))
+}
+
+/// Combine the parsed files into the addresses of the given interface,
+/// see [`addresses`]. `index_of` looks up the index of an interface
+/// that routes an IPv4 address.
+fn assign(
+    interface: Option<&str>,
+    ipv6: Vec<Inet6Addr>,
+    routes: &[Ipv4Route],
+    ipv4: Vec<Ipv4Addr>,
+    index_of: impl Fn(&str) -> u32,
+) -> Vec<InterfaceAddr> {
+    let mut addrs: Vec<_> = ipv6
+        .into_iter()
+        .filter(|addr| interface.is_none_or(|interface| addr.interface == interface))
+        .map(|addr| InterfaceAddr {
+            index: addr.index,
+            addr: addr.addr.into(),
+            prefix_len: addr.prefix_len,
+            flags: addr.flags,
+            label: None,
+            preferred_lifetime: None,
+        })
+        .collect();
+
+    for ipv4 in ipv4 {
+        // The most specific connected route is the subnet of the address.
+        let route = routes
+            .iter()
+            .filter(|route| route.gateway.is_unspecified() && route.contains(ipv4))
+            .max_by_key(|route| route.prefix_len());
+
+        let matches = match (interface, route) {
+            (Some(interface), Some(route)) => route.interface == interface,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        if matches {
+            addrs.push(InterfaceAddr {
+                index: route.map_or(0, |route| index_of(&route.interface)),
+                addr: IpAddr::V4(ipv4),
+                prefix_len: route.map_or(32, Ipv4Route::prefix_len),
+                flags: 0,
+                label: route.map(|route| route.interface.clone()),
+                preferred_lifetime: None,
+            });
+        }
+    }
+
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::OptimisticDad;
+
+    // Captured from a network namespace with two veth interfaces,
+    // addresses added with `nodad`, `home`, `preferred_lft 0`,
+    // `noprefixroute` and `mngtmpaddr` and a pending DAD.
+    const IF_INET6_MANY: &str = "\
+20010db8000000000000000000000001 03 80 00 82    veth0
+fd00beef000000000000000000000011 02 40 00 82    veth1
+20010db8000000000000000000000002 03 40 00 82    veth0
+fd00beef000000000000000000000012 02 40 00 82    veth1
+00000000000000000000000000000001 01 80 10 80       lo
+fe80000000000000d40d48fffee2f6c6 02 40 20 c0    veth1
+fd00dead000000000000000000000001 03 30 00 92    veth0
+fd00beef000000000000000000000001 02 40 00 82    veth1
+fd00beef000000000000000000000008 02 40 00 82    veth1
+2a0104f8000000000000000000000001 03 40 00 82    veth0
+fe80000000000000f806c5fffea8150c 03 40 20 c0    veth0
+fd00beef000000000000000000000004 02 40 00 82    veth1
+fd00beef000000000000000000000006 02 40 00 82    veth1
+fd00beef000000000000000000000002 02 40 00 82    veth1
+fd00beef000000000000000000000007 02 40 00 82    veth1
+2a0104f8000000000000000000000002 03 40 00 a2    veth0
+fd00beef000000000000000000000009 02 40 00 82    veth1
+fd00beef000000000000000000000005 02 40 00 82    veth1
+fd00beef000000000000000000000003 02 40 00 82    veth1
+fd00beef000000000000000000000010 02 40 00 82    veth1
+2a0104f8000000000000000000000003 03 40 00 c0    veth0
+";
+
+    // Captured from a container with a single interface.
+    const IF_INET6_CONTAINER: &str = "\
+fe8000000000000000fc00fffe000001 04 40 20 80     eth0
+fd000000000000000000000000000002 04 40 00 82     eth0
+00000000000000000000000000000001 01 80 10 80       lo
+";
+
+    // The routes of the namespace above, with the trailing padding
+    // of the kernel. Addresses are little endian.
+    const ROUTE: &str = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT                                                       
+veth0\t00000000\tFE4DA8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0                                                                              
+veth1\t0000000A\t00000000\t0001\t0\t0\t0\t000000FF\t0\t0\t0                                                                              
+veth0\t004DA8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0                                                                              
+veth1\t006433C6\tFE00000A\t0003\t0\t0\t100\t00FFFFFF\t0\t0\t0                                                                            
+";
+
+    // The main and local tables of the namespace above,
+    // including a /32 address without a connected route.
+    const FIB_TRIE: &str = "\
+Main:
+  +-- 0.0.0.0/0 3 1 5
+     +-- 0.0.0.0/4 2 0 2
+        |-- 0.0.0.0
+           /0 universe UNICAST
+        +-- 10.0.0.0/8 2 0 2
+           +-- 10.0.0.0/31 1 0 0
+              |-- 10.0.0.0
+                 /8 link UNICAST
+              |-- 10.0.0.1
+                 /32 host LOCAL
+           |-- 10.255.255.255
+              /32 link BROADCAST
+     +-- 96.0.0.0/3 2 0 2
+        |-- 100.64.0.1
+           /32 host LOCAL
+        +-- 127.0.0.0/8 2 0 2
+           +-- 127.0.0.0/31 1 0 0
+              |-- 127.0.0.0
+                 /8 host LOCAL
+              |-- 127.0.0.1
+                 /32 host LOCAL
+           |-- 127.255.255.255
+              /32 link BROADCAST
+     +-- 192.0.0.0/5 2 0 2
+        +-- 192.168.77.0/24 2 0 2
+           +-- 192.168.77.0/30 2 0 1
+              |-- 192.168.77.0
+                 /24 link UNICAST
+              |-- 192.168.77.1
+                 /32 host LOCAL
+              |-- 192.168.77.2
+                 /32 host LOCAL
+           |-- 192.168.77.255
+              /32 link BROADCAST
+        |-- 198.51.100.0
+           /24 universe UNICAST
+Local:
+  +-- 0.0.0.0/0 3 1 5
+     +-- 0.0.0.0/4 2 0 2
+        |-- 0.0.0.0
+           /0 universe UNICAST
+        +-- 10.0.0.0/8 2 0 2
+           +-- 10.0.0.0/31 1 0 0
+              |-- 10.0.0.0
+                 /8 link UNICAST
+              |-- 10.0.0.1
+                 /32 host LOCAL
+           |-- 10.255.255.255
+              /32 link BROADCAST
+     +-- 96.0.0.0/3 2 0 2
+        |-- 100.64.0.1
+           /32 host LOCAL
+        +-- 127.0.0.0/8 2 0 2
+           +-- 127.0.0.0/31 1 0 0
+              |-- 127.0.0.0
+                 /8 host LOCAL
+              |-- 127.0.0.1
+                 /32 host LOCAL
+           |-- 127.255.255.255
+              /32 link BROADCAST
+     +-- 192.0.0.0/5 2 0 2
+        +-- 192.168.77.0/24 2 0 2
+           +-- 192.168.77.0/30 2 0 1
+              |-- 192.168.77.0
+                 /24 link UNICAST
+              |-- 192.168.77.1
+                 /32 host LOCAL
+              |-- 192.168.77.2
+                 /32 host LOCAL
+           |-- 192.168.77.255
+              /32 link BROADCAST
+        |-- 198.51.100.0
+           /24 universe UNICAST
+";
+
+    fn inet6(addr: &str, index: u32, prefix_len: u8, flags: u32, interface: &str) -> Inet6Addr {
+        Inet6Addr {
+            addr: addr.parse().unwrap(),
+            index,
+            prefix_len,
+            flags,
+            interface: interface.into(),
+        }
+    }
+
+    fn route(interface: &str, dest: &str, gateway: &str, mask: &str) -> Ipv4Route {
+        Ipv4Route {
+            interface: interface.into(),
+            dest: dest.parse().unwrap(),
+            gateway: gateway.parse().unwrap(),
+            mask: mask.parse().unwrap(),
+        }
+    }
+
+    fn index_of(interface: &str) -> u32 {
+        match interface {
+            "veth1" => 2,
+            "veth0" => 3,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn if_inet6_container() {
+        assert_eq!(
+            parse_if_inet6(IF_INET6_CONTAINER),
+            [
+                inet6("fe80::fc:ff:fe00:1", 4, 64, 0x80, "eth0"),
+                inet6("fd00::2", 4, 64, 0x82, "eth0"),
+                inet6("::1", 1, 128, 0x80, "lo"),
+            ]
+        );
+    }
+
+    #[test]
+    fn if_inet6_many_addresses() {
+        let addrs = parse_if_inet6(IF_INET6_MANY);
+
+        assert_eq!(addrs.len(), 21);
+        assert_eq!(
+            addrs
+                .iter()
+                .filter(|addr| addr.interface == "veth1")
+                .count(),
+            13
+        );
+        // Every address of veth1 is in the kernel's hash order,
+        // which the parser keeps.
+        assert_eq!(addrs[1], inet6("fd00:beef::11", 2, 64, 0x82, "veth1"));
+        assert_eq!(addrs[19], inet6("fd00:beef::10", 2, 64, 0x82, "veth1"));
+    }
+
+    #[test]
+    fn if_inet6_flags() {
+        let addrs = parse_if_inet6(IF_INET6_MANY);
+        let find = |addr: &str| {
+            let addr: Ipv6Addr = addr.parse().unwrap();
+            addrs.iter().find(|entry| entry.addr == addr).unwrap()
+        };
+
+        // Only the low byte of the flags is printed,
+        // so `noprefixroute` and `mngtmpaddr` are lost.
+        assert_eq!(
+            find("2001:db8::1"),
+            &inet6("2001:db8::1", 3, 128, 0x82, "veth0")
+        );
+        assert_eq!(find("2001:db8::2").flags, 0x82);
+        // nodad and deprecated by `preferred_lft 0`.
+        assert_eq!(find("2a01:4f8::2").flags, 0xa2);
+        // Tentative while DAD runs.
+        assert_eq!(find("2a01:4f8::3").flags, 0xc0);
+        // Home address of Mobile IPv6 with a /48.
+        assert_eq!(
+            find("fd00:dead::1"),
+            &inet6("fd00:dead::1", 3, 48, 0x92, "veth0")
+        );
+        // Link-local with a tentative DAD.
+        assert_eq!(find("fe80::f806:c5ff:fea8:150c").flags, 0xc0);
+    }
+
+    #[test]
+    fn if_inet6_skips_malformed_lines() {
+        let contents = "\
+not an address
+fd00beef000000000000000000000001 02 40 00
+fd00beef000000000000000000000001 02 zz 00 82    veth1
+fd00beef0000000000000000000000010 02 40 00 82    veth1
+
+fd00beef000000000000000000000002 02 40 00 82    veth1
+";
+
+        assert_eq!(
+            parse_if_inet6(contents),
+            [inet6("fd00:beef::2", 2, 64, 0x82, "veth1")]
+        );
+        assert!(parse_if_inet6("").is_empty());
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn route_table() {
+        assert_eq!(
+            parse_route(ROUTE),
+            [
+                route("veth0", "0.0.0.0", "192.168.77.254", "0.0.0.0"),
+                route("veth1", "10.0.0.0", "0.0.0.0", "255.0.0.0"),
+                route("veth0", "192.168.77.0", "0.0.0.0", "255.255.255.0"),
+                route("veth1", "198.51.100.0", "10.0.0.254", "255.255.255.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn route_skips_header_and_malformed_lines() {
+        let contents = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+veth0\t00000000\tFE4DA8C0\t0003\t0\t0\t0
+veth0\tZZ4DA8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+veth0\t004DA8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+";
+
+        let routes = parse_route(contents);
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].prefix_len(), 24);
+        assert!(parse_route("").is_empty());
+        assert!(parse_route(contents.lines().next().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn route_contains() {
+        let subnet = route("veth0", "192.168.77.0", "0.0.0.0", "255.255.255.0");
+        assert!(subnet.contains(Ipv4Addr::new(192, 168, 77, 1)));
+        assert!(!subnet.contains(Ipv4Addr::new(192, 168, 78, 1)));
+
+        let default = route("veth0", "0.0.0.0", "192.168.77.254", "0.0.0.0");
+        assert_eq!(default.prefix_len(), 0);
+        assert!(default.contains(Ipv4Addr::new(198, 51, 100, 1)));
+    }
+
+    #[test]
+    fn fib_trie_local_addresses() {
+        // Broadcast, network and route entries are skipped
+        // and the addresses of both tables are deduplicated.
+        assert_eq!(
+            parse_fib_trie(FIB_TRIE),
+            [
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(100, 64, 0, 1),
+                Ipv4Addr::LOCALHOST,
+                Ipv4Addr::new(192, 168, 77, 1),
+                Ipv4Addr::new(192, 168, 77, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn fib_trie_ignores_local_without_leaf() {
+        let contents = "\
+Local:
+  +-- 0.0.0.0/0 3 0 5
+        /32 host LOCAL
+     |-- not an address
+        /32 host LOCAL
+     |-- 192.0.2.2
+        /32 host LOCAL
+";
+
+        assert_eq!(parse_fib_trie(contents), [Ipv4Addr::new(192, 0, 2, 2)]);
+        assert!(parse_fib_trie("").is_empty());
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn assign_to_interface() {
+        let routes = parse_route(ROUTE);
+        let addrs = assign(
+            Some("veth0"),
+            parse_if_inet6(IF_INET6_MANY),
+            &routes,
+            parse_fib_trie(FIB_TRIE),
+            index_of,
+        );
+
+        let ips: Vec<_> = addrs.iter().map(|addr| addr.addr.to_string()).collect();
+        assert_eq!(
+            ips,
+            [
+                "2001:db8::1",
+                "2001:db8::2",
+                "fd00:dead::1",
+                "2a01:4f8::1",
+                "fe80::f806:c5ff:fea8:150c",
+                "2a01:4f8::2",
+                "2a01:4f8::3",
+                "192.168.77.1",
+                "192.168.77.2",
+            ]
+        );
+
+        let ipv4 = &addrs[7];
+        assert_eq!(ipv4.index, 3);
+        assert_eq!(ipv4.prefix_len, 24);
+        assert_eq!(ipv4.label.as_deref(), Some("veth0"));
+
+        // Tentative addresses aren't usable, deprecated ones still are.
+        let usable: Vec<_> = addrs
+            .iter()
+            .filter(|addr| addr.addr.is_ipv6() && addr.is_usable(OptimisticDad::Reject))
+            .map(|addr| addr.addr.to_string())
+            .collect();
+        assert_eq!(
+            usable,
+            [
+                "2001:db8::1",
+                "2001:db8::2",
+                "fd00:dead::1",
+                "2a01:4f8::1",
+                "2a01:4f8::2"
+            ]
+        );
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn assign_prefers_most_specific_connected_route() {
+        let routes = [
+            route("veth1", "192.168.0.0", "0.0.0.0", "255.255.0.0"),
+            route("veth0", "192.168.77.0", "0.0.0.0", "255.255.255.0"),
+            // Routes via a gateway aren't connected.
+            route("veth1", "192.168.77.0", "192.168.0.1", "255.255.255.128"),
+        ];
+        let addrs = assign(
+            None,
+            Vec::new(),
+            &routes,
+            vec![
+                Ipv4Addr::new(192, 168, 77, 1),
+                Ipv4Addr::new(192, 168, 1, 1),
+            ],
+            index_of,
+        );
+
+        assert_eq!(addrs[0].label.as_deref(), Some("veth0"));
+        assert_eq!(addrs[0].prefix_len, 24);
+        assert_eq!(addrs[1].label.as_deref(), Some("veth1"));
+        assert_eq!(addrs[1].prefix_len, 16);
+        assert_eq!(addrs[1].index, 2);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn assign_unrouted_ipv4() {
+        let routes = parse_route(ROUTE);
+        let unrouted = Ipv4Addr::new(100, 64, 0, 1);
+
+        // Neither 100.64.0.1/32 nor the loopback address
+        // have a connected route in the main table.
+        let veth1 = assign(
+            Some("veth1"),
+            Vec::new(),
+            &routes,
+            parse_fib_trie(FIB_TRIE),
+            index_of,
+        );
+        assert_eq!(veth1.len(), 1);
+        assert_eq!(veth1[0].addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(veth1[0].prefix_len, 8);
+
+        let any = assign(
+            None,
+            Vec::new(),
+            &routes,
+            parse_fib_trie(FIB_TRIE),
+            index_of,
+        );
+        let addr = any
+            .iter()
+            .find(|addr| addr.addr == IpAddr::V4(unrouted))
+            .unwrap();
+        assert_eq!(addr.index, 0);
+        assert_eq!(addr.prefix_len, 32);
+        assert_eq!(addr.label, None);
+        assert_eq!(any.len(), 5);
+    }
+
+    #[test]
+    fn assign_filters_ipv6_by_interface() {
+        let addrs = assign(
+            Some("eth0"),
+            parse_if_inet6(IF_INET6_CONTAINER),
+            &[],
+            Vec::new(),
+            index_of,
+        );
+
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs
+            .iter()
+            .all(|addr| addr.index == 4 && addr.label.is_none()));
+        assert_eq!(
+            assign(
+                None,
+                parse_if_inet6(IF_INET6_CONTAINER),
+                &[],
+                Vec::new(),
+                index_of
+            )
+            .len(),
+            3
+        );
+        assert_eq!(
+            assign(
+                Some("eth1"),
+                parse_if_inet6(IF_INET6_CONTAINER),
+                &[],
+                Vec::new(),
+                index_of
+            ),
+            []
+        );
+    }
+}
@@ -0,0 +1,566 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// The purpose of a special-purpose address range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum RangeKind {
+    /// `0.0.0.0/8` (RFC 791) and `::/128` (RFC 4291).
+    Unspecified,
+    /// `127.0.0.0/8` (RFC 1122) and `::1/128` (RFC 4291).
+    Loopback,
+    /// The private IPv4 ranges of RFC 1918.
+    Private,
+    /// `fc00::/7` (RFC 4193).
+    UniqueLocal,
+    /// `100.64.0.0/10`, used for carrier-grade NAT (RFC 6598).
+    SharedAddressSpace,
+    /// `169.254.0.0/16` (RFC 3927) and `fe80::/10` (RFC 4291).
+    LinkLocal,
+    /// `192.0.0.0/24` and `2001::/23` (RFC 6890),
+    /// unless covered by a more specific entry.
+    ProtocolAssignments,
+    /// `192.0.0.0/29`, used by DS-Lite and 464XLAT (RFC 7335).
+    ServiceContinuity,
+    /// `192.0.0.8/32` (RFC 7600).
+    DummyIpv4,
+    /// `192.0.0.170/31`, used to discover the NAT64 prefix (RFC 7050).
+    Nat64Discovery,
+    /// The anycast addresses of the Port Control Protocol (RFC 7723),
+    /// TURN (RFC 8155) and DNS-SD service registration (RFC 9665)
+    /// within `192.0.0.0/24` and `2001:1::/120`.
+    Anycast,
+    /// The documentation ranges of RFC 5737, RFC 3849 and RFC 9637.
+    Documentation,
+    /// `198.18.0.0/15` (RFC 2544) and `2001:2::/48` (RFC 5180).
+    Benchmarking,
+    /// The AS112 ranges of RFC 7534 and RFC 7535.
+    As112,
+    /// `192.52.193.0/24` and `2001:3::/32` (RFC 7450).
+    Amt,
+    /// `192.88.99.0/24`, the deprecated 6to4 relay anycast (RFC 7526).
+    SixToFourRelay,
+    /// `2002::/16` (RFC 3056).
+    SixToFour,
+    /// `2001::/32` (RFC 4380).
+    Teredo,
+    /// `64:ff9b::/96` (RFC 6052) and `64:ff9b:1::/48` (RFC 8215).
+    Nat64,
+    /// `::ffff:0:0/96` (RFC 4291).
+    Ipv4Mapped,
+    /// `100::/64` (RFC 6666).
+    DiscardOnly,
+    /// `2001:10::/28` (RFC 4843, deprecated) and `2001:20::/28` (RFC 7343).
+    Orchid,
+    /// `2001:30::/28`, used for drone remote identification (RFC 9374).
+    DroneRemoteId,
+    /// `5f00::/16` (RFC 9602).
+    SegmentRouting,
+    /// `240.0.0.0/4` (RFC 1112).
+    Reserved,
+    /// `255.255.255.255/32` (RFC 919).
+    Broadcast,
+    /// `224.0.0.0/4` (RFC 5771) and `ff00::/8` (RFC 4291),
+    /// which aren't part of the special-purpose registries.
+    Multicast,
+}
+
+/// The IPv4 special-purpose ranges of the IANA registry (RFC 6890)
+/// and multicast, as prefix and prefix length. Some ranges are nested,
+/// use [`range_kind`] to look up the most specific one.
+pub const SPECIAL_RANGES_V4: &[(Ipv4Addr, u8, RangeKind)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8, RangeKind::Unspecified),
+    (Ipv4Addr::new(0, 0, 0, 0), 32, RangeKind::Unspecified),
+    (Ipv4Addr::new(10, 0, 0, 0), 8, RangeKind::Private),
+    (
+        Ipv4Addr::new(100, 64, 0, 0),
+        10,
+        RangeKind::SharedAddressSpace,
+    ),
+    (Ipv4Addr::new(127, 0, 0, 0), 8, RangeKind::Loopback),
+    (Ipv4Addr::new(169, 254, 0, 0), 16, RangeKind::LinkLocal),
+    (Ipv4Addr::new(172, 16, 0, 0), 12, RangeKind::Private),
+    (
+        Ipv4Addr::new(192, 0, 0, 0),
+        24,
+        RangeKind::ProtocolAssignments,
+    ),
+    (
+        Ipv4Addr::new(192, 0, 0, 0),
+        29,
+        RangeKind::ServiceContinuity,
+    ),
+    (Ipv4Addr::new(192, 0, 0, 8), 32, RangeKind::DummyIpv4),
+    (Ipv4Addr::new(192, 0, 0, 9), 32, RangeKind::Anycast),
+    (Ipv4Addr::new(192, 0, 0, 10), 32, RangeKind::Anycast),
+    (Ipv4Addr::new(192, 0, 0, 170), 31, RangeKind::Nat64Discovery),
+    (Ipv4Addr::new(192, 0, 2, 0), 24, RangeKind::Documentation),
+    (Ipv4Addr::new(192, 31, 196, 0), 24, RangeKind::As112),
+    (Ipv4Addr::new(192, 52, 193, 0), 24, RangeKind::Amt),
+    (Ipv4Addr::new(192, 88, 99, 0), 24, RangeKind::SixToFourRelay),
+    (Ipv4Addr::new(192, 168, 0, 0), 16, RangeKind::Private),
+    (Ipv4Addr::new(192, 175, 48, 0), 24, RangeKind::As112),
+    (Ipv4Addr::new(198, 18, 0, 0), 15, RangeKind::Benchmarking),
+    (Ipv4Addr::new(198, 51, 100, 0), 24, RangeKind::Documentation),
+    (Ipv4Addr::new(203, 0, 113, 0), 24, RangeKind::Documentation),
+    (Ipv4Addr::new(224, 0, 0, 0), 4, RangeKind::Multicast),
+    (Ipv4Addr::new(240, 0, 0, 0), 4, RangeKind::Reserved),
+    (Ipv4Addr::new(255, 255, 255, 255), 32, RangeKind::Broadcast),
+];
+
+/// The IPv6 special-purpose ranges of the IANA registry (RFC 6890)
+/// and multicast. See [`SPECIAL_RANGES_V4`].
+pub const SPECIAL_RANGES_V6: &[(Ipv6Addr, u8, RangeKind)] = &[
+    (Ipv6Addr::UNSPECIFIED, 128, RangeKind::Unspecified),
+    (Ipv6Addr::LOCALHOST, 128, RangeKind::Loopback),
+    (
+        Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0, 0),
+        96,
+        RangeKind::Ipv4Mapped,
+    ),
+    (
+        Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0),
+        96,
+        RangeKind::Nat64,
+    ),
+    (
+        Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0),
+        48,
+        RangeKind::Nat64,
+    ),
+    (
+        Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0),
+        64,
+        RangeKind::DiscardOnly,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0),
+        23,
+        RangeKind::ProtocolAssignments,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0),
+        32,
+        RangeKind::Teredo,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 1, 0, 0, 0, 0, 0, 1),
+        128,
+        RangeKind::Anycast,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 1, 0, 0, 0, 0, 0, 2),
+        128,
+        RangeKind::Anycast,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 1, 0, 0, 0, 0, 0, 3),
+        128,
+        RangeKind::Anycast,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 2, 0, 0, 0, 0, 0, 0),
+        48,
+        RangeKind::Benchmarking,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 3, 0, 0, 0, 0, 0, 0),
+        32,
+        RangeKind::Amt,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 4, 0x112, 0, 0, 0, 0, 0),
+        48,
+        RangeKind::As112,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x10, 0, 0, 0, 0, 0, 0),
+        28,
+        RangeKind::Orchid,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x20, 0, 0, 0, 0, 0, 0),
+        28,
+        RangeKind::Orchid,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x30, 0, 0, 0, 0, 0, 0),
+        28,
+        RangeKind::DroneRemoteId,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+        32,
+        RangeKind::Documentation,
+    ),
+    (
+        Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0),
+        16,
+        RangeKind::SixToFour,
+    ),
+    (
+        Ipv6Addr::new(0x2620, 0x4f, 0x8000, 0, 0, 0, 0, 0),
+        48,
+        RangeKind::As112,
+    ),
+    (
+        Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0),
+        20,
+        RangeKind::Documentation,
+    ),
+    (
+        Ipv6Addr::new(0x5f00, 0, 0, 0, 0, 0, 0, 0),
+        16,
+        RangeKind::SegmentRouting,
+    ),
+    (
+        Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0),
+        7,
+        RangeKind::UniqueLocal,
+    ),
+    (
+        Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0),
+        10,
+        RangeKind::LinkLocal,
+    ),
+    (
+        Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0),
+        8,
+        RangeKind::Multicast,
+    ),
+];
+
+/// Report whether the IPv4 address is within the prefix.
+/// Lengths above 32 are treated as 32.
+pub(crate) fn ipv4_within(addr: Ipv4Addr, prefix: Ipv4Addr, len: u8) -> bool {
+    let mask = u32::MAX
+        .checked_shl(32 - u32::from(len.min(32)))
+        .unwrap_or(0);
+    u32::from(addr) & mask == u32::from(prefix) & mask
+}
+
+/// Report whether the IPv6 address is within the prefix.
+/// Lengths above 128 are treated as 128.
+pub(crate) fn ipv6_within(addr: Ipv6Addr, prefix: Ipv6Addr, len: u8) -> bool {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(len.min(128)))
+        .unwrap_or(0);
+    u128::from(addr) & mask == u128::from(prefix) & mask
+}
+
+/// Get the most specific entry of [`SPECIAL_RANGES_V4`]
+/// the address is within.
+pub(crate) fn ipv4_range(ipv4: Ipv4Addr) -> Option<&'static (Ipv4Addr, u8, RangeKind)> {
+    SPECIAL_RANGES_V4
+        .iter()
+        .filter(|&&(prefix, len, _)| ipv4_within(ipv4, prefix, len))
+        .max_by_key(|&&(_, len, _)| len)
+}
+
+/// Get the most specific entry of [`SPECIAL_RANGES_V6`]
+/// the address is within.
+pub(crate) fn ipv6_range(ipv6: Ipv6Addr) -> Option<&'static (Ipv6Addr, u8, RangeKind)> {
+    SPECIAL_RANGES_V6
+        .iter()
+        .filter(|&&(prefix, len, _)| ipv6_within(ipv6, prefix, len))
+        .max_by_key(|&&(_, len, _)| len)
+}
+
+/// Get the kind of the most specific special-purpose range
+/// the address is within, or `None` if it isn't within any.
+/// IPv4-mapped IPv6 addresses are reported as [`RangeKind::Ipv4Mapped`].
+pub fn range_kind(addr: impl Into<IpAddr>) -> Option<RangeKind> {
+    match addr.into() {
+        IpAddr::V4(ipv4) => ipv4_range(ipv4).map(|&(_, _, kind)| kind),
+        IpAddr::V6(ipv6) => ipv6_range(ipv6).map(|&(_, _, kind)| kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use RangeKind::*;
+
+    /// The entries of the IANA IPv4 and IPv6 Special-Purpose Address
+    /// Registries, with the kind of range they are reported as.
+    #[rustfmt::skip]
+    const IANA: &[(&str, u8, RangeKind)] = &[
+        ("0.0.0.0",          8,   Unspecified),
+        ("0.0.0.0",          32,  Unspecified),
+        ("10.0.0.0",         8,   Private),
+        ("100.64.0.0",       10,  SharedAddressSpace),
+        ("127.0.0.0",        8,   Loopback),
+        ("169.254.0.0",      16,  LinkLocal),
+        ("172.16.0.0",       12,  Private),
+        ("192.0.0.0",        24,  ProtocolAssignments),
+        ("192.0.0.0",        29,  ServiceContinuity),
+        ("192.0.0.8",        32,  DummyIpv4),
+        ("192.0.0.9",        32,  Anycast),
+        ("192.0.0.10",       32,  Anycast),
+        ("192.0.0.170",      32,  Nat64Discovery),
+        ("192.0.0.171",      32,  Nat64Discovery),
+        ("192.0.2.0",        24,  Documentation),
+        ("192.31.196.0",     24,  As112),
+        ("192.52.193.0",     24,  Amt),
+        ("192.88.99.0",      24,  SixToFourRelay),
+        ("192.168.0.0",      16,  Private),
+        ("192.175.48.0",     24,  As112),
+        ("198.18.0.0",       15,  Benchmarking),
+        ("198.51.100.0",     24,  Documentation),
+        ("203.0.113.0",      24,  Documentation),
+        ("240.0.0.0",        4,   Reserved),
+        ("255.255.255.255",  32,  Broadcast),
+        ("::1",              128, Loopback),
+        ("::",               128, Unspecified),
+        ("::ffff:0:0",       96,  Ipv4Mapped),
+        ("64:ff9b::",        96,  Nat64),
+        ("64:ff9b:1::",      48,  Nat64),
+        ("100::",            64,  DiscardOnly),
+        ("2001::",           23,  ProtocolAssignments),
+        ("2001::",           32,  Teredo),
+        ("2001:1::1",        128, Anycast),
+        ("2001:1::2",        128, Anycast),
+        ("2001:1::3",        128, Anycast),
+        ("2001:2::",         48,  Benchmarking),
+        ("2001:3::",         32,  Amt),
+        ("2001:4:112::",     48,  As112),
+        ("2001:10::",        28,  Orchid),
+        ("2001:20::",        28,  Orchid),
+        ("2001:30::",        28,  DroneRemoteId),
+        ("2001:db8::",       32,  Documentation),
+        ("2002::",           16,  SixToFour),
+        ("2620:4f:8000::",   48,  As112),
+        ("3fff::",           20,  Documentation),
+        ("5f00::",           16,  SegmentRouting),
+        ("fc00::",           7,   UniqueLocal),
+        ("fe80::",           10,  LinkLocal),
+    ];
+
+    /// Get the first and last address of the prefix.
+    fn bounds(prefix: &str, len: u8) -> (IpAddr, IpAddr) {
+        match prefix.parse().unwrap() {
+            IpAddr::V4(ipv4) => {
+                let host = u32::MAX.checked_shr(u32::from(len)).unwrap_or(0);
+                (ipv4.into(), Ipv4Addr::from(u32::from(ipv4) | host).into())
+            }
+            IpAddr::V6(ipv6) => {
+                let host = u128::MAX.checked_shr(u32::from(len)).unwrap_or(0);
+                (ipv6.into(), Ipv6Addr::from(u128::from(ipv6) | host).into())
+            }
+        }
+    }
+
+    fn iana_within(addr: IpAddr, prefix: &str, len: u8) -> bool {
+        match (addr, prefix.parse().unwrap()) {
+            (IpAddr::V4(addr), IpAddr::V4(prefix)) => ipv4_within(addr, prefix, len),
+            (IpAddr::V6(addr), IpAddr::V6(prefix)) => ipv6_within(addr, prefix, len),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn registry_ranges_are_classified() {
+        for &(prefix, len, kind) in IANA {
+            let (first, last) = bounds(prefix, len);
+
+            // Nested entries only take the part of the range they cover.
+            for addr in [first, last] {
+                let nested = IANA.iter().any(|&(other, other_len, _)| {
+                    other_len > len && iana_within(addr, other, other_len)
+                });
+                if !nested {
+                    assert_eq!(range_kind(addr), Some(kind), "{addr} in {prefix}/{len}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn table_has_only_registry_ranges() {
+        let v4 = SPECIAL_RANGES_V4
+            .iter()
+            .map(|&(prefix, len, kind)| (IpAddr::from(prefix), len, kind));
+        let v6 = SPECIAL_RANGES_V6
+            .iter()
+            .map(|&(prefix, len, kind)| (IpAddr::from(prefix), len, kind));
+
+        // Adjacent registry entries may be merged, like the two
+        // addresses for NAT64 prefix discovery.
+        for (prefix, len, kind) in v4.chain(v6).filter(|&(.., kind)| kind != Multicast) {
+            let (first, last) = bounds(&prefix.to_string(), len);
+            for addr in [first, last] {
+                let registered = IANA.iter().any(|&(iana, iana_len, iana_kind)| {
+                    iana_kind == kind && iana_within(addr, iana, iana_len)
+                });
+                assert!(
+                    registered,
+                    "{addr} of {prefix}/{len} isn't registered as {kind:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn table_prefixes_have_no_host_bits() {
+        for &(prefix, len, _) in SPECIAL_RANGES_V4 {
+            let host = u32::MAX.checked_shr(u32::from(len)).unwrap_or(0);
+            assert_eq!(u32::from(prefix) & host, 0, "{prefix}/{len}");
+        }
+        for &(prefix, len, _) in SPECIAL_RANGES_V6 {
+            let host = u128::MAX.checked_shr(u32::from(len)).unwrap_or(0);
+            assert_eq!(u128::from(prefix) & host, 0, "{prefix}/{len}");
+        }
+    }
+
+    #[test]
+    fn most_specific_range_wins() {
+        #[rustfmt::skip]
+        let cases = [
+            ("192.0.0.0",       Some(ServiceContinuity)),
+            ("192.0.0.7",       Some(ServiceContinuity)),
+            ("192.0.0.8",       Some(DummyIpv4)),
+            ("192.0.0.9",       Some(Anycast)),
+            ("192.0.0.11",      Some(ProtocolAssignments)),
+            ("192.0.0.169",     Some(ProtocolAssignments)),
+            ("192.0.0.171",     Some(Nat64Discovery)),
+            ("192.0.0.255",     Some(ProtocolAssignments)),
+            ("0.0.0.0",         Some(Unspecified)),
+            ("2001::",          Some(Teredo)),
+            ("2001:1::",        Some(ProtocolAssignments)),
+            ("2001:1::3",       Some(Anycast)),
+            ("2001:1::4",       Some(ProtocolAssignments)),
+            ("2001:2::1",       Some(Benchmarking)),
+            ("2001:2:1::",      Some(ProtocolAssignments)),
+            ("2001:1ff::",      Some(ProtocolAssignments)),
+        ];
+
+        for (addr, expected) in cases {
+            assert_eq!(
+                range_kind(addr.parse::<IpAddr>().unwrap()),
+                expected,
+                "{addr}"
+            );
+        }
+    }
+
+    #[test]
+    fn range_boundaries() {
+        #[rustfmt::skip]
+        let cases = [
+            ("100.63.255.255",  None),
+            ("100.64.0.0",      Some(SharedAddressSpace)),
+            ("100.127.255.255", Some(SharedAddressSpace)),
+            ("100.128.0.0",     None),
+            ("172.15.255.255",  None),
+            ("172.31.255.255",  Some(Private)),
+            ("172.32.0.0",      None),
+            ("198.17.255.255",  None),
+            ("198.19.255.255",  Some(Benchmarking)),
+            ("223.255.255.255", None),
+            ("239.255.255.255", Some(Multicast)),
+            ("255.255.255.254", Some(Reserved)),
+            ("::2",             None),
+            ("::ffff:10.0.0.1", Some(Ipv4Mapped)),
+            ("1ff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", None),
+            ("2001:200::",      None),
+            ("2001:db7:ffff::", None),
+            ("fbff::",          None),
+            ("fdff::1",         Some(UniqueLocal)),
+            ("febf::1",         Some(LinkLocal)),
+            ("fec0::1",         None),
+        ];
+
+        for (addr, expected) in cases {
+            assert_eq!(
+                range_kind(addr.parse::<IpAddr>().unwrap()),
+                expected,
+                "{addr}"
+            );
+        }
+    }
+
+    #[test]
+    fn global_addresses_are_in_no_range() {
+        assert_eq!(range_kind(Ipv4Addr::new(8, 8, 8, 8)), None);
+        assert_eq!(range_kind(Ipv4Addr::new(1, 1, 1, 1)), None);
+        assert_eq!(range_kind("2a01:4f8::1".parse::<Ipv6Addr>().unwrap()), None);
+        assert_eq!(range_kind("2600::1".parse::<Ipv6Addr>().unwrap()), None);
+    }
+
+    #[test]
+    fn ranges_are_looked_up_with_their_entry() {
+        assert_eq!(
+            ipv4_range(Ipv4Addr::new(192, 0, 0, 170)),
+            Some(&(Ipv4Addr::new(192, 0, 0, 170), 31, Nat64Discovery))
+        );
+        assert_eq!(
+            ipv6_range("64:ff9b::192.0.2.1".parse().unwrap()),
+            Some(&(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0), 96, Nat64))
+        );
+        assert_eq!(ipv4_range(Ipv4Addr::new(8, 8, 8, 8)), None);
+    }
+
+    #[test]
+    fn ipv4_within_at_prefix_boundaries() {
+        let within = |addr: &str, prefix: &str, len| {
+            ipv4_within(addr.parse().unwrap(), prefix.parse().unwrap(), len)
+        };
+
+        assert!(within("8.8.8.8", "0.0.0.0", 0));
+        assert!(within("192.168.77.1", "192.168.0.0", 16));
+        assert!(!within("192.169.0.0", "192.168.0.0", 16));
+        assert!(within("192.168.77.1", "192.168.77.1", 32));
+        assert!(!within("192.168.77.2", "192.168.77.1", 32));
+        assert!(within("192.168.77.1", "192.168.77.1", u8::MAX));
+        assert!(within("192.168.1.1", "192.168.255.255", 16));
+    }
+
+    fn within(addr: &str, prefix: &str, len: u8) -> bool {
+        ipv6_within(addr.parse().unwrap(), prefix.parse().unwrap(), len)
+    }
+
+    #[test]
+    fn ipv6_within_at_prefix_boundaries() {
+        #[rustfmt::skip]
+        let cases = [
+            ("2a01:4f8::1",             "::",                 0,   true),
+            ("::",                      "ffff::",             0,   true),
+            ("2a01:4f8:0:1::1",         "2a01:4f8::",         63,  true),
+            ("2a01:4f8:0:1:ffff::",     "2a01:4f8::",         63,  true),
+            ("2a01:4f8:0:2::",          "2a01:4f8::",         63,  false),
+            ("2a01:4f8:0:1::1",         "2a01:4f8::",         64,  false),
+            ("2a01:4f8::ffff:ffff:ffff:ffff", "2a01:4f8::",   64,  true),
+            ("2a01:4f8::7fff:ffff:ffff:ffff", "2a01:4f8::",   65,  true),
+            ("2a01:4f8::8000:0:0:0",    "2a01:4f8::",         65,  false),
+            ("2a01:4f8::8000:0:0:0",    "2a01:4f8::8000:0:0:0", 65, true),
+            ("2a01:4f8::1",             "2a01:4f8::1",        128, true),
+            ("2a01:4f8::2",             "2a01:4f8::1",        128, false),
+        ];
+
+        for (addr, prefix, len, expected) in cases {
+            assert_eq!(
+                within(addr, prefix, len),
+                expected,
+                "{addr} in {prefix}/{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn ipv6_within_ignores_host_bits_of_the_prefix() {
+        assert!(within("2a01:4f8::1", "2a01:4f8::ffff", 64));
+        assert!(within("2a01:4f8:0:1::1", "2a01:4f8::ffff", 63));
+        assert!(!within("2a01:4f8:0:1::1", "2a01:4f8::ffff", 64));
+    }
+
+    #[test]
+    fn ipv6_within_clamps_long_prefixes() {
+        assert!(within("2a01:4f8::1", "2a01:4f8::1", 129));
+        assert!(within("2a01:4f8::1", "2a01:4f8::1", u8::MAX));
+        assert!(!within("2a01:4f8::2", "2a01:4f8::1", u8::MAX));
+    }
+}
@@ -0,0 +1,533 @@
+use std::cmp::Ordering;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, Ipv6MulticastScope};
+
+use crate::addrs::{self, InterfaceAddr};
+use crate::netlink::{AddrLabel, Netlink};
+use crate::{Destination, IpQuery, Result, SourcePreference};
+
+/// The default policy table of RFC 6724 section 2.1
+/// as `(prefix, prefix length, label)`.
+const DEFAULT_LABELS: [(Ipv6Addr, u8, u32); 9] = [
+    (Ipv6Addr::LOCALHOST, 128, 0),
+    (Ipv6Addr::UNSPECIFIED, 0, 1),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0, 0), 96, 4),
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16, 2),
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 32, 5),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7, 13),
+    (Ipv6Addr::UNSPECIFIED, 96, 3),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10, 11),
+    (Ipv6Addr::new(0x3ffe, 0, 0, 0, 0, 0, 0, 0), 16, 12),
+];
+
+/// The scope values of RFC 4291 section 2.7 used for comparisons.
+const SCOPE_LINK_LOCAL: u8 = 2;
+const SCOPE_SITE_LOCAL: u8 = 5;
+const SCOPE_GLOBAL: u8 = 14;
+
+fn default_labels() -> Vec<AddrLabel> {
+    DEFAULT_LABELS
+        .iter()
+        .map(|&(prefix, prefix_len, label)| AddrLabel {
+            prefix,
+            prefix_len,
+            index: 0,
+            label,
+        })
+        .collect()
+}
+
+/// Get the address label table of the kernel, falling back to
+/// the default table of RFC 6724 if it can't be dumped.
+fn label_table() -> Vec<AddrLabel> {
+    labels_or_default(Netlink::open().and_then(|mut netlink| netlink.addr_labels()))
+}
+
+/// Use the dumped table, which already contains the defaults the kernel
+/// started with and the entries the admin added, or the default table
+/// if the dump failed or the admin flushed the table.
+fn labels_or_default(dumped: io::Result<Vec<AddrLabel>>) -> Vec<AddrLabel> {
+    match dumped {
+        Ok(labels) if !labels.is_empty() => labels,
+        _ => default_labels(),
+    }
+}
+
+/// Convert an address to the IPv6 form used by RFC 6724,
+/// mapping IPv4 addresses to `::ffff:0:0/96`.
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
+        IpAddr::V6(ipv6) => ipv6,
+    }
+}
+
+fn common_prefix_len(a: Ipv6Addr, b: Ipv6Addr) -> u8 {
+    (u128::from(a) ^ u128::from(b)).leading_zeros() as u8
+}
+
+/// Look up the label of an address on the given interface.
+/// The longest matching prefix wins. Of equally long prefixes, entries
+/// restricted to the interface take precedence over those for all
+/// interfaces. Addresses without a matching entry get label 1 (`::/0`).
+fn label_of(labels: &[AddrLabel], addr: IpAddr, index: u32) -> u32 {
+    let ipv6 = to_ipv6(addr);
+
+    labels
+        .iter()
+        .filter(|entry| entry.index == 0 || entry.index == index)
+        .filter(|entry| common_prefix_len(ipv6, entry.prefix) >= entry.prefix_len)
+        .max_by_key(|entry| (entry.prefix_len, entry.index != 0))
+        .map_or(1, |entry| entry.label)
+}
+
+/// Get the scope of an address as defined by RFC 6724 section 3.1.
+fn scope_of(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(ipv4) if ipv4.is_link_local() || ipv4.is_loopback() => SCOPE_LINK_LOCAL,
+        IpAddr::V4(_) => SCOPE_GLOBAL,
+        IpAddr::V6(ipv6) if ipv6.is_unicast_link_local() || ipv6.is_loopback() => SCOPE_LINK_LOCAL,
+        IpAddr::V6(ipv6) if ipv6.segments()[0] & 0xffc0 == 0xfec0 => SCOPE_SITE_LOCAL,
+        IpAddr::V6(ipv6) => ipv6.multicast_scope().map_or(SCOPE_GLOBAL, |scope| {
+            // The discriminants aren't the scope values.
+            match scope {
+                Ipv6MulticastScope::InterfaceLocal => 1,
+                Ipv6MulticastScope::LinkLocal => SCOPE_LINK_LOCAL,
+                Ipv6MulticastScope::RealmLocal => 3,
+                Ipv6MulticastScope::AdminLocal => 4,
+                Ipv6MulticastScope::SiteLocal => SCOPE_SITE_LOCAL,
+                Ipv6MulticastScope::OrganizationLocal => 8,
+                _ => SCOPE_GLOBAL,
+            }
+        }),
+    }
+}
+
+/// The context of comparing two candidate source addresses.
+struct Ranking<'a> {
+    dest: IpAddr,
+    labels: &'a [AddrLabel],
+    /// The outgoing interface towards the destination, if known.
+    oif: Option<u32>,
+    prefer_temporary: bool,
+}
+
+impl Ranking<'_> {
+    /// Compare two candidates by the source address selection rules
+    /// of RFC 6724 section 5, the better one first.
+    /// Rule 4 (home addresses) doesn't apply without Mobile IPv6.
+    fn compare(&self, a: &InterfaceAddr, b: &InterfaceAddr) -> Ordering {
+        let dest_scope = scope_of(self.dest);
+        let dest_label = label_of(self.labels, self.dest, self.oif.unwrap_or(0));
+
+        // Rule 1: Prefer the destination itself.
+        (b.addr == self.dest)
+            .cmp(&(a.addr == self.dest))
+            // Rule 2: Prefer the appropriate scope.
+            .then_with(|| {
+                let (a_scope, b_scope) = (scope_of(a.addr), scope_of(b.addr));
+                match a_scope.cmp(&b_scope) {
+                    Ordering::Less if a_scope < dest_scope => Ordering::Greater,
+                    Ordering::Less => Ordering::Less,
+                    Ordering::Greater if b_scope < dest_scope => Ordering::Less,
+                    Ordering::Greater => Ordering::Greater,
+                    Ordering::Equal => Ordering::Equal,
+                }
+            })
+            // Rule 3: Avoid deprecated addresses.
+            .then_with(|| a.is_deprecated().cmp(&b.is_deprecated()))
+            // Rule 5: Prefer the outgoing interface.
+            .then_with(|| match self.oif {
+                Some(oif) => (b.index == oif).cmp(&(a.index == oif)),
+                None => Ordering::Equal,
+            })
+            // Rule 6: Prefer matching labels.
+            .then_with(|| {
+                let a_matches = label_of(self.labels, a.addr, a.index) == dest_label;
+                let b_matches = label_of(self.labels, b.addr, b.index) == dest_label;
+                b_matches.cmp(&a_matches)
+            })
+            // Rule 7: Prefer temporary addresses, or public ones
+            // if requested.
+            .then_with(|| match self.prefer_temporary {
+                true => b.is_temporary().cmp(&a.is_temporary()),
+                false => a.is_temporary().cmp(&b.is_temporary()),
+            })
+            // Rule 8: Use the longest matching prefix,
+            // up to the prefix length of the candidate.
+            .then_with(|| {
+                let matching = |candidate: &InterfaceAddr| {
+                    let len = common_prefix_len(to_ipv6(candidate.addr), to_ipv6(self.dest));
+                    match candidate.addr {
+                        IpAddr::V4(_) => len.saturating_sub(96).min(candidate.prefix_len),
+                        IpAddr::V6(_) => len.min(candidate.prefix_len),
+                    }
+                };
+                matching(b).cmp(&matching(a))
+            })
+    }
+}
+
+impl IpQuery<'_> {
+    /// Rank the usable addresses of the interface, or of all interfaces
+    /// if the query isn't bound to one, by the source address selection
+    /// rules of RFC 6724 for sending to the given destination,
+    /// the best candidate first.
+    ///
+    /// The labels are taken from the kernel's policy table (`ip addrlabel`)
+    /// like the kernel does, or from the default table of the RFC
+    /// if it can't be read. Temporary addresses are preferred
+    /// unless the query prefers [`SourcePreference::Public`].
+    /// Only addresses of the destination's family are ranked.
+    ///
+    /// Unlike the getters, this doesn't ask the kernel for its choice.
+    /// Routing decisions like the preferred source of a route
+    /// aren't taken into account.
+    pub fn rank_sources(&self, dest: impl Into<Destination>) -> Result<Vec<InterfaceAddr>> {
+        let dest = dest.into().addr;
+
+        let mut candidates: Vec<_> = addrs::addresses(self.interface)?
+            .into_iter()
+            .filter(|addr| addr.addr.is_ipv4() == dest.is_ipv4())
+            .filter(|addr| addr.is_usable(self.optimistic_dad))
+            .collect();
+
+        let oif = match self.interface {
+            Some(_) => Some(self.if_index()?),
+            None => Netlink::open()
+                .and_then(|mut netlink| netlink.route_get(dest, None, false))
+                .ok()
+                .and_then(|route| route.oif),
+        };
+
+        let labels = label_table();
+        let ranking = Ranking {
+            dest,
+            labels: &labels,
+            oif,
+            prefer_temporary: !self.source_preferences.contains(&SourcePreference::Public),
+        };
+
+        candidates.sort_by(|a, b| ranking.compare(a, b));
+        Ok(candidates)
+    }
+}
+
+/// Rank the usable addresses of the given interface, or of all interfaces
+/// if `None`, for sending to the given destination.
+/// See [`IpQuery::rank_sources`] for details.
+pub fn rank_sources(
+    interface: Option<&str>,
+    dest: impl Into<Destination>,
+) -> Result<Vec<InterfaceAddr>> {
+    IpQuery::with_interface(interface).rank_sources(dest)
+}
+
+/// Get the IPv6 address label table of the kernel (`ip addrlabel`)
+/// as `(prefix, prefix length, label)`, ordered like the kernel
+/// lists it. Entries restricted to a single interface are omitted.
+pub fn address_labels() -> Result<Vec<(Ipv6Addr, u8, u32)>> {
+    Ok(Netlink::open()?
+        .addr_labels()?
+        .into_iter()
+        .filter(|entry| entry.index == 0)
+        .map(|entry| (entry.prefix, entry.prefix_len, entry.label))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IFA_F_TEMPORARY: u32 = 0x01;
+    const IFA_F_DEPRECATED: u32 = 0x20;
+
+    fn entry(prefix: &str, prefix_len: u8, index: u32, label: u32) -> AddrLabel {
+        AddrLabel {
+            prefix: prefix.parse().unwrap(),
+            prefix_len,
+            index,
+            label,
+        }
+    }
+
+    fn candidate(index: u32, addr: &str, prefix_len: u8, flags: u32) -> InterfaceAddr {
+        InterfaceAddr {
+            index,
+            addr: addr.parse().unwrap(),
+            prefix_len,
+            flags,
+            label: None,
+            preferred_lifetime: None,
+        }
+    }
+
+    fn label(labels: &[AddrLabel], addr: &str) -> u32 {
+        label_of(labels, addr.parse().unwrap(), 2)
+    }
+
+    /// Sort the candidates for sending to `dest` from interface 2.
+    fn rank(labels: &[AddrLabel], dest: &str, candidates: &[InterfaceAddr]) -> Vec<IpAddr> {
+        let ranking = Ranking {
+            dest: dest.parse().unwrap(),
+            labels,
+            oif: Some(2),
+            prefer_temporary: true,
+        };
+
+        let mut candidates = candidates.to_vec();
+        candidates.sort_by(|a, b| ranking.compare(a, b));
+        candidates
+            .into_iter()
+            .map(|candidate| candidate.addr)
+            .collect()
+    }
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn default_labels_of_rfc_6724() {
+        let labels = default_labels();
+
+        #[rustfmt::skip]
+        let cases = [
+            ("::1",              0),
+            ("2a01:4f8::1",      1),
+            ("2002:c000:204::1", 2),
+            ("::c000:204",       3),
+            ("::ffff:192.0.2.4", 4),
+            ("192.0.2.4",        4),
+            ("2001:0:4136::1",   5),
+            ("3ffe::1",          12),
+            ("fec0::1",          11),
+            ("fd00::1",          13),
+            ("fc00::1",          13),
+            ("fe80::1",          1),
+        ];
+
+        for (addr, expected) in cases {
+            assert_eq!(label(&labels, addr), expected, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let labels = [
+            entry("::", 0, 0, 1),
+            entry("2a01:4f8::", 32, 0, 20),
+            entry("2a01:4f8:1::", 48, 0, 21),
+            entry("2a01:4f8:1:2::", 64, 0, 22),
+        ];
+
+        assert_eq!(label(&labels, "2a01:4f7::1"), 1);
+        assert_eq!(label(&labels, "2a01:4f8::1"), 20);
+        assert_eq!(label(&labels, "2a01:4f8:1::1"), 21);
+        assert_eq!(label(&labels, "2a01:4f8:1:2::1"), 22);
+        assert_eq!(label(&labels, "2a01:4f8:1:3::1"), 21);
+
+        // The order of the table doesn't matter.
+        let mut reversed = labels.to_vec();
+        reversed.reverse();
+        assert_eq!(label(&reversed, "2a01:4f8:1:2::1"), 22);
+    }
+
+    #[test]
+    fn unmatched_addresses_get_label_1() {
+        let labels = [entry("2a01:4f8::", 32, 0, 20)];
+        assert_eq!(label(&labels, "2a02::1"), 1);
+        assert_eq!(label(&[], "2a02::1"), 1);
+    }
+
+    #[test]
+    fn interface_entries_take_precedence() {
+        let labels = [
+            entry("2a01:4f8::", 32, 0, 20),
+            entry("2a01:4f8::", 32, 2, 30),
+            entry("2a01:4f8::", 48, 3, 40),
+        ];
+
+        let addr = "2a01:4f8::1".parse().unwrap();
+        assert_eq!(label_of(&labels, addr, 2), 30);
+        // Entries of other interfaces are ignored, however long.
+        assert_eq!(label_of(&labels, addr, 1), 20);
+        assert_eq!(label_of(&labels, addr, 3), 40);
+    }
+
+    #[test]
+    fn custom_labels_override_defaults() {
+        // What the kernel dumps after
+        // `ip addrlabel add prefix 2001:0:bad::/48 label 99`.
+        let mut labels = default_labels();
+        labels.insert(0, entry("2001:0:bad::", 48, 0, 99));
+
+        let labels = labels_or_default(Ok(labels));
+        assert_eq!(label(&labels, "2001:0:bad::1"), 99);
+        // The default for the surrounding prefix still applies.
+        assert_eq!(label(&labels, "2001:0:600d::1"), 5);
+        assert_eq!(label(&labels, "::1"), 0);
+    }
+
+    #[test]
+    fn dumped_table_replaces_defaults() {
+        // Deleted defaults stay deleted, like in the kernel.
+        let labels = labels_or_default(Ok(vec![entry("::", 0, 0, 7)]));
+        assert_eq!(label(&labels, "::1"), 7);
+        assert_eq!(label(&labels, "fd00::1"), 7);
+    }
+
+    #[test]
+    fn unavailable_table_falls_back_to_defaults() {
+        let error = io::Error::from_raw_os_error(libc::EPERM);
+        assert_eq!(labels_or_default(Err(error)), default_labels());
+        assert_eq!(labels_or_default(Ok(Vec::new())), default_labels());
+    }
+
+    #[test]
+    fn prefer_the_destination() {
+        let candidates = [
+            candidate(2, "2a01:4f8::2", 64, 0),
+            candidate(2, "2a01:4f8::1", 64, IFA_F_DEPRECATED),
+        ];
+        assert_eq!(
+            rank(&default_labels(), "2a01:4f8::1", &candidates),
+            ips(&["2a01:4f8::1", "2a01:4f8::2"])
+        );
+    }
+
+    #[test]
+    fn prefer_the_appropriate_scope() {
+        let candidates = [
+            candidate(2, "fe80::1", 64, 0),
+            candidate(2, "2a01:4f8::1", 64, 0),
+        ];
+        let labels = default_labels();
+
+        assert_eq!(
+            rank(&labels, "2a02::1", &candidates),
+            ips(&["2a01:4f8::1", "fe80::1"])
+        );
+        assert_eq!(
+            rank(&labels, "fe80::2", &candidates),
+            ips(&["fe80::1", "2a01:4f8::1"])
+        );
+        // The smallest scope that is large enough.
+        assert_eq!(
+            rank(
+                &labels,
+                "ff05::1",
+                &[candidate(2, "fec0::1", 10, 0), candidates[1].clone()]
+            ),
+            ips(&["fec0::1", "2a01:4f8::1"])
+        );
+    }
+
+    #[test]
+    fn avoid_deprecated_addresses() {
+        let candidates = [
+            candidate(2, "2a01:4f8::1", 64, IFA_F_DEPRECATED),
+            candidate(2, "2a01:4f8::2", 64, 0),
+        ];
+        assert_eq!(
+            rank(&default_labels(), "2a01:4f8::3", &candidates),
+            ips(&["2a01:4f8::2", "2a01:4f8::1"])
+        );
+    }
+
+    #[test]
+    fn prefer_the_outgoing_interface() {
+        let candidates = [
+            candidate(3, "2a01:4f8::1", 64, 0),
+            candidate(2, "2a02::1", 64, 0),
+        ];
+        assert_eq!(
+            rank(&default_labels(), "2a01:4f8::3", &candidates),
+            ips(&["2a02::1", "2a01:4f8::1"])
+        );
+    }
+
+    #[test]
+    fn prefer_matching_labels() {
+        // Rule 8 would prefer the address sharing the longer prefix.
+        let mut labels = default_labels();
+        labels.push(entry("2a01:4f8::2", 128, 0, 7));
+        let candidates = [
+            candidate(2, "2a01:4f8::2", 64, 0),
+            candidate(2, "2a02::1", 64, 0),
+        ];
+
+        assert_eq!(
+            rank(&labels, "2a01:4f8::3", &candidates),
+            ips(&["2a02::1", "2a01:4f8::2"])
+        );
+        assert_eq!(
+            rank(&default_labels(), "2a01:4f8::3", &candidates),
+            ips(&["2a01:4f8::2", "2a02::1"])
+        );
+    }
+
+    #[test]
+    fn temporary_addresses_follow_the_preference() {
+        let candidates = [
+            candidate(2, "2a01:4f8::1", 64, 0),
+            candidate(2, "2a01:4f8::2", 64, IFA_F_TEMPORARY),
+        ];
+        let labels = default_labels();
+        let mut ranking = Ranking {
+            dest: "2a02::1".parse().unwrap(),
+            labels: &labels,
+            oif: Some(2),
+            prefer_temporary: true,
+        };
+
+        assert_eq!(
+            ranking.compare(&candidates[1], &candidates[0]),
+            Ordering::Less
+        );
+        ranking.prefer_temporary = false;
+        assert_eq!(
+            ranking.compare(&candidates[0], &candidates[1]),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn longest_matching_prefix_up_to_the_prefix_length() {
+        let labels = default_labels();
+        let candidates = [
+            candidate(2, "2a01:4f8::1", 64, 0),
+            candidate(2, "2a01:4f8:1::1", 64, 0),
+        ];
+        assert_eq!(
+            rank(&labels, "2a01:4f8:1::2", &candidates),
+            ips(&["2a01:4f8:1::1", "2a01:4f8::1"])
+        );
+
+        // The match is cut off at the prefix length of the candidate.
+        let candidates = [
+            candidate(2, "2a01:4f8:1::1", 16, 0),
+            candidate(2, "2a01:4f8::1", 32, 0),
+        ];
+        assert_eq!(
+            rank(&labels, "2a01:4f8:1::2", &candidates),
+            ips(&["2a01:4f8::1", "2a01:4f8:1::1"])
+        );
+    }
+
+    #[test]
+    fn ipv4_prefixes_are_compared_without_the_mapping() {
+        let candidates = [
+            candidate(2, "10.0.0.1", 8, 0),
+            candidate(2, "192.168.1.1", 24, 0),
+        ];
+        assert_eq!(
+            rank(&default_labels(), "192.168.1.200", &candidates),
+            ips(&["192.168.1.1", "10.0.0.1"])
+        );
+        assert_eq!(
+            rank(&default_labels(), "10.1.2.3", &candidates),
+            ips(&["10.0.0.1", "192.168.1.1"])
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::net::IpAddr;
+
+use crate::{Error, IpQuery, Result};
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Parse the nameservers from the contents of a `resolv.conf` file,
+/// in the order they are listed.
+///
+/// Comments and lines other than `nameserver` directives are ignored.
+/// So are `nameserver` directives whose address can't be parsed.
+/// A zone suffix (`%eth0`) is stripped, probes use the query's interface.
+/// Use [`Destination`](crate::Destination) to parse addresses with zones.
+pub fn parse_resolv_conf(contents: &str) -> Vec<IpAddr> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                Some("nameserver") => words.next(),
+                _ => None,
+            }
+        })
+        .filter_map(|addr| addr.split('%').next()?.parse().ok())
+        .collect()
+}
+
+/// Read the nameservers from `/etc/resolv.conf`,
+/// failing if there are none.
+pub(crate) fn nameservers() -> Result<Vec<IpAddr>> {
+    let nameservers = parse_resolv_conf(&fs::read_to_string(RESOLV_CONF)?);
+    if nameservers.is_empty() {
+        return Err(Error::NoNameservers);
+    }
+
+    Ok(nameservers)
+}
+
+impl IpQuery<'_> {
+    /// Get the source address used for DNS queries to each of the
+    /// nameservers configured in `/etc/resolv.conf`, as
+    /// `(nameserver, source)` pairs in the order they are listed.
+    /// The probes are sent to port 53 so that port-based routing rules apply.
+    ///
+    /// A nameserver that can't be probed has the error instead of
+    /// a source, the others are still probed. For example,
+    /// link-local nameservers can only be probed
+    /// if the query is bound to an interface.
+    pub fn preferred_source_for_dns(&self) -> Result<Vec<(IpAddr, Result<IpAddr>)>> {
+        Ok(self.sources_for_dns(nameservers()?))
+    }
+
+    fn sources_for_dns(&self, nameservers: Vec<IpAddr>) -> Vec<(IpAddr, Result<IpAddr>)> {
+        nameservers
+            .into_iter()
+            .map(|nameserver| {
+                let source = self
+                    .dest_socket_addr(nameserver.into(), 53)
+                    .and_then(|dest| self.probe(dest));
+                (nameserver, source)
+            })
+            .collect()
+    }
+}
+
+/// Get the source address used for DNS queries to each of the configured
+/// nameservers on the given interface, or on any interface if `None`.
+/// See [`IpQuery::preferred_source_for_dns`] for details.
+pub fn preferred_source_for_dns(interface: Option<&str>) -> Result<Vec<(IpAddr, Result<IpAddr>)>> {
+    IpQuery::with_interface(interface).preferred_source_for_dns()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_nameservers() {
+        let contents = "\
+# Generated by NetworkManager
+search example.com
+nameserver 192.168.1.1
+nameserver\t2001:4860:4860::8888
+options edns0 trust-ad
+nameserver fe80::1%eth0
+";
+        assert_eq!(
+            parse_resolv_conf(contents),
+            ips(&["192.168.1.1", "2001:4860:4860::8888", "fe80::1"])
+        );
+    }
+
+    #[test]
+    fn parse_skips_invalid_lines() {
+        let contents = "\
+nameserver
+nameserver not-an-address
+nameserver 300.1.1.1
+ nameserver 10.0.0.1 trailing words
+#nameserver 10.0.0.2
+; nameserver 10.0.0.3
+nameservers 10.0.0.4
+NAMESERVER 10.0.0.5
+nameserver 10.0.0.6 # comment
+";
+        assert_eq!(parse_resolv_conf(contents), ips(&["10.0.0.1", "10.0.0.6"]));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(parse_resolv_conf("").is_empty());
+        assert!(parse_resolv_conf("search example.com\n\n").is_empty());
+    }
+
+    #[test]
+    fn parse_keeps_order_and_duplicates() {
+        let contents = "nameserver ::1\nnameserver 127.0.0.53\nnameserver ::1\n";
+        assert_eq!(
+            parse_resolv_conf(contents),
+            ips(&["::1", "127.0.0.53", "::1"])
+        );
+    }
+
+    #[test]
+    fn failed_probes_dont_hide_others() {
+        let query = IpQuery::any_interface();
+        let nameservers = ips(&["127.0.0.1", "fe80::1", "::1"]);
+
+        let sources = query.sources_for_dns(nameservers.clone());
+        assert_eq!(
+            sources
+                .iter()
+                .map(|(nameserver, _)| *nameserver)
+                .collect::<Vec<_>>(),
+            nameservers
+        );
+        assert_eq!(*sources[0].1.as_ref().unwrap(), nameservers[0]);
+        assert!(
+            matches!(sources[1].1, Err(Error::NoZone(_))),
+            "{:?}",
+            sources[1]
+        );
+        // IPv6 may be disabled.
+        if crate::ipv6_available() {
+            assert_eq!(*sources[2].1.as_ref().unwrap(), nameservers[2]);
+        }
+    }
+}
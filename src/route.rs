@@ -0,0 +1,526 @@
+use std::cmp::Reverse;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::netlink::{self, Netlink, Route};
+use crate::{if_index, if_name, Destination, Error, IpQuery, IpVersion, Result};
+
+/// The destinations of lookups through default routes.
+/// Any global address without a more specific route will do.
+const LOOKUP_V6: Ipv6Addr = Ipv6Addr::new(0x2000, 0, 0, 0, 0, 0, 0, 0);
+const LOOKUP_V4: Ipv4Addr = Ipv4Addr::new(1, 0, 0, 0);
+
+/// How well an interface is suited for reaching a destination,
+/// as returned by [`rank_interfaces`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InterfaceRanking {
+    pub interface: String,
+    /// Whether there is any route towards the destination
+    /// through this interface.
+    pub reachable: bool,
+    /// The prefix length of the matching route.
+    pub prefix_len: Option<u8>,
+    /// The metric of the matching route.
+    pub metric: Option<u32>,
+    /// The source address used towards the destination.
+    pub source: Option<IpAddr>,
+}
+
+impl InterfaceRanking {
+    fn unreachable(interface: &str) -> Self {
+        Self {
+            interface: interface.into(),
+            reachable: false,
+            prefix_len: None,
+            metric: None,
+            source: None,
+        }
+    }
+}
+
+fn is_unreachable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NetworkUnreachable | io::ErrorKind::HostUnreachable
+    )
+}
+
+/// Rank the given interfaces by the preference of the kernel
+/// for sending to the given destination.
+///
+/// Like the kernel, the most specific route wins,
+/// then the route with the lowest metric.
+/// Interfaces without a route towards the destination,
+/// or without a source address for it, are ranked last
+/// and flagged as unreachable. Ties keep the order of the input.
+pub fn rank_interfaces(dest: IpAddr, interfaces: &[&str]) -> Result<Vec<InterfaceRanking>> {
+    let mut netlink = Netlink::open()?;
+
+    let mut rankings = interfaces
+        .iter()
+        .map(|interface| {
+            let oif = if_index(interface)?;
+
+            let fib_match = match netlink.route_get(dest, Some(oif), true) {
+                Ok(route) => route,
+                Err(e) if is_unreachable(&e) => {
+                    return Ok(InterfaceRanking::unreachable(interface))
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // Without a usable source address, the interface
+            // can't be used either.
+            match netlink.route_get(dest, Some(oif), false) {
+                Ok(route) => Ok(ranking(interface, &fib_match, &route)),
+                Err(_) => Ok(InterfaceRanking::unreachable(interface)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    sort_rankings(&mut rankings);
+    Ok(rankings)
+}
+
+fn ranking(interface: &str, fib_match: &Route, route: &Route) -> InterfaceRanking {
+    InterfaceRanking {
+        interface: interface.into(),
+        reachable: true,
+        prefix_len: Some(fib_match.dst_len),
+        metric: Some(fib_match.metric.unwrap_or(0)),
+        source: route.prefsrc,
+    }
+}
+
+fn sort_rankings(rankings: &mut [InterfaceRanking]) {
+    rankings.sort_by_key(|ranking| {
+        (
+            !ranking.reachable,
+            Reverse(ranking.prefix_len),
+            ranking.metric,
+        )
+    });
+}
+
+/// A default route, as returned by [`default_routes`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DefaultRoute {
+    /// The gateway, or `None` for routes directly through the interface,
+    /// e.g. over point-to-point links.
+    pub gateway: Option<IpAddr>,
+    /// The index of the outgoing interface.
+    pub oif: u32,
+    /// The name of the outgoing interface,
+    /// or `None` if it was removed in the meantime.
+    pub interface: Option<String>,
+    /// The metric of the route, 0 if it has none.
+    pub metric: u32,
+    /// The routing table the route is in.
+    pub table: u32,
+    /// The source address used through this route. This is the
+    /// preferred source of the route if it has one, otherwise the address
+    /// the kernel selects for a lookup constrained to the interface,
+    /// or `None` if there is no usable address.
+    pub source: Option<IpAddr>,
+}
+
+fn lookup_dest(version: IpVersion) -> IpAddr {
+    match version {
+        IpVersion::V4 => IpAddr::V4(LOOKUP_V4),
+        IpVersion::V6 => IpAddr::V6(LOOKUP_V6),
+    }
+}
+
+/// Get the default routes of the given IP version of all routing tables,
+/// whether or not they are currently preferred, e.g. the routes
+/// of both uplinks of a dual-WAN router.
+///
+/// The routes are sorted by their metric. Ties keep the order of the
+/// kernel's dump. Multipath routes are listed once per nexthop, with
+/// only the interface of the nexthop and no gateway.
+pub fn default_routes(version: IpVersion) -> Result<Vec<DefaultRoute>> {
+    let family = match version {
+        IpVersion::V4 => netlink::AF_INET,
+        IpVersion::V6 => netlink::AF_INET6,
+    };
+
+    let mut netlink = Netlink::open()?;
+    let routes = netlink.routes(family)?;
+
+    Ok(collect_default_routes(
+        routes,
+        |oif| {
+            netlink
+                .route_get(lookup_dest(version), Some(oif), false)
+                .ok()
+                .and_then(|route| route.prefsrc)
+        },
+        if_name,
+    ))
+}
+
+/// Pick the unicast default routes of the dump, one per nexthop,
+/// sorted by their metric. The source of routes without a preferred one
+/// is looked up through their interface with `source_of`.
+fn collect_default_routes(
+    routes: Vec<Route>,
+    mut source_of: impl FnMut(u32) -> Option<IpAddr>,
+    name_of: impl Fn(u32) -> Option<String>,
+) -> Vec<DefaultRoute> {
+    let mut default_routes = Vec::new();
+    for route in routes {
+        if route.dst_len != 0 || route.ty != netlink::RTN_UNICAST {
+            continue;
+        }
+
+        let nexthops = match route.oif {
+            Some(oif) => vec![(oif, route.gateway)],
+            None => route.multipath.iter().map(|&oif| (oif, None)).collect(),
+        };
+
+        for (oif, gateway) in nexthops {
+            default_routes.push(DefaultRoute {
+                gateway,
+                oif,
+                interface: name_of(oif),
+                metric: route.metric.unwrap_or(0),
+                table: route.table,
+                source: route.prefsrc.or_else(|| source_of(oif)),
+            });
+        }
+    }
+
+    default_routes.sort_by_key(|route| route.metric);
+    default_routes
+}
+
+/// Get the source address of the given IP version the kernel selects
+/// for traffic through the default route of the given uplink,
+/// even if another uplink's default route is preferred.
+///
+/// Unlike the scope getters, this asks the routing table instead of
+/// connecting a socket, so the selected address isn't classified.
+pub fn preferred_source_via(interface: &str, version: IpVersion) -> Result<IpAddr> {
+    let oif = if_index(interface)?;
+    let dest = lookup_dest(version);
+
+    let route = match Netlink::open()?.route_get(dest, Some(oif), false) {
+        Ok(route) => route,
+        Err(e) if is_unreachable(&e) => {
+            return Err(Error::NoRoute {
+                interface: Some(interface.into()),
+                dest,
+            })
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    route.prefsrc.ok_or_else(|| Error::NoAddress {
+        interface: Some(interface.into()),
+        family: version,
+    })
+}
+
+/// The result of [`detect_multipath`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MultipathReport {
+    /// The distinct source addresses that were observed,
+    /// in the order they were first seen.
+    pub sources: Vec<IpAddr>,
+    /// The number of nexthops of the matching route,
+    /// or `None` if the routing table couldn't be queried.
+    pub nexthops: Option<usize>,
+}
+
+impl MultipathReport {
+    /// Report whether the source address appears to depend on the flow,
+    /// i.e. whether it differed between probes.
+    pub fn is_flow_dependent(&self) -> bool {
+        self.sources.len() > 1
+    }
+
+    /// Report whether the routing table has
+    /// multiple nexthops towards the destination.
+    pub fn is_multipath(&self) -> bool {
+        self.nexthops.is_some_and(|nexthops| nexthops > 1)
+    }
+}
+
+impl IpQuery<'_> {
+    /// Probe the source address towards the given destination `samples`
+    /// times (at least once), each time from a different local port,
+    /// to detect whether the choice depends on the flow hash
+    /// of an ECMP route.
+    ///
+    /// The destination can have a zone like in [`IpQuery::preferred_source_for`].
+    pub fn detect_multipath(
+        &self,
+        dest: impl Into<Destination>,
+        samples: usize,
+    ) -> Result<MultipathReport> {
+        let dest = self.dest_socket_addr(dest.into(), 0)?;
+        let unspecified = match dest {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+
+        let mut sources = Vec::new();
+        for _ in 0..samples.max(1) {
+            // Binding to port 0 assigns a new ephemeral port
+            // before the route lookup.
+            let source = self.probe_with(dest, None, |socket| {
+                socket.bind(&SocketAddr::new(unspecified, 0).into())
+            })?;
+
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+
+        let oif = self.interface.map(if_index).transpose()?;
+        let nexthops = Netlink::open()
+            .and_then(|mut netlink| netlink.route_get(dest.ip(), oif, true))
+            .ok()
+            .map(|route| route.multipath.len().max(1));
+
+        Ok(MultipathReport { sources, nexthops })
+    }
+}
+
+/// Probe the source address towards the given destination on the given
+/// interface, or on any interface if `None`, from different local ports.
+/// See [`IpQuery::detect_multipath`] for details.
+pub fn detect_multipath(
+    interface: Option<&str>,
+    dest: impl Into<Destination>,
+    samples: usize,
+) -> Result<MultipathReport> {
+    IpQuery::with_interface(interface).detect_multipath(dest, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(dst_len: u8, metric: Option<u32>, prefsrc: Option<&str>) -> Route {
+        Route {
+            dst_len,
+            metric,
+            prefsrc: prefsrc.map(|prefsrc| prefsrc.parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn reachable(interface: &str, dst_len: u8, metric: u32) -> InterfaceRanking {
+        let route = route(dst_len, Some(metric), None);
+        ranking(interface, &route, &route)
+    }
+
+    fn order(rankings: &[InterfaceRanking]) -> Vec<&str> {
+        rankings
+            .iter()
+            .map(|ranking| ranking.interface.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ranking_takes_source_from_constrained_lookup() {
+        let fib_match = route(48, None, None);
+        let lookup = route(128, Some(1024), Some("2a01:4f8::1"));
+
+        let ranking = ranking("eth0", &fib_match, &lookup);
+        assert_eq!(
+            ranking,
+            InterfaceRanking {
+                interface: "eth0".into(),
+                reachable: true,
+                prefix_len: Some(48),
+                metric: Some(0),
+                source: Some("2a01:4f8::1".parse().unwrap()),
+            }
+        );
+    }
+
+    #[test]
+    fn most_specific_route_wins() {
+        let mut rankings = vec![
+            reachable("default", 0, 100),
+            reachable("vpn", 16, 1000),
+            reachable("lan", 24, 2000),
+        ];
+
+        sort_rankings(&mut rankings);
+        assert_eq!(order(&rankings), ["lan", "vpn", "default"]);
+    }
+
+    #[test]
+    fn lowest_metric_breaks_prefix_ties() {
+        let mut rankings = vec![
+            reachable("wwan0", 0, 700),
+            reachable("wlan0", 0, 600),
+            reachable("eth0", 0, 100),
+        ];
+
+        sort_rankings(&mut rankings);
+        assert_eq!(order(&rankings), ["eth0", "wlan0", "wwan0"]);
+    }
+
+    #[test]
+    fn unreachable_interfaces_come_last() {
+        let mut rankings = vec![
+            InterfaceRanking::unreachable("down0"),
+            reachable("wwan0", 0, 700),
+            InterfaceRanking::unreachable("down1"),
+            reachable("eth0", 24, 100),
+        ];
+
+        sort_rankings(&mut rankings);
+        assert_eq!(order(&rankings), ["eth0", "wwan0", "down0", "down1"]);
+        assert!(!rankings[2].reachable && !rankings[3].reachable);
+    }
+
+    #[test]
+    fn ties_keep_input_order() {
+        let mut rankings = vec![
+            reachable("b", 0, 100),
+            reachable("a", 0, 100),
+            reachable("c", 0, 100),
+        ];
+
+        sort_rankings(&mut rankings);
+        assert_eq!(order(&rankings), ["b", "a", "c"]);
+    }
+
+    fn default_route(oif: u32, metric: u32, prefsrc: Option<&str>) -> Route {
+        Route {
+            oif: Some(oif),
+            ty: netlink::RTN_UNICAST,
+            table: 254,
+            ..route(0, Some(metric), prefsrc)
+        }
+    }
+
+    fn name_of(oif: u32) -> Option<String> {
+        match oif {
+            3 => Some("wan1".into()),
+            5 => Some("wan2".into()),
+            _ => None,
+        }
+    }
+
+    fn metrics(routes: &[DefaultRoute]) -> Vec<(u32, u32)> {
+        routes
+            .iter()
+            .map(|route| (route.oif, route.metric))
+            .collect()
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn default_routes_of_a_dump() {
+        use crate::netlink::{parse_route, tests::*};
+
+        let dump = [
+            DUMP_SUBNET,
+            DUMP_DEFAULT_TABLE,
+            DUMP_BLACKHOLE,
+            DUMP_DEFAULT_SRC,
+            DUMP_MULTIPATH,
+            DUMP_DEFAULT_VIA,
+        ];
+        let routes = dump.iter().map(|msg| parse_route(msg).unwrap()).collect();
+
+        let mut lookups = Vec::new();
+        let routes = collect_default_routes(
+            routes,
+            |oif| {
+                lookups.push(oif);
+                Some(format!("10.0.0.{}", oif).parse().unwrap())
+            },
+            name_of,
+        );
+
+        assert_eq!(
+            metrics(&routes),
+            [(3, 100), (5, 200), (5, 300), (3, 500), (5, 500)]
+        );
+        assert_eq!(
+            routes[0],
+            DefaultRoute {
+                gateway: Some("10.1.0.1".parse().unwrap()),
+                oif: 3,
+                interface: Some("wan1".into()),
+                metric: 100,
+                table: 254,
+                source: Some("10.0.0.3".parse().unwrap()),
+            }
+        );
+        // The preferred source of the route wins over the lookup.
+        assert_eq!(routes[1].source, Some("10.2.0.3".parse().unwrap()));
+        assert_eq!(routes[2].table, 1000);
+        assert_eq!(routes[2].gateway, None);
+        // Nexthops of multipath routes have no gateway.
+        assert_eq!(routes[3].gateway, None);
+        assert_eq!(routes[4].gateway, None);
+
+        assert_eq!(lookups, [5, 3, 5, 3]);
+    }
+
+    #[test]
+    fn metric_ties_keep_dump_order() {
+        let routes = vec![
+            default_route(5, 100, None),
+            default_route(3, 100, None),
+            default_route(7, 50, None),
+            default_route(4, 100, None),
+        ];
+
+        let routes = collect_default_routes(routes, |_| None, name_of);
+        assert_eq!(metrics(&routes), [(7, 50), (5, 100), (3, 100), (4, 100)]);
+    }
+
+    #[test]
+    fn missing_metrics_are_0() {
+        let mut route = default_route(3, 0, None);
+        route.metric = None;
+
+        let routes =
+            collect_default_routes(vec![default_route(5, 1, None), route], |_| None, name_of);
+        assert_eq!(metrics(&routes), [(3, 0), (5, 1)]);
+    }
+
+    #[test]
+    fn routes_without_prefsrc_are_looked_up() {
+        let routes = vec![
+            default_route(3, 100, None),
+            default_route(5, 200, Some("10.2.0.3")),
+            default_route(9, 300, None),
+        ];
+
+        let routes = collect_default_routes(
+            routes,
+            |oif| match oif {
+                3 => Some("10.1.0.2".parse().unwrap()),
+                5 => panic!("looked up despite the preferred source"),
+                _ => None,
+            },
+            name_of,
+        );
+
+        assert_eq!(routes[0].source, Some("10.1.0.2".parse().unwrap()));
+        assert_eq!(routes[1].source, Some("10.2.0.3".parse().unwrap()));
+        // No usable address, and the interface vanished.
+        assert_eq!(routes[2].source, None);
+        assert_eq!(routes[2].interface, None);
+    }
+
+    #[test]
+    fn only_unicast_default_routes_are_kept() {
+        let mut blackhole = default_route(3, 100, None);
+        blackhole.ty = 6;
+        let mut subnet = default_route(3, 100, None);
+        subnet.dst_len = 24;
+
+        assert!(collect_default_routes(vec![blackhole, subnet], |_| None, name_of).is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::{Error, IpQuery, Ipv4Scope, Ipv6Scope, Result, Scope};
+
+const FAMILIES: &[&str] = &["ipv6", "ipv4"];
+const IPV6_SCOPES: &[&str] = &["link-local", "ula", "gua", "loopback"];
+const IPV4_SCOPES: &[&str] = &["link-local", "private", "global", "loopback"];
+const INTERFACE: &[&str] = &["an interface name"];
+
+fn invalid(spec: &str, token: &str, expected: &'static [&'static str]) -> Error {
+    Error::InvalidSpec {
+        spec: spec.into(),
+        token: token.into(),
+        expected,
+    }
+}
+
+impl Ipv6Scope {
+    fn parse_in(spec: &str, token: &str) -> Result<Self> {
+        match token {
+            "link-local" => Ok(Self::UnicastLinkLocal),
+            "ula" => Ok(Self::UniqueLocal),
+            "gua" => Ok(Self::UnicastGlobal),
+            "loopback" => Ok(Self::Loopback),
+            _ => Err(invalid(spec, token, IPV6_SCOPES)),
+        }
+    }
+}
+
+impl Ipv4Scope {
+    fn parse_in(spec: &str, token: &str) -> Result<Self> {
+        match token {
+            "link-local" => Ok(Self::LinkLocal),
+            "private" => Ok(Self::Private),
+            "global" => Ok(Self::Global),
+            "loopback" => Ok(Self::Loopback),
+            _ => Err(invalid(spec, token, IPV4_SCOPES)),
+        }
+    }
+}
+
+impl FromStr for Ipv6Scope {
+    type Err = Error;
+
+    /// Parse `link-local`, `ula`, `gua` or `loopback`.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_in(s, s)
+    }
+}
+
+impl FromStr for Ipv4Scope {
+    type Err = Error;
+
+    /// Parse `link-local`, `private`, `global` or `loopback`.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_in(s, s)
+    }
+}
+
+impl fmt::Display for Ipv6Scope {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnicastLinkLocal => write!(fmt, "link-local"),
+            Self::UniqueLocal => write!(fmt, "ula"),
+            Self::UnicastGlobal => write!(fmt, "gua"),
+            Self::Loopback => write!(fmt, "loopback"),
+        }
+    }
+}
+
+impl fmt::Display for Ipv4Scope {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LinkLocal => write!(fmt, "link-local"),
+            Self::Private => write!(fmt, "private"),
+            Self::Global => write!(fmt, "global"),
+            Self::Loopback => write!(fmt, "loopback"),
+        }
+    }
+}
+
+impl Scope {
+    fn parse_in(spec: &str, s: &str) -> Result<Self> {
+        let Some((family, scope)) = s.split_once('-') else {
+            return Err(invalid(spec, s, FAMILIES));
+        };
+
+        match family {
+            "ipv6" => Ipv6Scope::parse_in(spec, scope).map(Self::V6),
+            "ipv4" => Ipv4Scope::parse_in(spec, scope).map(Self::V4),
+            _ => Err(invalid(spec, family, FAMILIES)),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    /// Parse `<family>-<scope>`, e.g. `ipv6-gua` or `ipv4-private`.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_in(s, s)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V6(scope) => write!(fmt, "ipv6-{}", scope),
+            Self::V4(scope) => write!(fmt, "ipv4-{}", scope),
+        }
+    }
+}
+
+/// Get the preferred outgoing address described by a specification
+/// of the form `<family>-<scope>[@<interface>]`, where
+///
+/// * `<family>` is `ipv6` or `ipv4`,
+/// * `<scope>` is `link-local`, `ula`, `gua` or `loopback` for IPv6
+///   and `link-local`, `private`, `global` or `loopback` for IPv4 and
+/// * `<interface>` is the interface to query, any interface if omitted.
+///
+/// Examples are `ipv6-gua`, `ipv6-ula@br-lan` and `ipv4-private@br-lan`.
+/// See [`IpQuery::get`] for the lookup.
+pub fn get_by_spec(spec: &str) -> Result<IpAddr> {
+    let (scope, interface) = match spec.split_once('@') {
+        Some((_, "")) => return Err(invalid(spec, "", INTERFACE)),
+        Some((scope, interface)) => (scope, Some(interface)),
+        None => (spec, None),
+    };
+
+    let scope = Scope::parse_in(spec, scope)?;
+    IpQuery::with_interface(interface).get(scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Scope; 8] = [
+        Scope::V6(Ipv6Scope::UnicastLinkLocal),
+        Scope::V6(Ipv6Scope::UniqueLocal),
+        Scope::V6(Ipv6Scope::UnicastGlobal),
+        Scope::V6(Ipv6Scope::Loopback),
+        Scope::V4(Ipv4Scope::LinkLocal),
+        Scope::V4(Ipv4Scope::Private),
+        Scope::V4(Ipv4Scope::Global),
+        Scope::V4(Ipv4Scope::Loopback),
+    ];
+
+    /// Get the offending token and the expected ones of a parse error.
+    fn rejected<T: fmt::Debug>(result: Result<T>) -> (String, &'static [&'static str]) {
+        match result {
+            Err(Error::InvalidSpec {
+                token, expected, ..
+            }) => (token, expected),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn scopes_round_trip() {
+        let names: Vec<_> = ALL.iter().map(Scope::to_string).collect();
+        assert_eq!(
+            names,
+            [
+                "ipv6-link-local",
+                "ipv6-ula",
+                "ipv6-gua",
+                "ipv6-loopback",
+                "ipv4-link-local",
+                "ipv4-private",
+                "ipv4-global",
+                "ipv4-loopback",
+            ]
+        );
+
+        for scope in ALL {
+            assert_eq!(scope.to_string().parse::<Scope>().unwrap(), scope);
+
+            match scope {
+                Scope::V6(scope) => {
+                    assert_eq!(scope.to_string().parse::<Ipv6Scope>().unwrap(), scope)
+                }
+                Scope::V4(scope) => {
+                    assert_eq!(scope.to_string().parse::<Ipv4Scope>().unwrap(), scope)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_listed_scope_parses() {
+        for family in FAMILIES {
+            let scopes = match *family {
+                "ipv6" => IPV6_SCOPES,
+                _ => IPV4_SCOPES,
+            };
+
+            for scope in scopes {
+                let spec = format!("{}-{}", family, scope);
+                assert_eq!(spec.parse::<Scope>().unwrap().to_string(), spec);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_families() {
+        for spec in [
+            "ipv5-gua",
+            "IPV6-gua",
+            "ip6-gua",
+            "-gua",
+            " ipv6-gua",
+            "inet6-ula",
+        ] {
+            let (token, expected) = rejected(spec.parse::<Scope>());
+            assert_eq!(token, spec.split_once('-').unwrap().0, "{}", spec);
+            assert_eq!(expected, FAMILIES);
+        }
+
+        // Without a separator, the whole specification is the family.
+        for spec in ["ipv6", "gua", ""] {
+            assert_eq!(rejected(spec.parse::<Scope>()), (spec.into(), FAMILIES));
+        }
+    }
+
+    #[test]
+    fn invalid_scopes() {
+        let cases = [
+            ("ipv6-private", "private", IPV6_SCOPES),
+            ("ipv6-global", "global", IPV6_SCOPES),
+            ("ipv6-", "", IPV6_SCOPES),
+            ("ipv6-GUA", "GUA", IPV6_SCOPES),
+            ("ipv6-gua ", "gua ", IPV6_SCOPES),
+            ("ipv6-link_local", "link_local", IPV6_SCOPES),
+            ("ipv4-gua", "gua", IPV4_SCOPES),
+            ("ipv4-ula", "ula", IPV4_SCOPES),
+            ("ipv4-", "", IPV4_SCOPES),
+            ("ipv4-link-local-", "link-local-", IPV4_SCOPES),
+        ];
+
+        for (spec, token, expected) in cases {
+            assert_eq!(
+                rejected(spec.parse::<Scope>()),
+                (token.into(), expected),
+                "{}",
+                spec
+            );
+        }
+
+        assert_eq!(
+            rejected("private".parse::<Ipv6Scope>()),
+            ("private".into(), IPV6_SCOPES)
+        );
+        assert_eq!(
+            rejected("ula".parse::<Ipv4Scope>()),
+            ("ula".into(), IPV4_SCOPES)
+        );
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected_before_lookup() {
+        let cases = [
+            ("ipv6-gua@", "", INTERFACE),
+            ("@veth0", "", FAMILIES),
+            ("ipv6-site@veth0", "site", IPV6_SCOPES),
+            ("ipv7-gua@veth0", "ipv7", FAMILIES),
+            ("ipv4-ula@br-lan", "ula", IPV4_SCOPES),
+        ];
+
+        for (spec, token, expected) in cases {
+            assert_eq!(
+                rejected(get_by_spec(spec)),
+                (token.into(), expected),
+                "{}",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn errors_pinpoint_the_token() {
+        assert_eq!(
+            get_by_spec("ipv4-ula@br-lan").unwrap_err().to_string(),
+            "invalid token `ula` in `ipv4-ula@br-lan`, \
+             expected one of link-local, private, global, loopback"
+        );
+        assert_eq!(
+            get_by_spec("ipv6-gua@").unwrap_err().to_string(),
+            "invalid token `` in `ipv6-gua@`, expected an interface name"
+        );
+        assert_eq!(
+            "ipv5-gua".parse::<Scope>().unwrap_err().to_string(),
+            "invalid token `ipv5` in `ipv5-gua`, expected one of ipv6, ipv4"
+        );
+        assert_eq!(
+            crate::ErrorKind::from(&"ipv5".parse::<Scope>().unwrap_err()),
+            crate::ErrorKind::InvalidInput
+        );
+    }
+}
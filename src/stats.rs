@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{ErrorKind, IpQuery, Result, Scope};
+
+const KINDS: [ErrorKind; 10] = [
+    ErrorKind::Io,
+    ErrorKind::WrongIpVersion,
+    ErrorKind::WrongScope,
+    ErrorKind::Timeout,
+    ErrorKind::NoAddress,
+    ErrorKind::NoRoute,
+    ErrorKind::AddrInUse,
+    ErrorKind::AddrNotAvailable,
+    ErrorKind::InvalidInput,
+    ErrorKind::Other,
+];
+
+/// The counters of a single interface and scope.
+#[derive(Debug, Default)]
+struct Counters {
+    successes: AtomicU64,
+    failures: [AtomicU64; KINDS.len()],
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    // Nanoseconds since the Unix epoch, 0 if there was none.
+    last_success: AtomicU64,
+    last_failure: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self, interface: Option<String>, scope: Scope) -> ScopeStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let time = |counter: &AtomicU64| {
+            Some(load(counter))
+                .filter(|&nanos| nanos != 0)
+                .map(|nanos| SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos))
+        };
+
+        ScopeStats {
+            interface,
+            scope,
+            successes: load(&self.successes),
+            failures: KINDS
+                .into_iter()
+                .zip(&self.failures)
+                .map(|(kind, counter)| (kind, load(counter)))
+                .filter(|&(_, count)| count != 0)
+                .collect(),
+            cache_hits: load(&self.cache_hits),
+            cache_misses: load(&self.cache_misses),
+            last_success: time(&self.last_success),
+            last_failure: time(&self.last_failure),
+        }
+    }
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(1, |since| since.as_nanos() as u64)
+}
+
+/// An interface, `None` for any, and a scope.
+type Key = (Option<String>, Scope);
+
+/// The statistics of a query, shared by its clones.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Stats(Arc<RwLock<HashMap<Key, Arc<Counters>>>>);
+
+impl Stats {
+    fn counters(&self, interface: Option<&str>, scope: Scope) -> Arc<Counters> {
+        let key = (interface.map(Into::into), scope);
+        if let Some(counters) = self.0.read().unwrap().get(&key) {
+            return counters.clone();
+        }
+
+        self.0.write().unwrap().entry(key).or_default().clone()
+    }
+
+    fn snapshot(&self) -> StatsSnapshot {
+        let mut scopes: Vec<_> = self
+            .0
+            .read()
+            .unwrap()
+            .iter()
+            .map(|((interface, scope), counters)| counters.snapshot(interface.clone(), *scope))
+            .collect();
+
+        scopes.sort_by(|a, b| {
+            (&a.interface, a.scope.to_string()).cmp(&(&b.interface, b.scope.to_string()))
+        });
+        StatsSnapshot { scopes }
+    }
+}
+
+/// A copy of the statistics collected by a query,
+/// see [`IpQuery::collect_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct StatsSnapshot {
+    /// The statistics of every interface and scope that was queried,
+    /// ordered by interface and scope.
+    pub scopes: Vec<ScopeStats>,
+}
+
+impl StatsSnapshot {
+    /// Get the statistics of the given interface and scope.
+    pub fn get(&self, interface: Option<&str>, scope: Scope) -> Option<&ScopeStats> {
+        self.scopes
+            .iter()
+            .find(|stats| stats.interface.as_deref() == interface && stats.scope == scope)
+    }
+}
+
+/// The statistics of a single interface and scope.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct ScopeStats {
+    /// The interface, `None` for queries that aren't bound to one.
+    pub interface: Option<String>,
+    /// Serialized in the form of [`get_by_spec`](crate::get_by_spec),
+    /// e.g. `ipv6-gua`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::diagnostic::serialize_display")
+    )]
+    pub scope: Scope,
+    /// The number of addresses that were returned.
+    pub successes: u64,
+    /// The number of errors by kind. Kinds that didn't occur are omitted.
+    pub failures: BTreeMap<ErrorKind, u64>,
+    /// The number of requests a [`CachedQuery`](crate::CachedQuery) answered from its cache.
+    pub cache_hits: u64,
+    /// The number of requests a [`CachedQuery`](crate::CachedQuery) had to probe for.
+    pub cache_misses: u64,
+    /// When the last address was returned, `None` if none was.
+    pub last_success: Option<SystemTime>,
+    /// When the last error was returned, `None` if none was.
+    pub last_failure: Option<SystemTime>,
+}
+
+impl ScopeStats {
+    /// Get the total number of errors.
+    pub fn total_failures(&self) -> u64 {
+        self.failures.values().sum()
+    }
+}
+
+impl IpQuery<'_> {
+    /// Collect statistics about the results of the getters of each scope
+    /// for the lifetime of the query, see [`IpQuery::stats`].
+    /// Clones of the query, including those made by a [`CachedQuery`](crate::CachedQuery),
+    /// share the statistics. Only the strict getters are counted;
+    /// operations made of several of them, e.g. [`IpQuery::get_all`],
+    /// count each of them.
+    ///
+    /// The counters are atomic, so concurrent queries don't wait
+    /// for each other except when a scope is queried for the first time.
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.stats = collect_stats.then(Stats::default);
+        self
+    }
+
+    /// Get a copy of the statistics collected so far. Empty unless
+    /// enabled using [`IpQuery::collect_stats`].
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.as_ref().map(Stats::snapshot).unwrap_or_default()
+    }
+
+    /// Count the result of a getter if statistics are collected.
+    pub(crate) fn count<T>(&self, scope: Scope, result: Result<T>) -> Result<T> {
+        if let Some(stats) = &self.stats {
+            let counters = stats.counters(self.interface, scope);
+            let now = nanos(self.active_clock().system_time());
+            match &result {
+                Ok(_) => {
+                    counters.successes.fetch_add(1, Ordering::Relaxed);
+                    counters.last_success.store(now, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    let kind = KINDS.iter().position(|&kind| kind == e.kind()).unwrap();
+                    counters.failures[kind].fetch_add(1, Ordering::Relaxed);
+                    counters.last_failure.store(now, Ordering::Relaxed);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Count a cache hit or miss if statistics are collected.
+    pub(crate) fn count_cache(&self, scope: Scope, hit: bool) {
+        if let Some(stats) = &self.stats {
+            let counters = stats.counters(self.interface, scope);
+            let counter = if hit {
+                &counters.cache_hits
+            } else {
+                &counters.cache_misses
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::thread;
+
+    use super::*;
+    use crate::test_support::MockClock;
+    use crate::{Clock, Error, IpVersion, Ipv4Scope, Ipv6Scope};
+
+    const GUA: Scope = Scope::V6(Ipv6Scope::UnicastGlobal);
+    const PRIVATE: Scope = Scope::V4(Ipv4Scope::Private);
+
+    fn ok() -> Result<()> {
+        Ok(())
+    }
+
+    fn no_address() -> Result<()> {
+        Err(Error::NoAddress {
+            interface: Some("eth0".into()),
+            family: IpVersion::V6,
+        })
+    }
+
+    fn io_error() -> Result<()> {
+        Err(io::Error::from_raw_os_error(libc::ENODEV).into())
+    }
+
+    #[test]
+    fn every_kind_has_a_counter() {
+        for (i, kind) in KINDS.into_iter().enumerate() {
+            // Fails to compile if a kind is added, so that it gets a counter.
+            match kind {
+                ErrorKind::Io
+                | ErrorKind::WrongIpVersion
+                | ErrorKind::WrongScope
+                | ErrorKind::Timeout
+                | ErrorKind::NoAddress
+                | ErrorKind::NoRoute
+                | ErrorKind::AddrInUse
+                | ErrorKind::AddrNotAvailable
+                | ErrorKind::InvalidInput
+                | ErrorKind::Other => {}
+            }
+            assert!(!KINDS[..i].contains(&kind), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let query = IpQuery::new("eth0");
+        query.count(GUA, ok()).unwrap();
+        query.count_cache(GUA, true);
+
+        assert!(query.stats().scopes.is_empty());
+    }
+
+    #[test]
+    fn success_and_failure_sequence() {
+        let query = IpQuery::new("eth0").collect_stats(true);
+        let start = SystemTime::now();
+
+        query.count(GUA, ok()).unwrap();
+        query.count(GUA, ok()).unwrap();
+        let after_success = SystemTime::now();
+        query.count(GUA, no_address()).unwrap_err();
+        query.count(GUA, io_error()).unwrap_err();
+        query.count(GUA, no_address()).unwrap_err();
+        query.count_cache(GUA, false);
+        query.count_cache(GUA, true);
+        query.count_cache(GUA, true);
+
+        let snapshot = query.stats();
+        assert_eq!(snapshot.scopes.len(), 1);
+        let stats = snapshot.get(Some("eth0"), GUA).unwrap();
+        assert_eq!(stats.successes, 2);
+        assert_eq!(
+            stats.failures,
+            BTreeMap::from([(ErrorKind::Io, 1), (ErrorKind::NoAddress, 2)])
+        );
+        assert_eq!(stats.total_failures(), 3);
+        assert_eq!((stats.cache_hits, stats.cache_misses), (2, 1));
+
+        let last_success = stats.last_success.unwrap();
+        let last_failure = stats.last_failure.unwrap();
+        assert!(start <= last_success && last_success <= after_success);
+        assert!(after_success <= last_failure);
+    }
+
+    #[test]
+    fn timestamps_follow_the_clock() {
+        let clock = MockClock::new();
+        let start = clock.system_time();
+        let query = IpQuery::new("eth0")
+            .clock(clock.clone())
+            .collect_stats(true);
+
+        query.count(GUA, ok()).unwrap();
+        clock.advance(Duration::from_secs(10));
+        query.count(GUA, no_address()).unwrap_err();
+
+        let snapshot = query.stats();
+        let stats = snapshot.get(Some("eth0"), GUA).unwrap();
+        assert_eq!(stats.last_success, Some(start));
+        assert_eq!(stats.last_failure, Some(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn only_failures() {
+        let query = IpQuery::any_interface().collect_stats(true);
+        query.count(PRIVATE, io_error()).unwrap_err();
+
+        let snapshot = query.stats();
+        let stats = snapshot.get(None, PRIVATE).unwrap();
+        assert_eq!(stats.successes, 0);
+        assert_eq!(stats.last_success, None);
+        assert!(stats.last_failure.is_some());
+        assert_eq!(snapshot.get(Some("eth0"), PRIVATE), None);
+        assert_eq!(snapshot.get(None, GUA), None);
+    }
+
+    #[test]
+    fn clones_share_and_snapshots_are_copies() {
+        let query = IpQuery::new("eth0").collect_stats(true);
+        let clone = query.clone();
+        query.count(GUA, ok()).unwrap();
+        let before = query.stats();
+        clone.count(GUA, ok()).unwrap();
+
+        assert_eq!(before.get(Some("eth0"), GUA).unwrap().successes, 1);
+        assert_eq!(query.stats().get(Some("eth0"), GUA).unwrap().successes, 2);
+
+        // Enabling it again starts over.
+        let reset = clone.collect_stats(true);
+        assert!(reset.stats().scopes.is_empty());
+    }
+
+    #[test]
+    fn ordered_by_interface_and_scope() {
+        let base = IpQuery::any_interface().collect_stats(true);
+        for interface in [Some("eth1"), None, Some("eth0")] {
+            let query = IpQuery {
+                interface,
+                ..base.clone()
+            };
+            for scope in [PRIVATE, GUA] {
+                query.count(scope, ok()).unwrap();
+            }
+        }
+
+        let keys: Vec<_> = base
+            .stats()
+            .scopes
+            .iter()
+            .map(|stats| (stats.interface.clone(), stats.scope.to_string()))
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        assert_eq!(keys.len(), 6);
+        assert_eq!(keys[0], (None, PRIVATE.to_string()));
+    }
+
+    #[test]
+    fn concurrent_counts_add_up() {
+        let query = IpQuery::new("eth0").collect_stats(true);
+
+        thread::scope(|s| {
+            for n in 0..8 {
+                let query = query.clone();
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let result = if (n + i) % 4 == 0 { io_error() } else { ok() };
+                        let _ = query.count(GUA, result);
+                        query.count_cache(GUA, i % 2 == 0);
+                    }
+                });
+            }
+        });
+
+        let snapshot = query.stats();
+        let stats = snapshot.get(Some("eth0"), GUA).unwrap();
+        assert_eq!(stats.successes + stats.total_failures(), 8000);
+        assert_eq!(stats.failures[&ErrorKind::Io], 2000);
+        assert_eq!((stats.cache_hits, stats.cache_misses), (4000, 4000));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_shape() {
+        let query = IpQuery::new("eth0").collect_stats(true);
+        query.count(GUA, no_address()).unwrap_err();
+
+        let json = serde_json::to_value(query.stats()).unwrap();
+        let stats = &json["scopes"][0];
+        assert_eq!(stats["interface"], "eth0");
+        assert_eq!(stats["scope"], "ipv6-gua");
+        assert_eq!(stats["successes"], 0);
+        assert_eq!(stats["failures"]["no-address"], 1);
+        assert_eq!(stats["last-success"], serde_json::Value::Null);
+        assert!(stats["last-failure"].is_object());
+    }
+}
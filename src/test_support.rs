@@ -0,0 +1,294 @@
+//! Throwaway network environments and a controllable clock
+//! for integration tests.
+//!
+//! A [`NetEnv`] runs a closure in a new network namespace
+//! containing a veth pair with the configured addresses and routes.
+//! The namespace only lives as long as the thread running the closure,
+//! so it is cleaned up even if the closure panics.
+//! Creating it requires `CAP_NET_ADMIN` and `CAP_SYS_ADMIN`,
+//! as well as the `ip` tool of iproute2. Use
+//! [`NetEnvError::is_unavailable`] to skip tests without them.
+//!
+//! A [`MockClock`] replaces the clock of a query, so that deadlines,
+//! cache ages and waiting don't depend on how fast the test runs.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::Clock;
+
+/// The errors that can occur when setting up a [`NetEnv`].
+#[derive(Debug)]
+pub enum NetEnvError {
+    IoError(io::Error),
+    CommandFailed(String, String),
+    MissingTool(&'static str),
+}
+
+impl NetEnvError {
+    /// Report whether the environment couldn't be created
+    /// due to missing privileges.
+    pub fn is_unprivileged(&self) -> bool {
+        matches!(self, Self::IoError(e) if e.kind() == io::ErrorKind::PermissionDenied)
+    }
+
+    /// Report whether the environment can't be created on this system
+    /// at all, due to missing privileges or a missing tool.
+    pub fn is_unavailable(&self) -> bool {
+        self.is_unprivileged() || matches!(self, Self::MissingTool(_))
+    }
+}
+
+impl std::error::Error for NetEnvError {}
+
+impl fmt::Display for NetEnvError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) if self.is_unprivileged() => write!(
+                fmt,
+                "can't create network namespace (missing CAP_NET_ADMIN?): {}",
+                e
+            ),
+            Self::IoError(e) => write!(fmt, "can't set up network environment: {}", e),
+            Self::CommandFailed(cmd, stderr) => {
+                write!(fmt, "command `{}` failed: {}", cmd, stderr.trim())
+            }
+            Self::MissingTool(tool) => write!(fmt, "can't find `{}` in PATH", tool),
+        }
+    }
+}
+
+impl From<io::Error> for NetEnvError {
+    fn from(err: io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+/// A throwaway network environment. See the [module documentation](self).
+pub struct NetEnv;
+
+impl NetEnv {
+    /// Start configuring a new environment.
+    pub fn builder() -> NetEnvBuilder {
+        NetEnvBuilder {
+            interface: "veth0".into(),
+            peer: "veth1".into(),
+            ipv6: Vec::new(),
+            ipv4: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+/// A builder for [`NetEnv`].
+#[derive(Clone, Debug)]
+pub struct NetEnvBuilder {
+    interface: String,
+    peer: String,
+    ipv6: Vec<String>,
+    ipv4: Vec<String>,
+    routes: Vec<String>,
+}
+
+impl NetEnvBuilder {
+    /// Set the names of the veth pair. The addresses and routes
+    /// are configured on the first one. Defaults to `veth0` and `veth1`.
+    pub fn interfaces(mut self, interface: &str, peer: &str) -> Self {
+        self.interface = interface.into();
+        self.peer = peer.into();
+        self
+    }
+
+    /// Add an IPv6 address in CIDR notation, e.g. `fd00:dead::1/64`.
+    /// Duplicate address detection is disabled so it is usable immediately.
+    pub fn ipv6(mut self, addr: &str) -> Self {
+        self.ipv6.push(addr.into());
+        self
+    }
+
+    /// Add an IPv4 address in CIDR notation, e.g. `192.168.77.1/24`.
+    pub fn ipv4(mut self, addr: &str) -> Self {
+        self.ipv4.push(addr.into());
+        self
+    }
+
+    /// Add a route through the interface, e.g. `default` or `2000::/3`.
+    /// Routes to `default` are added for both IP versions.
+    pub fn route(mut self, dest: &str) -> Self {
+        self.routes.push(dest.into());
+        self
+    }
+
+    /// Run the closure in the environment.
+    /// Panics of the closure are propagated after the environment is gone.
+    pub fn run<T: Send>(self, f: impl FnOnce() -> T + Send) -> Result<T, NetEnvError> {
+        let result = thread::scope(|s| {
+            s.spawn(|| {
+                self.setup()?;
+                Ok(f())
+            })
+            .join()
+        });
+
+        match result {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    fn setup(&self) -> Result<(), NetEnvError> {
+        // SAFETY: `unshare` has no memory safety preconditions.
+        // It only moves this thread into a new network namespace.
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        ip(&["link", "set", "lo", "up"])?;
+        ip(&[
+            "link",
+            "add",
+            &self.interface,
+            "type",
+            "veth",
+            "peer",
+            "name",
+            &self.peer,
+        ])?;
+
+        for interface in [&self.interface, &self.peer] {
+            let accept_dad = format!("/proc/sys/net/ipv6/conf/{}/accept_dad", interface);
+            fs::write(accept_dad, "0")?;
+        }
+
+        for addr in &self.ipv6 {
+            ip(&["addr", "add", addr, "dev", &self.interface, "nodad"])?;
+        }
+        for addr in &self.ipv4 {
+            ip(&["addr", "add", addr, "dev", &self.interface])?;
+        }
+
+        ip(&["link", "set", &self.interface, "up"])?;
+        ip(&["link", "set", &self.peer, "up"])?;
+
+        for dest in &self.routes {
+            if dest == "default" {
+                ip(&["-4", "route", "add", "default", "dev", &self.interface])?;
+                ip(&["-6", "route", "add", "default", "dev", &self.interface])?;
+            } else {
+                ip(&["route", "add", dest, "dev", &self.interface])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn ip(args: &[&str]) -> Result<(), NetEnvError> {
+    let output = Command::new("ip").args(args).output().map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            NetEnvError::MissingTool("ip")
+        } else {
+            e.into()
+        }
+    })?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(NetEnvError::CommandFailed(
+            format!("ip {}", args.join(" ")),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+/// A [`Clock`] that only advances when told to, for use with
+/// [`IpQuery::clock`](crate::IpQuery::clock).
+///
+/// Clones share the same time, so one of them can be passed
+/// to the query and advanced by the test. Sleeping advances
+/// the clock by the duration and returns immediately.
+/// The wall-clock time advances along with it.
+/// Waits in the kernel, e.g. for DNS answers, still take real time.
+/// If they time out, the clock is advanced by their timeout afterwards.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<(Instant, SystemTime)>>);
+
+impl MockClock {
+    /// Create a new clock that starts at the current point in time.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new((Instant::now(), SystemTime::now()))))
+    }
+
+    /// Advance the clock by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap();
+        now.0 += duration;
+        now.1 += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.0.lock().unwrap().0
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.0.lock().unwrap().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unavailable_errors() {
+        let unprivileged = NetEnvError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(unprivileged.is_unprivileged());
+        assert!(unprivileged.is_unavailable());
+
+        let missing = NetEnvError::MissingTool("ip");
+        assert!(!missing.is_unprivileged());
+        assert!(missing.is_unavailable());
+        assert_eq!(missing.to_string(), "can't find `ip` in PATH");
+
+        let failed = NetEnvError::CommandFailed("ip link".into(), "oops\n".into());
+        assert!(!failed.is_unavailable());
+        assert_eq!(failed.to_string(), "command `ip link` failed: oops");
+
+        let other = NetEnvError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert!(!other.is_unavailable());
+    }
+
+    #[test]
+    fn mock_clock_only_advances_when_told() {
+        let clock = MockClock::new();
+        let shared = clock.clone();
+        let start = clock.now();
+        let wall = clock.system_time();
+
+        assert_eq!(clock.now(), start);
+        shared.advance(Duration::from_secs(1));
+        assert_eq!(clock.now(), start + Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(3600));
+        assert_eq!(shared.now(), start + Duration::from_secs(3601));
+        assert_eq!(shared.system_time(), wall + Duration::from_secs(3601));
+    }
+}
@@ -0,0 +1,205 @@
+//! Detection of IPv6-mostly networks (RFC 8925), where IPv4
+//! is only provided to legacy hosts and IPv6-capable hosts
+//! reach IPv4 destinations through NAT64, possibly with a CLAT.
+
+use std::net::IpAddr;
+
+use crate::addrs;
+use crate::netlink::{self, Netlink};
+use crate::ranges::{self, RangeKind};
+use crate::{IpQuery, Ipv4Scope, Ipv6Scope, Result};
+
+/// The indicators of an IPv6-mostly network,
+/// as returned by [`IpQuery::v6_mostly_signals`].
+///
+/// DHCPv4 option 108 itself is only visible to the DHCP client,
+/// which doesn't configure an IPv4 address if it honors it.
+/// The crate can only observe the result: IPv4 is missing,
+/// but IPv6 works and IPv4 is still reachable through NAT64.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct V6MostlySignals {
+    /// Whether the interface has a usable private or global IPv4 address.
+    pub ipv4: bool,
+    /// Whether the interface has a usable IPv6 GUA or ULA.
+    pub ipv6: bool,
+    /// Whether any interface has an IPv4 address
+    /// of the CLAT range 192.0.0.0/29.
+    pub clat: bool,
+    /// Whether there is a route towards one of the well-known
+    /// NAT64 prefixes 64:ff9b::/96 and 64:ff9b:1::/48.
+    pub nat64_route: bool,
+    /// Whether the nameservers synthesize AAAA records.
+    /// Always `false` without the `dns64` feature.
+    pub dns64: bool,
+}
+
+impl V6MostlySignals {
+    /// Report whether IPv4 appears to be intentionally absent:
+    /// There is no IPv4 address, but IPv6 and at least one
+    /// of the NAT64 indicators.
+    pub fn ipv4_suppressed(&self) -> bool {
+        !self.ipv4 && self.ipv6 && (self.clat || self.nat64_route || self.dns64)
+    }
+}
+
+fn is_nat64_route(route: &netlink::Route) -> bool {
+    let Some(IpAddr::V6(dst)) = route.dst else {
+        return false;
+    };
+
+    // The well-known prefixes of RFC 6052 or RFC 8215, or within them.
+    matches!(
+        ranges::ipv6_range(dst),
+        Some(&(_, len, RangeKind::Nat64)) if route.dst_len >= len
+    )
+}
+
+/// Report whether the address is within the range of the IPv4 addresses
+/// of CLATs (RFC 7335).
+fn is_clat(addr: IpAddr) -> bool {
+    addr.is_ipv4() && ranges::range_kind(addr) == Some(RangeKind::ServiceContinuity)
+}
+
+impl IpQuery<'_> {
+    /// Collect the indicators of an IPv6-mostly network
+    /// for the interface, or for any interface if the query
+    /// isn't bound to one. See [`V6MostlySignals`].
+    ///
+    /// The addresses are checked with [`IpQuery::has`].
+    /// The routes of all tables are searched for NAT64 routes.
+    /// With the `dns64` feature, the nameservers are also asked
+    /// with [`IpQuery::detect_dns64`], but only if the other signals
+    /// don't already tell. Failing DNS queries count as no DNS64.
+    pub fn v6_mostly_signals(&self) -> Result<V6MostlySignals> {
+        let ipv4 = self.has(Ipv4Scope::Private.into())? || self.has(Ipv4Scope::Global.into())?;
+        let ipv6 = self.has(Ipv6Scope::UnicastGlobal.into())?
+            || self.has(Ipv6Scope::UniqueLocal.into())?;
+        let clat = addrs::find_address(None, |addr| is_clat(addr.addr))?.is_some();
+        let nat64_route = Netlink::open()?
+            .routes(netlink::AF_INET6)?
+            .iter()
+            .any(is_nat64_route);
+
+        #[cfg(feature = "dns64")]
+        let dns64 =
+            !ipv4 && ipv6 && !clat && !nat64_route && matches!(self.detect_dns64(), Ok(Some(_)));
+        #[cfg(not(feature = "dns64"))]
+        let dns64 = false;
+
+        Ok(V6MostlySignals {
+            ipv4,
+            ipv6,
+            clat,
+            nat64_route,
+            dns64,
+        })
+    }
+
+    /// Report whether the interface, or any interface if the query
+    /// isn't bound to one, is on an IPv6-mostly network and
+    /// IPv4 is suppressed. See [`V6MostlySignals::ipv4_suppressed`].
+    pub fn ipv4_suppressed(&self) -> Result<bool> {
+        Ok(self.v6_mostly_signals()?.ipv4_suppressed())
+    }
+}
+
+/// Report whether the given interface is on an IPv6-mostly network
+/// and IPv4 is suppressed. See [`IpQuery::ipv4_suppressed`] for details.
+pub fn ipv4_suppressed(interface: &str) -> Result<bool> {
+    IpQuery::new(interface).ipv4_suppressed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(dst: &str, dst_len: u8) -> netlink::Route {
+        netlink::Route {
+            dst: Some(dst.parse().unwrap()),
+            dst_len,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ipv4_is_suppressed_with_any_nat64_indicator() {
+        let ipv6_only = V6MostlySignals {
+            ipv6: true,
+            ..Default::default()
+        };
+        assert!(!ipv6_only.ipv4_suppressed());
+
+        for signals in [
+            V6MostlySignals {
+                clat: true,
+                ..ipv6_only
+            },
+            V6MostlySignals {
+                nat64_route: true,
+                ..ipv6_only
+            },
+            V6MostlySignals {
+                dns64: true,
+                ..ipv6_only
+            },
+        ] {
+            assert!(signals.ipv4_suppressed(), "{:?}", signals);
+        }
+    }
+
+    #[test]
+    fn ipv4_is_not_suppressed_with_ipv4_or_without_ipv6() {
+        let indicators = V6MostlySignals {
+            clat: true,
+            nat64_route: true,
+            dns64: true,
+            ..Default::default()
+        };
+
+        assert!(!V6MostlySignals {
+            ipv4: true,
+            ipv6: true,
+            ..indicators
+        }
+        .ipv4_suppressed());
+        assert!(!indicators.ipv4_suppressed());
+        assert!(!V6MostlySignals {
+            ipv4: true,
+            ..indicators
+        }
+        .ipv4_suppressed());
+        assert!(!V6MostlySignals::default().ipv4_suppressed());
+    }
+
+    #[test]
+    fn nat64_routes() {
+        assert!(is_nat64_route(&route("64:ff9b::", 96)));
+        assert!(is_nat64_route(&route("64:ff9b:1::", 48)));
+        // More specific routes within the prefixes count too.
+        assert!(is_nat64_route(&route("64:ff9b:1:abcd::", 64)));
+        assert!(is_nat64_route(&route("64:ff9b::c000:200", 120)));
+    }
+
+    #[test]
+    fn routes_covering_more_than_nat64() {
+        assert!(!is_nat64_route(&route("64:ff9b::", 64)));
+        assert!(!is_nat64_route(&route("64:ff9b:1::", 32)));
+        assert!(!is_nat64_route(&route("::", 0)));
+        assert!(!is_nat64_route(&route("2a01:4f8::", 32)));
+        assert!(!is_nat64_route(&route("192.0.0.0", 29)));
+        assert!(!is_nat64_route(&netlink::Route::default()));
+    }
+
+    #[test]
+    fn clat_addresses() {
+        for last in 0..8 {
+            assert!(is_clat(format!("192.0.0.{}", last).parse().unwrap()));
+        }
+
+        // 192.0.0.8 is the dummy address of RFC 7600.
+        assert!(!is_clat("192.0.0.8".parse().unwrap()));
+        assert!(!is_clat("192.0.0.170".parse().unwrap()));
+        assert!(!is_clat("192.168.0.1".parse().unwrap()));
+        assert!(!is_clat("::ffff:192.0.0.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,932 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+use crate::addrs::InterfaceAddr;
+use crate::clock::SharedClock;
+use crate::netlink::{self, Message, Netlink};
+use crate::{if_index, Clock, Error, IpQuery, MonotonicClock, Result};
+
+/// How often [`IpQuery::wait_for_ipv6_global_change`] probes again
+/// if there are no events, e.g. because only the routes changed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change reported by a [`Watcher`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WatchEvent {
+    /// An address was assigned to the interface.
+    AddressAdded(InterfaceAddr),
+    /// The flags of an address changed, e.g. because
+    /// duplicate address detection completed.
+    AddressChanged(InterfaceAddr),
+    /// An address was removed from the interface.
+    AddressRemoved(InterfaceAddr),
+    /// The interface was (re-)created with the given index.
+    InterfaceAdded { index: u32 },
+    /// The interface was removed or renamed.
+    /// Addresses the kernel didn't report as removed before
+    /// are dropped without separate events.
+    InterfaceRemoved,
+    /// Notifications were lost because they arrived faster than
+    /// they were read. The watcher resynchronizes with the kernel's
+    /// current state and reports the differences as regular events
+    /// following this one.
+    Overrun,
+}
+
+/// Where a [`Watcher`] gets its notifications and state from.
+pub(crate) trait WatchSource: Send {
+    /// Receive the notifications in the next datagram.
+    /// Fails with `ENOBUFS` if notifications were lost
+    /// and with `WouldBlock` if the read timeout expired.
+    fn recv_notifications(&mut self) -> io::Result<Vec<Message>>;
+
+    /// Set how long receiving notifications blocks, `None` for no limit.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Get the index of the interface with the given name,
+    /// `None` if it doesn't exist.
+    fn link_index(&mut self, name: &str) -> Result<Option<u32>>;
+
+    /// Get the current addresses of all interfaces.
+    fn addrs(&mut self) -> io::Result<Vec<netlink::Addr>>;
+}
+
+/// The notifications of the kernel.
+struct Kernel {
+    notifications: Netlink,
+}
+
+impl WatchSource for Kernel {
+    fn recv_notifications(&mut self) -> io::Result<Vec<Message>> {
+        self.notifications.recv_notifications()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.notifications.set_read_timeout(timeout)
+    }
+
+    fn link_index(&mut self, name: &str) -> Result<Option<u32>> {
+        match if_index(name) {
+            Ok(index) => Ok(Some(index)),
+            Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::ENODEV) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn addrs(&mut self) -> io::Result<Vec<netlink::Addr>> {
+        // The subscribed socket would drop the notifications
+        // that arrive during the dump.
+        Netlink::open()?.addrs(None)
+    }
+}
+
+/// Watches the addresses of an interface by name.
+///
+/// Interfaces like PPP or WireGuard links can disappear
+/// and come back with a different index. The watcher follows the name,
+/// so they are watched again as soon as they are re-created.
+///
+/// The addresses are followed through rtnetlink notifications, so like
+/// the rest of the crate the watcher is only available on Linux.
+pub struct Watcher {
+    source: Box<dyn WatchSource>,
+    interface: String,
+    index: Option<u32>,
+    addrs: Vec<InterfaceAddr>,
+    pending: VecDeque<WatchEvent>,
+    clock: Option<SharedClock>,
+}
+
+impl Watcher {
+    /// Start watching the given interface.
+    /// It doesn't have to exist yet.
+    pub fn new(interface: &str) -> Result<Self> {
+        let notifications = Netlink::subscribe(
+            netlink::RTMGRP_LINK | netlink::RTMGRP_IPV4_IFADDR | netlink::RTMGRP_IPV6_IFADDR,
+        )?;
+
+        Self::with_source(interface, Kernel { notifications })
+    }
+
+    pub(crate) fn with_source(interface: &str, source: impl WatchSource + 'static) -> Result<Self> {
+        let mut watcher = Self {
+            source: Box::new(source),
+            interface: interface.into(),
+            index: None,
+            addrs: Vec::new(),
+            pending: VecDeque::new(),
+            clock: None,
+        };
+
+        watcher.resync()?;
+        watcher.pending.clear();
+
+        Ok(watcher)
+    }
+
+    /// Use the given clock for the deadlines of [`Watcher::next_event_timeout`]
+    /// instead of [`MonotonicClock`], like [`IpQuery::clock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(SharedClock::new(clock));
+        self
+    }
+
+    fn active_clock(&self) -> &dyn Clock {
+        match &self.clock {
+            Some(clock) => clock.get(),
+            None => &MonotonicClock,
+        }
+    }
+
+    /// Get the index of the interface, or `None` if it doesn't exist.
+    pub fn index(&self) -> Option<u32> {
+        self.index
+    }
+
+    /// Get the addresses of the interface as known to the watcher,
+    /// including the changes of events that haven't been returned yet.
+    pub fn addresses(&self) -> &[InterfaceAddr] {
+        &self.addrs
+    }
+
+    /// Block until the next event.
+    pub fn next_event(&mut self) -> Result<WatchEvent> {
+        self.source.set_read_timeout(None)?;
+        loop {
+            if let Some(event) = self.next_pending()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Block until the next event or until the timeout expires,
+    /// returning `None` in the latter case.
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<WatchEvent>> {
+        let deadline = self.active_clock().now() + timeout;
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+
+            let start = self.active_clock().now();
+            let remaining = deadline.saturating_duration_since(start);
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            self.source.set_read_timeout(Some(remaining))?;
+            match self.next_pending() {
+                Ok(Some(event)) => return Ok(Some(event)),
+                Ok(None) => {}
+                Err(Error::IoError(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // Let clocks that only advance when sleeping
+                    // reach the deadline as well.
+                    let clock = self.active_clock();
+                    let elapsed = clock.now().saturating_duration_since(start);
+                    clock.sleep(remaining.saturating_sub(elapsed));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Return the next pending event or receive the next datagram
+    /// of notifications.
+    fn next_pending(&mut self) -> Result<Option<WatchEvent>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(Some(event));
+        }
+
+        match self.source.recv_notifications() {
+            Ok(msgs) => {
+                for msg in msgs {
+                    self.handle(&msg);
+                }
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                self.pending.push_back(WatchEvent::Overrun);
+                self.resync()?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(None)
+    }
+
+    /// Compare the cached state with a fresh dump
+    /// and queue events for the differences.
+    fn resync(&mut self) -> Result<()> {
+        let index = self.source.link_index(&self.interface)?;
+
+        if index != self.index {
+            if self.index.is_some() {
+                self.remove_interface();
+            }
+            if let Some(index) = index {
+                self.add_interface(index);
+            }
+        }
+
+        let Some(index) = self.index else {
+            return Ok(());
+        };
+
+        let current: Vec<_> = self
+            .source
+            .addrs()?
+            .iter()
+            .filter(|addr| addr.index == index)
+            .filter_map(InterfaceAddr::from_netlink)
+            .collect();
+
+        for addr in self.addrs.clone() {
+            if !current.iter().any(|current| current.addr == addr.addr) {
+                self.remove_addr(addr);
+            }
+        }
+        for addr in current {
+            self.update_addr(addr);
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, msg: &Message) {
+        match msg.ty {
+            netlink::RTM_NEWLINK | netlink::RTM_DELLINK => {
+                let Some(link) = netlink::parse_link_msg(&msg.payload) else {
+                    return;
+                };
+
+                let matches = link.name.as_deref() == Some(&self.interface);
+                let is_current = self.index == Some(link.index);
+
+                if msg.ty == netlink::RTM_DELLINK || !matches {
+                    if is_current {
+                        self.remove_interface();
+                    }
+                } else if !is_current {
+                    if self.index.is_some() {
+                        self.remove_interface();
+                    }
+                    self.add_interface(link.index);
+                }
+            }
+            netlink::RTM_NEWADDR | netlink::RTM_DELADDR => {
+                let addr = netlink::parse_addr_msg(&msg.payload);
+                let Some(addr) = addr.filter(|addr| Some(addr.index) == self.index) else {
+                    return;
+                };
+                let Some(addr) = InterfaceAddr::from_netlink(&addr) else {
+                    return;
+                };
+
+                if msg.ty == netlink::RTM_NEWADDR {
+                    self.update_addr(addr);
+                } else {
+                    self.remove_addr(addr);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn add_interface(&mut self, index: u32) {
+        self.index = Some(index);
+        self.pending.push_back(WatchEvent::InterfaceAdded { index });
+    }
+
+    fn remove_interface(&mut self) {
+        self.index = None;
+        self.addrs.clear();
+        self.pending.push_back(WatchEvent::InterfaceRemoved);
+    }
+
+    fn update_addr(&mut self, addr: InterfaceAddr) {
+        match self.addrs.iter_mut().find(|known| known.addr == addr.addr) {
+            Some(known) if *known == addr => {}
+            Some(known) => {
+                *known = addr.clone();
+                self.pending.push_back(WatchEvent::AddressChanged(addr));
+            }
+            None => {
+                self.addrs.push(addr.clone());
+                self.pending.push_back(WatchEvent::AddressAdded(addr));
+            }
+        }
+    }
+
+    fn remove_addr(&mut self, addr: InterfaceAddr) {
+        let len = self.addrs.len();
+        self.addrs.retain(|known| known.addr != addr.addr);
+
+        if self.addrs.len() != len {
+            self.pending.push_back(WatchEvent::AddressRemoved(addr));
+        }
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = Result<WatchEvent>;
+
+    /// Block until the next event. Never returns `None`.
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Watcher")
+            .field("interface", &self.interface)
+            .field("index", &self.index)
+            .field("addrs", &self.addrs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The outcome of [`IpQuery::wait_for_ipv6_global_change`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeOutcome {
+    /// The preferred GUA of the interface is a different one.
+    ChangedTo(Ipv6Addr),
+    /// The known address is (again) the preferred GUA.
+    Unchanged,
+    /// The interface had no GUA until the timeout expired.
+    Lost,
+}
+
+impl IpQuery<'_> {
+    /// Wait until the interface has a preferred GUA and report whether
+    /// it differs from the known one, e.g. to skip pointless DNS updates
+    /// after a PPPoE session bounced and got the same address again.
+    ///
+    /// Returns as soon as a GUA is available, so a known address that
+    /// is still assigned is reconfirmed immediately. While there is
+    /// none, e.g. because the interface is down or doesn't exist,
+    /// the interface is watched and probed again on every change
+    /// and at least once per second, measured by the [clock](IpQuery::clock)
+    /// of the query. It is just polled if netlink isn't permitted
+    /// or if the query isn't bound to an interface.
+    /// If there still is no GUA when the timeout expires,
+    /// the outcome is [`ChangeOutcome::Lost`]. Errors other than a missing address,
+    /// route or interface are returned.
+    pub fn wait_for_ipv6_global_change(
+        &self,
+        known: Ipv6Addr,
+        timeout: Duration,
+    ) -> Result<ChangeOutcome> {
+        let deadline = self.now() + timeout;
+
+        // Subscribe before probing, so that no change is missed in between.
+        let mut watcher = match self.interface.map(Watcher::new) {
+            Some(Ok(mut watcher)) => {
+                watcher.clock = self.clock.clone();
+                Some(watcher)
+            }
+            Some(Err(Error::IoError(e))) if e.kind() == io::ErrorKind::PermissionDenied => None,
+            Some(Err(e)) => return Err(e),
+            None => None,
+        };
+
+        wait_for_change(
+            known,
+            deadline,
+            self.active_clock(),
+            watcher.as_mut(),
+            || self.ipv6_unicast_global(),
+        )
+    }
+}
+
+/// Probe until there is a GUA, waiting for the next event of the watcher
+/// or polling in between.
+fn wait_for_change(
+    known: Ipv6Addr,
+    deadline: Instant,
+    clock: &dyn Clock,
+    mut watcher: Option<&mut Watcher>,
+    mut probe: impl FnMut() -> Result<Ipv6Addr>,
+) -> Result<ChangeOutcome> {
+    loop {
+        match probe() {
+            Ok(ipv6) if ipv6 == known => return Ok(ChangeOutcome::Unchanged),
+            Ok(ipv6) => return Ok(ChangeOutcome::ChangedTo(ipv6)),
+            Err(e)
+                if e.is_scope_miss()
+                    || e.is_transient()
+                    || e.raw_os_error() == Some(libc::ENODEV) => {}
+            Err(e) => return Err(e),
+        }
+
+        let remaining = deadline.saturating_duration_since(clock.now());
+        if remaining.is_zero() {
+            return Ok(ChangeOutcome::Lost);
+        }
+
+        let wait = remaining.min(POLL_INTERVAL);
+        match &mut watcher {
+            Some(watcher) => {
+                watcher.next_event_timeout(wait)?;
+            }
+            None => clock.sleep(wait),
+        }
+    }
+}
+
+/// Wait until the interface has a preferred GUA and report whether
+/// it differs from the known one.
+/// See [`IpQuery::wait_for_ipv6_global_change`] for details.
+pub fn wait_for_ipv6_global_change(
+    interface: &str,
+    known: Ipv6Addr,
+    timeout: Duration,
+) -> Result<ChangeOutcome> {
+    IpQuery::new(interface).wait_for_ipv6_global_change(known, timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_support::MockClock;
+    use crate::IpVersion;
+
+    const KNOWN: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+    const NEW: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 2);
+
+    /// A scripted kernel. The links and addresses are what resynchronizing
+    /// sees, the script what is received, one datagram at a time.
+    #[derive(Default)]
+    struct MockState {
+        script: VecDeque<io::Result<Vec<Message>>>,
+        links: Vec<(&'static str, u32)>,
+        addrs: Vec<netlink::Addr>,
+        dumps: usize,
+    }
+
+    #[derive(Clone, Default)]
+    struct Mock(Arc<Mutex<MockState>>);
+
+    impl Mock {
+        fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+            self.0.lock().unwrap()
+        }
+
+        fn push(&self, datagram: io::Result<Vec<Message>>) {
+            self.state().script.push_back(datagram);
+        }
+    }
+
+    impl WatchSource for Mock {
+        fn recv_notifications(&mut self) -> io::Result<Vec<Message>> {
+            self.state()
+                .script
+                .pop_front()
+                .unwrap_or_else(|| Err(io::ErrorKind::WouldBlock.into()))
+        }
+
+        fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn link_index(&mut self, name: &str) -> Result<Option<u32>> {
+            let links = &self.state().links;
+            Ok(links
+                .iter()
+                .find(|&&(link, _)| link == name)
+                .map(|&(_, index)| index))
+        }
+
+        fn addrs(&mut self) -> io::Result<Vec<netlink::Addr>> {
+            let mut state = self.state();
+            state.dumps += 1;
+            Ok(state.addrs.clone())
+        }
+    }
+
+    fn addr(index: u32, addr: &str) -> netlink::Addr {
+        netlink::Addr {
+            index,
+            prefix_len: 64,
+            local: Some(addr.parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn link_msg(ty: u16, index: u32, name: &str) -> Message {
+        let link = netlink::Link {
+            index,
+            name: Some(name.into()),
+            ..Default::default()
+        };
+
+        Message {
+            ty,
+            payload: link.to_payload(),
+        }
+    }
+
+    fn addr_msg(ty: u16, addr: &netlink::Addr) -> Message {
+        Message {
+            ty,
+            payload: addr.to_payload(),
+        }
+    }
+
+    fn added(addr: &netlink::Addr) -> WatchEvent {
+        WatchEvent::AddressAdded(InterfaceAddr::from_netlink(addr).unwrap())
+    }
+
+    fn removed(addr: &netlink::Addr) -> WatchEvent {
+        WatchEvent::AddressRemoved(InterfaceAddr::from_netlink(addr).unwrap())
+    }
+
+    /// Collect the events until the script runs out.
+    fn events(watcher: &mut Watcher) -> Vec<WatchEvent> {
+        std::iter::from_fn(|| watcher.next_event_timeout(Duration::from_secs(1)).unwrap()).collect()
+    }
+
+    /// Watch `ppp0` with a mock clock, so that timeouts don't take time.
+    fn ppp0(mock: &Mock) -> Watcher {
+        {
+            let mut state = mock.state();
+            state.links = vec![("lo", 1), ("ppp0", 5)];
+            state.addrs = vec![addr(1, "::1"), addr(5, "2a01:4f8::1")];
+        }
+
+        Watcher::with_source("ppp0", mock.clone())
+            .unwrap()
+            .clock(MockClock::new())
+    }
+
+    #[test]
+    fn initial_state() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        assert_eq!(watcher.index(), Some(5));
+        assert_eq!(
+            watcher.addresses(),
+            [InterfaceAddr::from_netlink(&addr(5, "2a01:4f8::1")).unwrap()]
+        );
+        assert_eq!(events(&mut watcher), []);
+    }
+
+    #[test]
+    fn interface_recreated_with_new_index() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        let old = addr(5, "2a01:4f8::1");
+        let new = addr(9, "2a01:4f8::2");
+        mock.push(Ok(vec![
+            addr_msg(netlink::RTM_DELADDR, &old),
+            link_msg(netlink::RTM_DELLINK, 5, "ppp0"),
+        ]));
+        mock.push(Ok(vec![
+            link_msg(netlink::RTM_NEWLINK, 9, "ppp0"),
+            // Late notifications of the old incarnation are ignored.
+            addr_msg(netlink::RTM_NEWADDR, &old),
+        ]));
+        mock.push(Ok(vec![addr_msg(netlink::RTM_NEWADDR, &new)]));
+
+        assert_eq!(
+            events(&mut watcher),
+            [
+                removed(&old),
+                WatchEvent::InterfaceRemoved,
+                WatchEvent::InterfaceAdded { index: 9 },
+                added(&new),
+            ]
+        );
+        assert_eq!(watcher.index(), Some(9));
+        assert_eq!(
+            watcher.addresses(),
+            [InterfaceAddr::from_netlink(&new).unwrap()]
+        );
+    }
+
+    #[test]
+    fn removed_interface_drops_addresses() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        mock.push(Ok(vec![link_msg(netlink::RTM_DELLINK, 5, "ppp0")]));
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 6, "ppp1")]));
+
+        assert_eq!(events(&mut watcher), [WatchEvent::InterfaceRemoved]);
+        assert_eq!(watcher.index(), None);
+        assert!(watcher.addresses().is_empty());
+    }
+
+    #[test]
+    fn renamed_interface() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        // The watched interface is renamed away,
+        // then another one is renamed to its name.
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 5, "ppp-old")]));
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 5, "ppp-old")]));
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 7, "ppp0")]));
+        // Other changes of the interface don't re-add it.
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 7, "ppp0")]));
+
+        assert_eq!(
+            events(&mut watcher),
+            [
+                WatchEvent::InterfaceRemoved,
+                WatchEvent::InterfaceAdded { index: 7 },
+            ]
+        );
+        assert_eq!(watcher.index(), Some(7));
+    }
+
+    #[test]
+    fn interface_created_later() {
+        let mock = Mock::default();
+        mock.state().links = vec![("lo", 1)];
+        let mut watcher = Watcher::with_source("wg0", mock.clone())
+            .unwrap()
+            .clock(MockClock::new());
+        assert_eq!(watcher.index(), None);
+
+        let addr = addr(3, "fd00::1");
+        mock.push(Ok(vec![
+            link_msg(netlink::RTM_NEWLINK, 3, "wg0"),
+            addr_msg(netlink::RTM_NEWADDR, &addr),
+        ]));
+
+        assert_eq!(
+            events(&mut watcher),
+            [WatchEvent::InterfaceAdded { index: 3 }, added(&addr)]
+        );
+    }
+
+    #[test]
+    fn address_changes() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        let mut known = addr(5, "2a01:4f8::1");
+        mock.push(Ok(vec![addr_msg(netlink::RTM_NEWADDR, &known)]));
+        known.flags = 0x20;
+        mock.push(Ok(vec![addr_msg(netlink::RTM_NEWADDR, &known)]));
+        let other = addr(6, "2a01:4f8::2");
+        mock.push(Ok(vec![addr_msg(netlink::RTM_DELADDR, &other)]));
+
+        assert_eq!(
+            events(&mut watcher),
+            [WatchEvent::AddressChanged(
+                InterfaceAddr::from_netlink(&known).unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn overrun_resyncs() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+        assert_eq!(mock.state().dumps, 1);
+
+        // The address changed while the notifications were lost.
+        let old = addr(5, "2a01:4f8::1");
+        let new = addr(5, "2a01:4f8::2");
+        mock.state().addrs = vec![addr(1, "::1"), new.clone()];
+        mock.push(Err(io::Error::from_raw_os_error(libc::ENOBUFS)));
+        // Notifications that arrived after the overrun replay changes
+        // the resync has already seen.
+        mock.push(Ok(vec![addr_msg(netlink::RTM_DELADDR, &old)]));
+        mock.push(Ok(vec![addr_msg(netlink::RTM_NEWADDR, &new)]));
+
+        assert_eq!(
+            events(&mut watcher),
+            [WatchEvent::Overrun, removed(&old), added(&new)]
+        );
+        assert_eq!(mock.state().dumps, 2);
+        assert_eq!(
+            watcher.addresses(),
+            [InterfaceAddr::from_netlink(&new).unwrap()]
+        );
+    }
+
+    #[test]
+    fn overrun_during_recreation() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        let new = addr(9, "2a01:4f8::1");
+        {
+            let mut state = mock.state();
+            state.links = vec![("lo", 1), ("ppp0", 9)];
+            state.addrs = vec![addr(1, "::1"), new.clone()];
+        }
+        mock.push(Err(io::Error::from_raw_os_error(libc::ENOBUFS)));
+
+        assert_eq!(
+            events(&mut watcher),
+            [
+                WatchEvent::Overrun,
+                WatchEvent::InterfaceRemoved,
+                WatchEvent::InterfaceAdded { index: 9 },
+                added(&new),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_errors_are_returned() {
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock);
+
+        mock.push(Err(io::Error::from_raw_os_error(libc::EBADF)));
+        let result = watcher.next_event_timeout(Duration::from_secs(1));
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::EBADF));
+    }
+
+    #[test]
+    fn timeouts_follow_the_clock() {
+        let clock = MockClock::new();
+        let mock = Mock::default();
+        let mut watcher = ppp0(&mock).clock(clock.clone());
+        let start = clock.now();
+
+        // Reads time out immediately, the clock is advanced to the deadline.
+        let event = watcher.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event, None);
+        assert_eq!(clock.now(), start + Duration::from_secs(5));
+
+        // Events are returned without waiting.
+        mock.push(Ok(vec![link_msg(netlink::RTM_DELLINK, 5, "ppp0")]));
+        let event = watcher.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event, Some(WatchEvent::InterfaceRemoved));
+        assert_eq!(clock.now(), start + Duration::from_secs(5));
+
+        // A clock that jumps past the deadline ends the wait.
+        clock.advance(Duration::from_secs(3600));
+        let event = watcher.next_event_timeout(Duration::ZERO).unwrap();
+        assert_eq!(event, None);
+        assert_eq!(clock.now(), start + Duration::from_secs(3605));
+    }
+
+    fn miss() -> Error {
+        Error::NoGua("fe80::1".parse().unwrap())
+    }
+
+    /// Wait for a change of [`KNOWN`] with the scripted probe results,
+    /// which are misses once they run out. Returns the outcome,
+    /// the number of probes and how much time passed.
+    fn wait(
+        clock: &MockClock,
+        watcher: Option<&mut Watcher>,
+        probes: impl IntoIterator<Item = Result<Ipv6Addr>>,
+        timeout: Duration,
+    ) -> (Result<ChangeOutcome>, usize, Duration) {
+        let mut probes = probes.into_iter();
+        let mut count = 0;
+        let start = clock.now();
+
+        let outcome = wait_for_change(KNOWN, start + timeout, clock, watcher, || {
+            count += 1;
+            probes.next().unwrap_or_else(|| Err(miss()))
+        });
+
+        (outcome, count, clock.now() - start)
+    }
+
+    fn watched(clock: &MockClock) -> (Mock, Watcher) {
+        let mock = Mock::default();
+        let watcher = ppp0(&mock).clock(clock.clone());
+
+        (mock, watcher)
+    }
+
+    #[test]
+    fn present_addresses_are_compared_immediately() {
+        let clock = MockClock::new();
+        let timeout = Duration::from_secs(10);
+
+        let (outcome, probes, elapsed) = wait(&clock, None, [Ok(KNOWN)], timeout);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Unchanged);
+        assert_eq!((probes, elapsed), (1, Duration::ZERO));
+
+        let (outcome, probes, elapsed) = wait(&clock, None, [Ok(NEW)], timeout);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::ChangedTo(NEW));
+        assert_eq!((probes, elapsed), (1, Duration::ZERO));
+    }
+
+    #[test]
+    fn polls_until_the_address_is_back() {
+        let clock = MockClock::new();
+        let probes = [Err(miss()), Err(miss()), Ok(KNOWN)];
+
+        let (outcome, probes, elapsed) = wait(&clock, None, probes, Duration::from_secs(10));
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Unchanged);
+        assert_eq!((probes, elapsed), (3, Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn lost_when_the_timeout_expires() {
+        let timeout = Duration::from_millis(3500);
+
+        // Probes at 0, 1, 2, 3 and 3.5 seconds.
+        let clock = MockClock::new();
+        let (outcome, probes, elapsed) = wait(&clock, None, [], timeout);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Lost);
+        assert_eq!((probes, elapsed), (5, timeout));
+
+        // Without events, the watcher times out like polling does.
+        let clock = MockClock::new();
+        let (_mock, mut watcher) = watched(&clock);
+        let (outcome, probes, elapsed) = wait(&clock, Some(&mut watcher), [], timeout);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Lost);
+        assert_eq!((probes, elapsed), (5, timeout));
+    }
+
+    #[test]
+    fn zero_timeout_probes_once() {
+        let clock = MockClock::new();
+
+        let (outcome, probes, _) = wait(&clock, None, [], Duration::ZERO);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Lost);
+        assert_eq!(probes, 1);
+
+        let (outcome, probes, _) = wait(&clock, None, [Ok(KNOWN)], Duration::ZERO);
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Unchanged);
+        assert_eq!(probes, 1);
+    }
+
+    #[test]
+    fn events_trigger_probes() {
+        let clock = MockClock::new();
+        let (mock, mut watcher) = watched(&clock);
+
+        let new = addr(5, "2a01:4f8::2");
+        mock.push(Ok(vec![addr_msg(netlink::RTM_NEWADDR, &new)]));
+        mock.push(Ok(vec![addr_msg(netlink::RTM_DELADDR, &new)]));
+
+        // Every event is followed by a probe without waiting for the poll.
+        let probes = [Err(miss()), Err(miss()), Ok(NEW)];
+        let (outcome, probes, elapsed) =
+            wait(&clock, Some(&mut watcher), probes, Duration::from_secs(10));
+        assert_eq!(outcome.unwrap(), ChangeOutcome::ChangedTo(NEW));
+        assert_eq!((probes, elapsed), (3, Duration::ZERO));
+    }
+
+    #[test]
+    fn datagrams_without_events_keep_waiting() {
+        let clock = MockClock::new();
+        let (mock, mut watcher) = watched(&clock);
+
+        // Changes of other interfaces aren't events.
+        mock.push(Ok(vec![link_msg(netlink::RTM_NEWLINK, 6, "ppp1")]));
+
+        let probes = [Err(miss()), Ok(KNOWN)];
+        let (outcome, probes, elapsed) =
+            wait(&clock, Some(&mut watcher), probes, Duration::from_secs(10));
+        assert_eq!(outcome.unwrap(), ChangeOutcome::Unchanged);
+        assert_eq!((probes, elapsed), (2, Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn missing_interfaces_and_routes_are_waited_out() {
+        let clock = MockClock::new();
+        let probes = [
+            Err(io::Error::from_raw_os_error(libc::ENODEV).into()),
+            Err(Error::NoAddress {
+                interface: Some("ppp0".into()),
+                family: IpVersion::V6,
+            }),
+            Err(io::Error::from_raw_os_error(libc::ENETUNREACH).into()),
+            Ok(NEW),
+        ];
+
+        let (outcome, probes, elapsed) = wait(&clock, None, probes, Duration::from_secs(10));
+        assert_eq!(outcome.unwrap(), ChangeOutcome::ChangedTo(NEW));
+        assert_eq!((probes, elapsed), (4, Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn other_errors_end_the_wait() {
+        let clock = MockClock::new();
+        let probes = [
+            Err(miss()),
+            Err(io::Error::from_raw_os_error(libc::EBADF).into()),
+        ];
+
+        let (outcome, probes, _) = wait(&clock, None, probes, Duration::from_secs(10));
+        assert_eq!(outcome.unwrap_err().raw_os_error(), Some(libc::EBADF));
+        assert_eq!(probes, 2);
+
+        let (mock, mut watcher) = watched(&clock);
+        mock.push(Err(io::Error::from_raw_os_error(libc::EBADF)));
+        let (outcome, probes, _) = wait(&clock, Some(&mut watcher), [], Duration::from_secs(10));
+        assert_eq!(outcome.unwrap_err().raw_os_error(), Some(libc::EBADF));
+        assert_eq!(probes, 1);
+    }
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::thread;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{all_preferred, all_preferred_with, Interface};
+
+const UPLINKS: u8 = 16;
+
+/// Add veth pairs whose first end has a private address each,
+/// and one that is down.
+fn add_uplinks() {
+    for n in 0..UPLINKS {
+        common::ip(&format!(
+            "link add uplink{} type veth peer name peer{}",
+            n, n
+        ));
+        common::ip(&format!("addr add 10.0.{}.1/24 dev uplink{}", n, n));
+        common::ip(&format!("link set uplink{} up", n));
+        common::ip(&format!("link set peer{} up", n));
+    }
+    common::ip("link add down0 type veth peer name down1");
+}
+
+fn is_uplink(interface: &Interface) -> bool {
+    interface.is_up() && interface.name.starts_with("uplink")
+}
+
+#[test]
+fn reports_of_many_interfaces() {
+    let result = common::run(NetEnv::builder(), || {
+        add_uplinks();
+
+        let caller = thread::current().id();
+        let calls = Mutex::new(Vec::new());
+        let results = all_preferred_with(is_uplink, |done, total, interface, report| {
+            calls.lock().unwrap().push((
+                done,
+                total,
+                interface.to_owned(),
+                report.as_ref().ok().and_then(|report| report.ipv4_private),
+                thread::current().id() != caller,
+            ));
+        })
+        .unwrap();
+
+        (
+            results,
+            calls.into_inner().unwrap(),
+            all_preferred().unwrap(),
+        )
+    });
+    let Some((results, calls, all)) = result else {
+        return;
+    };
+
+    let names: Vec<_> = results
+        .iter()
+        .map(|(interface, _)| interface.name.clone())
+        .collect();
+    assert_eq!(
+        names,
+        (0..UPLINKS)
+            .map(|n| format!("uplink{}", n))
+            .collect::<Vec<_>>()
+    );
+    for (n, (_, report)) in results.iter().enumerate() {
+        let report = report.as_ref().unwrap();
+        assert_eq!(report.ipv4_private, Some(Ipv4Addr::new(10, 0, n as u8, 1)));
+    }
+
+    assert_eq!(calls.len(), UPLINKS as usize);
+    let done: BTreeSet<_> = calls.iter().map(|call| call.0).collect();
+    assert_eq!(done, (1..=UPLINKS as usize).collect());
+    for (_, total, interface, ipv4_private, on_worker) in &calls {
+        assert_eq!(*total, UPLINKS as usize);
+        assert!(*on_worker);
+        let (_, report) = results.iter().find(|(i, _)| i.name == *interface).unwrap();
+        assert_eq!(*ipv4_private, report.as_ref().unwrap().ipv4_private);
+    }
+
+    // Without a filter, the down interface, loopback and the veth pair
+    // are reported as well.
+    let names: BTreeSet<_> = all
+        .iter()
+        .map(|(interface, _)| &interface.name[..])
+        .collect();
+    for name in ["lo", "veth0", "veth1", "down0", "uplink0", "peer0"] {
+        assert!(names.contains(name), "{}", name);
+    }
+    assert!(all.windows(2).all(|w| w[0].0.index < w[1].0.index));
+}
@@ -0,0 +1,111 @@
+//! Checks that the plain getters don't allocate on success,
+//! counting the allocations with a global allocator.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::IpQuery;
+
+struct Counting;
+
+thread_local! {
+    // Only allocations of the measuring thread are counted,
+    // other tests run concurrently.
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: Every call is forwarded to the system allocator.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        // SAFETY: The caller upholds the contract of `alloc`.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        // SAFETY: The caller upholds the contract of `alloc_zeroed`.
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        // SAFETY: The caller upholds the contract of `realloc`.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: The caller upholds the contract of `dealloc`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn count() {
+    // `try_with` because the thread locals may already be destroyed
+    // while a thread exits.
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        }
+    });
+}
+
+/// Count the heap allocations of the closure on this thread.
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATIONS.with(|n| n.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let result = f();
+    COUNTING.with(|counting| counting.set(false));
+
+    (result, ALLOCATIONS.with(Cell::get))
+}
+
+fn assert_no_allocations(name: &str, getter: impl FnOnce() -> bool) {
+    let (ok, n) = allocations(getter);
+    assert!(ok, "{} failed", name);
+    assert_eq!(n, 0, "{} allocated {} times", name, n);
+}
+
+#[test]
+fn counts_allocations() {
+    let (_, n) = allocations(|| Box::new(1));
+    assert_eq!(n, 1);
+}
+
+#[test]
+fn plain_getters_dont_allocate() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        // Warm up lazily initialized state, e.g. of the standard library.
+        preferred_ip::ipv6_unicast_global("veth0").unwrap();
+        preferred_ip::ipv4_private("veth0").unwrap();
+
+        assert_no_allocations("ipv6_unicast_link_local", || {
+            preferred_ip::ipv6_unicast_link_local("veth0").is_ok()
+        });
+        assert_no_allocations("ipv6_unique_local", || {
+            preferred_ip::ipv6_unique_local("veth0").is_ok()
+        });
+        assert_no_allocations("ipv6_unicast_global", || {
+            preferred_ip::ipv6_unicast_global("veth0").is_ok()
+        });
+        assert_no_allocations("ipv4_private", || {
+            preferred_ip::ipv4_private("veth0").is_ok()
+        });
+        assert_no_allocations("ipv4_loopback", || {
+            IpQuery::any_interface().ipv4_loopback().is_ok()
+        });
+    });
+}
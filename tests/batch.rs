@@ -0,0 +1,223 @@
+mod common;
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use preferred_ip::socket2::{Domain, Socket};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Destination, Error, IpQuery, ProbeProtocol, ProvidedSocket, SocketFactory};
+
+/// Count the sockets created per family.
+#[derive(Clone, Default)]
+struct Counter {
+    ipv4: Arc<AtomicUsize>,
+    ipv6: Arc<AtomicUsize>,
+}
+
+impl Counter {
+    fn factory(&self) -> impl SocketFactory + 'static {
+        let counter = self.clone();
+        move |domain, ty| {
+            let count = if domain == Domain::IPV6 {
+                &counter.ipv6
+            } else {
+                &counter.ipv4
+            };
+            count.fetch_add(1, Ordering::Relaxed);
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        }
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        (
+            self.ipv4.load(Ordering::Relaxed),
+            self.ipv6.load(Ordering::Relaxed),
+        )
+    }
+}
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+/// Routes with a different source address and an unreachable one.
+fn add_routes() {
+    common::ip("addr add 192.168.77.2/24 dev veth0");
+    common::ip("route add 203.0.113.0/24 dev veth0 src 192.168.77.2");
+    common::ip("route add unreachable 198.51.100.0/24");
+    common::ip("-6 route add fd00:beef::/64 dev veth0 src fd00:dead::1");
+}
+
+fn dests(dests: &[&str]) -> Vec<IpAddr> {
+    dests.iter().map(|dest| dest.parse().unwrap()).collect()
+}
+
+/// Compare results by their messages, since errors aren't comparable.
+fn messages(results: Vec<preferred_ip::Result<IpAddr>>) -> Vec<Result<IpAddr, String>> {
+    results
+        .into_iter()
+        .map(|result| result.map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[test]
+fn one_socket_per_family() {
+    common::run(env(), || {
+        add_routes();
+
+        let dests = dests(&[
+            "2606:4700::1111",
+            "192.0.2.1",
+            "fd00:beef::1",
+            "203.0.113.1",
+            "2001:db8::1",
+            "8.8.8.8",
+            "192.168.77.100",
+        ]);
+        let counter = Counter::default();
+        let results = IpQuery::new("veth0")
+            .socket_factory(counter.factory())
+            .preferred_sources_for(&dests);
+
+        assert_eq!(results.len(), dests.len());
+        assert!(results.iter().all(Result::is_ok), "{:?}", results);
+        assert_eq!(counter.counts(), (1, 1));
+    });
+}
+
+#[test]
+fn batch_agrees_with_single_probes() {
+    common::run(env(), || {
+        add_routes();
+
+        let dests = dests(&[
+            "2606:4700::1111",
+            "192.0.2.1",
+            "fd00:beef::1",
+            "203.0.113.1",
+            "198.51.100.1",
+            "fe80::1",
+            "192.168.77.100",
+            "203.0.113.2",
+            "::1",
+            "127.0.0.1",
+        ]);
+
+        for query in [IpQuery::new("veth0"), IpQuery::any_interface()] {
+            let batch = messages(query.preferred_sources_for(&dests));
+            let single = messages(
+                dests
+                    .iter()
+                    .map(|&dest| query.preferred_source_for(dest))
+                    .collect(),
+            );
+            assert_eq!(batch, single);
+        }
+    });
+}
+
+#[test]
+fn reconnecting_selects_the_source_again() {
+    common::run(env(), || {
+        add_routes();
+
+        // Sockets bound to an interface ignore the unreachable route.
+        let results = IpQuery::any_interface().preferred_sources_for(&dests(&[
+            "192.0.2.1",
+            "203.0.113.1",
+            "192.0.2.2",
+            "198.51.100.1",
+            "203.0.113.2",
+        ]));
+
+        let sources: Vec<_> = results
+            .iter()
+            .map(|result| result.as_ref().ok().map(IpAddr::to_string))
+            .collect();
+        // A failed connect doesn't spoil the socket for the next one.
+        assert_eq!(
+            sources,
+            [
+                Some("192.168.77.1".into()),
+                Some("192.168.77.2".into()),
+                Some("192.168.77.1".into()),
+                None,
+                Some("192.168.77.2".into()),
+            ]
+        );
+    });
+}
+
+#[test]
+fn failures_are_isolated() {
+    common::run(env(), || {
+        add_routes();
+
+        let zoned: Vec<Destination> = ["fe80::1%99", "198.51.100.1", "2606:4700::1111"]
+            .iter()
+            .map(|dest| dest.parse().unwrap())
+            .collect();
+        let results = IpQuery::new("veth0").preferred_sources_for(&zoned);
+        assert!(
+            matches!(results[0], Err(Error::ZoneMismatch { scope_id: 99, .. })),
+            "{:?}",
+            results[0]
+        );
+        assert!(results[1].is_ok(), "{:?}", results[1]);
+        assert_eq!(
+            results[2].as_ref().unwrap(),
+            &"2a01:4f8::1".parse::<IpAddr>().unwrap()
+        );
+
+        let results = IpQuery::any_interface().preferred_sources_for(&dests(&[
+            "fe80::1",
+            "198.51.100.1",
+            "2606:4700::1111",
+        ]));
+        assert!(
+            matches!(results[0], Err(Error::NoZone(_))),
+            "{:?}",
+            results[0]
+        );
+        assert!(
+            matches!(results[1], Err(Error::NoRoute { .. })),
+            "{:?}",
+            results[1]
+        );
+        assert_eq!(
+            results[2].as_ref().unwrap(),
+            &"2a01:4f8::1".parse::<IpAddr>().unwrap()
+        );
+    });
+}
+
+#[test]
+fn tcp_probes_use_a_socket_per_destination() {
+    common::run(env(), || {
+        let dests = dests(&["2606:4700::1111", "2001:db8::1", "192.0.2.1"]);
+        let counter = Counter::default();
+        let results = IpQuery::new("veth0")
+            .protocol(ProbeProtocol::Tcp)
+            .socket_factory(counter.factory())
+            .preferred_sources_for(&dests);
+
+        assert!(results.iter().all(Result::is_ok), "{:?}", results);
+        assert_eq!(counter.counts(), (1, 2));
+    });
+}
+
+#[test]
+fn empty_batch() {
+    let counter = Counter::default();
+    let results = IpQuery::any_interface()
+        .socket_factory(counter.factory())
+        .preferred_sources_for::<IpAddr>(&[]);
+
+    assert!(results.is_empty());
+    assert_eq!(counter.counts(), (0, 0));
+}
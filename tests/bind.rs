@@ -0,0 +1,309 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use preferred_ip::socket2::SockRef;
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{
+    bind_preferred_tcp_listener, bind_preferred_udp, Error, ErrorKind, InterfaceHandle, IpQuery,
+    IpVersion, Ipv4Scope, Ipv6Scope, ProbeObserver, Scope,
+};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const ULA: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+const V6_GUA: Scope = Scope::V6(Ipv6Scope::UnicastGlobal);
+const V4_PRIVATE: Scope = Scope::V4(Ipv4Scope::Private);
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+/// An observer that removes an address from `veth0` after the given
+/// number of probes, so that it vanishes between the probes and the bind.
+struct RemoveAfterProbes {
+    addr: &'static str,
+    probes: AtomicUsize,
+}
+
+impl RemoveAfterProbes {
+    fn new(addr: &'static str, probes: usize) -> Self {
+        Self {
+            addr,
+            probes: AtomicUsize::new(probes),
+        }
+    }
+}
+
+impl ProbeObserver for RemoveAfterProbes {
+    fn on_probe_end(
+        &self,
+        _: Option<&str>,
+        _: SocketAddr,
+        _: Option<Scope>,
+        _: Result<IpAddr, &Error>,
+        _: Duration,
+    ) {
+        if self.probes.fetch_sub(1, Ordering::Relaxed) == 1 {
+            common::ip(&format!("addr del {} dev veth0", self.addr));
+        }
+    }
+}
+
+fn device(socket: SockRef) -> Option<Vec<u8>> {
+    socket.device().unwrap()
+}
+
+#[test]
+fn udp_binds_the_preferred_address() {
+    common::run(env(), || {
+        for (scope, addr) in [
+            (V6_GUA, IpAddr::V6(GUA)),
+            (Scope::V6(Ipv6Scope::UniqueLocal), IpAddr::V6(ULA)),
+            (V4_PRIVATE, IpAddr::V4(PRIVATE)),
+        ] {
+            let socket = bind_preferred_udp("veth0", scope, 0).unwrap();
+            let local = socket.local_addr().unwrap();
+
+            assert_eq!(local.ip(), addr);
+            // Port 0 picks a free port.
+            assert_ne!(local.port(), 0);
+            assert_eq!(device((&socket).into()).as_deref(), Some(&b"veth0"[..]));
+        }
+    });
+}
+
+#[test]
+fn udp_binds_loopback() {
+    common::run(NetEnv::builder(), || {
+        common::ip("link set lo up");
+
+        let socket = bind_preferred_udp("lo", Scope::V6(Ipv6Scope::Loopback), 0).unwrap();
+        assert_eq!(socket.local_addr().unwrap().ip(), Ipv6Addr::LOCALHOST);
+
+        let socket = bind_preferred_udp("lo", Scope::V4(Ipv4Scope::Loopback), 0).unwrap();
+        assert_eq!(socket.local_addr().unwrap().ip(), Ipv4Addr::LOCALHOST);
+
+        // Bound sockets of loopback can talk to each other.
+        let peer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.send_to(b"ping", peer.local_addr().unwrap()).unwrap();
+        let mut buf = [0; 4];
+        let (len, from) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ping");
+        assert_eq!(from, socket.local_addr().unwrap());
+    });
+}
+
+#[test]
+fn udp_binds_link_local_with_scope_id() {
+    common::run(env(), || {
+        common::flush_ipv6("veth0");
+        common::ip("addr add fe80::1/64 dev veth0 nodad");
+
+        let socket = bind_preferred_udp("veth0", Scope::V6(Ipv6Scope::UnicastLinkLocal), 0);
+        let SocketAddr::V6(local) = socket.unwrap().local_addr().unwrap() else {
+            panic!("not an IPv6 socket");
+        };
+
+        assert_eq!(*local.ip(), "fe80::1".parse::<Ipv6Addr>().unwrap());
+        let index = InterfaceHandle::open("veth0").unwrap().index();
+        assert_eq!(local.scope_id(), index);
+    });
+}
+
+#[test]
+fn udp_port_in_use() {
+    common::run(env(), || {
+        let taken = UdpSocket::bind((GUA, 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let err = bind_preferred_udp("veth0", V6_GUA, port).unwrap_err();
+        assert!(
+            matches!(&err, Error::AddrInUse { interface, addr }
+                if interface.as_deref() == Some("veth0") && *addr == SocketAddr::new(GUA.into(), port)),
+            "{:?}",
+            err
+        );
+        assert_eq!(ErrorKind::from(&err), ErrorKind::AddrInUse);
+        assert!(err.is_transient());
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "[2a01:4f8::1]:{} is already in use on interface veth0",
+                port
+            )
+        );
+
+        // Other ports and addresses are still available.
+        bind_preferred_udp("veth0", V6_GUA, 0).unwrap();
+        let socket = bind_preferred_udp("veth0", V4_PRIVATE, port).unwrap();
+        assert_eq!(socket.local_addr().unwrap().port(), port);
+    });
+}
+
+#[test]
+fn udp_address_vanished_after_the_probe() {
+    common::run(env(), || {
+        let err = IpQuery::new("veth0")
+            .observer(RemoveAfterProbes::new("2a01:4f8::1/64", 1))
+            .bind_udp(V6_GUA, 0)
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, Error::AddrNotAvailable { interface, addr }
+                if interface.as_deref() == Some("veth0") && *addr == IpAddr::V6(GUA)),
+            "{:?}",
+            err
+        );
+        assert_eq!(ErrorKind::from(&err), ErrorKind::AddrNotAvailable);
+        assert_eq!(
+            err.to_string(),
+            "address 2a01:4f8::1 is no longer available on interface veth0"
+        );
+    });
+}
+
+#[test]
+fn udp_families_without_addresses() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+
+    common::run(env, || {
+        let err = bind_preferred_udp("veth0", V4_PRIVATE, 0).unwrap_err();
+        assert_eq!(ErrorKind::from(&err), ErrorKind::NoAddress, "{:?}", err);
+
+        // The socket has the family of the scope.
+        let socket = bind_preferred_udp("veth0", V6_GUA, 0).unwrap();
+        assert!(socket.local_addr().unwrap().is_ipv6());
+
+        common::block_inet_sockets(libc::EAFNOSUPPORT, false);
+        let err = bind_preferred_udp("veth0", V6_GUA, 0).unwrap_err();
+        assert!(
+            matches!(err, Error::FamilyDisabled(IpVersion::V6)),
+            "{:?}",
+            err
+        );
+    });
+}
+
+#[test]
+fn tcp_listens_on_the_preferred_address() {
+    common::run(env(), || {
+        for (scope, addr) in [(V6_GUA, IpAddr::V6(GUA)), (V4_PRIVATE, IpAddr::V4(PRIVATE))] {
+            let listener = bind_preferred_tcp_listener("veth0", scope, 0, 16).unwrap();
+            let local = listener.local_addr().unwrap();
+
+            assert_eq!(local.ip(), addr);
+            assert_ne!(local.port(), 0);
+            let socket = SockRef::from(&listener);
+            assert!(socket.reuse_address().unwrap());
+            assert_eq!(device(socket).as_deref(), Some(&b"veth0"[..]));
+
+            let mut client = TcpStream::connect(local).unwrap();
+            let (mut server, _) = listener.accept().unwrap();
+            client.write_all(b"ping").unwrap();
+            let mut buf = [0; 4];
+            server.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"ping");
+        }
+    });
+}
+
+#[test]
+fn tcp_listens_on_link_local_with_scope_id() {
+    common::run(env(), || {
+        common::flush_ipv6("veth0");
+        common::ip("addr add fe80::1/64 dev veth0 nodad");
+
+        let scope = Scope::V6(Ipv6Scope::UnicastLinkLocal);
+        let listener = bind_preferred_tcp_listener("veth0", scope, 0, 16).unwrap();
+        let SocketAddr::V6(local) = listener.local_addr().unwrap() else {
+            panic!("not an IPv6 listener");
+        };
+
+        assert_eq!(*local.ip(), "fe80::1".parse::<Ipv6Addr>().unwrap());
+        let index = InterfaceHandle::open("veth0").unwrap().index();
+        assert_eq!(local.scope_id(), index);
+    });
+}
+
+#[test]
+fn tcp_port_in_use() {
+    common::run(env(), || {
+        let taken = TcpListener::bind((GUA, 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        // SO_REUSEADDR doesn't allow a second listener.
+        let err = bind_preferred_tcp_listener("veth0", V6_GUA, port, 16).unwrap_err();
+        assert!(
+            matches!(&err, Error::AddrInUse { interface, addr }
+                if interface.as_deref() == Some("veth0") && *addr == SocketAddr::new(GUA.into(), port)),
+            "{:?}",
+            err
+        );
+        assert_eq!(ErrorKind::from(&err), ErrorKind::AddrInUse);
+
+        // It allows listening again while old connections linger.
+        let listener = bind_preferred_tcp_listener("veth0", V6_GUA, 0, 16).unwrap();
+        let local = listener.local_addr().unwrap();
+        let client = TcpStream::connect(local).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        // Closing first leaves the connection of the listener in TIME_WAIT.
+        drop(server);
+        drop(listener);
+        drop(client);
+        bind_preferred_tcp_listener("veth0", V6_GUA, local.port(), 16).unwrap();
+    });
+}
+
+#[test]
+fn tcp_address_vanished_after_the_probe() {
+    common::run(env(), || {
+        let err = IpQuery::new("veth0")
+            // Private addresses are probed with three destinations.
+            .observer(RemoveAfterProbes::new("192.168.77.1/24", 3))
+            .bind_tcp_listener(V4_PRIVATE, 0, 16)
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, Error::AddrNotAvailable { interface, addr }
+                if interface.as_deref() == Some("veth0") && *addr == IpAddr::V4(PRIVATE)),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "address 192.168.77.1 is no longer available on interface veth0"
+        );
+    });
+}
+
+#[test]
+fn tcp_families_without_addresses() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24").route("default");
+
+    common::run(env, || {
+        // Without IPv6 addresses, there are no IPv6 routes either.
+        common::flush_ipv6("veth0");
+        let err = bind_preferred_tcp_listener("veth0", V6_GUA, 0, 16).unwrap_err();
+        assert_eq!(ErrorKind::from(&err), ErrorKind::NoRoute, "{:?}", err);
+
+        let listener = bind_preferred_tcp_listener("veth0", V4_PRIVATE, 0, 16).unwrap();
+        assert!(listener.local_addr().unwrap().is_ipv4());
+
+        common::block_inet_sockets(libc::EAFNOSUPPORT, true);
+        let err = bind_preferred_tcp_listener("veth0", V4_PRIVATE, 0, 16).unwrap_err();
+        assert!(
+            matches!(err, Error::FamilyDisabled(IpVersion::V4)),
+            "{:?}",
+            err
+        );
+    });
+}
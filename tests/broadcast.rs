@@ -0,0 +1,89 @@
+mod common;
+
+use std::io;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use preferred_ip::socket2::Socket;
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, IpVersion, ProvidedSocket};
+
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+const BROADCAST: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 255);
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder().ipv4("192.168.77.1/24").route("default")
+}
+
+#[test]
+fn source_and_directed_broadcast() {
+    let found = common::run(env(), || {
+        (
+            IpQuery::new("veth0").ipv4_broadcast_source().unwrap(),
+            IpQuery::any_interface().ipv4_broadcast_source().unwrap(),
+        )
+    });
+    let Some((bound, any)) = found else { return };
+
+    assert_eq!(bound, (PRIVATE, BROADCAST));
+    assert_eq!(any, (PRIVATE, BROADCAST));
+}
+
+#[test]
+fn probe_sets_so_broadcast() {
+    common::run(env(), || {
+        // Connecting to a broadcast address requires SO_BROADCAST.
+        let plain = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let err = plain.connect((BROADCAST, 9)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let sockets = Arc::new(Mutex::new(Vec::new()));
+        let provided = sockets.clone();
+        let (source, _) = IpQuery::new("veth0")
+            .socket_factory(move |domain, ty| {
+                let socket = Socket::new(domain, ty, None)?;
+                provided.lock().unwrap().push(socket.try_clone()?);
+                Ok(ProvidedSocket::Unbound(socket))
+            })
+            .ipv4_broadcast_source()
+            .unwrap();
+        assert_eq!(source, PRIVATE);
+
+        // The directed broadcast succeeded, so there is no second probe.
+        let sockets = sockets.lock().unwrap();
+        assert_eq!(sockets.len(), 1);
+        assert!(sockets[0].broadcast().unwrap());
+    });
+}
+
+#[test]
+fn prefixes_without_broadcast_fail() {
+    for prefix in ["192.168.77.1/31", "192.168.77.1/32"] {
+        let env = NetEnv::builder().ipv4(prefix).route("default");
+        common::run(env, || {
+            let result = IpQuery::new("veth0").ipv4_broadcast_source();
+            assert!(
+                matches!(&result, Err(Error::NoBroadcast { interface }) if interface.as_deref() == Some("veth0")),
+                "{:?}",
+                result
+            );
+        });
+    }
+}
+
+#[test]
+fn interfaces_without_ipv4_fail() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+    common::run(env, || {
+        let result = IpQuery::new("veth0").ipv4_broadcast_source();
+        assert!(
+            matches!(
+                &result,
+                Err(Error::NoAddress { interface, family: IpVersion::V4 })
+                    if interface.as_deref() == Some("veth0")
+            ),
+            "{:?}",
+            result
+        );
+    });
+}
@@ -0,0 +1,122 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::{CachedQuery, Error, IpQuery, Ipv4Scope, ProvidedSocket, Scope};
+
+const LOOPBACK: Scope = Scope::V4(Ipv4Scope::Loopback);
+const THREADS: usize = 16;
+
+/// A factory that counts its calls and takes a while, so that all
+/// requests of a burst arrive while the first probe is in progress.
+/// Fails with the given error if there is one.
+fn slow_factory(
+    calls: Arc<AtomicUsize>,
+    fail: Option<fn() -> io::Error>,
+) -> impl Fn(Domain, Type) -> io::Result<ProvidedSocket> + Send + Sync + 'static {
+    move |domain, ty| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        match fail {
+            Some(err) => Err(err()),
+            None => Socket::new(domain, ty, None).map(ProvidedSocket::Unbound),
+        }
+    }
+}
+
+/// Let all threads request the scope at the same time.
+fn burst(cache: &CachedQuery<'_>) -> Vec<preferred_ip::Result<IpAddr>> {
+    let barrier = Barrier::new(THREADS);
+
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    barrier.wait();
+                    cache.get(LOOPBACK)
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+#[test]
+fn burst_shares_one_probe() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = CachedQuery::new(
+        IpQuery::any_interface()
+            .collect_stats(true)
+            .socket_factory(slow_factory(calls.clone(), None)),
+    );
+
+    let results = burst(&cache);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    for result in results {
+        assert_eq!(result.unwrap(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+
+    // Later requests are answered from the cache.
+    cache.get(LOOPBACK).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let snapshot = cache.stats();
+    let stats = snapshot.get(None, LOOPBACK).unwrap();
+    assert_eq!(stats.successes, 1);
+    assert_eq!(stats.cache_hits + stats.cache_misses, THREADS as u64 + 1);
+}
+
+#[test]
+fn burst_shares_the_error() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let fail = || io::Error::new(io::ErrorKind::ConnectionReset, "helper went away");
+    let cache = CachedQuery::new(
+        IpQuery::any_interface().socket_factory(slow_factory(calls.clone(), Some(fail))),
+    );
+
+    let results = burst(&cache);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Every caller gets the very same error, not a lossy copy.
+    let errors: Vec<_> = results
+        .into_iter()
+        .map(|result| match result {
+            Err(Error::SocketFactory(e)) => e,
+            result => panic!("{:?}", result),
+        })
+        .collect();
+    for e in &errors {
+        assert!(Arc::ptr_eq(e, &errors[0]));
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(e.to_string(), "helper went away");
+    }
+
+    // Errors aren't cached.
+    cache.get(LOOPBACK).unwrap_err();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn invalidation_during_a_burst() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = CachedQuery::new(
+        IpQuery::any_interface().socket_factory(slow_factory(calls.clone(), None)),
+    );
+
+    thread::scope(|s| {
+        let first = s.spawn(|| cache.get(LOOPBACK));
+        thread::sleep(Duration::from_millis(50));
+        cache.invalidate();
+        // Requests after the invalidation probe again.
+        let second = s.spawn(|| cache.get(LOOPBACK));
+
+        first.join().unwrap().unwrap();
+        second.join().unwrap().unwrap();
+    });
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,172 @@
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use std::process::Command;
+
+use preferred_ip::test_support::NetEnvBuilder;
+
+/// Run the closure in the environment,
+/// or return `None` if it can't be created on this system.
+pub fn run<T: Send>(env: NetEnvBuilder, f: impl FnOnce() -> T + Send) -> Option<T> {
+    match env.run(f) {
+        Ok(result) => Some(result),
+        Err(e) if e.is_unavailable() => {
+            eprintln!("skipping: {}", e);
+            None
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Run `ip` with the given arguments, panicking if it fails.
+pub fn ip(args: &str) {
+    let status = Command::new("ip").args(args.split(' ')).status().unwrap();
+    assert!(status.success(), "ip {}", args);
+}
+
+/// Remove all IPv6 addresses from the interface
+/// and keep the kernel from adding a link-local address again.
+/// This also removes the routes through the interface.
+pub fn flush_ipv6(interface: &str) {
+    ip(&format!("link set {} down", interface));
+    ip(&format!("link set {} addrgenmode none", interface));
+    ip(&format!("-6 addr flush dev {}", interface));
+    ip(&format!("link set {} up", interface));
+}
+
+/// Make `socket()` fail with the errno for IPv6, and for IPv4 if `v4`,
+/// on the current thread, like a seccomp sandbox does.
+/// Netlink and Unix sockets can still be created.
+pub fn block_inet_sockets(errno: i32, v4: bool) {
+    use libc::*;
+
+    let op = |code: u32, jt: u8, jf: u8, k: u32| sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    #[rustfmt::skip]
+    let filter = [
+        // The syscall number, then the low half of the first argument.
+        op(BPF_LD | BPF_W | BPF_ABS, 0, 0, 0),
+        op(BPF_JMP | BPF_JEQ | BPF_K, 0, 4, SYS_socket as u32),
+        op(BPF_LD | BPF_W | BPF_ABS, 0, 0, 16),
+        op(BPF_JMP | BPF_JEQ | BPF_K, 1, 0, AF_INET6 as u32),
+        op(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, if v4 { AF_INET as u32 } else { u32::MAX }),
+        op(BPF_RET | BPF_K, 0, 0, SECCOMP_RET_ERRNO | errno as u32),
+        op(BPF_RET | BPF_K, 0, 0, SECCOMP_RET_ALLOW),
+    ];
+    let prog = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut _,
+    };
+
+    // SAFETY: The filter outlives the call, which copies it.
+    unsafe {
+        assert_eq!(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0), 0);
+        assert_eq!(
+            prctl(
+                PR_SET_SECCOMP,
+                SECCOMP_MODE_FILTER,
+                &prog as *const sock_fprog
+            ),
+            0,
+            "{}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Read `IPV6_ADDR_PREFERENCES` of the socket.
+pub fn addr_preferences(socket: &preferred_ip::socket2::Socket) -> u32 {
+    let mut flags: libc::c_int = 0;
+    let mut len = std::mem::size_of_val(&flags) as libc::socklen_t;
+
+    // SAFETY: `flags` and `len` are valid for writes of their sizes.
+    let ret = unsafe {
+        libc::getsockopt(
+            std::os::fd::AsRawFd::as_raw_fd(socket),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_ADDR_PREFERENCES,
+            &mut flags as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0, "{}", std::io::Error::last_os_error());
+
+    flags as u32
+}
+
+/// A fake NetworkManager whose addresses can be changed at any time.
+/// Every interface is managed by it.
+#[cfg(feature = "networkmanager")]
+#[derive(Clone, Default)]
+pub struct FakeNm(std::sync::Arc<std::sync::Mutex<FakeNmState>>);
+
+#[cfg(feature = "networkmanager")]
+#[derive(Default)]
+struct FakeNmState {
+    ipv4: Vec<String>,
+    ipv6: Vec<String>,
+    lookups: usize,
+}
+
+#[cfg(feature = "networkmanager")]
+impl FakeNm {
+    pub fn new(ipv4: &[&str], ipv6: &[&str]) -> Self {
+        let nm = Self::default();
+        nm.set(ipv4, ipv6);
+        nm
+    }
+
+    /// Replace the addresses.
+    pub fn set(&self, ipv4: &[&str], ipv6: &[&str]) {
+        let mut state = self.0.lock().unwrap();
+        state.ipv4 = ipv4.iter().map(|&addr| addr.into()).collect();
+        state.ipv6 = ipv6.iter().map(|&addr| addr.into()).collect();
+    }
+
+    /// The number of times the addresses were looked up.
+    pub fn lookups(&self) -> usize {
+        self.0.lock().unwrap().lookups
+    }
+}
+
+#[cfg(feature = "networkmanager")]
+impl preferred_ip::networkmanager::NmBus for FakeNm {
+    fn device_by_ip_iface(&self, interface: &str) -> preferred_ip::Result<String> {
+        Ok(format!(
+            "/org/freedesktop/NetworkManager/Devices/{}",
+            interface
+        ))
+    }
+
+    fn primary_connection(&self) -> preferred_ip::Result<String> {
+        Ok("/org/freedesktop/NetworkManager/ActiveConnection/1".into())
+    }
+
+    fn ip_config(
+        &self,
+        _: &preferred_ip::networkmanager::NmObject,
+        version: preferred_ip::IpVersion,
+    ) -> preferred_ip::Result<String> {
+        Ok(format!(
+            "/org/freedesktop/NetworkManager/{}Config/1",
+            version
+        ))
+    }
+
+    fn address_data(
+        &self,
+        _: &str,
+        version: preferred_ip::IpVersion,
+    ) -> preferred_ip::Result<Vec<String>> {
+        let mut state = self.0.lock().unwrap();
+        state.lookups += 1;
+        Ok(match version {
+            preferred_ip::IpVersion::V4 => state.ipv4.clone(),
+            preferred_ip::IpVersion::V6 => state.ipv6.clone(),
+        })
+    }
+}
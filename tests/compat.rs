@@ -0,0 +1,88 @@
+// The functions under test are deprecated.
+#![allow(deprecated)]
+
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::compat::{self, Error};
+use preferred_ip::test_support::NetEnv;
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const ULA: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+#[test]
+fn addresses_of_each_scope() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        assert_eq!(compat::ipv6_unicast_global("veth0").unwrap(), GUA);
+        assert_eq!(compat::ipv6_unique_local("veth0").unwrap(), ULA);
+        assert_eq!(compat::ipv4_private("veth0").unwrap(), PRIVATE);
+
+        let err = compat::ipv4_global("veth0").unwrap_err();
+        assert!(matches!(err, Error::NoGlobal(PRIVATE)), "{:?}", err);
+        assert_eq!(
+            err.to_string(),
+            "ipv4 address 192.168.77.1 is not a global address"
+        );
+
+        let err = compat::ipv4_link_local("veth0").unwrap_err();
+        assert!(matches!(err, Error::NoV4LL(PRIVATE)), "{:?}", err);
+        assert_eq!(
+            err.to_string(),
+            "ipv4 address 192.168.77.1 is not a link-local address"
+        );
+    });
+}
+
+#[test]
+fn addresses_of_other_scopes_are_reported() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("100.64.0.1/10")
+        .route("default");
+
+    common::run(env, || {
+        let err = compat::ipv6_unicast_global("veth0").unwrap_err();
+        assert!(matches!(err, Error::NoGua(ULA)), "{:?}", err);
+        assert_eq!(err.to_string(), "ipv6 address fd00:dead::1 is not a gua");
+
+        let err = compat::ipv4_private("veth0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "none of 100.64.0.1, 100.64.0.1 and 100.64.0.1 are private ipv4"
+        );
+    });
+}
+
+#[test]
+fn kernel_errors_are_io_errors() {
+    // No IPv6 routes.
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        let err = compat::ipv6_unicast_global("nope0").unwrap_err();
+        assert!(
+            matches!(&err, Error::IoError(e) if e.raw_os_error() == Some(libc::ENODEV)),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "can't get ip address: io error: No such device (os error 19)"
+        );
+
+        // The error of the kernel is returned as is.
+        let err = compat::ipv6_unicast_global("veth0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't get ip address: io error: Network is unreachable (os error 101)"
+        );
+    });
+}
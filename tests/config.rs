@@ -0,0 +1,202 @@
+use preferred_ip::{Error, ErrorKind, IpQuery, ProbeConfig};
+
+const FULL_TOML: &str = r#"
+interface = "eth0"
+timeout-ms = 1500
+total-timeout-ms = 10000
+protocol = "tcp"
+backend = "socket"
+unmap-v4 = true
+deterministic = true
+verify-owner = true
+optimistic-dad = "accept-and-flag"
+min-preferred-lifetime-secs = 3600
+source-preferences = ["temporary", "home"]
+collect-stats = true
+"#;
+
+const FULL_JSON: &str = r#"{
+    "interface": "eth0",
+    "timeout-ms": 1500,
+    "total-timeout-ms": 10000,
+    "protocol": "tcp",
+    "backend": "socket",
+    "unmap-v4": true,
+    "deterministic": true,
+    "verify-owner": true,
+    "optimistic-dad": "accept-and-flag",
+    "min-preferred-lifetime-secs": 3600,
+    "source-preferences": ["temporary", "home"],
+    "collect-stats": true
+}"#;
+
+fn full() -> ProbeConfig {
+    ProbeConfig {
+        interface: Some("eth0".into()),
+        timeout_ms: Some(1500),
+        total_timeout_ms: Some(10_000),
+        protocol: Some("tcp".into()),
+        backend: Some("socket".into()),
+        unmap_v4: true,
+        deterministic: true,
+        verify_owner: true,
+        optimistic_dad: Some("accept-and-flag".into()),
+        min_preferred_lifetime_secs: Some(3600),
+        source_preferences: vec!["temporary".into(), "home".into()],
+        collect_stats: true,
+    }
+}
+
+fn invalid_config(config: &ProbeConfig) -> Vec<String> {
+    match config.validate() {
+        Err(Error::InvalidConfig(errors)) => errors,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn toml_round_trip() {
+    let config: ProbeConfig = toml::from_str(FULL_TOML).unwrap();
+    assert_eq!(config, full());
+
+    let serialized = toml::to_string(&config).unwrap();
+    assert_eq!(toml::from_str::<ProbeConfig>(&serialized).unwrap(), config);
+    config.validate().unwrap();
+}
+
+#[test]
+fn json_round_trip() {
+    let config: ProbeConfig = serde_json::from_str(FULL_JSON).unwrap();
+    assert_eq!(config, full());
+
+    let serialized = serde_json::to_string(&config).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ProbeConfig>(&serialized).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn everything_is_optional() {
+    let config: ProbeConfig = toml::from_str("").unwrap();
+    assert_eq!(config, ProbeConfig::default());
+    let config: ProbeConfig = serde_json::from_str("{}").unwrap();
+    assert_eq!(config, ProbeConfig::default());
+
+    let config: ProbeConfig = toml::from_str("interface = \"lo\"").unwrap();
+    assert_eq!(config.interface.as_deref(), Some("lo"));
+    assert!(!config.deterministic);
+    assert!(config.source_preferences.is_empty());
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let err = toml::from_str::<ProbeConfig>("fwmark = 3").unwrap_err();
+    assert!(
+        err.to_string().contains("unknown field `fwmark`"),
+        "{}",
+        err
+    );
+
+    // Fields are written in kebab case.
+    let err = serde_json::from_str::<ProbeConfig>(r#"{"timeout_ms": 10}"#).unwrap_err();
+    assert!(
+        err.to_string().contains("unknown field `timeout_ms`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn all_invalid_fields_are_reported() {
+    let config: ProbeConfig = toml::from_str(
+        r#"
+        interface = "a/b"
+        protocol = "sctp"
+        backend = "dhcp"
+        optimistic-dad = "maybe"
+        source-preferences = ["temporary", "stable", "care_of"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        invalid_config(&config),
+        [
+            r#"interface: invalid interface name "a/b": contains a path or alias separator"#,
+            "protocol: unknown protocol `sctp`",
+            "backend: unknown backend `dhcp`",
+            "optimistic-dad: unknown policy `maybe`",
+            "source-preferences: unknown preference `stable`",
+            "source-preferences: unknown preference `care_of`",
+        ]
+    );
+
+    let err = IpQuery::from_config(&config).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(
+        err.to_string()
+            .starts_with("invalid probe config: interface: invalid interface name"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn single_invalid_field() {
+    let config: ProbeConfig = serde_json::from_str(r#"{"protocol": "UDP"}"#).unwrap();
+    assert_eq!(
+        invalid_config(&config),
+        ["protocol: unknown protocol `UDP`"]
+    );
+}
+
+#[test]
+fn textual_forms() {
+    for protocol in ["udp", "tcp"] {
+        let config = ProbeConfig {
+            protocol: Some(protocol.into()),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+    }
+    for policy in ["reject", "accept", "accept-and-flag"] {
+        let config = ProbeConfig {
+            optimistic_dad: Some(policy.into()),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+    }
+    for backend in ["socket", "procfs"] {
+        let config = ProbeConfig {
+            backend: Some(backend.into()),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+    }
+
+    let config = ProbeConfig {
+        source_preferences: ["temporary", "public", "care-of", "home"]
+            .map(Into::into)
+            .to_vec(),
+        ..Default::default()
+    };
+    config.validate().unwrap();
+}
+
+#[test]
+fn network_manager_backend() {
+    let config = ProbeConfig {
+        backend: Some("network-manager".into()),
+        ..Default::default()
+    };
+
+    if cfg!(feature = "networkmanager") {
+        config.validate().unwrap();
+    } else {
+        assert_eq!(
+            invalid_config(&config),
+            ["backend: networkmanager support isn't enabled"]
+        );
+    }
+}
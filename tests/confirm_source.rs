@@ -0,0 +1,151 @@
+mod common;
+
+use std::io;
+use std::net::IpAddr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, IpVersion, ProvidedSocket};
+
+const SOURCE: &str = "2a01:4f8::1";
+const DEST: &str = "2a01:4f8:1::1";
+
+fn addr(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+#[test]
+fn assigned_source_is_confirmed() {
+    let result = common::run(env(), || {
+        let query = IpQuery::new("veth0");
+        (
+            query.confirm_source_route(addr(SOURCE), addr(DEST)),
+            query.confirm_source_route(addr("192.168.77.1"), addr("198.51.100.1")),
+            IpQuery::any_interface().confirm_source_route(addr(SOURCE), addr(DEST)),
+        )
+    });
+    let Some((ipv6, ipv4, any)) = result else {
+        return;
+    };
+
+    ipv6.unwrap();
+    ipv4.unwrap();
+    any.unwrap();
+}
+
+#[test]
+fn unassigned_source() {
+    let result = common::run(env(), || {
+        common::ip("-6 addr add 2a01:4f8:2::1/64 dev veth1 nodad");
+        (
+            // Assigned, but to another interface.
+            IpQuery::new("veth0").confirm_source_route(addr("2a01:4f8:2::1"), addr(DEST)),
+            // Not assigned at all, so binding fails.
+            IpQuery::any_interface().confirm_source_route(addr("2a01:4f8:3::1"), addr(DEST)),
+        )
+    });
+    let Some((other, none)) = result else { return };
+
+    assert!(
+        matches!(other, Err(Error::SourceNotAssigned { ref interface, source })
+            if interface.as_deref() == Some("veth0") && source == addr("2a01:4f8:2::1")),
+        "{:?}",
+        other
+    );
+    assert!(
+        matches!(none, Err(Error::SourceNotAssigned { interface: None, source })
+            if source == addr("2a01:4f8:3::1")),
+        "{:?}",
+        none
+    );
+}
+
+#[test]
+fn prohibit_route_is_prohibited() {
+    let result = common::run(env(), || {
+        common::ip("-6 route add prohibit 2a01:4f8:1::/48");
+        IpQuery::new("veth0").confirm_source_route(addr(SOURCE), addr(DEST))
+    });
+    let Some(result) = result else { return };
+
+    assert!(
+        matches!(result, Err(Error::Prohibited { source, dest, .. })
+            if source == addr(SOURCE) && dest == addr(DEST)),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn unreachable_route_has_no_source_route() {
+    let result = common::run(env(), || {
+        common::ip("-6 route add unreachable 2a01:4f8:1::/48");
+        common::ip("route add unreachable 198.51.100.0/24");
+        (
+            IpQuery::new("veth0").confirm_source_route(addr(SOURCE), addr(DEST)),
+            IpQuery::new("veth0").confirm_source_route(addr("192.168.77.1"), addr("198.51.100.1")),
+        )
+    });
+    let Some((ipv6, ipv4)) = result else { return };
+
+    assert!(
+        matches!(ipv6, Err(Error::NoSourceRoute { .. })),
+        "{:?}",
+        ipv6
+    );
+    assert!(
+        matches!(ipv4, Err(Error::NoSourceRoute { .. })),
+        "{:?}",
+        ipv4
+    );
+}
+
+#[test]
+fn route_through_another_interface() {
+    let result = common::run(env(), || {
+        common::ip("-6 route add 2a01:4f8:1::/48 dev veth1");
+        IpQuery::new("veth0").confirm_source_route(addr(SOURCE), addr(DEST))
+    });
+    let Some(result) = result else { return };
+
+    assert!(
+        matches!(result, Err(Error::NoSourceRoute { ref interface, .. })
+            if interface.as_deref() == Some("veth0")),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn factory_errors_are_kept() {
+    let result = common::run(env(), || {
+        IpQuery::new("veth0")
+            .socket_factory(|_, _| -> io::Result<ProvidedSocket> {
+                Err(io::Error::from_raw_os_error(libc::EACCES))
+            })
+            .confirm_source_route(addr(SOURCE), addr(DEST))
+    });
+    let Some(result) = result else { return };
+
+    assert!(
+        matches!(result, Err(Error::SocketFactory(ref e)) if e.raw_os_error() == Some(libc::EACCES)),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn wrong_family() {
+    let result = IpQuery::any_interface().confirm_source_route(addr("192.168.77.1"), addr(DEST));
+    assert!(
+        matches!(result, Err(Error::WrongIpVer(IpVersion::V6, source)) if source == addr("192.168.77.1")),
+        "{:?}",
+        result
+    );
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::test_support::{MockClock, NetEnv};
+use preferred_ip::{Backend, Clock, Error, IpQuery, Ipv4Scope, Ipv6Scope, ProvidedSocket, Scope};
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+fn index_of(interface: &str) -> u32 {
+    let interfaces = preferred_ip::interfaces().unwrap();
+    interfaces
+        .iter()
+        .find(|i| i.name == interface)
+        .unwrap()
+        .index
+}
+
+#[test]
+fn report_matches_the_getters() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0");
+        let report = query.get_all_diagnostic().unwrap();
+
+        assert_eq!(report.interface.as_deref(), Some("veth0"));
+        let scopes: Vec<_> = report.scopes.iter().map(|scope| scope.scope).collect();
+        assert_eq!(
+            scopes,
+            [
+                Scope::V6(Ipv6Scope::UnicastLinkLocal),
+                Scope::V6(Ipv6Scope::UniqueLocal),
+                Scope::V6(Ipv6Scope::UnicastGlobal),
+                Scope::V4(Ipv4Scope::LinkLocal),
+                Scope::V4(Ipv4Scope::Private),
+                Scope::V4(Ipv4Scope::Global),
+            ]
+        );
+
+        for scope in &report.scopes {
+            let expected = query.get(scope.scope).map_err(|e| e.to_string());
+            assert_eq!(scope.outcome, expected, "{}", scope.scope);
+            assert_eq!(scope.backend, Backend::Socket);
+            assert!(scope.fallbacks.is_empty());
+            assert!(!scope.probes.is_empty());
+        }
+    });
+}
+
+#[test]
+fn probes_are_reported_raw() {
+    common::run(env(), || {
+        let report = IpQuery::new("veth0").get_all_diagnostic().unwrap();
+        let probes = |scope| {
+            &report
+                .scopes
+                .iter()
+                .find(|diagnostic| diagnostic.scope == scope)
+                .unwrap()
+                .probes
+        };
+
+        for probe in probes(Scope::V6(Ipv6Scope::UniqueLocal)) {
+            let Ok(SocketAddr::V6(local)) = probe.local else {
+                panic!("{:?}", probe);
+            };
+            assert_eq!(local.ip().to_string(), "fd00:dead::1");
+            assert_ne!(local.port(), 0);
+            assert!(probe.dest.is_ipv6());
+        }
+
+        for probe in probes(Scope::V6(Ipv6Scope::UnicastLinkLocal)) {
+            let Ok(SocketAddr::V6(local)) = probe.local else {
+                panic!("{:?}", probe);
+            };
+            assert_eq!(local.scope_id(), index_of("veth0"));
+        }
+
+        // The kernel still picks the private address,
+        // only the classification rejects it.
+        for probe in probes(Scope::V4(Ipv4Scope::Global)) {
+            let local = probe.local.as_ref().unwrap();
+            assert_eq!(local.ip(), "192.168.77.1".parse::<IpAddr>().unwrap());
+            assert!(probe.dest.is_ipv4());
+        }
+    });
+}
+
+#[test]
+fn failures_are_reported_per_scope() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64");
+
+    common::run(env, || {
+        let report = IpQuery::new("veth0").get_all_diagnostic().unwrap();
+
+        for scope in &report.scopes {
+            match scope.scope {
+                // Without routes, the probes themselves fail.
+                Scope::V6(Ipv6Scope::UniqueLocal | Ipv6Scope::UnicastGlobal) => {
+                    let expected = scope.outcome.as_ref().unwrap_err();
+                    for probe in &scope.probes {
+                        assert_eq!(probe.local.as_ref(), Err(expected), "{:?}", probe);
+                    }
+                }
+                // Without an address, the probes either fail or
+                // report the unspecified address the kernel left.
+                Scope::V4(_) => {
+                    assert!(scope.outcome.is_err(), "{:?}", scope);
+                    for probe in &scope.probes {
+                        match &probe.local {
+                            Ok(local) => assert!(local.ip().is_unspecified(), "{:?}", probe),
+                            Err(e) => assert_eq!(Err(e), scope.outcome.as_ref(), "{:?}", probe),
+                        }
+                    }
+                }
+                Scope::V6(_) => assert!(scope.outcome.is_ok(), "{:?}", scope),
+            }
+        }
+    });
+}
+
+#[test]
+fn unbound_report_has_no_link_local_scope() {
+    common::run(env(), || {
+        let report = IpQuery::any_interface().get_all_diagnostic().unwrap();
+
+        assert_eq!(report.interface, None);
+        assert_eq!(report.scopes.len(), 5);
+        assert!(report
+            .scopes
+            .iter()
+            .all(|scope| scope.scope != Scope::V6(Ipv6Scope::UnicastLinkLocal)));
+    });
+}
+
+#[test]
+fn probe_durations_use_the_clock() {
+    common::run(env(), || {
+        let clock = MockClock::new();
+        let sleeper = clock.clone();
+
+        let report = IpQuery::new("veth0")
+            .clock(clock.clone())
+            .socket_factory(
+                move |domain: Domain, ty: Type| -> io::Result<ProvidedSocket> {
+                    sleeper.sleep(Duration::from_secs(1));
+                    Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+                },
+            )
+            .get_all_diagnostic()
+            .unwrap();
+
+        for probe in report.scopes.iter().flat_map(|scope| &scope.probes) {
+            assert_eq!(probe.duration, Duration::from_secs(1), "{:?}", probe);
+        }
+    });
+}
+
+#[test]
+fn expired_deadline_fails_the_report() {
+    let clock = MockClock::new();
+    let query = IpQuery::any_interface()
+        .clock(clock.clone())
+        .total_timeout(Duration::from_secs(1));
+    clock.advance(Duration::from_secs(1));
+
+    let result = query.get_all_diagnostic();
+    assert!(
+        matches!(
+            result,
+            Err(Error::Timeout {
+                operation: "diagnostic",
+                ..
+            })
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[cfg(feature = "networkmanager")]
+mod networkmanager {
+    use super::*;
+    use common::FakeNm;
+    use preferred_ip::FallbackKind;
+
+    fn report(query: IpQuery<'_>, scope: Scope) -> preferred_ip::ScopeDiagnostic {
+        let report = query.get_all_diagnostic().unwrap();
+        report
+            .scopes
+            .into_iter()
+            .find(|diagnostic| diagnostic.scope == scope)
+            .unwrap()
+    }
+
+    #[test]
+    fn outcomes_come_from_the_backend() {
+        let nm = FakeNm::new(&["93.184.216.34"], &["fd00::1", "2a01:4f8::1"]);
+        let query = IpQuery::new("eth0")
+            .backend(Backend::NetworkManager)
+            .networkmanager_bus(nm);
+
+        let gua = report(query.clone(), Scope::V6(Ipv6Scope::UnicastGlobal));
+        assert_eq!(gua.outcome, Ok("2a01:4f8::1".parse().unwrap()));
+        assert_eq!(gua.backend, Backend::NetworkManager);
+
+        let global = report(query, Scope::V4(Ipv4Scope::Global));
+        assert_eq!(global.outcome, Ok("93.184.216.34".parse().unwrap()));
+        assert_eq!(global.backend, Backend::NetworkManager);
+        assert!(global.fallbacks.is_empty());
+    }
+
+    #[test]
+    fn fallbacks_are_recorded() {
+        let nm = FakeNm::new(&["93.184.216.34"], &["::ffff:93.184.216.34"]);
+        let query = IpQuery::new("eth0")
+            .backend(Backend::NetworkManager)
+            .networkmanager_bus(nm.clone())
+            .unmap_v4(true);
+
+        let gua = report(query.clone(), Scope::V6(Ipv6Scope::UnicastGlobal));
+        assert_eq!(gua.outcome, Ok("93.184.216.34".parse().unwrap()));
+        assert_eq!(gua.fallbacks, [FallbackKind::UnmapV4]);
+        assert_eq!(gua.backend, Backend::NetworkManager);
+
+        nm.set(&[], &[]);
+        let gua = report(query, Scope::V6(Ipv6Scope::UnicastGlobal));
+        assert!(gua.outcome.is_err());
+        assert!(gua.fallbacks.is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{detect_dns64, parse_resolv_conf, Error, IpQuery};
+
+/// How the fake nameserver answers.
+#[derive(Clone, Copy)]
+enum Answer {
+    /// AAAA records for the well-known addresses in the prefix.
+    Synthesized(Ipv6Addr),
+    /// NXDOMAIN, as a resolver without DNS64 would.
+    NoSynthesis,
+    /// Nothing at all.
+    Silent,
+    /// SERVFAIL.
+    Failure,
+}
+
+/// Reply to the queries on the nameserver address with the answer.
+fn serve(addr: IpAddr, answer: Answer) {
+    let socket = UdpSocket::bind(SocketAddr::new(addr, 53)).unwrap();
+
+    thread::spawn(move || {
+        let mut buf = [0; 512];
+        while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+            let mut msg = buf[..len].to_vec();
+            msg[2] |= 0x80;
+            msg[3] = 0x80;
+
+            match answer {
+                Answer::Synthesized(prefix) => {
+                    msg[7] = 2;
+                    for last in [0xaa, 0xab] {
+                        let mut addr = prefix.octets();
+                        addr[12..].copy_from_slice(&[192, 0, 0, last]);
+
+                        msg.extend_from_slice(&[0xc0, 0x0c, 0, 28, 0, 1, 0, 0, 0, 60, 0, 16]);
+                        msg.extend_from_slice(&addr);
+                    }
+                }
+                Answer::NoSynthesis => msg[3] |= 3,
+                Answer::Silent => continue,
+                Answer::Failure => msg[3] |= 2,
+            }
+
+            socket.send_to(&msg, peer).unwrap();
+        }
+    });
+}
+
+/// Run the closure in a namespace where the first nameserver
+/// of `/etc/resolv.conf` is served by a fake one.
+fn with_nameserver<T: Send>(answer: Answer, f: impl FnOnce() -> T + Send) -> Option<T> {
+    let contents = fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+    let Some(&nameserver) = parse_resolv_conf(&contents).first() else {
+        eprintln!("skipping: no nameserver configured");
+        return None;
+    };
+
+    common::run(NetEnv::builder().route("default"), || {
+        match nameserver {
+            IpAddr::V4(ipv4) => common::ip(&format!("addr add {}/32 dev lo", ipv4)),
+            IpAddr::V6(ipv6) => common::ip(&format!("-6 addr add {}/128 dev lo nodad", ipv6)),
+        }
+        serve(nameserver, answer);
+
+        f()
+    })
+}
+
+#[test]
+fn detects_nat64_prefix() {
+    let prefix: Ipv6Addr = "64:ff9b::".parse().unwrap();
+    let detected = with_nameserver(Answer::Synthesized(prefix), || detect_dns64(None));
+
+    if let Some(detected) = detected {
+        assert_eq!(detected.unwrap(), Some(prefix));
+    }
+}
+
+#[test]
+fn detects_network_specific_prefix() {
+    let prefix: Ipv6Addr = "2001:db8:122:344::".parse().unwrap();
+    let detected = with_nameserver(Answer::Synthesized(prefix), || detect_dns64(None));
+
+    if let Some(detected) = detected {
+        assert_eq!(detected.unwrap(), Some(prefix));
+    }
+}
+
+#[test]
+fn no_synthesis_is_none() {
+    if let Some(detected) = with_nameserver(Answer::NoSynthesis, || detect_dns64(None)) {
+        assert_eq!(detected.unwrap(), None);
+    }
+}
+
+#[test]
+fn nameserver_failure_is_reported() {
+    if let Some(detected) = with_nameserver(Answer::Failure, || detect_dns64(None)) {
+        match detected {
+            Err(Error::IoError(e)) => assert_eq!(e.to_string(), "nameserver failed with rcode 2"),
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn silent_nameserver_times_out() {
+    let detected = with_nameserver(Answer::Silent, || {
+        IpQuery::any_interface()
+            .timeout(Duration::from_millis(100))
+            .detect_dns64()
+    });
+
+    if let Some(detected) = detected {
+        assert!(
+            matches!(
+                detected,
+                Err(Error::Timeout {
+                    operation: "dns64 query",
+                    ..
+                })
+            ),
+            "{:?}",
+            detected
+        );
+    }
+}
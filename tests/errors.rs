@@ -0,0 +1,186 @@
+mod common;
+
+use std::net::IpAddr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Backend, Error, IpQuery, IpVersion, Ipv4Scope, Ipv6Scope, Scope};
+
+fn is_no_address(result: &preferred_ip::Result<IpAddr>, expected: IpVersion) -> bool {
+    matches!(
+        result,
+        Err(Error::NoAddress { interface: Some(interface), family })
+            if interface == "veth0" && *family == expected
+    )
+}
+
+#[test]
+fn no_ipv4_address() {
+    let env = NetEnv::builder().route("default");
+
+    common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let results = [
+            query.ipv4_link_local().map(IpAddr::V4),
+            query.ipv4_private().map(IpAddr::V4),
+            query.ipv4_global().map(IpAddr::V4),
+            query
+                .ipv4_private_lenient()
+                .map(|lenient| lenient.addr.into()),
+            query.get(Scope::V4(Ipv4Scope::Private)),
+            query
+                .backend(Backend::Procfs)
+                .ipv4_private()
+                .map(IpAddr::V4),
+        ];
+
+        for result in &results {
+            assert!(is_no_address(result, IpVersion::V4), "{:?}", result);
+        }
+        assert_eq!(
+            results[0].as_ref().unwrap_err().to_string(),
+            "no usable IPv4 address on interface veth0"
+        );
+    });
+}
+
+#[test]
+fn no_ipv6_address() {
+    let env = NetEnv::builder().route("default");
+
+    common::run(env, || {
+        // The kernel would fall back to addresses of other interfaces.
+        common::flush_ipv6("veth0");
+        common::flush_ipv6("veth1");
+        common::ip("-6 addr flush dev lo");
+        common::ip("-6 route add default dev veth0");
+
+        let query = IpQuery::new("veth0");
+        let results = [
+            query.ipv6_unique_local().map(IpAddr::V6),
+            query.ipv6_unicast_global().map(IpAddr::V6),
+            query
+                .ipv6_unicast_global_lenient()
+                .map(|lenient| lenient.addr.into()),
+            query.get(Scope::V6(Ipv6Scope::UnicastGlobal)),
+            query
+                .backend(Backend::Procfs)
+                .ipv6_unique_local()
+                .map(IpAddr::V6),
+        ];
+
+        for result in &results {
+            assert!(is_no_address(result, IpVersion::V6), "{:?}", result);
+        }
+        assert_eq!(
+            results[0].as_ref().unwrap_err().to_string(),
+            "no usable IPv6 address on interface veth0"
+        );
+    });
+}
+
+#[test]
+fn get_all_skips_missing_families() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    let report = common::run(env, || IpQuery::new("veth0").get_all().unwrap());
+    let Some(report) = report else { return };
+
+    assert!(report.ipv6_unique_local.is_some());
+    assert_eq!(report.ipv4_private, None);
+    assert_eq!(report.ipv4_link_local, None);
+}
+
+fn no_route_towards(result: &preferred_ip::Result<IpAddr>) -> Option<IpAddr> {
+    match result {
+        Err(Error::NoRoute { dest, .. }) => Some(*dest),
+        _ => None,
+    }
+}
+
+#[test]
+fn no_route() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        let query = IpQuery::new("veth0");
+
+        let gua = query.ipv6_unicast_global().map(IpAddr::V6);
+        assert_eq!(no_route_towards(&gua), Some("2000::".parse().unwrap()));
+        // IPv4 destinations are assumed to be on-link
+        // if there is no route on the bound interface.
+        let private = IpQuery::any_interface().ipv4_private().map(IpAddr::V4);
+        assert_eq!(
+            no_route_towards(&private),
+            Some("10.0.0.0".parse().unwrap())
+        );
+
+        let err = gua.unwrap_err();
+        assert_eq!(err.kind(), preferred_ip::ErrorKind::NoRoute);
+        assert_eq!(
+            err.to_string(),
+            "no route towards probe network 2000:: on interface veth0"
+        );
+    });
+}
+
+#[test]
+fn unreachable_route() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        common::ip("-6 route add unreachable default");
+        common::ip("-4 route add unreachable default");
+        let query = IpQuery::any_interface();
+
+        let gua = query.ipv6_unicast_global().map(IpAddr::V6);
+        assert!(matches!(gua, Err(Error::NoRoute { .. })), "{:?}", gua);
+        let private = query.ipv4_private().map(IpAddr::V4);
+        assert!(
+            matches!(private, Err(Error::NoRoute { .. })),
+            "{:?}",
+            private
+        );
+    });
+}
+
+#[test]
+fn other_connect_errors_are_kept() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        common::ip("-4 route add prohibit default");
+
+        // Without a fixed backend, the query would fall back to procfs.
+        let result = IpQuery::any_interface()
+            .backend(Backend::Socket)
+            .ipv4_private();
+        assert!(matches!(result, Err(Error::IoError(_))), "{:?}", result);
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::EACCES));
+    });
+}
+
+#[test]
+fn real_errors_are_classified() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        common::ip("-4 route add unreachable default");
+        let query = IpQuery::any_interface().backend(Backend::Socket);
+
+        let unreachable = query.ipv4_private().unwrap_err();
+        assert!(unreachable.is_transient(), "{:?}", unreachable);
+
+        let missing = IpQuery::new("nonexistent0").ipv4_private().unwrap_err();
+        assert_eq!(missing.raw_os_error(), Some(libc::ENODEV));
+        assert!(!missing.is_transient());
+
+        common::ip("-4 route replace prohibit default");
+        let prohibited = query.ipv4_private().unwrap_err();
+        assert_eq!(prohibited.raw_os_error(), Some(libc::EACCES));
+        assert!(!prohibited.is_transient());
+    });
+}
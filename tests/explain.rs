@@ -0,0 +1,152 @@
+mod common;
+
+use std::fs;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Explanation, IpQuery, Ipv4Scope, Ipv6Scope, Scope};
+
+const GUA: Scope = Scope::V6(Ipv6Scope::UnicastGlobal);
+
+/// Run the query on an interface with a stable, a deprecated,
+/// a tentative and a newer stable GUA, which the kernel prefers
+/// and which is only preferred for an hour, as well as a ULA
+/// and two private IPv4 addresses.
+fn explain(
+    query: impl FnOnce() -> IpQuery<'static> + Send,
+    scope: Scope,
+) -> Option<(IpAddr, Explanation)> {
+    let env = NetEnv::builder().route("default");
+
+    common::run(env, || {
+        common::flush_ipv6("veth0");
+        common::ip("-6 addr add 2a01:4f8::3/64 dev veth0 nodad");
+        common::ip("-6 addr add 2a01:4f8::1/64 dev veth0 nodad preferred_lft 0");
+        common::ip("-6 addr add fd00::1/64 dev veth0 nodad");
+        common::ip("-6 addr add 2a01:4f8::5/64 dev veth0 nodad preferred_lft 3600 valid_lft 7200");
+        fs::write("/proc/sys/net/ipv6/conf/veth0/accept_dad", "1").unwrap();
+        fs::write("/proc/sys/net/ipv6/conf/veth0/dad_transmits", "100").unwrap();
+        common::ip("-6 addr add 2a01:4f8::4/64 dev veth0");
+        common::ip("-6 route add default dev veth0");
+        common::ip("addr add 192.168.77.2/24 dev veth0");
+        common::ip("addr add 192.168.77.1/24 dev veth0");
+        common::ip("route add default dev veth0");
+
+        query().get_explained(scope).unwrap()
+    })
+}
+
+#[test]
+fn probe_result_is_used_as_is() {
+    let Some((addr, explanation)) = explain(|| IpQuery::new("veth0"), GUA) else {
+        return;
+    };
+
+    assert_eq!(addr, "2a01:4f8::5".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        explanation.to_string(),
+        "\
+ipv6-gua on interface veth0:
+  probe towards 2000::: kernel chose 2a01:4f8::5
+  result: 2a01:4f8::5
+"
+    );
+}
+
+#[test]
+fn deterministic_lists_every_candidate() {
+    let query = || IpQuery::new("veth0").deterministic(true);
+    let Some((addr, explanation)) = explain(query, GUA) else {
+        return;
+    };
+
+    assert_eq!(addr, "2a01:4f8::3".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        explanation.to_string(),
+        "\
+ipv6-gua on interface veth0:
+  probe towards 2000::: kernel chose 2a01:4f8::5
+  candidate 192.168.77.2: rejected, out of scope
+  candidate 192.168.77.1: rejected, out of scope
+  candidate 2a01:4f8::4: rejected, tentative
+  candidate 2a01:4f8::5: accepted (preferred, stable)
+  candidate fd00::1: rejected, out of scope
+  candidate 2a01:4f8::1: accepted (deprecated, stable)
+  candidate 2a01:4f8::3: accepted (preferred, stable)
+  chose 2a01:4f8::3 (preferred, stable)
+  result: 2a01:4f8::3
+"
+    );
+}
+
+#[test]
+fn short_lifetime_replaces_the_probe_result() {
+    let query = || IpQuery::new("veth0").min_preferred_lifetime(Duration::from_secs(7200));
+    let Some((addr, explanation)) = explain(query, GUA) else {
+        return;
+    };
+
+    // The remaining lifetime may have ticked since the address was added.
+    let rendered = explanation.to_string().replace("3599s", "3600s");
+    assert_eq!(addr, "2a01:4f8::3".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        rendered,
+        "\
+ipv6-gua on interface veth0:
+  probe towards 2000::: kernel chose 2a01:4f8::5
+  fallback: ShortLifetime
+  candidate 192.168.77.2: rejected, out of scope
+  candidate 192.168.77.1: rejected, out of scope
+  candidate 2a01:4f8::4: rejected, tentative
+  candidate 2a01:4f8::5: rejected, only preferred for 3600s
+  candidate fd00::1: rejected, out of scope
+  candidate 2a01:4f8::1: rejected, only preferred for 0ns
+  candidate 2a01:4f8::3: accepted (preferred, stable)
+  chose 2a01:4f8::3 (preferred, stable)
+  result: 2a01:4f8::3
+"
+    );
+}
+
+#[test]
+fn ipv4_private_probes_each_range() {
+    let Some((addr, explanation)) =
+        explain(|| IpQuery::new("veth0"), Scope::V4(Ipv4Scope::Private))
+    else {
+        return;
+    };
+
+    assert_eq!(addr, "192.168.77.2".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        explanation.to_string(),
+        "\
+ipv4-private on interface veth0:
+  probe towards 10.0.0.0: kernel chose 192.168.77.2
+  probe towards 172.16.0.0: kernel chose 192.168.77.2
+  probe towards 192.168.0.0: kernel chose 192.168.77.2
+  result: 192.168.77.2
+"
+    );
+}
+
+#[cfg(feature = "networkmanager")]
+#[test]
+fn networkmanager_candidates_have_no_rank() {
+    let nm = common::FakeNm::new(&["93.184.216.34"], &["fd00::1", "2a01:4f8::1"]);
+    let query = IpQuery::new("eth0")
+        .backend(preferred_ip::Backend::NetworkManager)
+        .networkmanager_bus(nm);
+
+    // The backend doesn't report the properties of the addresses.
+    let (addr, explanation) = query.get_explained(GUA).unwrap();
+    assert_eq!(addr, "2a01:4f8::1".parse::<IpAddr>().unwrap());
+    assert_eq!(
+        explanation.to_string(),
+        "\
+ipv6-gua on interface eth0:
+  chose 2a01:4f8::1
+  result: 2a01:4f8::1
+"
+    );
+}
@@ -0,0 +1,302 @@
+mod common;
+
+use std::io;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, ProbeProtocol, ProvidedSocket, SourcePreference};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const OTHER_GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 1, 0, 0, 0, 0, 1);
+
+/// What the factory did before handing out a socket.
+#[derive(Clone, Copy)]
+enum Mode {
+    Unbound,
+    /// Bind to `veth1` and report the socket as bound.
+    Bound,
+    /// Bind to `veth1`, but report the socket as unbound.
+    Prebound,
+}
+
+/// A factory that records the requested sockets
+/// and keeps a handle to each of them for inspection.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<(Domain, Type, Socket)>>>);
+
+impl Recorder {
+    fn factory(
+        &self,
+        mode: Mode,
+    ) -> impl Fn(Domain, Type) -> io::Result<ProvidedSocket> + Send + Sync + 'static {
+        let recorded = self.0.clone();
+
+        move |domain, ty| {
+            let socket = Socket::new(domain, ty, None)?;
+            // An option the query doesn't touch, to check it's kept.
+            if domain == Domain::IPV6 {
+                socket.set_unicast_hops_v6(7)?;
+            }
+            if let Mode::Bound | Mode::Prebound = mode {
+                socket.bind_device(Some(b"veth1"))?;
+            }
+
+            recorded
+                .lock()
+                .unwrap()
+                .push((domain, ty, socket.try_clone()?));
+
+            Ok(match mode {
+                Mode::Bound => ProvidedSocket::Bound(socket),
+                Mode::Unbound | Mode::Prebound => ProvidedSocket::Unbound(socket),
+            })
+        }
+    }
+
+    /// Take the only recorded socket.
+    fn single(&self) -> (Domain, Type, Socket) {
+        let mut recorded = self.0.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        recorded.pop().unwrap()
+    }
+}
+
+/// An environment with a GUA on either interface
+/// and a less preferred default route through `veth1`.
+fn two_interfaces() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+fn add_other_gua() {
+    common::ip("-6 addr add 2a01:4f8:1::1/64 dev veth1 nodad");
+    common::ip("-6 route add default dev veth1 metric 2048");
+}
+
+#[test]
+fn query_sets_up_unbound_sockets() {
+    common::run(two_interfaces(), || {
+        let recorder = Recorder::default();
+        let addr = IpQuery::new("veth0")
+            .socket_factory(recorder.factory(Mode::Unbound))
+            .ipv6_unicast_global()
+            .unwrap();
+        assert_eq!(addr, GUA);
+
+        let (domain, ty, socket) = recorder.single();
+        assert_eq!((domain, ty), (Domain::IPV6, Type::DGRAM));
+        assert_eq!(socket.device().unwrap().as_deref(), Some(&b"veth0"[..]));
+        assert!(socket.only_v6().unwrap());
+        // Connected by the query, which assigned the source address.
+        let local = socket.local_addr().unwrap().as_socket().unwrap();
+        assert_eq!(local.ip(), GUA);
+        assert!(!socket.nonblocking().unwrap());
+        // Options of the factory are kept.
+        assert_eq!(socket.unicast_hops_v6().unwrap(), 7);
+    });
+}
+
+#[test]
+fn tcp_probes_request_stream_sockets() {
+    common::run(two_interfaces(), || {
+        let recorder = Recorder::default();
+        let addr = IpQuery::new("veth0")
+            .protocol(ProbeProtocol::Tcp)
+            .socket_factory(recorder.factory(Mode::Unbound))
+            .ipv6_unicast_global()
+            .unwrap();
+        assert_eq!(addr, GUA);
+
+        let (domain, ty, socket) = recorder.single();
+        assert_eq!((domain, ty), (Domain::IPV6, Type::STREAM));
+        assert!(socket.nonblocking().unwrap());
+    });
+}
+
+#[test]
+fn ipv4_probes_request_ipv4_sockets() {
+    common::run(two_interfaces(), || {
+        let recorder = Recorder::default();
+        IpQuery::new("veth0")
+            .socket_factory(recorder.factory(Mode::Unbound))
+            .ipv4_loopback()
+            .unwrap_err();
+
+        let (domain, ty, _) = recorder.single();
+        assert_eq!((domain, ty), (Domain::IPV4, Type::DGRAM));
+    });
+}
+
+#[test]
+fn bound_sockets_keep_their_interface() {
+    common::run(two_interfaces(), || {
+        add_other_gua();
+
+        let recorder = Recorder::default();
+        let addr = IpQuery::new("veth0")
+            .socket_factory(recorder.factory(Mode::Bound))
+            .ipv6_unicast_global()
+            .unwrap();
+        assert_eq!(addr, OTHER_GUA);
+
+        let (.., socket) = recorder.single();
+        assert_eq!(socket.device().unwrap().as_deref(), Some(&b"veth1"[..]));
+        assert!(socket.only_v6().unwrap());
+    });
+}
+
+#[test]
+fn unbound_sockets_are_rebound() {
+    common::run(two_interfaces(), || {
+        add_other_gua();
+
+        // A pooled socket that was last used for another interface.
+        let recorder = Recorder::default();
+        let addr = IpQuery::new("veth0")
+            .socket_factory(recorder.factory(Mode::Prebound))
+            .ipv6_unicast_global()
+            .unwrap();
+        assert_eq!(addr, GUA);
+
+        let (.., socket) = recorder.single();
+        assert_eq!(socket.device().unwrap().as_deref(), Some(&b"veth0"[..]));
+    });
+}
+
+#[test]
+fn unbound_sockets_are_unbound_for_any_interface() {
+    common::run(two_interfaces(), || {
+        add_other_gua();
+
+        let recorder = Recorder::default();
+        let addr = IpQuery::any_interface()
+            .socket_factory(recorder.factory(Mode::Prebound))
+            .ipv6_unicast_global()
+            .unwrap();
+        assert_eq!(addr, GUA);
+
+        let (.., socket) = recorder.single();
+        assert_eq!(socket.device().unwrap(), None);
+    });
+}
+
+#[test]
+fn factory_errors_are_reported() {
+    common::run(two_interfaces(), || {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+
+        let result = IpQuery::new("veth0")
+            .socket_factory(move |_, _| -> io::Result<ProvidedSocket> {
+                *counted.lock().unwrap() += 1;
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            })
+            .ipv6_unicast_global();
+
+        match result {
+            Err(e @ Error::SocketFactory(_)) => {
+                assert_eq!(e.to_string(), "socket factory failed: permission denied");
+                assert!(!e.is_transient());
+            }
+            other => panic!("{:?}", other),
+        }
+        // The query neither retries nor creates a socket of its own.
+        assert_eq!(*calls.lock().unwrap(), 1);
+    });
+}
+
+/// A pool of IPv6 sockets that are handed out to one query at a time.
+#[derive(Default)]
+struct Pool {
+    idle: Mutex<Vec<Socket>>,
+    returned: Condvar,
+}
+
+impl Pool {
+    fn take(&self) -> Socket {
+        let idle = self.idle.lock().unwrap();
+        let mut idle = self
+            .returned
+            .wait_while(idle, |idle| idle.is_empty())
+            .unwrap();
+        idle.pop().unwrap()
+    }
+
+    fn put(&self, socket: Socket) {
+        self.idle.lock().unwrap().push(socket);
+        self.returned.notify_one();
+    }
+}
+
+#[test]
+fn pooled_sockets_keep_no_options_of_other_queries() {
+    use SourcePreference::*;
+
+    const DEFAULT: u32 = libc::IPV6_PREFER_SRC_PUBTMP_DEFAULT as u32;
+    const HOME: u32 = libc::IPV6_PREFER_SRC_HOME as u32;
+    const TMP: u32 = libc::IPV6_PREFER_SRC_TMP as u32;
+    const PUBLIC: u32 = libc::IPV6_PREFER_SRC_PUBLIC as u32;
+    const COA: u32 = libc::IPV6_PREFER_SRC_COA as u32;
+
+    #[rustfmt::skip]
+    let configs: [(Option<&str>, &[SourcePreference], u32); 4] = [
+        (Some("veth0"), &[Temporary], TMP | HOME),
+        (None, &[], DEFAULT | HOME),
+        (Some("veth0"), &[Public, CareOf], PUBLIC | COA),
+        (None, &[CareOf], DEFAULT | COA),
+    ];
+
+    common::run(two_interfaces(), || {
+        // Fewer sockets than threads, so that they move between
+        // differently configured queries.
+        let pool = Pool::default();
+        for _ in 0..2 {
+            pool.put(Socket::new(Domain::IPV6, Type::DGRAM, None).unwrap());
+        }
+
+        thread::scope(|s| {
+            for (interface, preferences, expected) in configs {
+                let pool = &pool;
+                s.spawn(move || {
+                    for _ in 0..50 {
+                        let socket = pool.take();
+                        let provided = socket.try_clone().unwrap();
+                        let provided = Mutex::new(Some(provided));
+
+                        let query = match interface {
+                            Some(interface) => IpQuery::new(interface),
+                            None => IpQuery::any_interface(),
+                        };
+                        let addr = preferences
+                            .iter()
+                            .fold(query, |query, &preference| query.prefer_source(preference))
+                            .socket_factory(move |domain, ty| {
+                                assert_eq!((domain, ty), (Domain::IPV6, Type::DGRAM));
+                                let socket = provided.lock().unwrap().take().unwrap();
+                                Ok(ProvidedSocket::Unbound(socket))
+                            })
+                            .ipv6_unicast_global()
+                            .unwrap();
+                        assert_eq!(addr, GUA);
+
+                        let device = socket.device().unwrap();
+                        assert_eq!(device.as_deref(), interface.map(str::as_bytes));
+                        assert_eq!(
+                            common::addr_preferences(&socket),
+                            expected,
+                            "{:?} {:?}",
+                            interface,
+                            preferences
+                        );
+                        pool.put(socket);
+                    }
+                });
+            }
+        });
+    });
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::io;
+use std::net::Ipv4Addr;
+
+use preferred_ip::socket2::{Domain, Socket};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{
+    Connectivity, Error, ErrorKind, IpQuery, IpVersion, Ipv4Scope, Ipv6Scope, ProvidedSocket,
+};
+
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+fn is_disabled<T>(result: &preferred_ip::Result<T>, family: IpVersion) -> bool {
+    matches!(result, Err(Error::FamilyDisabled(disabled)) if *disabled == family)
+}
+
+/// Check the getters of a query on a system without IPv6.
+fn ipv6_is_disabled(query: IpQuery) {
+    for scope in [
+        Ipv6Scope::UnicastLinkLocal,
+        Ipv6Scope::UniqueLocal,
+        Ipv6Scope::UnicastGlobal,
+    ] {
+        let result = query.ipv6(scope);
+        assert!(is_disabled(&result, IpVersion::V6), "{:?}", result);
+    }
+    let err = query.ipv6_unicast_global().unwrap_err();
+    assert_eq!(ErrorKind::from(&err), ErrorKind::NoAddress);
+    assert_eq!(err.to_string(), "IPv6 is disabled on this system");
+
+    assert_eq!(query.ipv4_private().unwrap(), PRIVATE);
+    assert!(is_disabled(
+        &query.ipv6_first_of(&[Ipv6Scope::UnicastGlobal, Ipv6Scope::UniqueLocal]),
+        IpVersion::V6
+    ));
+    assert_eq!(
+        query
+            .ipv4_first_of(&[Ipv4Scope::Global, Ipv4Scope::Private])
+            .unwrap(),
+        (Ipv4Scope::Private, PRIVATE)
+    );
+
+    let report = query.get_all().unwrap();
+    assert_eq!(report.ipv6_unicast_link_local, None);
+    assert_eq!(report.ipv6_unique_local, None);
+    assert_eq!(report.ipv6_unicast_global, None);
+    assert_eq!(report.ipv4_private, Some(PRIVATE));
+    assert!(!report.incomplete);
+    assert_eq!(query.connectivity().unwrap(), Connectivity::V4Only);
+}
+
+#[test]
+fn disabled_ipv6_degrades_to_ipv4() {
+    common::run(env(), || {
+        common::block_inet_sockets(libc::EAFNOSUPPORT, false);
+        ipv6_is_disabled(IpQuery::new("veth0"));
+    });
+}
+
+#[test]
+fn disabled_ipv6_of_a_factory_degrades_to_ipv4() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0").socket_factory(|domain, ty| {
+            if domain == Domain::IPV6 {
+                return Err(io::Error::from_raw_os_error(libc::EAFNOSUPPORT));
+            }
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        });
+        ipv6_is_disabled(query);
+    });
+}
+
+#[test]
+fn disabled_families_fail_composites_only_if_all_are() {
+    common::run(env(), || {
+        common::block_inet_sockets(libc::EAFNOSUPPORT, true);
+        let query = IpQuery::new("veth0");
+
+        assert!(is_disabled(&query.ipv4_private(), IpVersion::V4));
+        assert!(is_disabled(&query.ipv4_global(), IpVersion::V4));
+        assert_eq!(query.connectivity().unwrap(), Connectivity::None);
+    });
+}
+
+#[test]
+fn availability_is_checked_once() {
+    let checked = common::run(env(), || {
+        common::block_inet_sockets(libc::EAFNOSUPPORT, false);
+        (
+            preferred_ip::ipv6_available(),
+            preferred_ip::ipv4_available(),
+        )
+    });
+    let Some((ipv6, ipv4)) = checked else { return };
+    assert!(!ipv6);
+    assert!(ipv4);
+
+    // This thread can create IPv6 sockets, but the first answer is kept.
+    Socket::new(Domain::IPV6, preferred_ip::socket2::Type::DGRAM, None).unwrap();
+    assert!(!preferred_ip::ipv6_available());
+}
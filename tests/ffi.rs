@@ -0,0 +1,44 @@
+mod common;
+
+use preferred_ip::ffi::{self, FfiAddressReport};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{AddressReport, ErrorKind};
+
+#[test]
+fn bindings_match_getters() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    let Some((report, ffi_report, getters)) = common::run(env, || {
+        let interface = String::from("veth0");
+        (
+            preferred_ip::get_all(Some("veth0")).unwrap(),
+            ffi::get_all(Some(interface.clone())).unwrap(),
+            [
+                ffi::ipv6_unicast_link_local(interface.clone()),
+                ffi::ipv6_unique_local(interface.clone()),
+                ffi::ipv6_unicast_global(interface.clone()),
+                ffi::ipv4_link_local(interface.clone()),
+                ffi::ipv4_private(interface.clone()),
+                ffi::ipv4_global(interface),
+            ],
+        )
+    }) else {
+        return;
+    };
+
+    assert_eq!(ffi_report, FfiAddressReport::from(report));
+    assert_eq!(AddressReport::try_from(&ffi_report).unwrap(), report);
+
+    let [link_local, ula, gua, ipv4_link_local, private, global] = getters;
+    assert_eq!(link_local.ok(), ffi_report.ipv6_unicast_link_local);
+    assert_eq!(ula.unwrap(), "fd00:dead::1");
+    assert_eq!(gua.unwrap(), "2a01:4f8::1");
+    assert_eq!(private.unwrap(), "192.168.77.1");
+    // The private address is the kernel's choice towards global destinations.
+    assert_eq!(ipv4_link_local.unwrap_err().kind(), ErrorKind::WrongScope);
+    assert_eq!(global.unwrap_err().kind(), ErrorKind::WrongScope);
+}
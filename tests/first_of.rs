@@ -0,0 +1,60 @@
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, Ipv4Scope, Ipv6Scope};
+
+const IPV6_FALLBACK: [Ipv6Scope; 3] = [
+    Ipv6Scope::UnicastGlobal,
+    Ipv6Scope::UniqueLocal,
+    Ipv6Scope::UnicastLinkLocal,
+];
+const IPV4_FALLBACK: [Ipv4Scope; 3] = [Ipv4Scope::Global, Ipv4Scope::Private, Ipv4Scope::LinkLocal];
+
+#[test]
+fn falls_back_to_next_scope() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    let Some((ipv6, ipv4)) = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        (
+            query.ipv6_first_of(&IPV6_FALLBACK).unwrap(),
+            query.ipv4_first_of(&IPV4_FALLBACK).unwrap(),
+        )
+    }) else {
+        return;
+    };
+
+    assert_eq!(
+        ipv6,
+        (
+            Ipv6Scope::UniqueLocal,
+            "fd00:dead::1".parse::<Ipv6Addr>().unwrap()
+        )
+    );
+    assert_eq!(ipv4, (Ipv4Scope::Private, Ipv4Addr::new(192, 168, 77, 1)));
+}
+
+#[test]
+fn missing_interface_aborts() {
+    let result = IpQuery::new("nonexistent0").ipv6_first_of(&IPV6_FALLBACK);
+    let err = result.unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENODEV), "{:?}", err);
+
+    let result = IpQuery::new("nonexistent0").ipv4_first_of(&IPV4_FALLBACK);
+    let err = result.unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENODEV), "{:?}", err);
+}
+
+#[test]
+fn empty_scopes_are_rejected() {
+    let result = preferred_ip::ipv6_first_of("lo", &[]);
+    assert!(matches!(result, Err(Error::NoScopes)), "{:?}", result);
+
+    let result = preferred_ip::ipv4_first_of("lo", &[]);
+    assert!(matches!(result, Err(Error::NoScopes)), "{:?}", result);
+}
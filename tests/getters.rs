@@ -0,0 +1,183 @@
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+use preferred_ip::socket2::Socket;
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Connectivity, Error, IpQuery, ProvidedSocket, SourcePreference};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const ULA: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+const CGNAT: Ipv4Addr = Ipv4Addr::new(100, 64, 0, 1);
+
+#[test]
+fn lenient_agrees_with_strict_on_matches() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        let query = IpQuery::new("veth0");
+
+        let gua = query.ipv6_unicast_global_lenient().unwrap();
+        assert_eq!((gua.addr, gua.classified), (GUA, true));
+        assert_eq!(query.ipv6_unicast_global().unwrap(), GUA);
+
+        let ula = query.ipv6_unique_local_lenient().unwrap();
+        assert_eq!((ula.addr, ula.classified), (ULA, true));
+        assert_eq!(query.ipv6_unique_local().unwrap(), ULA);
+
+        let private = query.ipv4_private_lenient().unwrap();
+        assert_eq!((private.addr, private.classified), (PRIVATE, true));
+        assert_eq!(query.ipv4_private().unwrap(), PRIVATE);
+
+        let link_local = query.ipv6_unicast_link_local_lenient().unwrap();
+        assert!(link_local.classified);
+        assert_eq!(query.ipv6_unicast_link_local().unwrap(), link_local.addr);
+    });
+}
+
+#[test]
+fn lenient_diverges_from_strict_on_mismatches() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("100.64.0.1/10")
+        .route("default");
+
+    common::run(env, || {
+        let query = IpQuery::new("veth0");
+
+        let gua = query.ipv6_unicast_global_lenient().unwrap();
+        assert_eq!((gua.addr, gua.classified), (ULA, false));
+        assert!(matches!(
+            query.ipv6_unicast_global(),
+            Err(Error::NoGua(ULA))
+        ));
+
+        let global = query.ipv4_global_lenient().unwrap();
+        assert_eq!((global.addr, global.classified), (CGNAT, false));
+        assert!(matches!(query.ipv4_global(), Err(Error::NoGlobal(CGNAT))));
+    });
+}
+
+#[test]
+fn connectivity() {
+    let dual_stack = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+    let v6_only = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+    let link_local_only = NetEnv::builder().route("default");
+
+    for (env, expected) in [
+        (dual_stack, Connectivity::DualStack),
+        (v6_only, Connectivity::V6Only),
+        (link_local_only, Connectivity::None),
+    ] {
+        let connectivity = common::run(env, || preferred_ip::connectivity(Some("veth0")));
+        if let Some(connectivity) = connectivity {
+            assert_eq!(connectivity.unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn ipv6_probes_are_v6_only() {
+    let sockets = Arc::new(Mutex::new(Vec::new()));
+    let recorded = sockets.clone();
+
+    let result = IpQuery::any_interface()
+        .socket_factory(move |domain, ty| {
+            let socket = Socket::new(domain, ty, None)?;
+            recorded.lock().unwrap().push(socket.try_clone()?);
+            Ok(ProvidedSocket::Unbound(socket))
+        })
+        .ipv6_loopback();
+    assert_eq!(result.unwrap(), Ipv6Addr::LOCALHOST);
+
+    let sockets = sockets.lock().unwrap();
+    assert_eq!(sockets.len(), 1);
+    assert!(sockets[0].only_v6().unwrap());
+}
+
+#[test]
+fn deterministic_selects_lowest_address() {
+    // The kernel prefers the primary IPv4 address, which was added first.
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::20/64")
+        .ipv6("2a01:4f8::3/64")
+        .ipv4("192.168.77.20/24")
+        .ipv4("192.168.77.3/24")
+        .route("default");
+
+    let Some((kernel, deterministic)) = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let kernel = (
+            query.ipv6_unicast_global().unwrap(),
+            query.ipv4_private().unwrap(),
+        );
+
+        let query = query.deterministic(true);
+        let deterministic = (
+            query.ipv6_unicast_global().unwrap(),
+            query.ipv4_private().unwrap(),
+        );
+        (kernel, deterministic)
+    }) else {
+        return;
+    };
+
+    assert_eq!(
+        kernel.1,
+        Ipv4Addr::new(192, 168, 77, 20),
+        "192.168.77.3 is a secondary address"
+    );
+    assert_eq!(deterministic.0, "2a01:4f8::3".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(deterministic.1, Ipv4Addr::new(192, 168, 77, 3));
+}
+
+#[test]
+fn source_preferences_are_set() {
+    use SourcePreference::*;
+
+    // The kernel reports the defaults for the kinds without a preference.
+    const DEFAULT: libc::c_int = libc::IPV6_PREFER_SRC_PUBTMP_DEFAULT;
+    const HOME: libc::c_int = libc::IPV6_PREFER_SRC_HOME;
+
+    for (preferences, expected) in [
+        (&[][..], DEFAULT | HOME),
+        (&[Temporary][..], libc::IPV6_PREFER_SRC_TMP | HOME),
+        (&[Public][..], libc::IPV6_PREFER_SRC_PUBLIC | HOME),
+        (&[CareOf][..], DEFAULT | libc::IPV6_PREFER_SRC_COA),
+        (&[Home, Temporary][..], libc::IPV6_PREFER_SRC_TMP | HOME),
+    ] {
+        let sockets = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sockets.clone();
+
+        let query = preferences
+            .iter()
+            .fold(IpQuery::any_interface(), |query, &preference| {
+                query.prefer_source(preference)
+            });
+        let result = query
+            .socket_factory(move |domain, ty| {
+                let socket = Socket::new(domain, ty, None)?;
+                recorded.lock().unwrap().push(socket.try_clone()?);
+                Ok(ProvidedSocket::Unbound(socket))
+            })
+            .ipv6_loopback();
+        assert_eq!(result.unwrap(), Ipv6Addr::LOCALHOST);
+
+        let sockets = sockets.lock().unwrap();
+        assert_eq!(
+            common::addr_preferences(&sockets[0]),
+            expected as u32,
+            "{:?}",
+            preferences
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! The global observer is registered once per process,
+//! so these tests have a binary of their own.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use preferred_ip::{IpQuery, ProbeObserver, Scope};
+
+#[derive(Clone, Default)]
+struct Counter(Arc<AtomicUsize>);
+
+impl ProbeObserver for Counter {
+    fn on_probe_start(&self, _: Option<&str>, _: SocketAddr, _: Option<Scope>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn global_observer() {
+    let global = Counter::default();
+    assert!(preferred_ip::set_global_observer(global.clone()));
+    assert!(!preferred_ip::set_global_observer(Counter::default()));
+
+    let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    assert_eq!(
+        preferred_ip::preferred_source_for(None, loopback).unwrap(),
+        loopback
+    );
+    assert_eq!(global.0.load(Ordering::SeqCst), 1);
+
+    // Queries with their own observer don't notify the global one.
+    let own = Counter::default();
+    IpQuery::any_interface()
+        .observer(own.clone())
+        .ipv4_loopback()
+        .unwrap();
+    assert_eq!(own.0.load(Ordering::SeqCst), 1);
+    assert_eq!(global.0.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::net::{Ipv6Addr, SocketAddr};
+
+use preferred_ip::socket2::SockRef;
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, InterfaceHandle, Ipv6Scope, Scope};
+
+const ULA: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1);
+
+fn is_enodev(err: &Error) -> bool {
+    matches!(err, Error::IoError(e) if e.raw_os_error() == Some(libc::ENODEV))
+}
+
+/// Delete the veth pair and create it again with the same
+/// names and addresses, but new indices.
+fn recreate_veth() {
+    common::ip("link del veth0");
+    common::ip("link add veth0 type veth peer name veth1");
+    common::ip("-6 addr add fd00:dead::1/64 dev veth0 nodad");
+    common::ip("link set veth0 up");
+    common::ip("link set veth1 up");
+    common::ip("-6 route add default dev veth0");
+}
+
+#[test]
+fn open_fails_fast_on_unknown_names() {
+    common::run(NetEnv::builder(), || {
+        let err = InterfaceHandle::open("nonexistent0").unwrap_err();
+        assert!(is_enodev(&err), "{:?}", err);
+
+        // Present in the namespace, but not in the list of interfaces.
+        common::ip("link del veth0");
+        let err = InterfaceHandle::open("veth0").unwrap_err();
+        assert!(is_enodev(&err), "{:?}", err);
+    });
+}
+
+#[test]
+fn recreated_interface_is_resolved_again() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    common::run(env, || {
+        let handle = InterfaceHandle::open("veth0").unwrap();
+        let shared = handle.clone();
+        assert_eq!(handle.ipv6_unique_local().unwrap(), ULA);
+        let stale = handle.index();
+
+        recreate_veth();
+        assert_ne!(stale, InterfaceHandle::open("veth0").unwrap().index());
+
+        // The query alone binds to the stale index.
+        let err = handle.query().ipv6_unique_local().unwrap_err();
+        assert!(matches!(err, Error::NoRoute { .. }), "{:?}", err);
+
+        assert_eq!(handle.ipv6_unique_local().unwrap(), ULA);
+        assert_ne!(handle.index(), stale);
+        assert_eq!(shared.index(), handle.index());
+    });
+}
+
+#[test]
+fn renamed_interface_keeps_working() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    common::run(env, || {
+        let handle = InterfaceHandle::open("veth0").unwrap();
+        let index = handle.index();
+
+        common::ip("link set veth0 down");
+        common::ip("link set veth0 name lan0");
+        common::ip("link set lan0 up");
+        common::ip("-6 addr add fd00:dead::1/64 dev lan0 nodad");
+        common::ip("-6 route add default dev lan0");
+
+        // Bound by index, the sockets still reach the interface.
+        assert_eq!(handle.ipv6_unique_local().unwrap(), ULA);
+        assert_eq!(handle.index(), index);
+    });
+}
+
+#[test]
+fn renamed_interface_is_bound_by_index() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    common::run(env, || {
+        let handle = InterfaceHandle::open("veth0").unwrap();
+        let index = handle.index();
+
+        common::ip("link set veth0 down");
+        common::ip("link set veth0 name lan0");
+        common::ip("link set lan0 up");
+        common::ip("-6 addr add fd00:dead::1/64 dev lan0 nodad");
+        common::ip("-6 addr add fe80::1/64 dev lan0 nodad");
+        common::ip("-6 route add default dev lan0");
+
+        let udp = handle
+            .bind_udp(Scope::V6(Ipv6Scope::UniqueLocal), 0)
+            .unwrap();
+        assert_eq!(udp.local_addr().unwrap().ip(), ULA);
+        let device = SockRef::from(&udp).device().unwrap();
+        assert_eq!(device.as_deref(), Some(&b"lan0"[..]));
+
+        // The scope id is the cached index, not that of the old name.
+        let tcp = handle
+            .bind_tcp_listener(Scope::V6(Ipv6Scope::UnicastLinkLocal), 0, 1)
+            .unwrap();
+        match tcp.local_addr().unwrap() {
+            SocketAddr::V6(addr) => assert_eq!(addr.scope_id(), index),
+            addr => panic!("{}", addr),
+        }
+        let device = SockRef::from(&tcp).device().unwrap();
+        assert_eq!(device.as_deref(), Some(&b"lan0"[..]));
+    });
+}
+
+#[test]
+fn binding_follows_a_recreated_interface() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    common::run(env, || {
+        let handle = InterfaceHandle::open("veth0").unwrap();
+        let scope = Scope::V6(Ipv6Scope::UniqueLocal);
+        handle.bind_udp(scope, 0).unwrap();
+        let stale = handle.index();
+
+        recreate_veth();
+
+        // The query alone binds to the stale index.
+        let err = handle.query().bind_udp(scope, 0).unwrap_err();
+        assert!(matches!(err, Error::NoRoute { .. }), "{:?}", err);
+
+        let udp = handle.bind_udp(scope, 0).unwrap();
+        assert_eq!(udp.local_addr().unwrap().ip(), ULA);
+        assert_ne!(handle.index(), stale);
+
+        let tcp = handle.bind_tcp_listener(scope, 0, 1).unwrap();
+        assert_eq!(tcp.local_addr().unwrap().ip(), ULA);
+        let device = SockRef::from(&tcp).device().unwrap();
+        assert_eq!(device.as_deref(), Some(&b"veth0"[..]));
+    });
+}
+
+#[test]
+fn deleted_interface_fails() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    common::run(env, || {
+        let handle = InterfaceHandle::open("veth0").unwrap();
+        let index = handle.index();
+        common::ip("link del veth0");
+
+        let err = handle.ipv6_unique_local().unwrap_err();
+        assert!(is_enodev(&err), "{:?}", err);
+        assert_eq!(handle.index(), index);
+    });
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use preferred_ip::socket2::Socket;
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{IpQuery, Ipv4Scope, Ipv6Scope, ProvidedSocket, Scope};
+
+#[test]
+fn predicates_match_the_addresses() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        assert!(preferred_ip::has_ipv6_unicast_link_local("veth0").unwrap());
+        assert!(preferred_ip::has_ipv6_unique_local("veth0").unwrap());
+        assert!(!preferred_ip::has_ipv6_unicast_global("veth0").unwrap());
+        assert!(!preferred_ip::has_ipv4_link_local("veth0").unwrap());
+        assert!(preferred_ip::has_ipv4_private("veth0").unwrap());
+        assert!(!preferred_ip::has_ipv4_global("veth0").unwrap());
+
+        common::ip("addr add 2a01:4f8::1/64 dev veth0 nodad");
+        common::ip("addr add 169.254.1.1/16 dev veth0");
+        common::ip("addr add 93.184.216.34/32 dev veth0");
+        assert!(preferred_ip::has_ipv6_unicast_global("veth0").unwrap());
+        assert!(preferred_ip::has_ipv4_link_local("veth0").unwrap());
+        assert!(preferred_ip::has_ipv4_global("veth0").unwrap());
+
+        // The peer only has its link-local address.
+        assert!(!preferred_ip::has_ipv6_unique_local("veth1").unwrap());
+        assert!(!preferred_ip::has_ipv4_private("veth1").unwrap());
+    });
+}
+
+#[test]
+fn unusable_addresses_are_ignored() {
+    common::run(NetEnv::builder(), || {
+        // The peer already has the address, so DAD fails.
+        std::fs::write("/proc/sys/net/ipv6/conf/veth0/accept_dad", "1").unwrap();
+        common::ip("addr add 2a01:4f8::1/64 dev veth1 nodad");
+        common::ip("addr add 2a01:4f8::1/64 dev veth0");
+        assert!(!preferred_ip::has_ipv6_unicast_global("veth0").unwrap());
+    });
+}
+
+#[test]
+fn predicates_enumerate_instead_of_probing() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("93.184.216.34/32");
+
+    common::run(env, || {
+        let probes = Arc::new(AtomicUsize::new(0));
+        let counted = probes.clone();
+        let query = IpQuery::new("veth0").socket_factory(move |domain, ty| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        });
+
+        assert!(query.has(Scope::V6(Ipv6Scope::UnicastGlobal)).unwrap());
+        assert!(query.has(Scope::V4(Ipv4Scope::Global)).unwrap());
+        assert!(!query.has(Scope::V4(Ipv4Scope::Private)).unwrap());
+        assert_eq!(probes.load(Ordering::Relaxed), 0);
+
+        assert!(query.ipv4_global().is_ok());
+        assert_ne!(probes.load(Ordering::Relaxed), 0);
+    });
+}
+
+#[test]
+fn missing_interface_is_an_error() {
+    for scope in [
+        Scope::V6(Ipv6Scope::UnicastLinkLocal),
+        Scope::V6(Ipv6Scope::UnicastGlobal),
+        Scope::V4(Ipv4Scope::Private),
+    ] {
+        let result = IpQuery::new("nonexistent0").has(scope);
+        assert_eq!(
+            result.unwrap_err().raw_os_error(),
+            Some(libc::ENODEV),
+            "{}",
+            scope
+        );
+    }
+
+    assert!(preferred_ip::has_ipv4_global("nonexistent0").is_err());
+}
+
+#[test]
+fn any_interface_has_loopback() {
+    common::run(NetEnv::builder(), || {
+        assert!(IpQuery::any_interface()
+            .has(Scope::V6(Ipv6Scope::UnicastLinkLocal))
+            .unwrap());
+        assert!(!IpQuery::any_interface()
+            .has(Scope::V6(Ipv6Scope::UnicastGlobal))
+            .unwrap());
+    });
+}
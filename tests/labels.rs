@@ -0,0 +1,50 @@
+mod common;
+
+use std::net::Ipv4Addr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery};
+
+#[test]
+fn select_by_label() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24");
+
+    let Some((mgmt, addrs, missing)) = common::run(env, || {
+        common::ip("addr add 192.168.77.2/24 dev veth0 label veth0:mgmt");
+        common::ip("addr add 10.0.0.1/8 dev veth0");
+        common::ip("addr add 10.0.0.2/8 dev veth0 label veth0:mgmt");
+
+        (
+            preferred_ip::ipv4_by_label("veth0", "veth0:mgmt"),
+            preferred_ip::interface_addresses("veth0").unwrap(),
+            IpQuery::new("veth0").ipv4_by_label("veth0:backup"),
+        )
+    }) else {
+        return;
+    };
+
+    assert_eq!(mgmt.unwrap(), Ipv4Addr::new(192, 168, 77, 2));
+
+    for addr in addrs {
+        match addr.addr.to_string().as_str() {
+            "192.168.77.1" | "10.0.0.1" => assert_eq!(addr.label.as_deref(), Some("veth0")),
+            "192.168.77.2" | "10.0.0.2" => assert_eq!(addr.label.as_deref(), Some("veth0:mgmt")),
+            _ => assert!(addr.addr.is_ipv6() && addr.label.is_none(), "{:?}", addr),
+        }
+    }
+
+    match missing {
+        Err(Error::NoLabel {
+            interface,
+            label,
+            available,
+        }) => {
+            assert_eq!(interface.as_deref(), Some("veth0"));
+            assert_eq!(label, "veth0:backup");
+            assert_eq!(available, ["veth0", "veth0:mgmt"]);
+        }
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, IpVersion, ToBindAddr};
+
+#[test]
+fn all_link_local_addresses() {
+    let env = NetEnv::builder()
+        .ipv6("fe80::1/64")
+        .ipv6("fe80::2/64")
+        .ipv6("fd00:dead::1/64");
+
+    let Some((preferred, all, index)) = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let index = preferred_ip::interfaces()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "veth0")
+            .unwrap()
+            .index;
+        (
+            query.ipv6_unicast_link_local().unwrap(),
+            query.ipv6_unicast_link_local_all().unwrap(),
+            index,
+        )
+    }) else {
+        return;
+    };
+
+    // Two configured addresses and the one generated by the kernel.
+    assert_eq!(all.len(), 3, "{:?}", all);
+    assert_eq!(all[0].addr, preferred);
+    assert!(all.iter().all(|addr| addr.scope_id == index), "{:?}", all);
+    assert!(all
+        .iter()
+        .any(|addr| addr.addr == "fe80::1".parse::<Ipv6Addr>().unwrap()));
+    assert!(all
+        .iter()
+        .any(|addr| addr.addr == "fe80::2".parse::<Ipv6Addr>().unwrap()));
+}
+
+#[test]
+fn no_link_local_address() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    let Some(result) = common::run(env, || {
+        common::flush_ipv6("veth0");
+        IpQuery::new("veth0").ipv6_unicast_link_local_all()
+    }) else {
+        return;
+    };
+
+    assert!(
+        matches!(
+            result,
+            Err(Error::NoAddress { interface: Some(ref interface), family: IpVersion::V6 })
+                if interface == "veth0"
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn bind_addresses_carry_the_zone() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+
+    let result = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let scoped = query.ipv6_unicast_link_local_all().unwrap()[0];
+        let enumerated = preferred_ip::interface_addresses("veth0")
+            .unwrap()
+            .into_iter()
+            .find(|addr| addr.addr == IpAddr::V6(scoped.addr))
+            .unwrap();
+        let plain = query.ipv6_unicast_link_local().unwrap();
+        let gua = query.ipv6_unicast_global().unwrap();
+
+        (
+            UdpSocket::bind(scoped.to_bind_addr(0)).map(|s| s.local_addr().unwrap()),
+            UdpSocket::bind(enumerated.to_bind_addr(0)).map(|s| s.local_addr().unwrap()),
+            UdpSocket::bind(plain.to_bind_addr(0)),
+            UdpSocket::bind(gua.to_bind_addr(0)).map(|s| s.local_addr().unwrap()),
+            scoped,
+        )
+    });
+    let Some((scoped, enumerated, plain, gua, addr)) = result else {
+        return;
+    };
+
+    let scoped = scoped.unwrap();
+    assert_eq!(scoped.ip(), IpAddr::V6(addr.addr));
+    assert!(matches!(scoped, SocketAddr::V6(s) if s.scope_id() == addr.scope_id));
+    assert_eq!(enumerated.unwrap().ip(), scoped.ip());
+    // Without the zone, the kernel can't tell the link.
+    assert_eq!(plain.unwrap_err().raw_os_error(), Some(libc::EINVAL));
+    assert_eq!(gua.unwrap().ip(), "2a01:4f8::1".parse::<IpAddr>().unwrap());
+}
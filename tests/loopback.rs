@@ -0,0 +1,158 @@
+//! These tests use the loopback interface of the host
+//! and run without privileges.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::thread;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Error, IpQuery, Ipv4Scope, Ipv6Scope, Scope};
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Run the closure in a thread without any capabilities.
+/// Capabilities are per thread, so the rest of the tests keep theirs.
+fn unprivileged<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|s| {
+        s.spawn(|| {
+            let mut header = CapHeader {
+                version: LINUX_CAPABILITY_VERSION_3,
+                pid: 0,
+            };
+            let data = [CapData::default(), CapData::default()];
+            // SAFETY: Both pointers are valid for the duration of the call
+            // and the data has the two elements version 3 requires.
+            let ret = unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) };
+            assert_eq!(ret, 0, "capset: {}", std::io::Error::last_os_error());
+
+            f()
+        })
+        .join()
+        .unwrap()
+    })
+}
+
+#[test]
+fn loopback_addresses_of_lo() {
+    let (ipv6, ipv4, v6_generic, v4_generic, any) = unprivileged(|| {
+        let query = IpQuery::new("lo");
+        (
+            query.ipv6_loopback(),
+            query.ipv4_loopback(),
+            query.get(Scope::V6(Ipv6Scope::Loopback)),
+            query.get(Scope::V4(Ipv4Scope::Loopback)),
+            IpQuery::any_interface().ipv4_loopback(),
+        )
+    });
+
+    assert_eq!(ipv6.unwrap(), Ipv6Addr::LOCALHOST);
+    assert_eq!(ipv4.unwrap(), Ipv4Addr::LOCALHOST);
+    assert_eq!(v6_generic.unwrap(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+    assert_eq!(v4_generic.unwrap(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    assert_eq!(any.unwrap(), Ipv4Addr::LOCALHOST);
+}
+
+#[test]
+fn other_scopes_of_lo_explain_the_miss() {
+    let results = unprivileged(|| {
+        let query = IpQuery::new("lo");
+        [
+            (
+                Scope::V6(Ipv6Scope::UnicastLinkLocal),
+                query.ipv6_unicast_link_local().map(IpAddr::V6),
+            ),
+            (
+                Scope::V6(Ipv6Scope::UniqueLocal),
+                query.ipv6_unique_local().map(IpAddr::V6),
+            ),
+            (
+                Scope::V6(Ipv6Scope::UnicastGlobal),
+                query.ipv6_unicast_global().map(IpAddr::V6),
+            ),
+            (
+                Scope::V4(Ipv4Scope::LinkLocal),
+                query.ipv4_link_local().map(IpAddr::V4),
+            ),
+            (
+                Scope::V4(Ipv4Scope::Private),
+                query.ipv4_private().map(IpAddr::V4),
+            ),
+            (
+                Scope::V4(Ipv4Scope::Global),
+                query.ipv4_global().map(IpAddr::V4),
+            ),
+        ]
+    });
+
+    for (scope, result) in results {
+        assert!(
+            matches!(&result, Err(Error::LoopbackInterface { interface, scope: s })
+                if interface == "lo" && *s == scope),
+            "{}: {:?}",
+            scope,
+            result
+        );
+    }
+}
+
+#[test]
+fn loopback_miss_messages() {
+    let query = IpQuery::new("lo");
+
+    assert_eq!(
+        query.ipv6_unicast_global().unwrap_err().to_string(),
+        "loopback interface lo has no ipv6 gua address"
+    );
+    assert_eq!(
+        query.ipv4_private().unwrap_err().to_string(),
+        "loopback interface lo has no ipv4 private address"
+    );
+}
+
+#[test]
+fn loopback_scopes_of_other_interfaces() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    let result = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        (
+            query.ipv6_loopback(),
+            query.ipv4_loopback(),
+            query.ipv6_unique_local(),
+        )
+    });
+    let Some((ipv6, ipv4, ula)) = result else {
+        return;
+    };
+
+    // The other interfaces' own addresses are named, not the loopback.
+    assert!(
+        matches!(ipv6, Err(Error::NoLoopback(IpAddr::V6(ipv6))) if ipv6.is_unicast_link_local()),
+        "{:?}",
+        ipv6
+    );
+    assert!(
+        matches!(ipv4, Err(Error::NoLoopback(ip)) if ip == IpAddr::V4(Ipv4Addr::new(192, 168, 77, 1))),
+        "{:?}",
+        ipv4
+    );
+    assert!(matches!(ula, Err(Error::NoUla(_))), "{:?}", ula);
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{ipv4_multicast_source, ipv6_multicast_source, Error, IpQuery, ProvidedSocket};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+
+#[test]
+fn ipv6_group_scope_selects_source_scope() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64");
+
+    common::run(env, || {
+        let link_local = IpQuery::new("veth0").ipv6_unicast_link_local().unwrap();
+
+        #[rustfmt::skip]
+        let table = [
+            ("ff01::1", link_local), // interface-local
+            ("ff02::fb", link_local), // link-local, mDNS
+            ("ff02::c", link_local), // link-local, SSDP
+            ("ff05::1:3", GUA), // site-local, DHCPv6 servers
+            ("ff08::1", GUA), // organization-local
+            ("ff0e::101", GUA), // global, NTP
+        ];
+
+        for (group, expected) in table {
+            let group: Ipv6Addr = group.parse().unwrap();
+            let source = ipv6_multicast_source("veth0", group).unwrap();
+            assert_eq!(source, expected, "{}", group);
+        }
+    });
+}
+
+#[test]
+fn ipv6_unicast_group_is_rejected() {
+    // The group is checked before anything is probed.
+    for group in ["2a01:4f8::1", "fe80::1", "::", "::1"] {
+        let group: Ipv6Addr = group.parse().unwrap();
+        let result = ipv6_multicast_source("nonexistent0", group);
+        assert!(
+            matches!(result, Err(Error::NotMulticast(ip)) if ip == IpAddr::V6(group)),
+            "{:?}",
+            result
+        );
+    }
+}
+
+const LINK_LOCAL_V4: Ipv4Addr = Ipv4Addr::new(169, 254, 10, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+/// Add a link-scoped address before a global one,
+/// so that the first address of at most link scope differs
+/// from the first one of global scope.
+fn add_ipv4_addresses(interface: &str) {
+    common::ip(&format!(
+        "addr add 169.254.10.1/16 dev {} scope link",
+        interface
+    ));
+    common::ip(&format!("addr add 192.168.77.1/24 dev {}", interface));
+}
+
+#[test]
+fn ipv4_group_scope_selects_source_scope() {
+    common::run(NetEnv::builder(), || {
+        add_ipv4_addresses("veth0");
+
+        #[rustfmt::skip]
+        let table = [
+            ("224.0.0.251", LINK_LOCAL_V4), // link-local, mDNS
+            ("224.0.0.22", LINK_LOCAL_V4), // link-local, IGMPv3
+            ("239.255.255.250", PRIVATE), // administratively scoped, SSDP
+            ("224.0.1.1", PRIVATE), // internetwork control, NTP
+            ("233.252.0.1", PRIVATE), // global, documentation
+        ];
+
+        for (group, expected) in table {
+            let group: Ipv4Addr = group.parse().unwrap();
+            let source = ipv4_multicast_source("veth0", group).unwrap();
+            assert_eq!(source, expected, "{}", group);
+        }
+    });
+}
+
+/// A factory whose sockets count as bound without being bound,
+/// so that only `IP_MULTICAST_IF` selects the interface.
+fn unbound_but_provided(domain: Domain, ty: Type) -> io::Result<ProvidedSocket> {
+    Socket::new(domain, ty, None).map(ProvidedSocket::Bound)
+}
+
+#[test]
+fn ipv4_group_on_high_interface_index() {
+    // 300 doesn't fit into a byte. Another interface has the index
+    // with swapped bytes, so a byte order mixup selects it.
+    let swapped = 300u32.swap_bytes();
+
+    common::run(NetEnv::builder(), || {
+        common::ip("link add high0 index 300 type veth peer name high1");
+        common::ip(&format!(
+            "link add swap0 index {} type veth peer name swap1",
+            swapped
+        ));
+        for interface in ["high0", "swap0"] {
+            common::ip(&format!("link set {} up", interface));
+        }
+        add_ipv4_addresses("high0");
+        common::ip("addr add 10.99.0.1/24 dev swap0");
+
+        let groups = [
+            (Ipv4Addr::new(224, 0, 0, 251), LINK_LOCAL_V4),
+            (Ipv4Addr::new(239, 255, 255, 250), PRIVATE),
+        ];
+        for (group, expected) in groups {
+            let bound = IpQuery::new("high0");
+            assert_eq!(bound.ipv4_multicast_source(group).unwrap(), expected);
+
+            let selected = IpQuery::new("high0").socket_factory(unbound_but_provided);
+            assert_eq!(selected.ipv4_multicast_source(group).unwrap(), expected);
+
+            let other = IpQuery::new("swap0").socket_factory(unbound_but_provided);
+            assert_eq!(
+                other.ipv4_multicast_source(group).unwrap(),
+                Ipv4Addr::new(10, 99, 0, 1)
+            );
+        }
+    });
+}
+
+#[test]
+fn ipv4_unicast_group_is_rejected() {
+    for group in ["192.168.77.1", "240.0.0.1", "255.255.255.255", "0.0.0.0"] {
+        let group: Ipv4Addr = group.parse().unwrap();
+        let result = ipv4_multicast_source("nonexistent0", group);
+        assert!(
+            matches!(result, Err(Error::NotMulticast(ip)) if ip == IpAddr::V4(group)),
+            "{:?}",
+            result
+        );
+    }
+}
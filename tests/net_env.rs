@@ -0,0 +1,85 @@
+mod common;
+
+use std::net::IpAddr;
+use std::panic;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::IpVersion;
+
+#[test]
+fn creates_configured_environment() {
+    let env = NetEnv::builder()
+        .interfaces("test0", "test1")
+        .ipv6("2001:db8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .ipv4("169.254.7.1/16")
+        .route("default")
+        .route("2000::/3");
+
+    let Some((interfaces, addrs, routes)) = common::run(env, || {
+        (
+            preferred_ip::interfaces().unwrap(),
+            preferred_ip::interface_addresses("test0").unwrap(),
+            preferred_ip::default_routes(IpVersion::V4).unwrap(),
+        )
+    }) else {
+        return;
+    };
+
+    let mut names: Vec<_> = interfaces.iter().map(|i| i.name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["lo", "test0", "test1"]);
+    assert!(interfaces.iter().all(|i| i.is_up()), "{:?}", interfaces);
+
+    let addrs: Vec<IpAddr> = addrs.iter().map(|addr| addr.addr).collect();
+    for expected in ["2001:db8::1", "fd00:dead::1", "192.168.77.1", "169.254.7.1"] {
+        let expected: IpAddr = expected.parse().unwrap();
+        assert!(addrs.contains(&expected), "{} not in {:?}", expected, addrs);
+    }
+
+    assert_eq!(routes.len(), 1, "{:?}", routes);
+    assert_eq!(routes[0].interface.as_deref(), Some("test0"));
+}
+
+#[test]
+fn environment_is_isolated() {
+    let env = NetEnv::builder().interfaces("isolated0", "isolated1");
+
+    let Some(inside) = common::run(env, || preferred_ip::interfaces().unwrap()) else {
+        return;
+    };
+    assert!(inside.iter().any(|i| i.name == "isolated0"));
+
+    let outside = preferred_ip::interfaces().unwrap();
+    assert!(!outside.iter().any(|i| i.name.starts_with("isolated")));
+}
+
+#[test]
+fn panics_are_propagated() {
+    let env = NetEnv::builder();
+    let Some(()) = common::run(env.clone(), || ()) else {
+        return;
+    };
+
+    let result = panic::catch_unwind(|| env.run(|| panic!("inside")));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"inside"));
+}
+
+#[test]
+fn invalid_config_fails() {
+    let env = NetEnv::builder();
+    let Some(()) = common::run(env.clone(), || ()) else {
+        return;
+    };
+
+    let err = env.ipv6("not-an-address").run(|| ()).unwrap_err();
+    assert!(!err.is_unavailable());
+    assert!(
+        err.to_string()
+            .starts_with("command `ip addr add not-an-address"),
+        "{}",
+        err
+    );
+}
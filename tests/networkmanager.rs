@@ -0,0 +1,142 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+use common::FakeNm;
+use preferred_ip::{Backend, CachedQuery, Error, ErrorKind, IpQuery, Ipv4Scope, Ipv6Scope, Scope};
+
+const MAPPED: &str = "::ffff:93.184.216.34";
+const IPV4: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+
+fn query(nm: &FakeNm) -> IpQuery<'static> {
+    IpQuery::new("eth0")
+        .backend(Backend::NetworkManager)
+        .networkmanager_bus(nm.clone())
+}
+
+#[test]
+fn mapped_ipv6_is_reported() {
+    let nm = FakeNm::new(&["93.184.216.34"], &[MAPPED]);
+
+    let result = query(&nm).ipv6_unicast_global();
+    assert!(
+        matches!(result, Err(Error::GotMappedV4 { mapped: IPV4 })),
+        "{:?}",
+        result
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "ipv6 probe returned ipv4-mapped address of 93.184.216.34, use the ipv4 getters"
+    );
+
+    let result = query(&nm).get(Scope::V6(Ipv6Scope::UnicastGlobal));
+    assert!(
+        matches!(result, Err(Error::GotMappedV4 { .. })),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn unmap_v4_reroutes_get() {
+    let nm = FakeNm::new(&["93.184.216.34"], &[MAPPED]);
+    let query = query(&nm).unmap_v4(true);
+
+    assert_eq!(
+        query.get(Scope::V6(Ipv6Scope::UnicastGlobal)).unwrap(),
+        IpAddr::V4(IPV4)
+    );
+    // The typed getters still fail.
+    assert!(matches!(
+        query.ipv6_unicast_global(),
+        Err(Error::GotMappedV4 { mapped: IPV4 })
+    ));
+    assert!(matches!(
+        query.ipv6(Ipv6Scope::UnicastGlobal),
+        Err(Error::GotMappedV4 { mapped: IPV4 })
+    ));
+}
+
+#[test]
+fn unmapped_addresses_are_classified() {
+    let nm = FakeNm::new(&["93.184.216.34"], &["fd00::1", "2a01:4f8::1"]);
+
+    assert_eq!(
+        query(&nm).ipv6_unicast_global().unwrap(),
+        "2a01:4f8::1".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        query(&nm)
+            .unmap_v4(true)
+            .get(Scope::V6(Ipv6Scope::UniqueLocal))
+            .unwrap(),
+        "fd00::1".parse::<IpAddr>().unwrap()
+    );
+}
+
+#[test]
+fn cache_survives_unmapped_answers() {
+    let nm = FakeNm::new(&["93.184.216.34"], &[MAPPED]);
+    let cache = CachedQuery::new(query(&nm).unmap_v4(true));
+    let global = Scope::V6(Ipv6Scope::UnicastGlobal);
+
+    // The typed getter fails instead of panicking on the IPv4 answer,
+    // both when probing and when serving it from the cache.
+    for _ in 0..2 {
+        assert!(matches!(
+            cache.ipv6(Ipv6Scope::UnicastGlobal),
+            Err(Error::GotMappedV4 { mapped: IPV4 })
+        ));
+    }
+    assert_eq!(cache.get(global).unwrap(), IpAddr::V4(IPV4));
+    assert_eq!(cache.ipv4(Ipv4Scope::Global).unwrap(), IPV4);
+
+    nm.set(&["93.184.216.34"], &["2a01:4f8::1"]);
+    cache.invalidate();
+    assert_eq!(
+        cache.ipv6(Ipv6Scope::UnicastGlobal).unwrap(),
+        "2a01:4f8::1".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+
+    nm.set(&["93.184.216.34"], &[MAPPED]);
+    cache.invalidate();
+    assert!(matches!(
+        cache.ipv6(Ipv6Scope::UnicastGlobal),
+        Err(Error::GotMappedV4 { mapped: IPV4 })
+    ));
+    assert_eq!(cache.get(global).unwrap(), IpAddr::V4(IPV4));
+}
+
+#[test]
+fn stats_follow_the_backend() {
+    let nm = FakeNm::new(&["93.184.216.34"], &["2a01:4f8::1"]);
+    let cache = CachedQuery::new(query(&nm).collect_stats(true));
+    let global = Scope::V6(Ipv6Scope::UnicastGlobal);
+
+    cache.get(global).unwrap();
+    cache.get(global).unwrap();
+    nm.set(&["93.184.216.34"], &[]);
+    cache.invalidate();
+    cache.get(global).unwrap_err();
+    nm.set(&["93.184.216.34"], &["fd00::1"]);
+    cache.get(global).unwrap_err();
+    cache.ipv4(Ipv4Scope::Global).unwrap();
+
+    let snapshot = cache.stats();
+    let stats = snapshot.get(Some("eth0"), global).unwrap();
+    assert_eq!(stats.successes, 1);
+    assert_eq!((stats.cache_hits, stats.cache_misses), (1, 3));
+    // No address at all, then only a ULA.
+    assert_eq!(
+        stats.failures,
+        BTreeMap::from([(ErrorKind::NoAddress, 1), (ErrorKind::WrongScope, 1)])
+    );
+    assert!(stats.last_failure > stats.last_success);
+
+    let stats = snapshot
+        .get(Some("eth0"), Scope::V4(Ipv4Scope::Global))
+        .unwrap();
+    assert_eq!((stats.successes, stats.cache_misses), (1, 1));
+    assert_eq!(stats.last_failure, None);
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use preferred_ip::socket2::Socket;
+use preferred_ip::test_support::{MockClock, NetEnv};
+use preferred_ip::{
+    Clock, Error, FallbackKind, IpQuery, Ipv4Scope, Ipv6Scope, ProbeObserver, ProvidedSocket, Scope,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Event {
+    Start {
+        interface: Option<String>,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+    },
+    End {
+        interface: Option<String>,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        outcome: Result<IpAddr, String>,
+        duration: Duration,
+    },
+    Fallback {
+        interface: Option<String>,
+        kind: FallbackKind,
+    },
+}
+
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+impl Recorder {
+    fn events(&self) -> Vec<Event> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl ProbeObserver for Recorder {
+    fn on_probe_start(&self, interface: Option<&str>, dest: SocketAddr, scope: Option<Scope>) {
+        self.0.lock().unwrap().push(Event::Start {
+            interface: interface.map(Into::into),
+            dest,
+            scope,
+        });
+    }
+
+    fn on_probe_end(
+        &self,
+        interface: Option<&str>,
+        dest: SocketAddr,
+        scope: Option<Scope>,
+        outcome: Result<IpAddr, &Error>,
+        duration: Duration,
+    ) {
+        self.0.lock().unwrap().push(Event::End {
+            interface: interface.map(Into::into),
+            dest,
+            scope,
+            outcome: outcome.map_err(|e| e.to_string()),
+            duration,
+        });
+    }
+
+    fn on_fallback(&self, interface: Option<&str>, kind: FallbackKind) {
+        self.0.lock().unwrap().push(Event::Fallback {
+            interface: interface.map(Into::into),
+            kind,
+        });
+    }
+}
+
+/// An observer whose hooks all panic.
+struct Panicking;
+
+impl ProbeObserver for Panicking {
+    fn on_probe_start(&self, _: Option<&str>, _: SocketAddr, _: Option<Scope>) {
+        panic!("on_probe_start");
+    }
+
+    fn on_probe_end(
+        &self,
+        _: Option<&str>,
+        _: SocketAddr,
+        _: Option<Scope>,
+        _: Result<IpAddr, &Error>,
+        _: Duration,
+    ) {
+        panic!("on_probe_end");
+    }
+
+    fn on_fallback(&self, _: Option<&str>, _: FallbackKind) {
+        panic!("on_fallback");
+    }
+}
+
+#[test]
+fn probe_payload() {
+    let recorder = Recorder::default();
+    let clock = MockClock::new();
+    let sleeping = clock.clone();
+
+    let result = IpQuery::any_interface()
+        .observer(recorder.clone())
+        .clock(clock)
+        .socket_factory(move |domain, ty| -> io::Result<ProvidedSocket> {
+            sleeping.sleep(Duration::from_millis(1500));
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        })
+        .ipv4_loopback();
+    assert_eq!(result.unwrap(), Ipv4Addr::LOCALHOST);
+
+    let scope = Some(Scope::V4(Ipv4Scope::Loopback));
+    let events = recorder.events();
+    let [Event::Start { dest, .. }, _] = events.as_slice() else {
+        panic!("{:?}", events);
+    };
+    assert!(dest.ip().is_loopback(), "{}", dest);
+    assert_eq!(
+        events,
+        [
+            Event::Start {
+                interface: None,
+                dest: *dest,
+                scope,
+            },
+            Event::End {
+                interface: None,
+                dest: *dest,
+                scope,
+                outcome: Ok(Ipv4Addr::LOCALHOST.into()),
+                duration: Duration::from_millis(1500),
+            },
+        ]
+    );
+}
+
+#[test]
+fn failed_probe_payload() {
+    let recorder = Recorder::default();
+
+    let result = IpQuery::new("nonexistent0")
+        .observer(recorder.clone())
+        .ipv6_unicast_global();
+    let err = result.unwrap_err();
+
+    let events = recorder.events();
+    let [Event::Start {
+        interface,
+        scope: start_scope,
+        ..
+    }, Event::End { scope, outcome, .. }] = events.as_slice()
+    else {
+        panic!("{:?}", events);
+    };
+    assert_eq!(interface.as_deref(), Some("nonexistent0"));
+    assert_eq!(*start_scope, Some(Scope::V6(Ipv6Scope::UnicastGlobal)));
+    assert_eq!(scope, start_scope);
+    assert_eq!(outcome.as_ref().unwrap_err(), &err.to_string());
+}
+
+#[test]
+fn probes_are_reported_in_order() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24").route("default");
+
+    let Some((result, events)) = common::run(env, || {
+        let recorder = Recorder::default();
+        let result = IpQuery::new("veth0")
+            .observer(recorder.clone())
+            .ipv4_private();
+        (result, recorder.events())
+    }) else {
+        return;
+    };
+    assert_eq!(result.unwrap(), Ipv4Addr::new(192, 168, 77, 1));
+
+    // Every probe ends before the next one starts.
+    assert!(events.len() >= 2 && events.len() % 2 == 0, "{:?}", events);
+    for pair in events.chunks(2) {
+        let [Event::Start { dest, .. }, Event::End {
+            dest: end_dest,
+            outcome,
+            ..
+        }] = pair
+        else {
+            panic!("{:?}", events);
+        };
+        assert_eq!(dest, end_dest);
+        assert!(outcome.is_ok(), "{:?}", outcome);
+    }
+    let Event::End { outcome, .. } = events.last().unwrap() else {
+        unreachable!()
+    };
+    assert_eq!(outcome, &Ok(Ipv4Addr::new(192, 168, 77, 1).into()));
+}
+
+#[test]
+fn fallbacks_are_reported() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::2/64").route("default");
+
+    let Some((result, events)) = common::run(env, || {
+        // The kernel prefers this address, whose lifetime is short.
+        common::ip("addr add 2a01:4f8::1/64 dev veth0 nodad preferred_lft 60 valid_lft 600");
+
+        let recorder = Recorder::default();
+        let result = IpQuery::new("veth0")
+            .observer(recorder.clone())
+            .min_preferred_lifetime(Duration::from_secs(3600))
+            .ipv6_unicast_global();
+        (result, recorder.events())
+    }) else {
+        return;
+    };
+    assert_eq!(result.unwrap(), "2a01:4f8::2".parse::<IpAddr>().unwrap());
+
+    let fallback = Event::Fallback {
+        interface: Some("veth0".into()),
+        kind: FallbackKind::ShortLifetime,
+    };
+    // The fallback follows the probe it replaces.
+    assert_eq!(events.len(), 3, "{:?}", events);
+    assert!(matches!(events[1], Event::End { .. }), "{:?}", events);
+    assert_eq!(events[2], fallback);
+}
+
+#[test]
+fn panicking_hooks_are_isolated() {
+    let result = IpQuery::any_interface().observer(Panicking).ipv4_loopback();
+    assert_eq!(result.unwrap(), Ipv4Addr::LOCALHOST);
+
+    let result = IpQuery::new("nonexistent0")
+        .observer(Panicking)
+        .ipv4_private();
+    assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ENODEV));
+}
+
+#[test]
+fn panicking_fallback_hook_is_isolated() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::2/64").route("default");
+
+    let Some(result) = common::run(env, || {
+        common::ip("addr add 2a01:4f8::1/64 dev veth0 nodad preferred_lft 60 valid_lft 600");
+
+        IpQuery::new("veth0")
+            .observer(Panicking)
+            .min_preferred_lifetime(Duration::from_secs(3600))
+            .ipv6_unicast_global()
+    }) else {
+        return;
+    };
+    assert_eq!(result.unwrap(), "2a01:4f8::2".parse::<IpAddr>().unwrap());
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::fs;
+use std::net::Ipv6Addr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{IpQuery, OptimisticDad};
+
+const STABLE: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 0x20);
+const OPTIMISTIC: Ipv6Addr = Ipv6Addr::new(0x2000, 0, 0, 0, 0, 0, 0, 0x10);
+
+/// Keep duplicate address detection of new addresses on the interface
+/// running for the rest of the test, and let the kernel choose
+/// optimistic addresses like preferred ones.
+fn slow_dad(interface: &str) {
+    for (sysctl, value) in [
+        ("conf/{}/accept_dad", "1"),
+        ("conf/{}/optimistic_dad", "1"),
+        ("conf/{}/use_optimistic", "1"),
+        ("conf/{}/dad_transmits", "100"),
+        ("neigh/{}/retrans_time_ms", "10000"),
+    ] {
+        let path = format!("/proc/sys/net/ipv6/{}", sysctl.replace("{}", interface));
+        fs::write(path, value).unwrap();
+    }
+}
+
+#[test]
+fn optimistic_dad_policies() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::20/64").route("default");
+
+    let Some(results) = common::run(env, || {
+        slow_dad("veth0");
+        common::ip("addr add 2000::10/64 dev veth0 optimistic");
+
+        let addrs = preferred_ip::interface_addresses("veth0").unwrap();
+        let optimistic = addrs.iter().find(|addr| addr.addr == OPTIMISTIC).unwrap();
+        assert!(optimistic.is_optimistic(), "{:?}", optimistic);
+        assert!(optimistic.is_tentative(), "{:?}", optimistic);
+
+        let query = |policy| IpQuery::new("veth0").optimistic_dad(policy);
+        (
+            query(OptimisticDad::Accept).ipv6_unicast_global().unwrap(),
+            query(OptimisticDad::Accept)
+                .ipv6_unicast_global_lenient()
+                .unwrap(),
+            query(OptimisticDad::AcceptAndFlag)
+                .ipv6_unicast_global_lenient()
+                .unwrap(),
+            query(OptimisticDad::Reject).ipv6_unicast_global().unwrap(),
+            query(OptimisticDad::Reject)
+                .deterministic(true)
+                .ipv6_unicast_global()
+                .unwrap(),
+        )
+    }) else {
+        return;
+    };
+
+    let (accepted, unflagged, flagged, rejected, deterministic) = results;
+    // The kernel prefers the address closer to its probe destination.
+    assert_eq!(accepted, OPTIMISTIC);
+    assert_eq!(unflagged.addr, OPTIMISTIC);
+    assert!(!unflagged.optimistic);
+    assert_eq!(flagged.addr, OPTIMISTIC);
+    assert!(flagged.optimistic);
+    assert_eq!(rejected, STABLE);
+    assert_eq!(deterministic, STABLE);
+}
+
+#[test]
+fn tentative_addresses_are_skipped() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::20/64").route("default");
+
+    let Some(result) = common::run(env, || {
+        slow_dad("veth0");
+        common::ip("addr add 2000::10/64 dev veth0");
+
+        let addrs = preferred_ip::interface_addresses("veth0").unwrap();
+        let tentative = addrs.iter().find(|addr| addr.addr == OPTIMISTIC).unwrap();
+        assert!(tentative.is_tentative() && !tentative.is_optimistic());
+
+        IpQuery::new("veth0")
+            .deterministic(true)
+            .ipv6_unicast_global()
+            .unwrap()
+    }) else {
+        return;
+    };
+
+    assert_eq!(result, STABLE);
+}
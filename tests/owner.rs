@@ -0,0 +1,167 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{address_owners, Error, IpQuery};
+
+const FOREIGN: Ipv4Addr = Ipv4Addr::new(10, 1, 1, 1);
+
+/// `veth0` has no IPv4 address of its own, so the kernel
+/// picks the one of `veth1` for probes through it.
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder().route("default")
+}
+
+#[test]
+fn foreign_source_is_rejected() {
+    let result = common::run(env(), || {
+        common::ip("addr add 10.1.1.1/24 dev veth1");
+
+        let query = IpQuery::new("veth0");
+        (
+            query.ipv4_private(),
+            query.clone().verify_owner(true).ipv4_private(),
+        )
+    });
+    let Some((unverified, verified)) = result else {
+        return;
+    };
+
+    assert_eq!(unverified.unwrap(), FOREIGN);
+    assert!(
+        matches!(&verified, Err(Error::ForeignSource { interface, addr, owner })
+            if interface.as_deref() == Some("veth0")
+                && *addr == FOREIGN
+                && owner.as_deref() == Some("veth1")),
+        "{:?}",
+        verified
+    );
+    assert_eq!(
+        verified.unwrap_err().to_string(),
+        "source address 10.1.1.1 chosen on interface veth0 belongs to interface veth1"
+    );
+}
+
+#[test]
+fn shared_address_passes() {
+    let result = common::run(env(), || {
+        // An anycast address on both interfaces.
+        common::ip("addr add 10.1.1.1/24 dev veth1");
+        common::ip("addr add 10.1.1.1/32 dev veth0");
+
+        IpQuery::new("veth0").verify_owner(true).ipv4_private()
+    });
+    let Some(result) = result else { return };
+
+    assert_eq!(result.unwrap(), FOREIGN);
+}
+
+#[test]
+fn own_address_passes() {
+    let env = env().ipv4("192.168.77.1/24").ipv6("2a01:4f8::1/64");
+
+    let result = common::run(env, || {
+        common::ip("addr add 10.1.1.1/24 dev veth1");
+
+        let query = IpQuery::new("veth0").verify_owner(true);
+        (query.ipv4_private(), query.ipv6_unicast_global())
+    });
+    let Some((ipv4, ipv6)) = result else { return };
+
+    assert_eq!(ipv4.unwrap(), Ipv4Addr::new(192, 168, 77, 1));
+    assert_eq!(ipv6.unwrap(), "2a01:4f8::1".parse::<Ipv6Addr>().unwrap());
+}
+
+fn owners(addr: &str) -> Vec<String> {
+    address_owners(addr.parse::<IpAddr>().unwrap()).unwrap()
+}
+
+#[test]
+fn owners_of_shared_addresses() {
+    let env = env().ipv4("192.168.77.1/24").ipv6("2a01:4f8::1/64");
+
+    let result = common::run(env, || {
+        common::ip("addr add 2a01:4f8::1/128 dev veth1 nodad");
+        common::ip("addr add 2a01:4f8::2/128 dev veth1 nodad");
+        (
+            owners("2a01:4f8::1"),
+            owners("2a01:4f8::2"),
+            owners("192.168.77.1"),
+            owners("::ffff:192.168.77.1"),
+            owners("2a01:4f8::3"),
+            owners("::1"),
+            owners("::"),
+        )
+    });
+    let Some((shared, other, own, mapped, unknown, loopback, unspecified)) = result else {
+        return;
+    };
+
+    // The kernel registers the peer of a veth pair first,
+    // so veth1 has the lower index.
+    assert_eq!(shared, ["veth1", "veth0"]);
+    assert_eq!(other, ["veth1"]);
+    assert_eq!(own, ["veth0"]);
+    assert_eq!(mapped, ["veth0"]);
+    assert!(unknown.is_empty());
+    assert_eq!(loopback, ["lo"]);
+    assert!(unspecified.is_empty());
+}
+
+#[test]
+fn lenient_getters_report_shared_addresses() {
+    let env = env().ipv4("192.168.77.1/24").ipv6("2a01:4f8::1/64");
+
+    let result = common::run(env, || {
+        common::ip("addr add 2a01:4f8::1/128 dev veth1 nodad");
+        common::ip("addr add 192.168.77.1/32 dev veth1");
+
+        let bound = IpQuery::new("veth0");
+        let any = IpQuery::any_interface();
+        (
+            bound.ipv6_unicast_global_lenient().unwrap(),
+            bound.ipv4_private_lenient().unwrap(),
+            any.ipv6_unicast_global_lenient().unwrap(),
+        )
+    });
+    let Some((gua, private, any)) = result else {
+        return;
+    };
+
+    assert_eq!(gua.addr, "2a01:4f8::1".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(gua.shared_with, ["veth1"]);
+    assert_eq!(private.addr, Ipv4Addr::new(192, 168, 77, 1));
+    assert_eq!(private.shared_with, ["veth1"]);
+    assert_eq!(any.shared_with, ["veth1", "veth0"]);
+}
+
+#[test]
+fn unique_and_link_local_addresses_are_not_shared() {
+    let env = env().ipv6("2a01:4f8::1/64");
+
+    let result = common::run(env, || {
+        common::flush_ipv6("veth1");
+        common::ip("addr add fe80::1/64 dev veth0 nodad");
+        common::ip("addr add fe80::1/64 dev veth1 nodad");
+
+        let query = IpQuery::new("veth0");
+        (
+            owners("fe80::1"),
+            query.ipv6_unicast_link_local_lenient().unwrap(),
+            query.ipv6_unicast_global_lenient().unwrap(),
+            IpQuery::any_interface()
+                .ipv6_unicast_global_lenient()
+                .unwrap(),
+        )
+    });
+    let Some((owners, link_local, gua, any)) = result else {
+        return;
+    };
+
+    // The kernel has it on both, but it is only unique per link.
+    assert_eq!(owners, ["veth1", "veth0"]);
+    assert!(link_local.shared_with.is_empty(), "{:?}", link_local);
+    assert!(gua.shared_with.is_empty());
+    assert!(any.shared_with.is_empty());
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{
+    Backend, IpQuery, IpVersion, PlannedProbe, ProbeObserver, ProbePlan, ProbeProtocol,
+    ProvidedSocket, Scope, SocketPlan, SourcePreference,
+};
+
+/// What a query did: the probes it sent and clones of the sockets
+/// its factory provided, to read their options afterwards.
+#[derive(Clone, Default)]
+struct Recorder {
+    probes: Arc<Mutex<Vec<PlannedProbe>>>,
+    sockets: Arc<Mutex<Vec<(Domain, Type, Socket)>>>,
+}
+
+impl ProbeObserver for Recorder {
+    fn on_probe_start(&self, _: Option<&str>, dest: SocketAddr, scope: Option<Scope>) {
+        let scope = scope.expect("get_all only probes scopes");
+        self.probes
+            .lock()
+            .unwrap()
+            .push(PlannedProbe { scope, dest });
+    }
+}
+
+impl Recorder {
+    fn factory(&self) -> impl Fn(Domain, Type) -> std::io::Result<ProvidedSocket> {
+        let sockets = self.sockets.clone();
+        move |domain, ty| {
+            let socket = Socket::new(domain, ty, None)?;
+            sockets
+                .lock()
+                .unwrap()
+                .push((domain, ty, socket.try_clone()?));
+            Ok(ProvidedSocket::Unbound(socket))
+        }
+    }
+
+    /// Run `get_all` and check that it did what the plan says.
+    fn check(&self, query: IpQuery, plan: &ProbePlan) {
+        query.get_all().unwrap();
+
+        assert_eq!(*self.probes.lock().unwrap(), plan.probes);
+        for (domain, ty, socket) in self.sockets.lock().unwrap().iter() {
+            let planned = planned_socket(plan, *domain);
+            check_socket(planned, *ty, socket);
+        }
+    }
+}
+
+fn planned_socket(plan: &ProbePlan, domain: Domain) -> &SocketPlan {
+    let family = match domain {
+        Domain::IPV6 => IpVersion::V6,
+        _ => IpVersion::V4,
+    };
+    plan.sockets
+        .iter()
+        .find(|socket| socket.family == family)
+        .expect("a socket of an unplanned family was created")
+}
+
+fn check_socket(planned: &SocketPlan, ty: Type, socket: &Socket) {
+    let expected_ty = match planned.protocol {
+        ProbeProtocol::Udp => Type::DGRAM,
+        ProbeProtocol::Tcp => Type::STREAM,
+    };
+    assert_eq!(ty, expected_ty);
+    assert!(planned.provided);
+
+    let device = socket.device().unwrap();
+    assert_eq!(
+        device.as_deref(),
+        planned.bind_device.as_deref().map(str::as_bytes)
+    );
+
+    if planned.family == IpVersion::V6 {
+        assert_eq!(socket.only_v6().unwrap(), planned.only_v6);
+
+        let flags = common::addr_preferences(socket);
+        for preference in planned.source_preferences.iter().flatten() {
+            let flag = match preference {
+                SourcePreference::Temporary => libc::IPV6_PREFER_SRC_TMP,
+                SourcePreference::Public => libc::IPV6_PREFER_SRC_PUBLIC,
+                SourcePreference::CareOf => libc::IPV6_PREFER_SRC_COA,
+                SourcePreference::Home => libc::IPV6_PREFER_SRC_HOME,
+            };
+            assert_ne!(flags & flag as u32, 0, "{:?} isn't set", preference);
+        }
+    }
+}
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+#[test]
+fn provided_sockets_match_the_plan() {
+    common::run(env(), || {
+        let configs: [fn() -> IpQuery<'static>; 4] = [
+            || IpQuery::new("veth0"),
+            IpQuery::any_interface,
+            || {
+                IpQuery::new("veth0")
+                    .protocol(ProbeProtocol::Tcp)
+                    .prefer_source(SourcePreference::Temporary)
+            },
+            || IpQuery::any_interface().prefer_source(SourcePreference::Public),
+        ];
+
+        for config in configs {
+            let recorder = Recorder::default();
+            let query = config()
+                .socket_factory(recorder.factory())
+                .observer(recorder.clone());
+            let plan = query.dry_run();
+            assert_eq!(plan.sockets.len(), 2, "{}", plan);
+
+            recorder.check(query, &plan);
+            assert!(!recorder.sockets.lock().unwrap().is_empty());
+        }
+    });
+}
+
+#[test]
+fn probes_match_the_plan() {
+    common::run(env(), || {
+        for query in [IpQuery::new("veth0"), IpQuery::any_interface()] {
+            let recorder = Recorder::default();
+            let query = query.observer(recorder.clone());
+            let plan = query.dry_run();
+            assert!(plan.sockets.iter().all(|socket| !socket.provided));
+
+            recorder.check(query, &plan);
+        }
+    });
+}
+
+#[test]
+fn procfs_plans_no_probes() {
+    common::run(env(), || {
+        let recorder = Recorder::default();
+        let query = IpQuery::new("veth0")
+            .backend(Backend::Procfs)
+            .socket_factory(recorder.factory())
+            .observer(recorder.clone());
+        let plan = query.dry_run();
+        assert_eq!(plan.backends, [Backend::Procfs]);
+        assert_eq!(plan.sockets, []);
+
+        recorder.check(query, &plan);
+        assert!(recorder.sockets.lock().unwrap().is_empty());
+    });
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{interface_addresses, Error, IpPrefix, IpQuery, IpVersion, PrefixVerdict};
+
+const FIRST: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 1, 0, 0, 0, 0, 5);
+const SECOND: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 2, 0, 0, 0, 0, 5);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+const SHARED: Ipv4Addr = Ipv4Addr::new(100, 64, 0, 1);
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8:1::5/64")
+        .ipv6("2a01:4f8:2::5/64")
+        .ipv4("192.168.77.1/24")
+        .ipv4("100.64.0.1/10")
+        .route("default")
+}
+
+fn net<T: std::str::FromStr>(net: &str) -> T
+where
+    T::Err: std::fmt::Debug,
+{
+    net.parse().unwrap()
+}
+
+#[test]
+fn nets_select_like_pairs() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0");
+
+        for (prefix, len) in [(FIRST, 64), (SECOND, 64), (SECOND, 48), (SECOND, 128)] {
+            let v6 = Ipv6Net::new(prefix, len).unwrap();
+            for net in [IpPrefix::from(v6), IpNet::V6(v6).into()] {
+                assert_eq!(
+                    query.ipv6_in_prefix(net).ok(),
+                    query.ipv6_in_prefix((prefix, len)).ok()
+                );
+                assert_eq!(
+                    query.verify_ipv6_within(net).unwrap(),
+                    query.verify_ipv6_within((prefix, len)).unwrap()
+                );
+            }
+        }
+
+        for (subnet, len) in [(PRIVATE, 24), (SHARED, 10), (PRIVATE, 32), (SHARED, 16)] {
+            let v4 = Ipv4Net::new(subnet, len).unwrap();
+            assert_eq!(
+                query.ipv4_in_subnet(v4).ok(),
+                query.ipv4_in_subnet((subnet, len)).ok()
+            );
+            assert_eq!(
+                query.ipv4_in_subnet(IpNet::V4(v4)).ok(),
+                query.ipv4_in_subnet((subnet, len)).ok()
+            );
+        }
+    });
+}
+
+#[test]
+fn selectors_accept_ipnet() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0");
+
+        assert_eq!(
+            query
+                .ipv6_in_prefix(net::<Ipv6Net>("2a01:4f8:2::/64"))
+                .unwrap(),
+            SECOND
+        );
+        // Host bits are ignored.
+        assert_eq!(
+            query
+                .ipv6_in_prefix(net::<IpNet>("2a01:4f8:2::1234/64"))
+                .unwrap(),
+            SECOND
+        );
+        // A bare address is a /128.
+        assert_eq!(query.ipv6_in_prefix(SECOND).unwrap(), SECOND);
+        assert!(query
+            .ipv6_in_prefix(net::<Ipv6Net>("2a01:4f8:3::/64"))
+            .is_err());
+        assert_eq!(
+            preferred_ip::ipv6_in_prefix("veth0", net::<Ipv6Net>("2a01:4f8:1::/64")).unwrap(),
+            FIRST
+        );
+
+        assert_eq!(
+            query
+                .ipv4_in_subnet(net::<Ipv4Net>("100.64.0.0/10"))
+                .unwrap(),
+            SHARED
+        );
+        assert_eq!(
+            query
+                .ipv4_in_subnet(net::<IpNet>("192.168.77.99/24"))
+                .unwrap(),
+            PRIVATE
+        );
+        assert_eq!(query.ipv4_in_subnet(PRIVATE).unwrap(), PRIVATE);
+        assert!(matches!(
+            query.ipv4_in_subnet(net::<Ipv4Net>("10.0.0.0/8")),
+            Err(Error::NoAddress {
+                family: IpVersion::V4,
+                ..
+            })
+        ));
+        assert_eq!(
+            preferred_ip::ipv4_in_subnet("veth0", net::<Ipv4Net>("192.168.0.0/16")).unwrap(),
+            PRIVATE
+        );
+
+        let preferred = query.ipv6_unicast_global().unwrap();
+        let other = if preferred == FIRST { SECOND } else { FIRST };
+        assert_eq!(
+            query
+                .verify_ipv6_within(Ipv6Net::new(preferred, 64).unwrap())
+                .unwrap(),
+            PrefixVerdict::Inside(preferred)
+        );
+        assert_eq!(
+            preferred_ip::verify_ipv6_within(
+                "veth0",
+                IpNet::from(Ipv6Net::new(other, 64).unwrap())
+            )
+            .unwrap(),
+            PrefixVerdict::Outside {
+                addr: preferred,
+                suggestion: Some(other),
+            }
+        );
+    });
+}
+
+#[test]
+fn selectors_reject_the_other_family() {
+    let query = IpQuery::new("veth0");
+
+    let v4 = net::<IpNet>("192.168.77.0/24");
+    let v6 = net::<IpNet>("2a01:4f8::/32");
+    assert!(matches!(
+        query.ipv6_in_prefix(v4),
+        Err(Error::WrongIpVer(IpVersion::V6, IpAddr::V4(_)))
+    ));
+    assert!(matches!(
+        query.verify_ipv6_within(v4),
+        Err(Error::WrongIpVer(IpVersion::V6, IpAddr::V4(_)))
+    ));
+    assert!(matches!(
+        query.ipv4_in_subnet(v6),
+        Err(Error::WrongIpVer(IpVersion::V4, IpAddr::V6(_)))
+    ));
+}
+
+#[test]
+fn interface_addresses_convert_to_ipnet() {
+    common::run(env(), || {
+        let addrs = interface_addresses("veth0").unwrap();
+        let nets: Vec<IpNet> = addrs.iter().map(IpNet::from).collect();
+
+        assert!(nets.contains(&net("2a01:4f8:1::5/64")));
+        assert!(nets.contains(&net("2a01:4f8:2::5/64")));
+        assert!(nets.contains(&net("192.168.77.1/24")));
+        assert!(nets.contains(&net("100.64.0.1/10")));
+
+        // And back, through the prefixes of the crate.
+        for (addr, net) in addrs.iter().zip(&nets) {
+            assert_eq!(IpPrefix::from(addr), IpPrefix::from(*net));
+            assert_eq!(IpNet::from(IpPrefix::from(addr)), *net);
+        }
+    });
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::fs;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{ipv6_privacy_policy, IpQuery, PrivacyPolicy, SourcePreference};
+
+fn set_use_tempaddr(conf: &str, value: &str) {
+    let path = format!("/proc/sys/net/ipv6/conf/{}/use_tempaddr", conf);
+    fs::write(path, value).unwrap();
+}
+
+#[test]
+fn policy_of_the_interface() {
+    let result = common::run(NetEnv::builder(), || {
+        let mut policies = Vec::new();
+        for value in ["0", "1", "2"] {
+            set_use_tempaddr("veth0", value);
+            policies.push(ipv6_privacy_policy("veth0").unwrap());
+        }
+        // Interfaces don't follow later changes of `default`.
+        set_use_tempaddr("default", "2");
+        set_use_tempaddr("veth1", "0");
+        policies.push(ipv6_privacy_policy("veth1").unwrap());
+
+        (policies, ipv6_privacy_policy("nonexistent0"))
+    });
+    let Some((policies, missing)) = result else {
+        return;
+    };
+
+    assert_eq!(
+        policies,
+        [
+            PrivacyPolicy::Disabled,
+            PrivacyPolicy::PreferPublic,
+            PrivacyPolicy::PreferTemporary,
+            PrivacyPolicy::Disabled,
+        ]
+    );
+    assert_eq!(missing.unwrap_err().raw_os_error(), Some(libc::ENODEV));
+}
+
+#[test]
+fn gua_is_checked_against_the_policy() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+
+    let result = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let consistent = |query: &IpQuery<'_>| {
+            query
+                .ipv6_unicast_global_lenient()
+                .unwrap()
+                .privacy_consistent
+        };
+
+        set_use_tempaddr("veth0", "0");
+        let disabled = consistent(&query);
+        // There is no temporary address, so the stable one is
+        // only consistent if temporary addresses aren't preferred.
+        set_use_tempaddr("veth0", "2");
+        let prefer_temporary = consistent(&query);
+        let prefer_public = consistent(&query.clone().prefer_source(SourcePreference::Public));
+
+        (disabled, prefer_temporary, prefer_public)
+    });
+    let Some((disabled, prefer_temporary, prefer_public)) = result else {
+        return;
+    };
+
+    assert_eq!(disabled, Some(true));
+    assert_eq!(prefer_temporary, Some(false));
+    assert_eq!(prefer_public, Some(true));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Backend, IpQuery};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const ULA: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xdead, 0, 0, 0, 0, 0, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+#[test]
+fn procfs_agrees_with_socket_probes() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        let probed = IpQuery::new("veth0");
+        let procfs = IpQuery::new("veth0").backend(Backend::Procfs);
+
+        assert_eq!(procfs.ipv6_unicast_global().unwrap(), GUA);
+        assert_eq!(procfs.ipv6_unique_local().unwrap(), ULA);
+        assert_eq!(procfs.ipv4_private().unwrap(), PRIVATE);
+        assert_eq!(
+            procfs.ipv6_unicast_link_local().unwrap(),
+            probed.ipv6_unicast_link_local().unwrap()
+        );
+        assert_eq!(
+            procfs.ipv6_unicast_global().unwrap(),
+            probed.ipv6_unicast_global().unwrap()
+        );
+    });
+}
+
+#[test]
+fn procfs_selects_by_scope_only() {
+    // The kernel prefers the address with the longest match with
+    // the probe destination, procfs picks the lowest one.
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("2000::1/64")
+        .route("default");
+
+    common::run(env, || {
+        let procfs = IpQuery::new("veth0").backend(Backend::Procfs);
+        assert_eq!(
+            procfs.ipv6_unicast_global().unwrap(),
+            "2000::1".parse::<Ipv6Addr>().unwrap()
+        );
+    });
+}
+
+#[test]
+fn procfs_keeps_addresses_of_other_interfaces_apart() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24").route("default");
+
+    common::run(env, || {
+        common::ip("addr add 10.0.0.1/8 dev veth1");
+
+        let veth0 = IpQuery::new("veth0").backend(Backend::Procfs);
+        let veth1 = IpQuery::new("veth1").backend(Backend::Procfs);
+        assert_eq!(veth0.ipv4_private().unwrap(), PRIVATE);
+        assert_eq!(veth1.ipv4_private().unwrap(), Ipv4Addr::new(10, 0, 0, 1));
+    });
+}
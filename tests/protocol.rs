@@ -0,0 +1,74 @@
+mod common;
+
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{probe_consistency, IpQuery, ProbeProtocol};
+
+const UDP_SOURCE: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const TCP_SOURCE: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 2);
+
+#[test]
+fn tcp_probe_never_waits_for_handshake() {
+    // Nothing answers on the peer, so a blocking connect
+    // would wait for the SYN retransmissions to time out.
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+
+    common::run(env, || {
+        let start = Instant::now();
+        let gua = IpQuery::new("veth0")
+            .protocol(ProbeProtocol::Tcp)
+            .ipv6_unicast_global()
+            .unwrap();
+
+        assert_eq!(gua, UDP_SOURCE);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    });
+}
+
+#[test]
+fn protocols_agree_without_policy_routing() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        for dest in ["2001:4860::1", "10.1.2.3"] {
+            let dest: IpAddr = dest.parse().unwrap();
+            let consistency = probe_consistency("veth0", dest).unwrap();
+            assert!(consistency.is_consistent(), "{:?}", consistency);
+        }
+    });
+}
+
+#[test]
+fn protocols_differ_with_policy_routing() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("2a01:4f8::2/64");
+
+    common::run(env, || {
+        common::ip("-6 route add default dev veth0 src 2a01:4f8::1");
+        common::ip("-6 route add default dev veth0 src 2a01:4f8::2 table 100");
+        common::ip("-6 rule add ipproto tcp table 100");
+
+        let query = IpQuery::new("veth0");
+        assert_eq!(query.ipv6_unicast_global().unwrap(), UDP_SOURCE);
+        assert_eq!(
+            query
+                .clone()
+                .protocol(ProbeProtocol::Tcp)
+                .ipv6_unicast_global()
+                .unwrap(),
+            TCP_SOURCE
+        );
+
+        let dest: IpAddr = "2001:4860::1".parse().unwrap();
+        let consistency = probe_consistency("veth0", dest).unwrap();
+        assert_eq!(consistency.udp, IpAddr::V6(UDP_SOURCE));
+        assert_eq!(consistency.tcp, IpAddr::V6(TCP_SOURCE));
+        assert!(!consistency.is_consistent());
+    });
+}
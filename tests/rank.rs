@@ -0,0 +1,92 @@
+mod common;
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::IpQuery;
+
+const NEAR: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 5);
+const FAR: Ipv6Addr = Ipv6Addr::new(0x2a02, 0, 0, 0, 0, 0, 0, 5);
+const DEST: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 0x99);
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::5/64")
+        .ipv6("2a02::5/64")
+        .route("default")
+}
+
+fn ranked(query: &IpQuery<'_>) -> Vec<IpAddr> {
+    query
+        .rank_sources(IpAddr::V6(DEST))
+        .unwrap()
+        .into_iter()
+        .map(|addr| addr.addr)
+        .filter(|addr| !matches!(addr, IpAddr::V6(ipv6) if ipv6.is_unicast_link_local()))
+        .collect()
+}
+
+#[test]
+fn kernel_table_is_dumped() {
+    let labels = common::run(NetEnv::builder(), || {
+        let before = preferred_ip::address_labels().unwrap();
+        common::ip("addrlabel add prefix 2001:db8:bad::/48 label 99");
+        common::ip("addrlabel add prefix 2001:db8:600d::/48 dev veth0 label 98");
+        (before, preferred_ip::address_labels().unwrap())
+    });
+    let Some((before, after)) = labels else {
+        return;
+    };
+
+    assert!(
+        before.contains(&(Ipv6Addr::LOCALHOST, 128, 0)),
+        "{:?}",
+        before
+    );
+    assert!(
+        before.contains(&(Ipv6Addr::UNSPECIFIED, 0, 1)),
+        "{:?}",
+        before
+    );
+
+    let bad = "2001:db8:bad::".parse().unwrap();
+    assert!(after.contains(&(bad, 48, 99)), "{:?}", after);
+    // Entries restricted to an interface are omitted.
+    assert!(
+        after.iter().all(|&(_, _, label)| label != 98),
+        "{:?}",
+        after
+    );
+    assert_eq!(after.len(), before.len() + 1);
+}
+
+#[test]
+fn custom_labels_change_the_ranking() {
+    let ranks = common::run(env(), || {
+        let query = IpQuery::new("veth0");
+        let before = ranked(&query);
+        // Labeling the near address differently from the destination
+        // overrides rule 8 (longest matching prefix).
+        common::ip("addrlabel add prefix 2a01:4f8::5/128 label 99");
+        (before, ranked(&query))
+    });
+    let Some((before, after)) = ranks else { return };
+
+    assert_eq!(before, [IpAddr::V6(NEAR), IpAddr::V6(FAR)]);
+    assert_eq!(after, [IpAddr::V6(FAR), IpAddr::V6(NEAR)]);
+}
+
+#[test]
+fn interface_labels_change_the_ranking() {
+    let ranks = common::run(env(), || {
+        common::ip("addrlabel add prefix 2a01:4f8::5/128 dev veth0 label 99");
+        (
+            ranked(&IpQuery::new("veth0")),
+            preferred_ip::rank_sources(Some("veth0"), IpAddr::V6(DEST)).unwrap(),
+        )
+    });
+    let Some((ranked, all)) = ranks else { return };
+
+    assert_eq!(ranked, [IpAddr::V6(FAR), IpAddr::V6(NEAR)]);
+    assert!(all.iter().all(|addr| addr.addr.is_ipv6()));
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use std::net::IpAddr;
+
+use preferred_ip::test_support::{NetEnv, NetEnvBuilder};
+use preferred_ip::{
+    default_routes, detect_multipath, preferred_source_via, rank_interfaces, Error, IpVersion,
+};
+
+#[test]
+fn rank_interfaces_by_route() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        common::ip("link add wan1 type veth peer name wan1-peer");
+        common::ip("link add down0 type veth peer name down0-peer");
+        common::ip("addr add 10.1.0.1/24 dev wan1");
+        common::ip("link set wan1 up");
+        common::ip("route add default dev veth0 metric 200");
+        common::ip("route add default dev wan1 metric 100");
+        common::ip("route add 203.0.113.0/24 dev veth0 metric 300");
+
+        let dest: IpAddr = "198.51.100.1".parse().unwrap();
+        let rankings = rank_interfaces(dest, &["down0", "veth0", "wan1"]).unwrap();
+        let order: Vec<_> = rankings.iter().map(|r| r.interface.as_str()).collect();
+        assert_eq!(order, ["wan1", "veth0", "down0"]);
+        assert_eq!(rankings[0].metric, Some(100));
+        assert_eq!(rankings[0].source, Some("10.1.0.1".parse().unwrap()));
+        assert_eq!(rankings[1].source, Some("192.168.77.1".parse().unwrap()));
+        assert!(!rankings[2].reachable);
+
+        // The more specific route wins despite its metric.
+        let dest: IpAddr = "203.0.113.1".parse().unwrap();
+        let rankings = rank_interfaces(dest, &["wan1", "veth0"]).unwrap();
+        assert_eq!(rankings[0].interface, "veth0");
+        assert_eq!(rankings[0].prefix_len, Some(24));
+    });
+}
+
+#[test]
+fn detect_multipath_sources() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24");
+
+    common::run(env, || {
+        common::ip("link add wan1 type veth peer name wan1-peer");
+        common::ip("addr add 10.1.0.1/24 dev wan1");
+        common::ip("link set wan1 up");
+        common::ip("route add default nexthop dev veth0 nexthop dev wan1");
+        // Hash the ports as well, so that the probes from
+        // different ports take different nexthops.
+        std::fs::write("/proc/sys/net/ipv4/fib_multipath_hash_policy", "1").unwrap();
+
+        let dest: IpAddr = "198.51.100.1".parse().unwrap();
+        let report = detect_multipath(None, dest, 64).unwrap();
+        assert_eq!(report.nexthops, Some(2));
+        assert!(report.is_multipath());
+        assert!(report.is_flow_dependent(), "{:?}", report);
+
+        let mut sources = report.sources.clone();
+        sources.sort();
+        let expected: Vec<IpAddr> =
+            vec!["10.1.0.1".parse().unwrap(), "192.168.77.1".parse().unwrap()];
+        assert_eq!(sources, expected);
+    });
+}
+
+#[test]
+fn detect_single_path() {
+    let env = NetEnv::builder().ipv4("192.168.77.1/24").route("default");
+
+    common::run(env, || {
+        let dest: IpAddr = "198.51.100.1".parse().unwrap();
+        let report = detect_multipath(Some("veth0"), dest, 16).unwrap();
+        assert_eq!(report.sources, ["192.168.77.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(report.nexthops, Some(1));
+        assert!(!report.is_multipath());
+        assert!(!report.is_flow_dependent());
+
+        // At least one probe is made.
+        let report = detect_multipath(Some("veth0"), dest, 0).unwrap();
+        assert_eq!(report.sources.len(), 1);
+    });
+}
+
+fn dual_wan() -> NetEnvBuilder {
+    NetEnv::builder().ipv4("10.1.0.2/24")
+}
+
+/// Add a second uplink next to veth0 and default routes through both.
+fn add_uplinks() {
+    common::ip("link add wan2 type veth peer name wan2-peer");
+    common::ip("link set wan2 up");
+    common::ip("link set wan2-peer up");
+    common::ip("addr add 10.2.0.2/24 dev wan2");
+    common::ip("addr add 10.2.0.3/24 dev wan2");
+    common::ip("route add default via 10.1.0.1 dev veth0 metric 200");
+    common::ip("route add default via 10.2.0.1 dev wan2 metric 100 src 10.2.0.3");
+    common::ip("route add default dev veth0 metric 300 table 1000");
+    common::ip("route add blackhole default metric 400");
+}
+
+#[test]
+fn default_routes_of_all_uplinks() {
+    let routes = common::run(dual_wan(), || {
+        add_uplinks();
+        default_routes(IpVersion::V4).unwrap()
+    });
+    let Some(routes) = routes else { return };
+
+    let summary: Vec<_> = routes
+        .iter()
+        .map(|route| {
+            (
+                route.interface.as_deref().unwrap(),
+                route.metric,
+                route.table,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("wan2", 100, 254),
+            ("veth0", 200, 254),
+            ("veth0", 300, 1000)
+        ]
+    );
+
+    assert_eq!(routes[0].gateway, Some("10.2.0.1".parse().unwrap()));
+    assert_eq!(routes[0].source, Some("10.2.0.3".parse().unwrap()));
+    // Without a preferred source, the address is looked up
+    // through the interface.
+    assert_eq!(routes[1].gateway, Some("10.1.0.1".parse().unwrap()));
+    assert_eq!(routes[1].source, Some("10.1.0.2".parse().unwrap()));
+    assert_eq!(routes[2].gateway, None);
+}
+
+#[test]
+fn preferred_source_through_each_uplink() {
+    let sources = common::run(dual_wan(), || {
+        add_uplinks();
+        (
+            preferred_source_via("veth0", IpVersion::V4).unwrap(),
+            preferred_source_via("wan2", IpVersion::V4).unwrap(),
+            preferred_source_via("nonexistent0", IpVersion::V4).unwrap_err(),
+        )
+    });
+    let Some((veth0, wan2, missing)) = sources else {
+        return;
+    };
+
+    // veth0 isn't the best uplink, its source is reported anyway.
+    assert_eq!(veth0, "10.1.0.2".parse::<IpAddr>().unwrap());
+    assert_eq!(wan2, "10.2.0.3".parse::<IpAddr>().unwrap());
+    assert_eq!(missing.raw_os_error(), Some(libc::ENODEV));
+}
+
+#[test]
+fn uplink_without_address() {
+    let result = common::run(NetEnv::builder(), || {
+        common::ip("route add default dev veth0");
+        (
+            preferred_source_via("veth0", IpVersion::V4),
+            default_routes(IpVersion::V4).unwrap(),
+        )
+    });
+    let Some((source, routes)) = result else {
+        return;
+    };
+
+    assert!(
+        matches!(
+            source,
+            Err(Error::NoAddress {
+                family: IpVersion::V4,
+                ..
+            })
+        ),
+        "{:?}",
+        source
+    );
+    assert_eq!(routes.len(), 1);
+    assert_eq!(routes[0].source, None);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Backend, Error, FallbackKind, IpQuery, ProbeObserver};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const PRIVATE: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 1);
+
+/// An observer that records the fallbacks.
+#[derive(Clone, Default)]
+struct Fallbacks(Arc<Mutex<Vec<FallbackKind>>>);
+
+impl Fallbacks {
+    fn take(&self) -> Vec<FallbackKind> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl ProbeObserver for Fallbacks {
+    fn on_fallback(&self, _: Option<&str>, kind: FallbackKind) {
+        self.0.lock().unwrap().push(kind);
+    }
+}
+
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default")
+}
+
+fn is_sandboxed(result: &preferred_ip::Result<impl std::fmt::Debug>, errno: i32) -> bool {
+    matches!(
+        result,
+        Err(Error::SandboxRestricted { syscall: "socket", source })
+            if source.raw_os_error() == Some(errno)
+    )
+}
+
+#[test]
+fn restricted_creation_falls_back_to_procfs() {
+    for errno in [libc::EPERM, libc::EACCES] {
+        common::run(env(), move || {
+            common::block_inet_sockets(errno, true);
+            let fallbacks = Fallbacks::default();
+            let query = IpQuery::new("veth0").observer(fallbacks.clone());
+
+            assert_eq!(query.ipv6_unicast_global().unwrap(), GUA);
+            assert_eq!(fallbacks.take().last(), Some(&FallbackKind::Procfs));
+            assert_eq!(query.ipv4_private().unwrap(), PRIVATE);
+            assert_eq!(fallbacks.take().last(), Some(&FallbackKind::Procfs));
+        });
+    }
+}
+
+#[test]
+fn explicit_socket_backend_reports_the_sandbox() {
+    for errno in [libc::EPERM, libc::EACCES] {
+        common::run(env(), move || {
+            common::block_inet_sockets(errno, true);
+            let fallbacks = Fallbacks::default();
+            let query = IpQuery::new("veth0")
+                .backend(Backend::Socket)
+                .observer(fallbacks.clone());
+
+            let result = query.ipv6_unicast_global();
+            assert!(is_sandboxed(&result, errno), "{:?}", result);
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .ends_with("consider the procfs backend"));
+            assert!(is_sandboxed(&query.ipv4_private(), errno));
+            assert_eq!(fallbacks.take(), []);
+        });
+    }
+}
+
+#[test]
+fn only_restricted_families_fall_back() {
+    common::run(env(), || {
+        common::block_inet_sockets(libc::EPERM, false);
+        let fallbacks = Fallbacks::default();
+        let query = IpQuery::new("veth0").observer(fallbacks.clone());
+
+        assert_eq!(query.ipv4_private().unwrap(), PRIVATE);
+        assert_eq!(fallbacks.take(), []);
+        assert_eq!(query.ipv6_unicast_global().unwrap(), GUA);
+        assert_eq!(fallbacks.take().last(), Some(&FallbackKind::Procfs));
+
+        let socket_only = IpQuery::new("veth0").backend(Backend::Socket);
+        assert_eq!(socket_only.ipv4_private().unwrap(), PRIVATE);
+        assert!(is_sandboxed(
+            &socket_only.ipv6_unicast_global(),
+            libc::EPERM
+        ));
+    });
+}
+
+#[test]
+fn other_creation_failures_dont_fall_back() {
+    common::run(env(), || {
+        common::block_inet_sockets(libc::EMFILE, true);
+        let fallbacks = Fallbacks::default();
+
+        let result = IpQuery::new("veth0")
+            .observer(fallbacks.clone())
+            .ipv6_unicast_global();
+        assert!(
+            matches!(&result, Err(Error::IoError(e)) if e.raw_os_error() == Some(libc::EMFILE)),
+            "{:?}",
+            result
+        );
+        assert_eq!(fallbacks.take(), []);
+    });
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::net::IpAddr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{get_by_spec, IpQuery, Ipv4Scope, Ipv6Scope, Result, Scope};
+
+/// Call the typed getter of the scope.
+fn typed(query: &IpQuery, scope: Scope) -> Result<IpAddr> {
+    match scope {
+        Scope::V6(scope) => match scope {
+            Ipv6Scope::UnicastLinkLocal => query.ipv6_unicast_link_local(),
+            Ipv6Scope::UniqueLocal => query.ipv6_unique_local(),
+            Ipv6Scope::UnicastGlobal => query.ipv6_unicast_global(),
+            Ipv6Scope::Loopback => query.ipv6_loopback(),
+        }
+        .map(IpAddr::V6),
+        Scope::V4(scope) => match scope {
+            Ipv4Scope::LinkLocal => query.ipv4_link_local(),
+            Ipv4Scope::Private => query.ipv4_private(),
+            Ipv4Scope::Global => query.ipv4_global(),
+            Ipv4Scope::Loopback => query.ipv4_loopback(),
+        }
+        .map(IpAddr::V4),
+    }
+}
+
+const ALL: [Scope; 8] = [
+    Scope::V6(Ipv6Scope::UnicastLinkLocal),
+    Scope::V6(Ipv6Scope::UniqueLocal),
+    Scope::V6(Ipv6Scope::UnicastGlobal),
+    Scope::V6(Ipv6Scope::Loopback),
+    Scope::V4(Ipv4Scope::LinkLocal),
+    Scope::V4(Ipv4Scope::Private),
+    Scope::V4(Ipv4Scope::Global),
+    Scope::V4(Ipv4Scope::Loopback),
+];
+
+/// Check that the specification dispatches to the typed getter,
+/// comparing errors by their messages.
+fn assert_parity(spec: &str, query: &IpQuery, scope: Scope) {
+    let by_spec = get_by_spec(spec).map_err(|e| e.to_string());
+    let typed = typed(query, scope).map_err(|e| e.to_string());
+    assert_eq!(by_spec, typed, "{}", spec);
+}
+
+#[test]
+fn specs_dispatch_to_typed_getters() {
+    let env = NetEnv::builder()
+        .ipv6("2a01:4f8::1/64")
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        let mut successes = Vec::new();
+
+        for interface in ["veth0", "veth1", "lo"] {
+            let query = IpQuery::new(interface);
+
+            for scope in ALL {
+                let spec = format!("{}@{}", scope, interface);
+                assert_parity(&spec, &query, scope);
+                if typed(&query, scope).is_ok() {
+                    successes.push(spec);
+                }
+            }
+        }
+        for scope in ALL {
+            assert_parity(&scope.to_string(), &IpQuery::any_interface(), scope);
+        }
+
+        // Both outcomes are covered. The kernel uses IPv4 addresses
+        // of other interfaces if the bound one has none.
+        assert_eq!(
+            successes,
+            [
+                "ipv6-link-local@veth0",
+                "ipv6-ula@veth0",
+                "ipv6-gua@veth0",
+                "ipv4-private@veth0",
+                "ipv6-link-local@veth1",
+                "ipv4-private@veth1",
+                "ipv6-loopback@lo",
+                "ipv4-private@lo",
+                "ipv4-loopback@lo",
+            ]
+        );
+    });
+}
+
+#[test]
+fn specs_dispatch_to_get() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    common::run(env, || {
+        for scope in ALL {
+            let by_spec = get_by_spec(&format!("{}@veth0", scope)).map_err(|e| e.to_string());
+            let get = IpQuery::new("veth0").get(scope).map_err(|e| e.to_string());
+            assert_eq!(by_spec, get, "{}", scope);
+        }
+    });
+}
+
+#[test]
+fn unknown_interfaces_fail_like_typed_getters() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64");
+
+    common::run(env, || {
+        assert_parity(
+            "ipv6-ula@nonexistent0",
+            &IpQuery::new("nonexistent0"),
+            Scope::V6(Ipv6Scope::UniqueLocal),
+        );
+        assert_parity(
+            "ipv4-private@nonexistent0",
+            &IpQuery::new("nonexistent0"),
+            Scope::V4(Ipv4Scope::Private),
+        );
+    });
+}
@@ -0,0 +1,212 @@
+mod common;
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use preferred_ip::socket2::{Domain, Socket, Type};
+use preferred_ip::test_support::{MockClock, NetEnv};
+use preferred_ip::{Clock, Error, IpQuery, ProvidedSocket, SocketFactory};
+
+/// A factory that blocks for the given duration of the clock
+/// before providing a socket, like a stalled privileged helper.
+fn sleeping(clock: impl Clock + 'static, delay: Duration) -> impl SocketFactory {
+    move |domain: Domain, ty: Type| -> io::Result<ProvidedSocket> {
+        clock.sleep(delay);
+        Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+    }
+}
+
+fn is_timeout(result: &preferred_ip::Result<Ipv4Addr>, expected: &str) -> bool {
+    matches!(result, Err(Error::Timeout { operation, .. }) if *operation == expected)
+}
+
+#[test]
+fn slow_factory_times_out() {
+    let clock = MockClock::new();
+    let start = Instant::now();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::from_secs(1))
+        .socket_factory(sleeping(clock, Duration::from_secs(5)))
+        .ipv4_loopback();
+
+    assert!(is_timeout(&result, "socket"), "{:?}", result);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn slow_factory_times_out_in_real_time() {
+    let start = Instant::now();
+
+    let result = IpQuery::any_interface()
+        .timeout(Duration::from_millis(50))
+        .socket_factory(move |domain, ty| {
+            thread::sleep(Duration::from_millis(200));
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        })
+        .ipv4_loopback();
+
+    assert!(is_timeout(&result, "socket"), "{:?}", result);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn fast_factory_succeeds() {
+    let clock = MockClock::new();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::from_secs(1))
+        .socket_factory(sleeping(clock, Duration::from_millis(500)))
+        .ipv4_loopback();
+
+    assert_eq!(result.unwrap(), Ipv4Addr::LOCALHOST);
+}
+
+#[test]
+fn zero_timeout_is_no_timeout() {
+    let clock = MockClock::new();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::ZERO)
+        .socket_factory(sleeping(clock, Duration::from_secs(3600)))
+        .ipv4_loopback();
+
+    assert_eq!(result.unwrap(), Ipv4Addr::LOCALHOST);
+}
+
+#[test]
+fn deadline_shorter_than_timeout_wins() {
+    let clock = MockClock::new();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::from_secs(5))
+        .deadline(clock.now() + Duration::from_secs(1))
+        .socket_factory(sleeping(clock, Duration::from_secs(2)))
+        .ipv4_loopback();
+
+    assert!(is_timeout(&result, "socket"), "{:?}", result);
+}
+
+#[test]
+fn timeout_shorter_than_deadline_wins() {
+    let clock = MockClock::new();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::from_secs(1))
+        .deadline(clock.now() + Duration::from_secs(5))
+        .socket_factory(sleeping(clock, Duration::from_secs(2)))
+        .ipv4_loopback();
+
+    assert!(is_timeout(&result, "socket"), "{:?}", result);
+}
+
+#[test]
+fn within_timeout_and_deadline_succeeds() {
+    let clock = MockClock::new();
+
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .timeout(Duration::from_secs(3))
+        .deadline(clock.now() + Duration::from_secs(3))
+        .socket_factory(sleeping(clock, Duration::from_secs(2)))
+        .ipv4_loopback();
+
+    assert_eq!(result.unwrap(), Ipv4Addr::LOCALHOST);
+}
+
+#[test]
+fn expired_deadline_skips_probe() {
+    let clock = MockClock::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+
+    let query = IpQuery::any_interface()
+        .clock(clock.clone())
+        .total_timeout(Duration::from_secs(1))
+        .socket_factory(move |domain, ty| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            Socket::new(domain, ty, None).map(ProvidedSocket::Unbound)
+        });
+    clock.advance(Duration::from_secs(1));
+
+    let result = query.ipv4_loopback();
+    assert!(is_timeout(&result, "probe"), "{:?}", result);
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn private_fails_on_deadline_between_probes() {
+    let clock = MockClock::new();
+
+    // The three probes take a second each,
+    // so the deadline expires during the last one.
+    let result = IpQuery::any_interface()
+        .clock(clock.clone())
+        .total_timeout(Duration::from_millis(2500))
+        .socket_factory(sleeping(clock, Duration::from_secs(1)))
+        .ipv4_private();
+
+    assert!(is_timeout(&result, "socket"), "{:?}", result);
+}
+
+#[test]
+fn get_all_reports_partial_results() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    let report = common::run(env, || {
+        let clock = MockClock::new();
+
+        // Link-local and ULA are probed before the deadline expires.
+        IpQuery::new("veth0")
+            .clock(clock.clone())
+            .total_timeout(Duration::from_millis(2500))
+            .socket_factory(sleeping(clock, Duration::from_secs(1)))
+            .get_all()
+            .unwrap()
+    });
+    let Some(report) = report else { return };
+
+    assert!(report.incomplete);
+    assert!(report.ipv6_unicast_link_local.is_some());
+    assert_eq!(
+        report.ipv6_unique_local,
+        Some("fd00:dead::1".parse().unwrap())
+    );
+    assert_eq!(report.ipv6_unicast_global, None);
+    assert_eq!(report.ipv4_private, None);
+}
+
+#[test]
+fn get_all_without_deadline_is_complete() {
+    let env = NetEnv::builder()
+        .ipv6("fd00:dead::1/64")
+        .ipv4("192.168.77.1/24")
+        .route("default");
+
+    let report = common::run(env, || {
+        let clock = MockClock::new();
+
+        IpQuery::new("veth0")
+            .clock(clock.clone())
+            .timeout(Duration::from_secs(2))
+            .socket_factory(sleeping(clock, Duration::from_secs(1)))
+            .get_all()
+            .unwrap()
+    });
+    let Some(report) = report else { return };
+
+    assert!(!report.incomplete);
+    assert_eq!(report.ipv4_private, Some(Ipv4Addr::new(192, 168, 77, 1)));
+}
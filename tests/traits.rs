@@ -0,0 +1,208 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::net::Ipv6Addr;
+
+use preferred_ip::{
+    AddressReport, ChangeOutcome, DefaultRoute, DiagnosticReport, ErrorKind, Explanation,
+    InterfaceAddr, InterfaceRanking, IpPrefix, IpQuery, IpVersion, Ipv4Scope, Ipv6Scope, Lenient,
+    MultipathReport, PlannedProbe, ProbeConsistency, ProbePlan, RangeKind, Scope, ScopeDiagnostic,
+    ScopeStats, ScopedIpv6Addr, SocketPlan, StatsSnapshot, WatchEvent,
+};
+
+fn collection<T: Debug + Clone + Eq + Hash>() {}
+fn ordered<T: Ord>() {}
+fn copy<T: Copy>() {}
+fn default<T: Default>() {}
+
+#[test]
+fn public_types_can_be_collected() {
+    collection::<AddressReport>();
+    collection::<ChangeOutcome>();
+    collection::<DefaultRoute>();
+    collection::<DiagnosticReport>();
+    collection::<ErrorKind>();
+    collection::<Explanation>();
+    collection::<InterfaceAddr>();
+    collection::<InterfaceRanking>();
+    collection::<IpPrefix>();
+    collection::<IpVersion>();
+    collection::<Lenient<Ipv6Addr>>();
+    collection::<MultipathReport>();
+    collection::<PlannedProbe>();
+    collection::<ProbeConsistency>();
+    collection::<ProbePlan>();
+    collection::<RangeKind>();
+    collection::<Scope>();
+    collection::<ScopeDiagnostic>();
+    collection::<ScopeStats>();
+    collection::<ScopedIpv6Addr>();
+    collection::<SocketPlan>();
+    collection::<StatsSnapshot>();
+    collection::<WatchEvent>();
+
+    ordered::<ErrorKind>();
+    ordered::<IpVersion>();
+    ordered::<Ipv4Scope>();
+    ordered::<Ipv6Scope>();
+    ordered::<Scope>();
+    ordered::<ScopedIpv6Addr>();
+
+    copy::<AddressReport>();
+    copy::<ChangeOutcome>();
+    copy::<IpPrefix>();
+    copy::<PlannedProbe>();
+    copy::<ProbeConsistency>();
+    copy::<Scope>();
+    copy::<ScopedIpv6Addr>();
+
+    default::<AddressReport>();
+    default::<DiagnosticReport>();
+    default::<MultipathReport>();
+    default::<StatsSnapshot>();
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Check that `Eq`, `Ord`, `PartialOrd` and `Hash` agree on all pairs.
+fn consistent<T: Ord + Hash + Debug>(values: &[T]) {
+    for a in values {
+        for b in values {
+            assert_eq!(a.partial_cmp(b), Some(a.cmp(b)), "{:?} {:?}", a, b);
+            assert_eq!(a == b, a.cmp(b).is_eq(), "{:?} {:?}", a, b);
+            if a == b {
+                assert_eq!(hash(a), hash(b), "{:?}", a);
+            }
+        }
+    }
+}
+
+fn scopes() -> Vec<Scope> {
+    let v6 = [
+        Ipv6Scope::UnicastLinkLocal,
+        Ipv6Scope::UniqueLocal,
+        Ipv6Scope::UnicastGlobal,
+        Ipv6Scope::Loopback,
+    ];
+    let v4 = [
+        Ipv4Scope::LinkLocal,
+        Ipv4Scope::Private,
+        Ipv4Scope::Global,
+        Ipv4Scope::Loopback,
+    ];
+
+    v6.into_iter()
+        .map(Scope::V6)
+        .chain(v4.into_iter().map(Scope::V4))
+        .collect()
+}
+
+#[test]
+fn scopes_are_ordered_by_version_then_width() {
+    let scopes = scopes();
+    consistent(&scopes);
+
+    // Listed in their order.
+    assert!(scopes.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(IpVersion::V4 < IpVersion::V6);
+
+    // What get_all probes is in the same order.
+    let plan = IpQuery::any_interface().dry_run();
+    let probed: Vec<_> = plan.probes.iter().map(|probe| probe.scope).collect();
+    assert!(!probed.is_empty());
+    assert!(
+        probed.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        probed
+    );
+}
+
+#[test]
+fn scopes_can_be_keys() {
+    let scopes = scopes();
+    let by_hash: HashMap<Scope, usize> = scopes.iter().copied().zip(0..).collect();
+    let by_order: BTreeSet<Scope> = scopes.iter().rev().copied().collect();
+
+    assert_eq!(by_hash.len(), scopes.len());
+    assert!(scopes.iter().zip(0..).all(|(scope, i)| by_hash[scope] == i));
+    assert!(by_order.iter().eq(&scopes));
+}
+
+fn scoped(addr: &str, scope_id: u32) -> ScopedIpv6Addr {
+    ScopedIpv6Addr {
+        addr: addr.parse().unwrap(),
+        scope_id,
+    }
+}
+
+#[test]
+fn scoped_addresses_are_ordered_by_address_then_scope_id() {
+    let mut addrs = vec![
+        scoped("fe80::2", 1),
+        scoped("fe80::1", 3),
+        scoped("fe80::1", 2),
+        scoped("2a01:4f8::1", 9),
+        scoped("fe80::1", 2),
+    ];
+    consistent(&addrs);
+
+    addrs.sort();
+    assert_eq!(
+        addrs,
+        [
+            scoped("2a01:4f8::1", 9),
+            scoped("fe80::1", 2),
+            scoped("fe80::1", 2),
+            scoped("fe80::1", 3),
+            scoped("fe80::2", 1),
+        ]
+    );
+
+    addrs.dedup();
+    let unique: HashSet<_> = addrs.iter().copied().collect();
+    assert_eq!(unique.len(), addrs.len());
+    assert!(unique.contains(&scoped("fe80::1", 3)));
+    assert!(!unique.contains(&scoped("fe80::1", 4)));
+}
+
+#[test]
+fn error_kinds_are_consistent() {
+    let kinds = [
+        ErrorKind::Io,
+        ErrorKind::WrongIpVersion,
+        ErrorKind::WrongScope,
+        ErrorKind::Timeout,
+        ErrorKind::NoAddress,
+        ErrorKind::NoRoute,
+        ErrorKind::AddrInUse,
+        ErrorKind::AddrNotAvailable,
+        ErrorKind::InvalidInput,
+        ErrorKind::Other,
+    ];
+    consistent(&kinds);
+    assert_eq!(kinds.iter().collect::<HashSet<_>>().len(), kinds.len());
+}
+
+#[test]
+fn equal_reports_hash_equally() {
+    let mut report = AddressReport {
+        ipv6_unicast_global: Some("2a01:4f8::1".parse().unwrap()),
+        ..Default::default()
+    };
+    let copy = report;
+    assert_eq!(report, copy);
+    assert_eq!(hash(&report), hash(&copy));
+
+    report.ipv4_private = Some("192.168.77.1".parse().unwrap());
+    let reports: HashSet<_> = [report, copy, report, AddressReport::default()].into();
+    assert_eq!(reports.len(), 3);
+
+    assert_eq!(AddressReport::default().ipv6_unicast_global, None);
+    assert!(DiagnosticReport::default().scopes.is_empty());
+    assert_eq!(MultipathReport::default().nexthops, None);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::time::Duration;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Connectivity, IpQuery, V6MostlySignals};
+
+fn v6_only() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder().ipv6("2a01:4f8::1/64").route("default")
+}
+
+/// Without other indicators, the nameserver is asked with the `dns64`
+/// feature. It doesn't exist, so don't wait long for it.
+fn query() -> IpQuery<'static> {
+    IpQuery::new("veth0").timeout(Duration::from_millis(100))
+}
+
+fn signals() -> V6MostlySignals {
+    query().v6_mostly_signals().unwrap()
+}
+
+#[test]
+fn nat64_route_suppresses_ipv4() {
+    let result = common::run(v6_only(), || {
+        common::ip("-6 route add 64:ff9b::/96 dev veth0");
+        (
+            signals(),
+            preferred_ip::ipv4_suppressed("veth0").unwrap(),
+            preferred_ip::connectivity(Some("veth0")).unwrap(),
+        )
+    });
+    let Some((signals, suppressed, connectivity)) = result else {
+        return;
+    };
+
+    assert!(signals.ipv6 && !signals.ipv4, "{:?}", signals);
+    assert!(signals.nat64_route && !signals.clat, "{:?}", signals);
+    assert!(suppressed);
+    assert_eq!(connectivity, Connectivity::V6Mostly);
+}
+
+#[test]
+fn nat64_routes_of_other_tables_count() {
+    let signals = common::run(v6_only(), || {
+        common::ip("-6 route add 64:ff9b:1::/48 dev veth0 table 100");
+        signals()
+    });
+    let Some(signals) = signals else { return };
+
+    assert!(signals.nat64_route, "{:?}", signals);
+}
+
+#[test]
+fn clat_suppresses_ipv4() {
+    let signals = common::run(v6_only(), || {
+        // The CLAT address is on another interface than the uplink.
+        common::ip("addr add 192.0.0.1/29 dev veth1");
+        signals()
+    });
+    let Some(signals) = signals else { return };
+
+    assert!(signals.clat && !signals.ipv4, "{:?}", signals);
+    assert!(signals.ipv4_suppressed());
+}
+
+#[test]
+fn plain_ipv6_only_is_not_v6_mostly() {
+    let result = common::run(v6_only(), || (signals(), query().connectivity().unwrap()));
+    let Some((signals, connectivity)) = result else {
+        return;
+    };
+
+    assert!(!signals.clat && !signals.nat64_route && !signals.dns64);
+    assert!(!signals.ipv4_suppressed());
+    assert_eq!(connectivity, Connectivity::V6Only);
+}
+
+#[test]
+fn ipv4_address_is_not_suppressed() {
+    let env = v6_only().ipv4("192.168.77.1/24");
+
+    let result = common::run(env, || {
+        common::ip("-6 route add 64:ff9b::/96 dev veth0");
+        (
+            signals(),
+            preferred_ip::connectivity(Some("veth0")).unwrap(),
+        )
+    });
+    let Some((signals, connectivity)) = result else {
+        return;
+    };
+
+    assert!(signals.ipv4 && signals.nat64_route, "{:?}", signals);
+    assert!(!signals.ipv4_suppressed());
+    assert_eq!(connectivity, Connectivity::DualStack);
+}
+
+#[test]
+fn link_local_ipv6_is_not_enough() {
+    let signals = common::run(NetEnv::builder(), || {
+        common::ip("-6 route add 64:ff9b::/96 dev veth0");
+        common::ip("addr add 192.0.0.1/29 dev veth1");
+        signals()
+    });
+    let Some(signals) = signals else { return };
+
+    assert!(!signals.ipv6, "{:?}", signals);
+    assert!(!signals.ipv4_suppressed());
+}
+
+#[test]
+fn missing_interface_is_an_error() {
+    assert!(preferred_ip::ipv4_suppressed("nonexistent0").is_err());
+}
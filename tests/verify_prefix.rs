@@ -0,0 +1,100 @@
+mod common;
+
+use std::net::Ipv6Addr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{IpQuery, PrefixVerdict};
+
+const LOW: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 5);
+const HIGH: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0x8000, 0, 0, 5);
+const NEXT: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 1, 0, 0, 0, 5);
+
+/// LOW and HIGH share a /64 but not a /65, all three share a /63.
+fn env() -> preferred_ip::test_support::NetEnvBuilder {
+    NetEnv::builder()
+        .ipv6("2a01:4f8::5/64")
+        .ipv6("2a01:4f8::8000:0:0:5/64")
+        .ipv6("2a01:4f8:0:1::5/64")
+        .route("default")
+}
+
+fn expected(preferred: Ipv6Addr, inside: &[Ipv6Addr]) -> PrefixVerdict {
+    match inside.contains(&preferred) {
+        true => PrefixVerdict::Inside(preferred),
+        false => PrefixVerdict::Outside {
+            addr: preferred,
+            suggestion: inside.iter().min().copied(),
+        },
+    }
+}
+
+#[test]
+fn prefixes_of_every_length_are_matched() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0");
+        let preferred = query.ipv6_unicast_global().unwrap();
+        let verify = |prefix: Ipv6Addr, len| query.verify_ipv6_within((prefix, len)).unwrap();
+
+        assert_eq!(verify(LOW, 63), PrefixVerdict::Inside(preferred));
+        assert_eq!(verify(NEXT, 63), PrefixVerdict::Inside(preferred));
+        assert_eq!(verify(LOW, 64), expected(preferred, &[LOW, HIGH]));
+        assert_eq!(verify(HIGH, 64), expected(preferred, &[LOW, HIGH]));
+        assert_eq!(verify(NEXT, 64), expected(preferred, &[NEXT]));
+        for addr in [LOW, HIGH, NEXT] {
+            assert_eq!(verify(addr, 65), expected(preferred, &[addr]));
+            assert_eq!(verify(addr, 128), expected(preferred, &[addr]));
+        }
+    });
+}
+
+#[test]
+fn outside_without_suggestion() {
+    common::run(env(), || {
+        let query = IpQuery::new("veth0");
+        let preferred = query.ipv6_unicast_global().unwrap();
+
+        let other = Ipv6Addr::new(0x2a01, 0x4f8, 0, 2, 0, 0, 0, 0);
+        assert_eq!(
+            query.verify_ipv6_within((other, 63)).unwrap(),
+            PrefixVerdict::Outside {
+                addr: preferred,
+                suggestion: None,
+            }
+        );
+    });
+}
+
+#[test]
+fn suggestions_come_from_the_interface() {
+    common::run(env(), || {
+        common::ip("addr add 2a01:4f8:0:2::5/64 dev veth1 nodad");
+        let query = IpQuery::new("veth0");
+        let preferred = query.ipv6_unicast_global().unwrap();
+        let peer = Ipv6Addr::new(0x2a01, 0x4f8, 0, 2, 0, 0, 0, 5);
+
+        assert_eq!(
+            query.verify_ipv6_within((peer, 64)).unwrap(),
+            PrefixVerdict::Outside {
+                addr: preferred,
+                suggestion: None,
+            }
+        );
+        assert!(matches!(
+            IpQuery::any_interface().verify_ipv6_within((peer, 64)).unwrap(),
+            PrefixVerdict::Outside { suggestion: Some(addr), .. } | PrefixVerdict::Inside(addr)
+                if addr == peer
+        ));
+    });
+}
+
+#[test]
+fn no_global_address() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    let verdict = common::run(env, || {
+        preferred_ip::verify_ipv6_within("veth0", (LOW, 64)).unwrap()
+    });
+    let Some(verdict) = verdict else { return };
+
+    assert_eq!(verdict, PrefixVerdict::NoGlobal);
+}
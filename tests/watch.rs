@@ -0,0 +1,141 @@
+mod common;
+
+use std::net::{IpAddr, Ipv6Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use preferred_ip::test_support::{MockClock, NetEnv};
+use preferred_ip::{ChangeOutcome, Clock, IpQuery, WatchEvent, Watcher};
+
+const GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+const OTHER_GUA: Ipv6Addr = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 2);
+
+/// Collect the events until none arrive for a while,
+/// leaving out addresses other than the given ones.
+fn events(watcher: &mut Watcher, addrs: &[&str]) -> Vec<WatchEvent> {
+    let addrs: Vec<IpAddr> = addrs.iter().map(|addr| addr.parse().unwrap()).collect();
+
+    std::iter::from_fn(|| {
+        watcher
+            .next_event_timeout(Duration::from_millis(200))
+            .unwrap()
+    })
+    .filter(|event| match event {
+        WatchEvent::AddressAdded(addr)
+        | WatchEvent::AddressChanged(addr)
+        | WatchEvent::AddressRemoved(addr) => addrs.contains(&addr.addr),
+        _ => true,
+    })
+    .collect()
+}
+
+#[test]
+fn follows_recreated_interface() {
+    let env = NetEnv::builder();
+
+    let Some((first, second)) = common::run(env, || {
+        let mut watcher = Watcher::new("ppp0").unwrap();
+        assert_eq!(watcher.index(), None);
+
+        common::ip("link add ppp0 type veth peer name ppp0-peer");
+        common::ip("addr add 192.168.77.1/24 dev ppp0");
+        let first = events(&mut watcher, &["192.168.77.1"]);
+
+        common::ip("link del ppp0");
+        common::ip("link add ppp0 type veth peer name ppp0-peer");
+        common::ip("addr add 192.168.77.2/24 dev ppp0");
+        let second = events(&mut watcher, &["192.168.77.1", "192.168.77.2"]);
+
+        assert_eq!(watcher.addresses().len(), 1);
+        (first, second)
+    }) else {
+        return;
+    };
+
+    let [WatchEvent::InterfaceAdded { index: first_index }, WatchEvent::AddressAdded(addr)] =
+        first.as_slice()
+    else {
+        panic!("{:?}", first);
+    };
+    assert_eq!(addr.addr, "192.168.77.1".parse::<IpAddr>().unwrap());
+    assert_eq!(addr.index, *first_index);
+
+    // The kernel removes the addresses before the interface.
+    let [WatchEvent::AddressRemoved(removed), WatchEvent::InterfaceRemoved, WatchEvent::InterfaceAdded { index }, WatchEvent::AddressAdded(addr)] =
+        second.as_slice()
+    else {
+        panic!("{:?}", second);
+    };
+    assert_eq!(removed.addr, "192.168.77.1".parse::<IpAddr>().unwrap());
+    assert_ne!(index, first_index);
+    assert_eq!(addr.addr, "192.168.77.2".parse::<IpAddr>().unwrap());
+    assert_eq!(addr.index, *index);
+}
+
+#[test]
+fn assigned_addresses_are_compared() {
+    let env = NetEnv::builder().ipv6("2a01:4f8::1/64").route("default");
+
+    let outcomes = common::run(env, || {
+        let query = IpQuery::new("veth0");
+        let timeout = Duration::from_secs(10);
+        (
+            query.wait_for_ipv6_global_change(GUA, timeout).unwrap(),
+            query
+                .wait_for_ipv6_global_change(OTHER_GUA, timeout)
+                .unwrap(),
+        )
+    });
+    let Some((same, other)) = outcomes else {
+        return;
+    };
+
+    assert_eq!(same, ChangeOutcome::Unchanged);
+    assert_eq!(other, ChangeOutcome::ChangedTo(GUA));
+}
+
+#[test]
+fn waits_for_the_address() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    let outcome = common::run(env, || {
+        let start = Instant::now();
+        thread::spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            common::ip("addr add 2a01:4f8::2/64 dev veth0 nodad");
+        });
+
+        let outcome = IpQuery::new("veth0")
+            .wait_for_ipv6_global_change(GUA, Duration::from_secs(10))
+            .unwrap();
+        (outcome, start.elapsed())
+    });
+    let Some((outcome, elapsed)) = outcome else {
+        return;
+    };
+
+    assert_eq!(outcome, ChangeOutcome::ChangedTo(OTHER_GUA));
+    // The address event ends the wait, not the timeout.
+    assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+}
+
+#[test]
+fn lost_after_the_timeout_of_the_clock() {
+    let env = NetEnv::builder().ipv6("fd00:dead::1/64").route("default");
+
+    let advanced = common::run(env, || {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let outcome = IpQuery::new("veth0")
+            .clock(clock.clone())
+            .wait_for_ipv6_global_change(GUA, Duration::from_millis(1500))
+            .unwrap();
+        (outcome, clock.now() - start)
+    });
+    let Some((outcome, advanced)) = advanced else {
+        return;
+    };
+
+    assert_eq!(outcome, ChangeOutcome::Lost);
+    assert_eq!(advanced, Duration::from_millis(1500));
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use std::net::IpAddr;
+
+use preferred_ip::test_support::NetEnv;
+use preferred_ip::{Destination, Error, IpQuery};
+
+#[test]
+fn link_local_destination_with_zone() {
+    let env = NetEnv::builder().interfaces("zone0", "zone1");
+
+    let Some((link_local, results)) = common::run(env, || {
+        let link_local = IpQuery::new("zone0").ipv6_unicast_link_local().unwrap();
+        let dest: Destination = "fe80::1%zone0".parse().unwrap();
+        let other: Destination = "fe80::1%zone1".parse().unwrap();
+
+        (
+            link_local,
+            [
+                preferred_ip::preferred_source_for(None, dest),
+                preferred_ip::preferred_source_for(Some("zone0"), dest),
+                preferred_ip::preferred_source_for(
+                    Some("zone0"),
+                    "fe80::1".parse::<IpAddr>().unwrap(),
+                ),
+                preferred_ip::preferred_source_for(Some("zone0"), other),
+                preferred_ip::preferred_source_for(None, "fe80::1".parse::<IpAddr>().unwrap()),
+            ],
+        )
+    }) else {
+        return;
+    };
+
+    let [unbound, bound, implicit, mismatch, missing] = results;
+    assert_eq!(unbound.unwrap(), IpAddr::V6(link_local));
+    assert_eq!(bound.unwrap(), IpAddr::V6(link_local));
+    assert_eq!(implicit.unwrap(), IpAddr::V6(link_local));
+    assert!(
+        matches!(mismatch, Err(Error::ZoneMismatch { ref interface, .. }) if interface == "zone0"),
+        "{:?}",
+        mismatch
+    );
+    assert!(matches!(missing, Err(Error::NoZone(_))), "{:?}", missing);
+}